@@ -1,14 +1,20 @@
-use crate::{gpr, tools};
+use crate::profile::Profile;
+use crate::steps::Step;
+use crate::velocity::VelocityModel;
+use crate::{cmp, gpr, io, tools};
 /// Functions to handle the command line interface (CLI)
-use clap::Parser;
-use std::{path::PathBuf, time::Duration};
+use clap::{Parser, Subcommand};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 #[derive(Debug, Parser)]
 #[clap(author, version, about, long_about = None)]
 #[clap(group(
         clap::ArgGroup::new("step_choice")
         .required(false)
-        .args(&["steps", "default", "default_with_topo"]),
+        .args(&["steps", "default", "default_with_topo", "profile"]),
     ))
 ]
 #[clap(group(
@@ -18,28 +24,36 @@ use std::{path::PathBuf, time::Duration};
     ))
 ]
 pub struct Args {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
     /// Filepath of the header file or a glob pattern of many files
     #[clap(short, long)]
     pub filepath: Option<String>,
 
-    /// Velocity of the medium in m/ns. Defaults to the typical velocity of ice.
-    #[clap(short, long, default_value = "0.168")]
-    pub velocity: f32,
+    /// Velocity of the medium in m/ns. Defaults to the typical velocity of ice (0.168).
+    #[clap(short, long, global = true)]
+    pub velocity: Option<f32>,
+
+    /// Load a layered velocity model from a CSV file for depth conversion, with the columns
+    /// "depth" (m) or "twt" (ns), and "velocity" (m/ns) or "density" (kg/m³)
+    #[clap(long, global = true)]
+    pub velocity_model: Option<PathBuf>,
 
     /// Only show metadata for the file
     #[clap(short, long)]
     pub info: bool,
 
     /// Load a separate ".cor" file. If not given, it will be searched for automatically
-    #[clap(short, long)]
+    #[clap(short, long, global = true)]
     pub cor: Option<PathBuf>,
 
     /// Correct elevation values with a DEM
-    #[clap(short, long)]
+    #[clap(short, long, global = true)]
     pub dem: Option<PathBuf>,
 
     /// Which coordinate reference system to project coordinates in.
-    #[clap(long)]
+    #[clap(long, global = true)]
     pub crs: Option<String>,
 
     /// Export the location track to a comma separated values (CSV) file. Defaults to the output filename location and stem +
@@ -47,6 +61,22 @@ pub struct Args {
     #[clap(short, long)]
     pub track: Option<Option<PathBuf>>,
 
+    /// Export the mean amplitude spectrum of the processed data to a comma separated values (CSV)
+    /// file. Defaults to the output filename location and stem + "_spectrum.csv"
+    #[clap(long)]
+    pub spectrum: Option<Option<PathBuf>>,
+
+    /// Export the picked horizons (see the "pick_horizon" step) to a comma separated values (CSV)
+    /// file. Defaults to the output filename location and stem + "_picks.csv"
+    #[clap(long)]
+    pub picks: Option<Option<PathBuf>>,
+
+    /// Export the fitted diffraction hyperbolae (see the "fit_hyperbolae" step) as a velocity
+    /// model that can be given to "--velocity-model". Defaults to the output filename location and
+    /// stem + "_velocity.csv"
+    #[clap(long)]
+    pub velocity_table: Option<Option<PathBuf>>,
+
     /// Process with the default profile. See "--show-default" to list the profile.
     #[clap(long)]
     pub default: bool,
@@ -67,12 +97,16 @@ pub struct Args {
     #[clap(long)]
     pub steps: Option<String>,
 
-    /// Output filename or directory. Defaults to the input filename with a ".nc" extension
+    /// Processing profile file (".toml", ".yaml" or ".yml") with steps and run settings. Settings given as arguments take precedence over the profile.
+    #[clap(long)]
+    pub profile: Option<PathBuf>,
+
+    /// Output filename or directory. The format is chosen from the extension (".nc", ".sgy" or ".segy"). Defaults to the input filename with a ".nc" extension
     #[clap(short, long)]
     pub output: Option<PathBuf>,
 
     /// Suppress progress messages
-    #[clap(short, long)]
+    #[clap(short, long, global = true)]
     pub quiet: bool,
 
     /// Render an image of the profile and save it to the specified path. Defaults to a jpg in the
@@ -89,16 +123,122 @@ pub struct Args {
     pub merge: Option<String>,
 
     /// Override the antenna center frequency (in MHz) of the file metadata
-    #[clap(long)]
+    #[clap(long, global = true)]
     pub override_antenna_mhz: Option<f32>,
+
+    /// One-based SEG-Y trace header byte positions of the x, y and elevation values, separated by commas. Default: "73,77,41"
+    #[clap(long, global = true)]
+    pub segy_coord_bytes: Option<String>,
+
+    /// Scalar to apply to SEG-Y coordinates and elevations instead of the trace header scalars. Negative values are divisors, e.g. -100 for centimeters
+    #[clap(long, global = true, allow_hyphen_values = true)]
+    pub segy_scalar: Option<f64>,
+
+    /// Unit of the 16 bit SEG-Y sample interval fields: "ps", "ns" or "us". Many GPR writers use "ps". The extended interval of revision 2 files is always used if set. Default: "ps" for files written by radal, otherwise "us"
+    #[clap(long, global = true)]
+    pub segy_interval_unit: Option<String>,
+
+    /// SEG-Y revision of exported files: 1 or 2. Revision 2 also has the exact sample interval in the extended header fields. Default: 2
+    #[clap(long, global = true)]
+    pub segy_revision: Option<u8>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Convert a GPR dataset from one format to another without processing it
+    ///
+    /// The readers and writers are chosen from the file extensions.
+    Translate {
+        /// Filepath of the input file
+        input: PathBuf,
+        /// Filepath of the output file
+        output: PathBuf,
+    },
+    /// Re-run the recorded processing of an exported NetCDF file on its raw data and confirm that
+    /// the result matches
+    Replay {
+        /// Filepath of the processed NetCDF file
+        filepath: PathBuf,
+        /// Filepath of the raw data, if it is not where it was when processed
+        #[clap(long)]
+        raw: Option<PathBuf>,
+    },
+    /// Estimate velocities from a common-midpoint (CMP) or wide-angle (WARR) pulseEKKO gather
+    ///
+    /// The semblance of normal moveout hyperbolae is computed for a range of velocities, and the
+    /// picked RMS velocities are exported as interval velocities in a velocity model that can be
+    /// given to "--velocity-model".
+    Cmp {
+        /// Filepath of the ".hd" file of the gather
+        filepath: PathBuf,
+        /// The increase in antenna separation between traces (m). If not given, the trace
+        /// positions of the ".dt1" file are used as antenna separations
+        #[clap(long)]
+        spacing: Option<f32>,
+        /// The antenna separation of the first trace (m) when "--spacing" is given. Defaults to
+        /// the antenna separation of the ".hd" file
+        #[clap(long)]
+        first_offset: Option<f32>,
+        /// The slowest velocity of the semblance spectrum (m/ns). Default: 0.05
+        #[clap(long)]
+        min_velocity: Option<f32>,
+        /// The fastest velocity of the semblance spectrum (m/ns). Default: 0.3
+        #[clap(long)]
+        max_velocity: Option<f32>,
+        /// The velocity step of the semblance spectrum (m/ns). Default: 0.001
+        #[clap(long)]
+        velocity_step: Option<f32>,
+        /// The time window that the semblance is summed over (ns). Defaults to one period of the
+        /// antenna center frequency
+        #[clap(long)]
+        window_ns: Option<f32>,
+        /// The minimum semblance (0-1) of an automatic pick. Default: 0.3
+        #[clap(long)]
+        min_semblance: Option<f32>,
+        /// RMS velocity picks as zero-offset return time (ns) and velocity (m/ns) separated by a
+        /// colon, e.g. "80:0.21 250:0.18". If not given, the semblance maxima are picked
+        /// automatically
+        #[clap(long)]
+        velocity_picks: Option<String>,
+        /// Filepath or directory of the velocity model CSV. Defaults to the input filename
+        /// location and stem + "_velocity.csv"
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+        /// Render the semblance spectrum with the picks. Defaults to the input filename location
+        /// and stem + "_semblance.jpg"
+        #[clap(short, long)]
+        render: Option<Option<PathBuf>>,
+    },
 }
 
+/// The velocity of the medium (m/ns) if none is given, which is the typical velocity of ice
+pub const DEFAULT_MEDIUM_VELOCITY: f32 = 0.168;
+
 pub enum CliAction {
-    Run(gpr::RunParams),
+    Run(Box<gpr::RunParams>),
+    Cmp(Box<cmp::CmpParams>),
+    Replay {
+        filepath: PathBuf,
+        raw: Option<PathBuf>,
+        quiet: bool,
+    },
     Error(String),
     Done,
 }
 pub fn args_to_action(args: &Args) -> CliAction {
+    match &args.command {
+        Some(Command::Translate { input, output }) => return translate_action(args, input, output),
+        Some(Command::Replay { filepath, raw }) => {
+            return CliAction::Replay {
+                filepath: filepath.clone(),
+                raw: raw.clone(),
+                quiet: args.quiet,
+            }
+        }
+        Some(Command::Cmp { .. }) => return cmp_action(args),
+        None => (),
+    }
+
     if args.show_all_steps {
         println!("Name\t\tDescription");
 
@@ -115,7 +255,15 @@ pub fn args_to_action(args: &Args) -> CliAction {
         return CliAction::Done;
     }
 
-    let merge: Option<Duration> = match &args.merge {
+    let profile = match &args.profile {
+        Some(filepath) => match Profile::load(filepath) {
+            Ok(p) => p,
+            Err(e) => return CliAction::Error(e),
+        },
+        None => Profile::default(),
+    };
+
+    let merge: Option<Duration> = match args.merge.as_ref().or(profile.merge.as_ref()) {
         Some(merge_string) => match parse_duration::parse(merge_string) {
             Ok(d) => Some(d),
             Err(e) => return CliAction::Error(format!("Error parsing --merge string: {:?}", e)),
@@ -123,6 +271,23 @@ pub fn args_to_action(args: &Args) -> CliAction {
         None => None,
     };
 
+    let segy_options = match segy_options(args) {
+        Ok(o) => o,
+        Err(e) => return CliAction::Error(e),
+    };
+
+    let velocity_model = match args
+        .velocity_model
+        .as_ref()
+        .or(profile.velocity_model.as_ref())
+    {
+        Some(filepath) => match VelocityModel::from_csv(filepath) {
+            Ok(m) => Some(m),
+            Err(e) => return CliAction::Error(e),
+        },
+        None => None,
+    };
+
     let filepaths = match &args.filepath {
         Some(fp) => glob::glob(fp)
             .unwrap()
@@ -136,18 +301,24 @@ pub fn args_to_action(args: &Args) -> CliAction {
         }
     };
 
-    let steps: Vec<String> = match args.info {
+    let steps: Vec<Step> = match args.info {
         true => Vec::new(),
         false => match args.default_with_topo {
             true => {
                 let mut profile = gpr::default_processing_profile();
-                profile.push("correct_topography".to_string());
+                profile.push(Step::CorrectTopography);
                 profile
             }
             false => match args.default {
                 true => gpr::default_processing_profile(),
+                false if args.profile.is_some() => profile.steps,
                 false => match &args.steps {
-                    Some(steps) => match tools::parse_step_list(steps) {
+                    Some(steps) => match tools::parse_step_list(steps).and_then(|steps| {
+                        steps
+                            .iter()
+                            .map(|step| step.parse::<Step>().map_err(|e| e.to_string()))
+                            .collect::<Result<Vec<Step>, String>>()
+                    }) {
                         Ok(s) => s,
                         Err(e) => return CliAction::Error(e),
                     },
@@ -160,44 +331,232 @@ pub fn args_to_action(args: &Args) -> CliAction {
         },
     };
 
-    let allowed_steps = gpr::all_available_steps()
-        .iter()
-        .map(|s| s[0])
-        .collect::<Vec<&str>>();
-    for step in &steps {
-        if !allowed_steps.iter().any(|allowed| step.contains(allowed)) {
-            return CliAction::Error(format!("Unrecognized step: {}", step));
-        }
-    }
-
     let params = gpr::RunParams {
         filepaths,
-        output_path: args.output.clone(),
+        output_path: args.output.clone().or(profile.output),
         only_info: args.info,
-        dem_path: args.dem.clone(),
-        cor_path: args.cor.clone(),
-        medium_velocity: args.velocity,
-        crs: args.crs.clone(),
+        dem_path: args.dem.clone().or(profile.dem),
+        cor_path: args.cor.clone().or(profile.cor),
+        medium_velocity: args
+            .velocity
+            .or(profile.velocity)
+            .unwrap_or(DEFAULT_MEDIUM_VELOCITY),
+        velocity_model,
+        crs: args.crs.clone().or(profile.crs),
         quiet: args.quiet,
         track_path: args.track.clone(),
+        spectrum_path: args.spectrum.clone(),
+        picks_path: args.picks.clone(),
+        velocity_table_path: args.velocity_table.clone(),
         steps,
         no_export: args.no_export,
-        render_path: args.render.clone(),
+        render_path: args.render.clone().or(profile.render),
         merge,
+        override_antenna_mhz: args.override_antenna_mhz.or(profile.override_antenna_mhz),
+        segy_options,
+    };
+
+    CliAction::Run(Box::new(params))
+}
+
+/// Parse the SEG-Y reader settings from the arguments
+///
+/// # Errors
+/// - The coordinate bytes are not two or three comma separated integers
+/// - The sample interval unit is not understood
+/// - The revision is not 1 or 2
+fn segy_options(args: &Args) -> Result<io::SegyOptions, String> {
+    let mut options = io::SegyOptions {
+        scalar: args.segy_scalar,
+        ..Default::default()
+    };
+
+    if let Some(coord_bytes) = &args.segy_coord_bytes {
+        let bytes = coord_bytes
+            .split(',')
+            .map(|s| s.trim().parse::<usize>())
+            .collect::<Result<Vec<usize>, _>>()
+            .map_err(|e| format!("Error parsing --segy-coord-bytes: {e}"))?;
+        match bytes.len() {
+            2 | 3 => options.coordinate_bytes[..bytes.len()].copy_from_slice(&bytes),
+            n => {
+                return Err(format!(
+                    "Expected two or three values in --segy-coord-bytes, got {n}"
+                ))
+            }
+        }
+    }
+
+    if let Some(unit) = &args.segy_interval_unit {
+        options.sample_interval_unit_ns = match unit.trim().to_lowercase().as_str() {
+            "ps" => Some(0.001),
+            "ns" => Some(1.),
+            "us" | "µs" => Some(1000.),
+            other => {
+                return Err(format!(
+                    "Unknown --segy-interval-unit: {other:?}. Choose from \"ps\", \"ns\" or \"us\""
+                ))
+            }
+        };
+    }
+
+    if let Some(revision) = args.segy_revision {
+        if ![1, 2].contains(&revision) {
+            return Err(format!(
+                "Unknown --segy-revision: {revision}. Choose from 1 or 2"
+            ));
+        }
+        options.revision = revision;
+    }
+
+    Ok(options)
+}
+
+/// Convert the arguments of the "cmp" subcommand to CMP/WARR analysis parameters
+///
+/// # Arguments
+/// - `args`: The parsed arguments (with the "cmp" subcommand)
+fn cmp_action(args: &Args) -> CliAction {
+    let Some(Command::Cmp {
+        filepath,
+        spacing,
+        first_offset,
+        min_velocity,
+        max_velocity,
+        velocity_step,
+        window_ns,
+        min_semblance,
+        velocity_picks,
+        output,
+        render,
+    }) = &args.command
+    else {
+        return CliAction::Error("Expected the \"cmp\" subcommand".to_string());
+    };
+
+    let picks = match velocity_picks.as_deref().map(parse_velocity_picks) {
+        Some(Ok(picks)) => Some(picks),
+        Some(Err(e)) => return CliAction::Error(e),
+        None => None,
+    };
+
+    CliAction::Cmp(Box::new(cmp::CmpParams {
+        filepath: filepath.clone(),
+        spacing: *spacing,
+        first_offset: *first_offset,
+        min_velocity: min_velocity.unwrap_or(cmp::DEFAULT_MIN_VELOCITY),
+        max_velocity: max_velocity.unwrap_or(cmp::DEFAULT_MAX_VELOCITY),
+        velocity_step: velocity_step.unwrap_or(cmp::DEFAULT_VELOCITY_STEP),
+        window_ns: *window_ns,
+        min_semblance: min_semblance.unwrap_or(cmp::DEFAULT_MIN_SEMBLANCE),
+        picks,
+        output_path: output.clone(),
+        render_path: render.clone(),
         override_antenna_mhz: args.override_antenna_mhz,
+        quiet: args.quiet,
+    }))
+}
+
+/// Parse velocity picks, given as return time and velocity pairs, e.g. "80:0.21 250:0.18"
+///
+/// # Errors
+/// - A pick is not two numbers separated by a colon
+fn parse_velocity_picks(text: &str) -> Result<Vec<(f32, f32)>, String> {
+    text.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|s| !s.is_empty())
+        .map(|pick| {
+            pick.split_once(':')
+                .and_then(|(time, velocity)| {
+                    time.parse::<f32>().ok().zip(velocity.parse::<f32>().ok())
+                })
+                .ok_or(format!(
+                    "Error parsing --velocity-picks: expected a return time and velocity separated by a colon (e.g. \"80:0.21\"), got {pick:?}"
+                ))
+        })
+        .collect()
+}
+
+/// Validate the arguments of the "translate" subcommand and convert them to run parameters
+///
+/// A translation is a run without any processing steps, where the output format is given by the
+/// extension of the output filepath.
+///
+/// # Arguments
+/// - `args`: The parsed arguments (for the global options)
+/// - `input`: The input filepath
+/// - `output`: The output filepath
+fn translate_action(args: &Args, input: &Path, output: &Path) -> CliAction {
+    for (filepath, allowed, kind) in [
+        (input, io::IMPORT_EXTENSIONS, "input"),
+        (output, io::EXPORT_EXTENSIONS, "output"),
+    ] {
+        let ext = filepath
+            .extension()
+            .and_then(|s| s.to_str())
+            .map(|s| s.to_lowercase());
+        if !ext.as_ref().is_some_and(|e| allowed.contains(&e.as_str())) {
+            return CliAction::Error(format!(
+                "Unsupported {kind} format: {filepath:?}. Supported extensions: {}",
+                allowed.join(", ")
+            ));
+        }
+    }
+    if output.is_dir() {
+        return CliAction::Error(format!("Output path is a directory: {output:?}"));
+    }
+    let segy_options = match segy_options(args) {
+        Ok(o) => o,
+        Err(e) => return CliAction::Error(e),
+    };
+    let velocity_model = match args.velocity_model.as_deref().map(VelocityModel::from_csv) {
+        Some(Ok(m)) => Some(m),
+        Some(Err(e)) => return CliAction::Error(e),
+        None => None,
     };
 
-    CliAction::Run(params)
+    CliAction::Run(Box::new(gpr::RunParams {
+        filepaths: vec![input.to_path_buf()],
+        output_path: Some(output.to_path_buf()),
+        only_info: false,
+        dem_path: args.dem.clone(),
+        cor_path: args.cor.clone(),
+        medium_velocity: args.velocity.unwrap_or(DEFAULT_MEDIUM_VELOCITY),
+        velocity_model,
+        crs: args.crs.clone(),
+        quiet: args.quiet,
+        track_path: None,
+        spectrum_path: None,
+        picks_path: None,
+        velocity_table_path: None,
+        steps: Vec::new(),
+        no_export: false,
+        render_path: None,
+        merge: None,
+        override_antenna_mhz: args.override_antenna_mhz,
+        segy_options,
+    }))
 }
 
 #[cfg(feature = "cli")]
 #[allow(dead_code)] // For maturin
 pub fn main(arguments: Args) -> i32 {
     match args_to_action(&arguments) {
-        CliAction::Run(params) => match gpr::run(params) {
+        CliAction::Run(params) => match gpr::run(*params) {
             Ok(_) => 0,
             Err(e) => error(&format!("{e:?}"), 1),
         },
+        CliAction::Cmp(params) => match cmp::run(*params) {
+            Ok(_) => 0,
+            Err(e) => error(&format!("{e}"), 1),
+        },
+        CliAction::Replay {
+            filepath,
+            raw,
+            quiet,
+        } => match gpr::replay(&filepath, raw.as_deref(), quiet) {
+            Ok(_) => 0,
+            Err(e) => error(&format!("{e}"), 1),
+        },
         CliAction::Error(message) => error(&message, 1),
         CliAction::Done => 0,
     }
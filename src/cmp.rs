@@ -0,0 +1,468 @@
+/// Velocity analysis of common-midpoint (CMP) and wide-angle (WARR) gathers
+///
+/// In a CMP sounding, the transmitter and receiver are moved apart symmetrically around a fixed
+/// midpoint, and in a WARR sounding, one antenna stays fixed while the other is moved away. The
+/// return time of a flat reflector then increases with the antenna separation along a normal
+/// moveout (NMO) hyperbola, t² = t0² + (x / v)², whose shape gives the root-mean-square (RMS)
+/// velocity above the reflector. Both gathers are analysed the same way, which is valid for flat
+/// layers.
+///
+/// The velocities are found with a semblance spectrum: for every zero-offset return time and trial
+/// velocity, the traces are sampled along the hyperbola, and the semblance is the energy of their
+/// stack relative to the energy of the traces (0-1). Reflections show up as maxima at their RMS
+/// velocity.
+use ndarray::Array2;
+use rayon::prelude::*;
+use std::error::Error;
+use std::path::PathBuf;
+
+use crate::io;
+use crate::velocity::{self, VelocityModel};
+
+/// The slowest velocity of the semblance spectrum if none is given (m/ns)
+pub const DEFAULT_MIN_VELOCITY: f32 = 0.05;
+/// The fastest velocity of the semblance spectrum if none is given (m/ns)
+pub const DEFAULT_MAX_VELOCITY: f32 = 0.3;
+/// The velocity step of the semblance spectrum if none is given (m/ns)
+pub const DEFAULT_VELOCITY_STEP: f32 = 0.001;
+/// The minimum semblance of an automatic pick if none is given
+pub const DEFAULT_MIN_SEMBLANCE: f32 = 0.3;
+/// The energy added to the denominator of the semblance, relative to the mean energy of the data
+const SEMBLANCE_STABILIZATION: f64 = 0.01;
+
+/// A gather of traces with different antenna separations around one location
+#[derive(Debug, Clone)]
+pub struct Gather {
+    /// The data, with one trace per column
+    pub data: Array2<f32>,
+    /// The antenna separation of each trace (m)
+    pub offsets: Vec<f32>,
+    /// The time between samples (ns)
+    pub sample_interval: f32,
+    /// The return time of the first sample (ns), which is negative if time zero is later
+    pub start_time: f32,
+    /// The center frequency of the antenna (MHz)
+    pub antenna_mhz: f32,
+}
+
+/// A semblance spectrum of a gather
+#[derive(Debug, Clone)]
+pub struct Semblance {
+    /// The semblance (0-1) of each zero-offset return time (rows) and velocity (columns)
+    pub values: Array2<f32>,
+    /// The mean squared amplitude of the stack in the window of each row and column
+    pub power: Array2<f32>,
+    /// The velocity of each column (m/ns)
+    pub velocities: Vec<f32>,
+    /// The zero-offset return time of the first row (ns)
+    pub start_time: f32,
+    /// The time between rows (ns)
+    pub sample_interval: f32,
+}
+
+impl Gather {
+    /// The semblance spectrum of the gather
+    ///
+    /// The traces are interpolated linearly along the NMO hyperbola of each zero-offset return
+    /// time (one per sample) and velocity. Rows before time zero have a semblance of 0. A small
+    /// energy (1% of the mean energy of the data) is added to the denominator, so that windows
+    /// with almost no signal do not have a high semblance.
+    ///
+    /// # Arguments
+    /// - `velocities`: The trial velocities (m/ns)
+    /// - `window`: The height of the time window that the semblance is summed over (ns)
+    ///
+    /// # Errors
+    /// The gather has no samples or no traces
+    pub fn semblance(&self, velocities: &[f32], window: f32) -> Result<Semblance, String> {
+        let (n_samples, n_traces) = self.data.dim();
+        if n_samples == 0 || n_traces == 0 {
+            return Err(format!(
+                "The gather needs samples and traces, but has {n_samples} samples and {n_traces} traces"
+            ));
+        }
+        let half_window = (window / 2. / self.sample_interval).round() as usize;
+        let stabilization = SEMBLANCE_STABILIZATION
+            * self.data.iter().map(|v| (*v as f64).powi(2)).sum::<f64>()
+            / self.data.len().max(1) as f64
+            * self.offsets.len().pow(2) as f64;
+
+        let columns = velocities
+            .par_iter()
+            .map(|velocity| {
+                // The stack, energy and number of traces along the hyperbola of each row. They are
+                // summed in double precision, so that weak samples do not lose precision.
+                let mut stack = vec![0_f64; n_samples];
+                let mut energy = vec![0_f64; n_samples];
+                let mut count = vec![0_f64; n_samples];
+                for row in 0..n_samples {
+                    let zero_offset_time = self.start_time + row as f32 * self.sample_interval;
+                    if zero_offset_time < 0. {
+                        continue;
+                    }
+                    for (col, offset) in self.offsets.iter().enumerate() {
+                        let time = (zero_offset_time.powi(2) + (offset / velocity).powi(2)).sqrt();
+                        let sample = (time - self.start_time) / self.sample_interval;
+                        if sample > (n_samples - 1) as f32 {
+                            continue;
+                        }
+                        let lower = sample.floor() as usize;
+                        let weight = sample - lower as f32;
+                        let upper = (lower + 1).min(n_samples - 1);
+                        let value = (self.data[[lower, col]] * (1. - weight)
+                            + self.data[[upper, col]] * weight)
+                            as f64;
+                        stack[row] += value;
+                        energy[row] += value.powi(2);
+                        count[row] += 1.;
+                    }
+                }
+                (0..n_samples)
+                    .map(|row| {
+                        let window = row.saturating_sub(half_window)
+                            ..row.saturating_add(half_window).min(n_samples - 1) + 1;
+                        let window_length = window.len() as f64;
+                        let numerator =
+                            stack[window.clone()].iter().map(|s| s.powi(2)).sum::<f64>();
+                        let denominator = window
+                            .map(|r| count[r] * energy[r] + stabilization)
+                            .sum::<f64>();
+                        let semblance = match denominator > 0. {
+                            true => (numerator / denominator) as f32,
+                            false => 0.,
+                        };
+                        (semblance, (numerator / window_length) as f32)
+                    })
+                    .collect::<Vec<(f32, f32)>>()
+            })
+            .collect::<Vec<Vec<(f32, f32)>>>();
+
+        Ok(Semblance {
+            values: Array2::from_shape_fn((n_samples, velocities.len()), |(row, col)| {
+                columns[col][row].0
+            }),
+            power: Array2::from_shape_fn((n_samples, velocities.len()), |(row, col)| {
+                columns[col][row].1
+            }),
+            velocities: velocities.to_vec(),
+            start_time: self.start_time,
+            sample_interval: self.sample_interval,
+        })
+    }
+}
+
+impl Semblance {
+    /// The zero-offset return time of a row (ns)
+    pub fn return_time(&self, row: usize) -> f32 {
+        self.start_time + row as f32 * self.sample_interval
+    }
+
+    /// Pick RMS velocities automatically at maxima of the semblance
+    ///
+    /// Of the velocities of a row with a semblance of at least `min_semblance`, the one with the
+    /// most stack power is the best. A pick is a best velocity whose stack power is the highest
+    /// within a window in time. The stack power decides instead of the semblance, because an
+    /// oscillating wavelet also stacks coherently along hyperbolae that are one period off, but
+    /// with less power. Rows within one window of time zero are skipped, since the direct air and
+    /// ground waves arrive there.
+    ///
+    /// # Arguments
+    /// - `window`: The height of the time window (ns)
+    /// - `min_semblance`: The minimum semblance of a pick (0-1)
+    ///
+    /// # Returns
+    /// The zero-offset return time (ns) and RMS velocity (m/ns) of each pick, sorted by time
+    pub fn pick(&self, window: f32, min_semblance: f32) -> Vec<(f32, f32)> {
+        let n_rows = self.values.nrows();
+        let window_rows = ((window / self.sample_interval).round() as usize).max(1);
+        // The best column and its stack power of each row, if any column is coherent enough
+        let best = (0..n_rows)
+            .map(|row| {
+                (0..self.velocities.len())
+                    .filter(|col| self.values[[row, *col]] >= min_semblance)
+                    .map(|col| (col, self.power[[row, col]]))
+                    .max_by(|a, b| a.1.total_cmp(&b.1))
+            })
+            .collect::<Vec<Option<(usize, f32)>>>();
+
+        (0..n_rows)
+            .filter(|row| self.return_time(*row) >= window)
+            .filter_map(|row| {
+                let (col, power) = best[row]?;
+                (row.saturating_sub(window_rows)..(row + window_rows + 1).min(n_rows))
+                    .all(|r| r == row || best[r].is_none_or(|(_, p)| p < power))
+                    .then_some((self.return_time(row), self.velocities[col]))
+            })
+            .collect()
+    }
+}
+
+/// Write RMS velocity picks as a velocity model CSV, which can be given to `--velocity-model`
+///
+/// The picks are listed as comments, followed by the interval velocities of
+/// [`velocity::dix_interval_velocities`] by two-way return time.
+///
+/// # Arguments
+/// - `picks`: The zero-offset return time (ns) and RMS velocity (m/ns) of each pick
+///
+/// # Errors
+/// - There are no picks, or a velocity is not positive
+pub fn velocity_table(picks: &[(f32, f32)]) -> Result<String, String> {
+    if picks.is_empty() {
+        return Err("No velocities were picked".to_string());
+    }
+    // Make sure that the layers describe a valid model
+    VelocityModel::from_rms_velocities(picks)?;
+
+    let mut text = "# RMS velocity picks: twt_ns,rms_velocity\n".to_string();
+    for (time, velocity) in picks {
+        text += &format!("# {time},{velocity}\n");
+    }
+    text += "twt,velocity\n";
+    for (time, velocity) in velocity::dix_interval_velocities(picks) {
+        text += &format!("{time},{velocity}\n");
+    }
+    Ok(text)
+}
+
+/// The settings of a CMP/WARR velocity analysis
+#[derive(Debug, Clone)]
+pub struct CmpParams {
+    /// The ".hd" file of a pulseEKKO gather
+    pub filepath: PathBuf,
+    /// The increase in antenna separation between traces (m). If None, the trace positions are
+    /// used as separations.
+    pub spacing: Option<f32>,
+    /// The antenna separation of the first trace with a spacing (m). If None, the antenna
+    /// separation of the header.
+    pub first_offset: Option<f32>,
+    /// The slowest velocity of the semblance spectrum (m/ns)
+    pub min_velocity: f32,
+    /// The fastest velocity of the semblance spectrum (m/ns)
+    pub max_velocity: f32,
+    /// The velocity step of the semblance spectrum (m/ns)
+    pub velocity_step: f32,
+    /// The semblance window (ns). If None, one period of the antenna center frequency.
+    pub window_ns: Option<f32>,
+    /// The minimum semblance of an automatic pick
+    pub min_semblance: f32,
+    /// RMS velocity picks as zero-offset return time (ns) and velocity (m/ns). If None, they are
+    /// picked automatically.
+    pub picks: Option<Vec<(f32, f32)>>,
+    /// The velocity model output path or directory. If None, next to the input with a
+    /// "_velocity.csv" suffix.
+    pub output_path: Option<PathBuf>,
+    /// Render the semblance spectrum to a path or directory, or next to the input if None
+    pub render_path: Option<Option<PathBuf>>,
+    pub override_antenna_mhz: Option<f32>,
+    pub quiet: bool,
+}
+
+/// Run a CMP/WARR velocity analysis
+///
+/// The gather is loaded, its semblance spectrum is computed, and the given or automatic RMS
+/// velocity picks are exported as a velocity model.
+///
+/// # Arguments
+/// - `params`: The analysis settings
+///
+/// # Returns
+/// The zero-offset return time (ns) and RMS velocity (m/ns) of each pick
+///
+/// # Errors
+/// - The velocity range or the window is invalid
+/// - The gather cannot be loaded
+/// - No velocities were picked
+/// - The outputs cannot be written
+pub fn run(params: CmpParams) -> Result<Vec<(f32, f32)>, Box<dyn Error>> {
+    if params.min_velocity <= 0.
+        || params.max_velocity <= params.min_velocity
+        || params.velocity_step <= 0.
+    {
+        return Err(format!(
+            "Invalid velocity range: {} to {} m/ns in steps of {} m/ns",
+            params.min_velocity, params.max_velocity, params.velocity_step
+        )
+        .into());
+    }
+    if let Some(window_ns) = params.window_ns.filter(|w| !w.is_finite() || *w <= 0.) {
+        return Err(format!(
+            "The semblance window needs to be a positive number of ns, not {window_ns}"
+        )
+        .into());
+    }
+    if !params.quiet {
+        println!("Processing CMP gather {:?}", params.filepath);
+    }
+    let gather = io::load_pe_gather(
+        &params.filepath,
+        params.spacing,
+        params.first_offset,
+        params.override_antenna_mhz,
+    )?;
+
+    let n_velocities =
+        ((params.max_velocity - params.min_velocity) / params.velocity_step).round() as usize + 1;
+    let velocities = (0..n_velocities)
+        .map(|i| params.min_velocity + i as f32 * params.velocity_step)
+        .collect::<Vec<f32>>();
+    let window = params.window_ns.unwrap_or(1000. / gather.antenna_mhz);
+    let semblance = gather.semblance(&velocities, window)?;
+
+    let picks = match &params.picks {
+        Some(picks) => picks.clone(),
+        None => semblance.pick(window, params.min_semblance),
+    };
+    if !params.quiet {
+        for (time, velocity) in &picks {
+            println!("Picked an RMS velocity of {velocity} m/ns at {time} ns");
+        }
+    }
+
+    let output_path =
+        io::sidecar_csv_filepath(params.output_path.as_ref(), &params.filepath, "_velocity");
+    if !params.quiet {
+        println!("Exporting the velocity model to {:?}", output_path);
+    }
+    std::fs::write(output_path, velocity_table(&picks)?)?;
+
+    if let Some(potential_fp) = &params.render_path {
+        let filename = params
+            .filepath
+            .file_stem()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string()
+            + "_semblance";
+        let render_filepath = match potential_fp {
+            Some(fp) => match fp.is_dir() {
+                true => fp.join(filename).with_extension("jpg"),
+                false => fp.clone(),
+            },
+            None => params
+                .filepath
+                .with_file_name(filename)
+                .with_extension("jpg"),
+        };
+        if !params.quiet {
+            println!("Rendering the semblance spectrum to {:?}", render_filepath);
+        }
+        io::render_semblance_jpg(&semblance, &picks, &render_filepath)?;
+    }
+
+    Ok(picks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run, velocity_table, CmpParams, Gather};
+    use crate::velocity::VelocityModel;
+
+    /// Reflections at 100 ns (0.2 m/ns) and 250 ns (under a 0.15 m/ns layer), with 0.5 ns
+    /// samples, 0.5 m offset steps and time zero at sample 20
+    fn make_gather() -> (Gather, f32) {
+        let rms = ((0.2_f32.powi(2) * 100. + 0.15_f32.powi(2) * 150.) / 250.).sqrt();
+        let offsets = (0..30).map(|i| 1. + i as f32 * 0.5).collect::<Vec<f32>>();
+        let wavelet =
+            |t: f32| (2. * std::f32::consts::PI * 0.1 * t).cos() * (-(t / 5.).powi(2)).exp();
+        let data = ndarray::Array2::from_shape_fn((800, offsets.len()), |(row, col)| {
+            let time = (row as f32 - 20.) * 0.5;
+            [(100_f32, 0.2_f32), (250., rms)]
+                .iter()
+                .map(|(t0, v)| {
+                    let t = (t0.powi(2) + (offsets[col] / v).powi(2)).sqrt();
+                    wavelet((time - t) / 0.5)
+                })
+                .sum()
+        });
+        (
+            Gather {
+                data,
+                offsets,
+                sample_interval: 0.5,
+                start_time: -10.,
+                antenna_mhz: 200.,
+            },
+            rms,
+        )
+    }
+
+    #[test]
+    fn test_semblance() {
+        let (gather, rms) = make_gather();
+        let velocities = (0..251)
+            .map(|i| 0.05 + i as f32 * 0.001)
+            .collect::<Vec<f32>>();
+        let semblance = gather.semblance(&velocities, 5.).unwrap();
+        assert_eq!(semblance.values.dim(), (800, 251));
+        assert!(semblance.values.iter().all(|v| (0. ..=1.0001).contains(v)));
+        // Before time zero, there is nothing to stack
+        assert_eq!(semblance.values[[10, 100]], 0.);
+        assert_eq!(semblance.return_time(20), 0.);
+
+        let picks = semblance.pick(5., 0.3);
+        assert_eq!(picks.len(), 2, "{picks:?}");
+        assert!((picks[0].0 - 100.).abs() < 1.);
+        assert!((picks[0].1 - 0.2).abs() < 0.003, "{picks:?}");
+        assert!((picks[1].0 - 250.).abs() < 1.);
+        assert!((picks[1].1 - rms).abs() < 0.003, "{picks:?}");
+        assert!(semblance.pick(5., 1.1).is_empty());
+
+        // The table has the picks as comments and the interval velocities as a model
+        let table = velocity_table(&[(100., 0.2), (250., rms)]).unwrap();
+        assert_eq!(table.lines().filter(|l| l.starts_with('#')).count(), 3);
+        let model = VelocityModel::from_csv_str(&table).unwrap();
+        assert!((model.layers()[1].0 - 10.).abs() < 1e-4);
+        assert!((model.layers()[1].1 - 0.15).abs() < 1e-4);
+        assert!(velocity_table(&[]).is_err());
+
+        // A gather without samples or traces has no spectrum
+        for (shape, offsets) in [((0, 3), vec![1., 1.5, 2.]), ((10, 0), vec![])] {
+            let empty = Gather {
+                data: ndarray::Array2::zeros(shape),
+                offsets,
+                sample_interval: 0.5,
+                start_time: 0.,
+                antenna_mhz: 200.,
+            };
+            assert!(empty.semblance(&velocities, 5.).is_err(), "{shape:?}");
+        }
+    }
+
+    #[test]
+    fn test_run_validation() {
+        let params = CmpParams {
+            filepath: "CMP_001.hd".into(),
+            spacing: None,
+            first_offset: None,
+            min_velocity: 0.05,
+            max_velocity: 0.3,
+            velocity_step: 0.001,
+            window_ns: None,
+            min_semblance: 0.3,
+            picks: None,
+            output_path: None,
+            render_path: None,
+            override_antenna_mhz: None,
+            quiet: true,
+        };
+        for window_ns in [0., -5., f32::INFINITY, f32::NAN] {
+            let error = run(CmpParams {
+                window_ns: Some(window_ns),
+                ..params.clone()
+            })
+            .unwrap_err();
+            assert!(error.to_string().contains("semblance window"), "{error}");
+        }
+        let error = run(CmpParams {
+            max_velocity: 0.01,
+            ..params
+        })
+        .unwrap_err();
+        assert!(
+            error.to_string().contains("Invalid velocity range"),
+            "{error}"
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use ndarray::{ArrayBase, DataMut, Ix1};
+use ndarray::{Array1, ArrayBase, Axis, DataMut, Ix1};
 use num::Float;
 use num_complex::Complex;
 
@@ -14,6 +14,7 @@ use num_complex::Complex;
 /// the response is ~0 dB at f0 = sqrt(low*high).
 ///
 /// Coefficients follow RBJ/W3C cookbook HPF/LPF; filter is DF‑II‑Transposed for good numerics.
+/// The filter is causal, so it delays the signal. See [`bandpass_zero_phase`] for no delay.
 pub fn bandpass_hpf_then_lpf<T: Float, S: DataMut<Elem = T>>(
     data: &mut ArrayBase<S, Ix1>,
     low_cutoff: T,
@@ -22,6 +23,93 @@ pub fn bandpass_hpf_then_lpf<T: Float, S: DataMut<Elem = T>>(
     q: Option<T>,
     normalize_at_center: bool,
 ) -> Result<(), &'static str> {
+    let (hpf, lpf, gain) =
+        design_bandpass(low_cutoff, high_cutoff, sample_rate, q, normalize_at_center)?;
+
+    // ----- Run HPF then LPF (DF2‑T) -----
+    apply_biquad_df2t_in_place(data, hpf.0, hpf.1, hpf.2, hpf.3, hpf.4);
+    apply_biquad_df2t_in_place(data, lpf.0, lpf.1, lpf.2, lpf.3, lpf.4);
+    if gain != T::one() {
+        for i in 0..data.len() {
+            data[i] = data[i] * gain;
+        }
+    }
+
+    Ok(())
+}
+
+/// Apply the band‑pass of [`bandpass_hpf_then_lpf`] forward and then backward (zero phase).
+///
+/// The backward pass cancels the phase shift of the forward pass, so reflections are not shifted
+/// in time. The magnitude response is squared, which doubles the attenuation (in dB) outside of
+/// the band. To reduce transients at the start and end, the trace is padded with its odd
+/// reflection about the edge samples before filtering, and the padding is removed afterwards.
+///
+/// The arguments are the same as for [`bandpass_hpf_then_lpf`]. With `normalize_at_center`, the
+/// gain at f0 = sqrt(low*high) of the combined passes is ~0 dB.
+pub fn bandpass_zero_phase<T: Float, S: DataMut<Elem = T>>(
+    data: &mut ArrayBase<S, Ix1>,
+    low_cutoff: T,
+    high_cutoff: T,
+    sample_rate: Option<T>,
+    q: Option<T>,
+    normalize_at_center: bool,
+) -> Result<(), &'static str> {
+    let (hpf, lpf, gain) =
+        design_bandpass(low_cutoff, high_cutoff, sample_rate, q, normalize_at_center)?;
+    let n = data.len();
+    if n == 0 {
+        return Ok(());
+    }
+
+    // ----- Pad with the odd reflection about the edges -----
+    let pad = ZERO_PHASE_PAD.min(n - 1);
+    let two = T::from(2.0).unwrap();
+    let first = data[0];
+    let last = data[n - 1];
+    let mut padded = Array1::<T>::zeros(n + 2 * pad);
+    for i in 0..pad {
+        padded[i] = two * first - data[pad - i];
+        padded[n + pad + i] = two * last - data[n - 2 - i];
+    }
+    for i in 0..n {
+        padded[pad + i] = data[i];
+    }
+
+    // ----- Forward, then backward -----
+    for _ in 0..2 {
+        apply_biquad_df2t_in_place(&mut padded, hpf.0, hpf.1, hpf.2, hpf.3, hpf.4);
+        apply_biquad_df2t_in_place(&mut padded, lpf.0, lpf.1, lpf.2, lpf.3, lpf.4);
+        padded.invert_axis(Axis(0));
+    }
+
+    // The gain is applied once per pass
+    let gain = gain * gain;
+    for i in 0..n {
+        data[i] = padded[pad + i] * gain;
+    }
+
+    Ok(())
+}
+
+/// The number of samples that traces are padded with at each edge for zero-phase filtering.
+/// This follows the convention of three times the number of filter coefficients (scipy's filtfilt).
+const ZERO_PHASE_PAD: usize = 15;
+
+/// Biquad coefficients (b0, b1, b2, a1, a2) with a0 normalized to 1.
+type Biquad<T> = (T, T, T, T, T);
+
+/// Validate the arguments and design the HPF and LPF of the band‑pass.
+///
+/// Returns the HPF, the LPF and the gain that normalizes one pass to ~0 dB at the band center (or
+/// 1 if `normalize_at_center` is false).
+fn design_bandpass<T: Float>(
+    low_cutoff: T,
+    high_cutoff: T,
+    sample_rate: Option<T>,
+    q: Option<T>,
+    normalize_at_center: bool,
+) -> Result<(Biquad<T>, Biquad<T>, T), &'static str> {
     // ----- Validate -----
     if !(low_cutoff.is_finite() && high_cutoff.is_finite()) {
         return Err("cutoffs must be finite");
@@ -60,8 +148,10 @@ pub fn bandpass_hpf_then_lpf<T: Float, S: DataMut<Elem = T>>(
     };
 
     // ----- Design HPF(low) and LPF(high) -----
-    let (b0_h, b1_h, b2_h, a1_h, a2_h) = design_hpf_rbj::<T>(low_cutoff, fs, q)?;
-    let (b0_l, b1_l, b2_l, a1_l, a2_l) = design_lpf_rbj::<T>(high_cutoff, fs, q)?;
+    let hpf = design_hpf_rbj::<T>(low_cutoff, fs, q)?;
+    let lpf = design_lpf_rbj::<T>(high_cutoff, fs, q)?;
+    let (b0_h, b1_h, b2_h, a1_h, a2_h) = hpf;
+    let (b0_l, b1_l, b2_l, a1_l, a2_l) = lpf;
 
     // Optional: normalize ~0 dB at geometric center
     let gain = if normalize_at_center {
@@ -79,21 +169,12 @@ pub fn bandpass_hpf_then_lpf<T: Float, S: DataMut<Elem = T>>(
         one
     };
 
-    // ----- Run HPF then LPF (DF2‑T) -----
-    apply_biquad_df2t_in_place(data, b0_h, b1_h, b2_h, a1_h, a2_h);
-    apply_biquad_df2t_in_place(data, b0_l, b1_l, b2_l, a1_l, a2_l);
-    if gain != one {
-        for i in 0..data.len() {
-            data[i] = data[i] * gain;
-        }
-    }
-
-    Ok(())
+    Ok((hpf, lpf, gain))
 }
 
 /// RBJ/W3C HPF biquad with a0 normalized to 1 (case: Q).
 /// Returns (b0, b1, b2, a1, a2).
-fn design_hpf_rbj<T: Float>(f_c: T, fs: T, q: T) -> Result<(T, T, T, T, T), &'static str> {
+fn design_hpf_rbj<T: Float>(f_c: T, fs: T, q: T) -> Result<Biquad<T>, &'static str> {
     let zero = T::zero();
     if !(f_c > zero && fs > zero && q > zero) {
         return Err("invalid params");
@@ -116,7 +197,7 @@ fn design_hpf_rbj<T: Float>(f_c: T, fs: T, q: T) -> Result<(T, T, T, T, T), &'st
 
 /// RBJ/W3C LPF biquad with a0 normalized to 1 (case: Q).
 /// Returns (b0, b1, b2, a1, a2).
-fn design_lpf_rbj<T: Float>(f_c: T, fs: T, q: T) -> Result<(T, T, T, T, T), &'static str> {
+fn design_lpf_rbj<T: Float>(f_c: T, fs: T, q: T) -> Result<Biquad<T>, &'static str> {
     let zero = T::zero();
     if !(f_c > zero && fs > zero && q > zero) {
         return Err("invalid params");
@@ -531,4 +612,120 @@ mod tests {
             "cascading did not clearly improve attenuation"
         );
     }
+
+    /// The group delay (in samples) at a normalized frequency, from the phase slope of the filter
+    fn group_delay<F: Fn(&mut Array1<f32>)>(filter: F, freq_norm: f32) -> f32 {
+        let n = 8192;
+        let phase = |f: f32| {
+            let mut y = sine_norm(f, n);
+            filter(&mut y);
+            // Project the steady-state output on the input sine and its quadrature
+            let (mut in_phase, mut quadrature) = (0_f32, 0_f32);
+            for i in n / 4..3 * n / 4 {
+                let w = std::f32::consts::PI * f * i as f32;
+                in_phase += y[i] * w.sin();
+                quadrature += y[i] * w.cos();
+            }
+            quadrature.atan2(in_phase)
+        };
+        let df = 0.01 * freq_norm;
+        let dphase = phase(freq_norm + df) - phase(freq_norm - df);
+        -dphase / (std::f32::consts::PI * 2. * df)
+    }
+
+    #[test]
+    fn zero_phase_no_group_delay_at_center() {
+        let low = 0.10_f32;
+        let high = 0.30_f32;
+        let f0 = (low * high).sqrt();
+
+        // The causal filter delays the passband
+        let causal = group_delay(
+            |y| bandpass_hpf_then_lpf(y, low, high, None, None, true).unwrap(),
+            f0,
+        );
+        assert!(causal > 1., "causal group delay {causal}");
+
+        let zero_phase = group_delay(
+            |y| bandpass_zero_phase(y, low, high, None, None, true).unwrap(),
+            f0,
+        );
+        assert!(
+            zero_phase.abs() < 0.05,
+            "zero-phase group delay {zero_phase}"
+        );
+
+        // The tone itself is unchanged, with ~unity gain
+        let n = 4096;
+        let x = sine_norm(f0, n);
+        let mut y = x.clone();
+        bandpass_zero_phase(&mut y, low, high, None, None, true).unwrap();
+        let middle = s![n / 4..3 * n / 4];
+        let max_error = (&y.slice(middle) - &x.slice(middle))
+            .mapv(f32::abs)
+            .fold(0_f32, |a, b| a.max(*b));
+        assert!(max_error < 0.02, "zero-phase output differs by {max_error}");
+    }
+
+    #[test]
+    fn zero_phase_keeps_wavelet_peak() {
+        // A Ricker-like wavelet at the band centre
+        let n = 512;
+        let center = 200;
+        let x = Array1::from_shape_fn(n, |i| {
+            let t = (i as f32 - center as f32) / 4.;
+            (1. - 2. * t.powi(2)) * (-t.powi(2)).exp()
+        });
+        let argmax = |v: &Array1<f32>| {
+            v.iter()
+                .enumerate()
+                .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+                .unwrap()
+                .0
+        };
+
+        let mut y = x.clone();
+        bandpass_zero_phase(&mut y, 0.05, 0.3, None, None, true).unwrap();
+        assert_eq!(argmax(&y), center);
+        // The response is symmetric about the peak
+        for i in 1..20 {
+            assert_relative_eq!(y[center - i], y[center + i], epsilon = 1e-4);
+        }
+
+        let mut y_causal = x.clone();
+        bandpass_hpf_then_lpf(&mut y_causal, 0.05, 0.3, None, None, true).unwrap();
+        assert!(argmax(&y_causal) > center);
+    }
+
+    #[test]
+    fn zero_phase_improves_skirts_and_edges() {
+        let n = 16_384;
+        let low = 0.25_f32;
+        let high = 0.50_f32;
+        let x_center = sine_norm((low * high).sqrt(), n);
+        let x_low = sine_norm(0.10, n);
+
+        let mut y1_l = x_low.clone();
+        bandpass_hpf_then_lpf(&mut y1_l, low, high, None, None, true).unwrap();
+        let mut y2_c = x_center.clone();
+        bandpass_zero_phase(&mut y2_c, low, high, None, None, true).unwrap();
+        let mut y2_l = x_low.clone();
+        bandpass_zero_phase(&mut y2_l, low, high, None, None, true).unwrap();
+
+        // Unity at the centre, and the attenuation (in dB) is about doubled
+        assert_relative_eq!(rms(&y2_c) / rms(&x_center), 1.0, max_relative = 0.05);
+        let att1 = 20.0 * (rms(&y1_l) / rms(&x_center)).log10();
+        let att2 = 20.0 * (rms(&y2_l) / rms(&y2_c)).log10();
+        assert!(att2 < att1 - 5.0, "{att2} dB vs {att1} dB");
+
+        // The padding keeps the edges free of large transients
+        let peak = y2_c.iter().fold(0_f32, |m, v| m.max(v.abs()));
+        assert!(peak < 1.5, "unexpectedly large peak {peak}");
+
+        // Short and empty traces are fine
+        let mut short = Array1::<f32>::from_vec(vec![1., -1.]);
+        bandpass_zero_phase(&mut short, low, high, None, None, true).unwrap();
+        assert!(short.iter().all(|v| v.is_finite()));
+        bandpass_zero_phase(&mut Array1::<f32>::zeros(0), low, high, None, None, true).unwrap();
+    }
 }
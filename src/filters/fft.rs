@@ -0,0 +1,522 @@
+/// Frequency-domain filtering of traces
+///
+/// Each trace is transformed with an FFT, multiplied by a real (zero-phase) frequency response,
+/// and transformed back. All frequencies are in MHz, with the sample rate of the data (usually
+/// `GPRMeta::frequency`).
+use ndarray::{Array1, Array2};
+use rayon::prelude::*;
+use rustfft::{num_complex::Complex, FftPlanner};
+
+/// The frequencies (MHz) of the non-negative FFT bins of a trace
+///
+/// # Arguments
+/// - `n`: The number of samples of the transformed trace
+/// - `sample_rate_mhz`: The sample rate (MHz)
+pub fn frequencies_mhz(n: usize, sample_rate_mhz: f32) -> Array1<f32> {
+    Array1::from_shape_fn(n / 2 + 1, |i| i as f32 * sample_rate_mhz / n as f32)
+}
+
+/// Multiply the spectrum of every trace by a frequency response
+///
+/// The traces are zero-padded to at least twice their length before the transform, so that the
+/// filtered end of a trace does not wrap around to its start.
+///
+/// # Arguments
+/// - `data`: The data, with one trace per column
+/// - `sample_rate_mhz`: The sample rate (MHz)
+/// - `response`: The gain at a (non-negative) frequency in MHz. It is applied to both the positive
+///   and negative frequencies, so the filter has zero phase.
+pub fn apply_frequency_response<F: Fn(f32) -> f32 + Sync>(
+    data: &mut Array2<f32>,
+    sample_rate_mhz: f32,
+    response: F,
+) {
+    let (n_samples, n_traces) = data.dim();
+    if n_samples == 0 {
+        return;
+    }
+    let n_fft = (2 * n_samples).next_power_of_two();
+    let gains = Array1::from_shape_fn(n_fft, |i| {
+        // Bins above the Nyquist frequency are the negative frequencies
+        let bin = i.min(n_fft - i);
+        response(bin as f32 * sample_rate_mhz / n_fft as f32)
+    });
+
+    let mut planner = FftPlanner::<f32>::new();
+    let forward = planner.plan_fft_forward(n_fft);
+    let inverse = planner.plan_fft_inverse(n_fft);
+
+    let filtered = (0..n_traces)
+        .into_par_iter()
+        .map(|col| {
+            let mut buffer = vec![Complex::new(0_f32, 0_f32); n_fft];
+            for (value, sample) in buffer.iter_mut().zip(data.column(col)) {
+                value.re = *sample;
+            }
+            forward.process(&mut buffer);
+            for (value, gain) in buffer.iter_mut().zip(gains.iter()) {
+                *value *= *gain;
+            }
+            inverse.process(&mut buffer);
+            buffer
+                .iter()
+                .take(n_samples)
+                .map(|v| v.re / n_fft as f32)
+                .collect::<Vec<f32>>()
+        })
+        .collect::<Vec<Vec<f32>>>();
+
+    for (mut column, values) in data.columns_mut().into_iter().zip(filtered) {
+        column.assign(&Array1::from_vec(values));
+    }
+}
+
+/// The gain of a bandpass with cosine-tapered (Tukey) corners
+///
+/// The gain is 0 below `corners[0]`, rises along a half cosine to 1 at `corners[1]`, is 1 until
+/// `corners[2]`, and falls along a half cosine to 0 at `corners[3]`.
+///
+/// # Arguments
+/// - `frequency`: The frequency to evaluate (MHz)
+/// - `corners`: The four corner frequencies in ascending order (MHz)
+pub fn tukey_bandpass_response(frequency: f32, corners: [f32; 4]) -> f32 {
+    let [low_cut, low_pass, high_pass, high_cut] = corners;
+    let taper =
+        |distance: f32, width: f32| 0.5 * (1. - (std::f32::consts::PI * distance / width).cos());
+
+    if (low_pass..=high_pass).contains(&frequency) {
+        1.
+    } else if frequency <= low_cut || frequency >= high_cut {
+        0.
+    } else if frequency < low_pass {
+        taper(frequency - low_cut, low_pass - low_cut)
+    } else {
+        taper(high_cut - frequency, high_cut - high_pass)
+    }
+}
+
+/// The gain of a notch filter with a cosine shape at a frequency and its harmonics
+///
+/// The gain is 0 at the notch frequencies, and rises along a half cosine to 1 at half the width
+/// away from them.
+///
+/// # Arguments
+/// - `frequency`: The frequency to evaluate (MHz)
+/// - `notch_frequency`: The frequency to remove (MHz)
+/// - `width`: The full width of each notch (MHz)
+/// - `harmonics`: The number of harmonics to remove, including the notch frequency itself. For
+///   example, 3 removes 1x, 2x and 3x the notch frequency.
+pub fn notch_response(frequency: f32, notch_frequency: f32, width: f32, harmonics: usize) -> f32 {
+    (1..=harmonics)
+        .map(|harmonic| {
+            let distance = (frequency - notch_frequency * harmonic as f32).abs();
+            match distance < width / 2. {
+                true => 0.5 * (1. - (2. * std::f32::consts::PI * distance / width).cos()),
+                false => 1.,
+            }
+        })
+        .fold(1., f32::min)
+}
+
+/// The mean amplitude spectrum of all traces
+///
+/// # Arguments
+/// - `data`: The data, with one trace per column
+/// - `sample_rate_mhz`: The sample rate (MHz)
+///
+/// # Returns
+/// The frequencies (MHz) of the non-negative FFT bins, and the mean amplitude of the traces at
+/// each frequency.
+pub fn mean_amplitude_spectrum(
+    data: &Array2<f32>,
+    sample_rate_mhz: f32,
+) -> (Array1<f32>, Array1<f32>) {
+    let (n_samples, n_traces) = data.dim();
+    let frequencies = frequencies_mhz(n_samples, sample_rate_mhz);
+    if n_samples == 0 || n_traces == 0 {
+        return (frequencies.clone(), Array1::zeros(frequencies.len()));
+    }
+
+    let fft = FftPlanner::<f32>::new().plan_fft_forward(n_samples);
+    let n_bins = frequencies.len();
+    let amplitude_sum = (0..n_traces)
+        .into_par_iter()
+        .map(|col| {
+            let mut buffer = data
+                .column(col)
+                .iter()
+                .map(|v| Complex::new(*v, 0.))
+                .collect::<Vec<Complex<f32>>>();
+            fft.process(&mut buffer);
+            buffer
+                .iter()
+                .take(n_bins)
+                .map(|v| v.norm() / n_samples as f32)
+                .collect::<Array1<f32>>()
+        })
+        .reduce(|| Array1::zeros(n_bins), |a, b| a + b);
+
+    (frequencies, amplitude_sum / n_traces as f32)
+}
+
+/// Whether the events in an f-k zone are kept or removed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FkMode {
+    /// Keep only the events in the zone
+    Pass,
+    /// Remove the events in the zone
+    Reject,
+}
+
+impl std::str::FromStr for FkMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pass" => Ok(Self::Pass),
+            "reject" => Ok(Self::Reject),
+            other => Err(format!("Unknown f-k filter mode: {other}")),
+        }
+    }
+}
+
+impl std::fmt::Display for FkMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pass => write!(f, "pass"),
+            Self::Reject => write!(f, "reject"),
+        }
+    }
+}
+
+/// The signed index of an FFT bin, where bins above the Nyquist frequency are negative
+///
+/// # Arguments
+/// - `bin`: The index of the bin
+/// - `n`: The length of the transform
+pub fn signed_bin(bin: usize, n: usize) -> f32 {
+    match bin <= n / 2 {
+        true => bin as f32,
+        false => bin as f32 - n as f32,
+    }
+}
+
+/// The 2D (f-k) transform of the data, zero-padded to the given size
+///
+/// # Arguments
+/// - `data`: The data, with one trace per column
+/// - `n_time`: The length of the transforms in time (at least the number of samples)
+/// - `n_space`: The length of the transforms in space (at least the number of traces)
+///
+/// # Returns
+/// One spectrum per wavenumber bin, with `n_time` frequency bins each.
+pub fn forward_fk(data: &Array2<f32>, n_time: usize, n_space: usize) -> Vec<Vec<Complex<f32>>> {
+    let mut planner = FftPlanner::<f32>::new();
+    let forward_time = planner.plan_fft_forward(n_time);
+    let forward_space = planner.plan_fft_forward(n_space);
+
+    // Transform each trace in time
+    let columns = (0..data.ncols())
+        .into_par_iter()
+        .map(|col| {
+            let mut buffer = vec![Complex::new(0_f32, 0_f32); n_time];
+            for (value, sample) in buffer.iter_mut().zip(data.column(col)) {
+                value.re = *sample;
+            }
+            forward_time.process(&mut buffer);
+            buffer
+        })
+        .collect::<Vec<Vec<Complex<f32>>>>();
+
+    // Transform each frequency in space
+    let rows = (0..n_time)
+        .into_par_iter()
+        .map(|row| {
+            let mut buffer = vec![Complex::new(0_f32, 0_f32); n_space];
+            for (value, column) in buffer.iter_mut().zip(columns.iter()) {
+                *value = column[row];
+            }
+            forward_space.process(&mut buffer);
+            buffer
+        })
+        .collect::<Vec<Vec<Complex<f32>>>>();
+
+    (0..n_space)
+        .into_par_iter()
+        .map(|col| rows.iter().map(|row| row[col]).collect())
+        .collect()
+}
+
+/// Transform rows of wavenumber spectra back to space, without scaling
+///
+/// # Arguments
+/// - `spectra`: One row (e.g. of frequencies or times) per wavenumber bin
+/// - `n_traces`: The number of traces to keep of the transformed rows
+///
+/// # Returns
+/// The rows, with `n_traces` values each.
+pub fn inverse_space(spectra: &[Vec<Complex<f32>>], n_traces: usize) -> Vec<Vec<Complex<f32>>> {
+    let n_space = spectra.len();
+    let n_rows = spectra.first().map(|s| s.len()).unwrap_or(0);
+    let inverse_space = FftPlanner::<f32>::new().plan_fft_inverse(n_space);
+
+    (0..n_rows)
+        .into_par_iter()
+        .map(|row| {
+            let mut buffer = spectra
+                .iter()
+                .map(|s| s[row])
+                .collect::<Vec<Complex<f32>>>();
+            inverse_space.process(&mut buffer);
+            buffer.truncate(n_traces);
+            buffer
+        })
+        .collect()
+}
+
+/// Transform an f-k spectrum (from [`forward_fk`]) back to data
+///
+/// # Arguments
+/// - `spectra`: One spectrum per wavenumber bin
+/// - `n_samples`: The number of samples of the data
+/// - `n_traces`: The number of traces of the data
+pub fn inverse_fk(spectra: &[Vec<Complex<f32>>], n_samples: usize, n_traces: usize) -> Array2<f32> {
+    let n_space = spectra.len();
+    let rows = inverse_space(spectra, n_traces);
+    let n_time = rows.len();
+    let inverse_time = FftPlanner::<f32>::new().plan_fft_inverse(n_time);
+
+    // Transform each trace back to time
+    let scale = (n_time * n_space) as f32;
+    let traces = (0..n_traces)
+        .into_par_iter()
+        .map(|col| {
+            let mut buffer = rows
+                .iter()
+                .map(|row| row[col])
+                .collect::<Vec<Complex<f32>>>();
+            inverse_time.process(&mut buffer);
+            buffer
+                .iter()
+                .take(n_samples)
+                .map(|v| v.re / scale)
+                .collect::<Vec<f32>>()
+        })
+        .collect::<Vec<Vec<f32>>>();
+
+    let mut data = Array2::zeros((n_samples, n_traces));
+    for (mut column, values) in data.columns_mut().into_iter().zip(traces) {
+        column.assign(&Array1::from_vec(values));
+    }
+    data
+}
+
+/// Filter events by their slope in a frequency-wavenumber (f-k) transform of the data
+///
+/// An event with the slope `dt/dx` (ns/m) in the data lies on a line through the origin of the
+/// f-k plane with `|k| / |f| = dt/dx`. Events in both dip directions are treated the same. The
+/// gain of the zone rises along a cosine taper from `slopes[0] * (1 - taper)` to `slopes[0]`, and
+/// falls from `slopes[1]` to `slopes[1] * (1 + taper)`.
+///
+/// The data are zero-padded to at least twice their size in both dimensions, so that the
+/// filtered events do not wrap around the edges.
+///
+/// # Arguments
+/// - `data`: The data, with one trace per column. The traces need to be equidistant.
+/// - `sample_rate_mhz`: The sample rate (MHz)
+/// - `trace_spacing`: The distance between traces (m)
+/// - `slopes`: The minimum and maximum absolute slope of the zone (ns/m)
+/// - `mode`: Whether to keep or remove the events in the zone
+/// - `taper`: The width of the tapers as a fraction of the slopes
+pub fn fk_filter(
+    data: &mut Array2<f32>,
+    sample_rate_mhz: f32,
+    trace_spacing: f32,
+    slopes: [f32; 2],
+    mode: FkMode,
+    taper: f32,
+) {
+    let (n_samples, n_traces) = data.dim();
+    if n_samples == 0 || n_traces == 0 {
+        return;
+    }
+    let n_time = (2 * n_samples).next_power_of_two();
+    let n_space = (2 * n_traces).next_power_of_two();
+    let corners = [
+        slopes[0] * (1. - taper).max(0.),
+        slopes[0],
+        slopes[1],
+        slopes[1] * (1. + taper),
+    ];
+
+    let mut spectra = forward_fk(data, n_time, n_space);
+    spectra
+        .par_iter_mut()
+        .enumerate()
+        .for_each(|(col, spectrum)| {
+            let wavenumber = signed_bin(col, n_space).abs() / (n_space as f32 * trace_spacing);
+            for (row, value) in spectrum.iter_mut().enumerate() {
+                // The frequency in GHz, so that k / f is in ns/m
+                let frequency =
+                    signed_bin(row, n_time).abs() * sample_rate_mhz / n_time as f32 * 1e-3;
+                let slope = if wavenumber == 0. {
+                    0.
+                } else if frequency == 0. {
+                    f32::INFINITY
+                } else {
+                    wavenumber / frequency
+                };
+                let in_zone = tukey_bandpass_response(slope, corners);
+                *value *= match mode {
+                    FkMode::Pass => in_zone,
+                    FkMode::Reject => 1. - in_zone,
+                };
+            }
+        });
+    *data = inverse_fk(&spectra, n_samples, n_traces);
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array2;
+
+    /// Traces with the sum of sines of the given frequencies (MHz), sampled at 1000 MHz
+    fn sines(frequencies: &[f32], n_samples: usize, n_traces: usize) -> Array2<f32> {
+        Array2::from_shape_fn((n_samples, n_traces), |(i, _)| {
+            frequencies
+                .iter()
+                .map(|f| (2. * std::f32::consts::PI * f * i as f32 / 1000.).sin())
+                .sum()
+        })
+    }
+
+    /// The amplitude at the bin closest to a frequency
+    fn amplitude_at(data: &Array2<f32>, frequency: f32) -> f32 {
+        let (frequencies, amplitudes) = super::mean_amplitude_spectrum(data, 1000.);
+        let bin = frequencies
+            .iter()
+            .enumerate()
+            .min_by(|a, b| (a.1 - frequency).abs().total_cmp(&(b.1 - frequency).abs()))
+            .unwrap()
+            .0;
+        amplitudes[bin]
+    }
+
+    #[test]
+    fn test_responses() {
+        let corners = [50., 100., 200., 300.];
+        assert_eq!(super::tukey_bandpass_response(20., corners), 0.);
+        assert!((super::tukey_bandpass_response(75., corners) - 0.5).abs() < 1e-6);
+        assert_eq!(super::tukey_bandpass_response(150., corners), 1.);
+        assert!((super::tukey_bandpass_response(250., corners) - 0.5).abs() < 1e-6);
+        assert_eq!(super::tukey_bandpass_response(400., corners), 0.);
+        // Without a taper, the corners are sharp
+        assert_eq!(
+            super::tukey_bandpass_response(100., [100., 100., 200., 200.]),
+            1.
+        );
+
+        assert_eq!(super::notch_response(60., 60., 10., 3), 0.);
+        assert_eq!(super::notch_response(180., 60., 10., 3), 0.);
+        assert_eq!(super::notch_response(240., 60., 10., 3), 1.);
+        assert!((super::notch_response(62.5, 60., 10., 1) - 0.5).abs() < 1e-6);
+        assert_eq!(super::notch_response(90., 60., 10., 1), 1.);
+    }
+
+    #[test]
+    fn test_mean_amplitude_spectrum() {
+        let data = sines(&[125.], 256, 4);
+        let (frequencies, amplitudes) = super::mean_amplitude_spectrum(&data, 1000.);
+        assert_eq!(frequencies.len(), 129);
+        assert_eq!(frequencies[32], 125.);
+        // A unit sine has half its amplitude in the positive frequency bin
+        assert!((amplitudes[32] - 0.5).abs() < 1e-4);
+        assert!(amplitudes[10] < 1e-4);
+    }
+
+    #[test]
+    fn test_apply_frequency_response() {
+        let n = 1024;
+        let data = sines(&[50., 125., 300.], n, 3);
+
+        let mut bandpassed = data.clone();
+        super::apply_frequency_response(&mut bandpassed, 1000., |f| {
+            super::tukey_bandpass_response(f, [80., 100., 150., 200.])
+        });
+        assert!(amplitude_at(&bandpassed, 125.) > 0.45);
+        assert!(amplitude_at(&bandpassed, 50.) < 0.02);
+        assert!(amplitude_at(&bandpassed, 300.) < 0.02);
+
+        // The filter has zero phase, so the passed sine is not shifted
+        let passed = sines(&[125.], n, 1);
+        let middle = n / 4..3 * n / 4;
+        let max_error = middle
+            .map(|i| (bandpassed[[i, 0]] - passed[[i, 0]]).abs())
+            .fold(0_f32, f32::max);
+        assert!(max_error < 0.05, "{max_error}");
+
+        let mut notched = data.clone();
+        super::apply_frequency_response(&mut notched, 1000., |f| {
+            super::notch_response(f, 50., 20., 1)
+        });
+        assert!(amplitude_at(&notched, 50.) < 0.02);
+        assert!(amplitude_at(&notched, 300.) > 0.45);
+
+        // A flat response does not change the data
+        let mut unchanged = data.clone();
+        super::apply_frequency_response(&mut unchanged, 1000., |_| 1.);
+        assert!(unchanged
+            .iter()
+            .zip(data.iter())
+            .all(|(a, b)| (a - b).abs() < 1e-4));
+    }
+
+    #[test]
+    fn test_fk_filter() {
+        use super::FkMode;
+        // Two plane waves of 100 MHz sampled at 1000 MHz with traces every 0.1 m: a flat one and
+        // one dipping by 5 ns/m, in a Gaussian window to avoid sharp edges
+        let (n_samples, n_traces) = (256, 128);
+        let wave = |slope: f32| {
+            Array2::from_shape_fn((n_samples, n_traces), |(row, col)| {
+                // One sample per ns
+                let t = row as f32 - 128. - slope * col as f32 * 0.1;
+                let x = col as f32 - 64.;
+                (2. * std::f32::consts::PI * 0.1 * t).sin()
+                    * (-(t / 30.).powi(2)).exp()
+                    * (-(x / 30.).powi(2)).exp()
+            })
+        };
+        let flat = wave(0.);
+        let dipping = wave(5.);
+        let data = &flat + &dipping;
+        let rms_difference = |a: &Array2<f32>, b: &Array2<f32>| {
+            ((a - b).mapv(|v| v.powi(2)).sum() / a.len() as f32).sqrt()
+        };
+        let rms = |a: &Array2<f32>| rms_difference(a, &Array2::zeros(a.dim()));
+
+        let mut rejected = data.clone();
+        super::fk_filter(&mut rejected, 1000., 0.1, [3., 8.], FkMode::Reject, 0.2);
+        assert!(rms_difference(&rejected, &flat) < 0.1 * rms(&dipping));
+
+        let mut passed = data.clone();
+        super::fk_filter(&mut passed, 1000., 0.1, [3., 8.], FkMode::Pass, 0.2);
+        assert!(rms_difference(&passed, &dipping) < 0.1 * rms(&dipping));
+
+        // Passing all slopes does not change the data
+        let mut unchanged = data.clone();
+        super::fk_filter(
+            &mut unchanged,
+            1000.,
+            0.1,
+            [0., f32::INFINITY],
+            FkMode::Pass,
+            0.,
+        );
+        assert!(rms_difference(&unchanged, &data) < 1e-4);
+
+        assert_eq!("reject".parse::<FkMode>(), Ok(FkMode::Reject));
+        assert_eq!(FkMode::Pass.to_string(), "pass");
+        assert!("stop".parse::<FkMode>().is_err());
+    }
+}
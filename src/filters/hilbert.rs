@@ -0,0 +1,154 @@
+/// Attributes of the analytic signal of traces
+///
+/// The analytic signal is the trace plus i times its Hilbert transform. Its magnitude is the
+/// envelope (instantaneous amplitude) of the trace, which is independent of the phase of the
+/// wavelet, and its angle is the instantaneous phase. The rate of change of the phase is the
+/// instantaneous frequency.
+use ndarray::Array2;
+use rayon::prelude::*;
+use rustfft::{num_complex::Complex, FftPlanner};
+
+/// The analytic signal of every trace, from a Hilbert transform
+///
+/// The negative frequencies of each trace are removed and the positive frequencies are doubled,
+/// so that the real part is the trace and the imaginary part is its Hilbert transform. The traces
+/// are zero-padded to at least twice their length, so that the end of a trace does not wrap
+/// around to its start.
+///
+/// # Arguments
+/// - `data`: The data, with one trace per column
+///
+/// # Returns
+/// The complex analytic signal, with the same shape as the data
+pub fn analytic_signal(data: &Array2<f32>) -> Array2<Complex<f32>> {
+    let (n_samples, n_traces) = data.dim();
+    if n_samples == 0 || n_traces == 0 {
+        return Array2::zeros((n_samples, n_traces));
+    }
+    let n_fft = (2 * n_samples).next_power_of_two();
+    let mut planner = FftPlanner::<f32>::new();
+    let forward = planner.plan_fft_forward(n_fft);
+    let inverse = planner.plan_fft_inverse(n_fft);
+
+    let signals = (0..n_traces)
+        .into_par_iter()
+        .map(|col| {
+            let mut buffer = vec![Complex::new(0_f32, 0_f32); n_fft];
+            for (value, sample) in buffer.iter_mut().zip(data.column(col)) {
+                value.re = *sample;
+            }
+            forward.process(&mut buffer);
+            // The zero and Nyquist frequencies are kept as they are
+            for (i, value) in buffer.iter_mut().enumerate().skip(1) {
+                match i.cmp(&(n_fft / 2)) {
+                    std::cmp::Ordering::Less => *value *= 2.,
+                    std::cmp::Ordering::Equal => (),
+                    std::cmp::Ordering::Greater => *value = Complex::new(0., 0.),
+                }
+            }
+            inverse.process(&mut buffer);
+            buffer.truncate(n_samples);
+            buffer
+        })
+        .collect::<Vec<Vec<Complex<f32>>>>();
+
+    Array2::from_shape_fn((n_samples, n_traces), |(row, col)| {
+        signals[col][row] / n_fft as f32
+    })
+}
+
+/// The envelope (instantaneous amplitude) of every trace
+///
+/// # Arguments
+/// - `data`: The data, with one trace per column
+pub fn envelope(data: &Array2<f32>) -> Array2<f32> {
+    analytic_signal(data).mapv(|value| value.norm())
+}
+
+/// The instantaneous phase of every trace
+///
+/// # Arguments
+/// - `data`: The data, with one trace per column
+///
+/// # Returns
+/// The phase in radians (-π to π), where a positive peak has a phase of 0
+pub fn instantaneous_phase(data: &Array2<f32>) -> Array2<f32> {
+    analytic_signal(data).mapv(|value| value.arg())
+}
+
+/// The instantaneous frequency of every trace
+///
+/// The frequency is the change of the phase between the samples before and after each sample
+/// (or between the sample and its only neighbour at the ends of a trace). It is noisy where the
+/// envelope is close to zero.
+///
+/// # Arguments
+/// - `data`: The data, with one trace per column
+/// - `sample_rate_mhz`: The sample rate (MHz)
+///
+/// # Returns
+/// The frequency (MHz), which is negative where the phase goes backward
+pub fn instantaneous_frequency(data: &Array2<f32>, sample_rate_mhz: f32) -> Array2<f32> {
+    let signal = analytic_signal(data);
+    let (n_samples, n_traces) = signal.dim();
+    Array2::from_shape_fn((n_samples, n_traces), |(row, col)| {
+        let before = row.saturating_sub(1);
+        let after = (row + 1).min(n_samples - 1);
+        if after == before {
+            return 0.;
+        }
+        // The angle of the product is the phase change, without having to unwrap the phase
+        let change = (signal[[after, col]] * signal[[before, col]].conj()).arg();
+        change / (2. * std::f32::consts::PI) * sample_rate_mhz / (after - before) as f32
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array2;
+
+    /// A 100 MHz wavelet in a Gaussian window, sampled at 1000 MHz
+    fn make_wavelet() -> Array2<f32> {
+        Array2::from_shape_fn((256, 2), |(i, _)| {
+            let t = i as f32 - 128.;
+            (2. * std::f32::consts::PI * 0.1 * t).cos() * (-(t / 20.).powi(2)).exp()
+        })
+    }
+
+    #[test]
+    fn test_attributes() {
+        let data = make_wavelet();
+
+        // The real part of the analytic signal is the data
+        let signal = super::analytic_signal(&data);
+        assert!(signal
+            .iter()
+            .zip(data.iter())
+            .all(|(a, b)| (a.re - b).abs() < 1e-4));
+
+        let envelope = super::envelope(&data);
+        let phase = super::instantaneous_phase(&data);
+        let frequency = super::instantaneous_frequency(&data, 1000.);
+        for i in 64..192 {
+            let t = i as f32 - 128.;
+            assert!(
+                (envelope[[i, 1]] - (-(t / 20.).powi(2)).exp()).abs() < 0.01,
+                "{i}"
+            );
+            assert!(
+                (frequency[[i, 1]] - 100.).abs() < 1.,
+                "{i}: {}",
+                frequency[[i, 1]]
+            );
+        }
+        // The peak of a cosine has zero phase, which then increases by 0.2π per sample
+        assert!(phase[[128, 0]].abs() < 0.01);
+        assert!((phase[[130, 0]] - 0.4 * std::f32::consts::PI).abs() < 0.01);
+
+        assert_eq!(super::envelope(&Array2::zeros((0, 3))).dim(), (0, 3));
+        assert_eq!(
+            super::instantaneous_frequency(&Array2::ones((1, 2)), 1000.),
+            Array2::<f32>::zeros((1, 2))
+        );
+    }
+}
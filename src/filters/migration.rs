@@ -0,0 +1,172 @@
+/// Frequency-wavenumber (f-k) migration of zero-offset profiles
+///
+/// Both methods use the exploding reflector model, where the profile is treated as recorded
+/// with half the velocity of the medium. The migrated data stay in two-way time, so that they can
+/// be processed further like the unmigrated data. The traces need to be equidistant, and the
+/// surface is assumed to be flat.
+use super::fft::{forward_fk, inverse_fk, inverse_space, signed_bin};
+use ndarray::Array2;
+use rayon::prelude::*;
+use rustfft::num_complex::Complex;
+
+/// Migrate the data with Stolt (f-k) migration, assuming a constant velocity
+///
+/// Each frequency of the migrated data is interpolated from the frequency of the recorded data
+/// that has the same vertical wavenumber, and scaled by the Jacobian of the mapping.
+///
+/// # Arguments
+/// - `data`: The data, with one equidistant trace per column
+/// - `sample_rate_mhz`: The sample rate (MHz)
+/// - `trace_spacing`: The distance between traces (m)
+/// - `velocity`: The velocity of the medium (m/ns)
+pub fn stolt(data: &mut Array2<f32>, sample_rate_mhz: f32, trace_spacing: f32, velocity: f32) {
+    let (n_samples, n_traces) = data.dim();
+    if n_samples == 0 || n_traces == 0 {
+        return;
+    }
+    let n_time = (2 * n_samples).next_power_of_two();
+    let n_space = (2 * n_traces).next_power_of_two();
+    // The frequency resolution (GHz) and the exploding reflector velocity (m/ns)
+    let frequency_step = sample_rate_mhz * 1e-3 / n_time as f32;
+    let exploding_velocity = velocity / 2.;
+    let max_bin = (n_time / 2 - 1) as f32;
+
+    let spectra = forward_fk(data, n_time, n_space);
+    let migrated = spectra
+        .par_iter()
+        .enumerate()
+        .map(|(col, spectrum)| {
+            let wavenumber = signed_bin(col, n_space) / (n_space as f32 * trace_spacing);
+            (0..n_time)
+                .map(|row| {
+                    let migrated_frequency = signed_bin(row, n_time) * frequency_step;
+                    if migrated_frequency == 0. {
+                        return Complex::new(0., 0.);
+                    }
+                    let frequency = migrated_frequency.signum()
+                        * (migrated_frequency.powi(2) + (exploding_velocity * wavenumber).powi(2))
+                            .sqrt();
+                    let position = frequency / frequency_step;
+                    if position.abs() > max_bin {
+                        return Complex::new(0., 0.);
+                    }
+                    let lower = position.floor();
+                    let weight = position - lower;
+                    let bin = |b: f32| (b as isize).rem_euclid(n_time as isize) as usize;
+                    let value =
+                        spectrum[bin(lower)] * (1. - weight) + spectrum[bin(lower + 1.)] * weight;
+
+                    value * (migrated_frequency / frequency)
+                })
+                .collect::<Vec<Complex<f32>>>()
+        })
+        .collect::<Vec<Vec<Complex<f32>>>>();
+
+    *data = inverse_fk(&migrated, n_samples, n_traces);
+}
+
+/// Migrate the data with Gazdag phase-shift migration, with a velocity that varies with depth
+///
+/// The recorded wavefield is continued downward one sample at a time, with the velocity of that
+/// sample, and the migrated sample is the wavefield at time zero. Evanescent waves are removed.
+///
+/// # Arguments
+/// - `data`: The data, with one equidistant trace per column
+/// - `sample_rate_mhz`: The sample rate (MHz)
+/// - `trace_spacing`: The distance between traces (m)
+/// - `velocities`: The velocity of the medium (m/ns) at each sample of the migrated traces
+pub fn phase_shift(
+    data: &mut Array2<f32>,
+    sample_rate_mhz: f32,
+    trace_spacing: f32,
+    velocities: &[f32],
+) {
+    let (n_samples, n_traces) = data.dim();
+    if n_samples == 0 || n_traces == 0 {
+        return;
+    }
+    let n_time = (2 * n_samples).next_power_of_two();
+    let n_space = (2 * n_traces).next_power_of_two();
+    let frequency_step = sample_rate_mhz * 1e-3 / n_time as f32;
+    let sample_interval = 1e3 / sample_rate_mhz;
+
+    let spectra = forward_fk(data, n_time, n_space);
+    let images = spectra
+        .into_par_iter()
+        .enumerate()
+        .map(|(col, mut spectrum)| {
+            let wavenumber = signed_bin(col, n_space) / (n_space as f32 * trace_spacing);
+            let mut image = Vec::<Complex<f32>>::with_capacity(n_samples);
+            // The phase shift of one sample only changes with the velocity
+            let mut shifts = Vec::<Complex<f32>>::new();
+            let mut shift_velocity = f32::NAN;
+            for velocity in velocities.iter().take(n_samples) {
+                image.push(spectrum.iter().sum());
+
+                if *velocity != shift_velocity {
+                    shift_velocity = *velocity;
+                    let exploding_wavenumber = velocity / 2. * wavenumber;
+                    shifts = (0..n_time)
+                        .map(|row| {
+                            let frequency = signed_bin(row, n_time) * frequency_step;
+                            let squared = frequency.powi(2) - exploding_wavenumber.powi(2);
+                            match squared >= 0. {
+                                true => Complex::from_polar(
+                                    1.,
+                                    2. * std::f32::consts::PI
+                                        * frequency.signum()
+                                        * squared.sqrt()
+                                        * sample_interval,
+                                ),
+                                false => Complex::new(0., 0.),
+                            }
+                        })
+                        .collect();
+                }
+                for (value, shift) in spectrum.iter_mut().zip(shifts.iter()) {
+                    *value *= shift;
+                }
+            }
+            image.resize(n_samples, Complex::new(0., 0.));
+            image
+        })
+        .collect::<Vec<Vec<Complex<f32>>>>();
+
+    let rows = inverse_space(&images, n_traces);
+    let scale = (n_time * n_space) as f32;
+    *data = Array2::from_shape_fn((n_samples, n_traces), |(row, col)| {
+        rows[row][col].re / scale
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array2;
+
+    #[test]
+    fn test_flat_reflector_is_unchanged() {
+        // A horizontal 100 MHz reflector at 100 ns, sampled at 1000 MHz, in a Gaussian window
+        let data = Array2::from_shape_fn((256, 64), |(row, col)| {
+            let t = row as f32 - 100.;
+            let x = col as f32 - 32.;
+            (2. * std::f32::consts::PI * 0.1 * t).sin()
+                * (-(t / 10.).powi(2)).exp()
+                * (-(x / 12.).powi(2)).exp()
+        });
+        let max_error = |migrated: &Array2<f32>| {
+            // Only compare the middle, where the reflector is nearly flat
+            (migrated - &data)
+                .slice(ndarray::s![.., 24..40])
+                .iter()
+                .fold(0_f32, |max, v| max.max(v.abs()))
+        };
+
+        let mut stolt = data.clone();
+        super::stolt(&mut stolt, 1000., 0.5, 0.168);
+        assert!(max_error(&stolt) < 0.1);
+
+        let mut phase_shift = data.clone();
+        super::phase_shift(&mut phase_shift, 1000., 0.5, &[0.168; 256]);
+        assert!(max_error(&phase_shift) < 0.1);
+    }
+}
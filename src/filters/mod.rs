@@ -1,8 +1,13 @@
 use crate::tools;
 use ndarray::{Array, Array2};
 use num::{Float, FromPrimitive};
+use rayon::prelude::*;
+use std::ops::Range;
 
 pub mod bandpass;
+pub mod fft;
+pub mod hilbert;
+pub mod migration;
 
 pub fn abslog<T: Float>(data: &mut Array2<T>) {
     data.mapv_inplace(|v| v.abs());
@@ -70,6 +75,262 @@ pub fn average_traces<T: Float + FromPrimitive>(
     Ok(averaged)
 }
 
+/// A statistic of a window of values, e.g. the background of traces or the wow of a trace
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Statistic {
+    Mean,
+    Median,
+}
+
+impl std::str::FromStr for Statistic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mean" => Ok(Self::Mean),
+            "median" => Ok(Self::Median),
+            other => Err(format!("Unknown statistic: {other}")),
+        }
+    }
+}
+
+impl std::fmt::Display for Statistic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mean => write!(f, "mean"),
+            Self::Median => write!(f, "median"),
+        }
+    }
+}
+
+/// Subtract the mean or median trace (the background) from every trace
+///
+/// # Arguments
+/// - `data`: The data, with one trace per column
+/// - `statistic`: Whether the background is the mean or the median of the traces
+/// - `window`: The number of traces in a sliding window that is centered on each trace. A window
+///   of 0 or one larger than the data width means that the background of all traces is used.
+/// - `rows`: The rows (samples) where the background is removed
+pub fn background_removal<T: Float + FromPrimitive + Send + Sync>(
+    data: &mut Array2<T>,
+    statistic: Statistic,
+    window: usize,
+    rows: Range<usize>,
+) {
+    let ncols = data.shape()[1];
+    if ncols == 0 {
+        return;
+    }
+    let window = match window {
+        0 => ncols,
+        w => w.min(ncols),
+    };
+    // The windows keep their size at the edges by being shifted inward
+    let window_start = |col: usize| col.saturating_sub(window / 2).min(ncols - window);
+
+    let backgrounds = rows
+        .clone()
+        .into_par_iter()
+        .map(|row| {
+            let values = data.row(row).to_vec();
+            match statistic {
+                Statistic::Mean => running_mean(&values, |col| {
+                    let start = window_start(col);
+                    start..start + window
+                }),
+                Statistic::Median => {
+                    let mut buffer = Vec::<T>::with_capacity(window);
+                    let mut median = |start: usize| {
+                        buffer.clear();
+                        buffer.extend_from_slice(&values[start..start + window]);
+                        median_inplace(&mut buffer)
+                    };
+                    match window == ncols {
+                        // The background is the same for all traces
+                        true => vec![median(0); ncols],
+                        false => (0..ncols).map(|col| median(window_start(col))).collect(),
+                    }
+                }
+            }
+        })
+        .collect::<Vec<Vec<T>>>();
+
+    for (row, background) in rows.zip(backgrounds) {
+        for (value, background) in data.row_mut(row).iter_mut().zip(background) {
+            *value = *value - background;
+        }
+    }
+}
+
+/// Subtract a centered running mean or median from every trace (dewow)
+///
+/// The running statistic is a low-pass version of the trace, so subtracting it removes the slowly
+/// varying "wow" that is induced in the receiver by the direct wave, without affecting the
+/// shorter reflection wavelets. The window is truncated at the top and bottom of the trace so
+/// that it stays centered.
+///
+/// # Arguments
+/// - `data`: The data, with one trace per column
+/// - `statistic`: Whether the running mean or the running median is subtracted
+/// - `window`: The number of samples in the window. Even windows are extended by one sample.
+pub fn dewow<T: Float + FromPrimitive + Send + Sync>(
+    data: &mut Array2<T>,
+    statistic: Statistic,
+    window: usize,
+) {
+    let nrows = data.shape()[0];
+    if nrows == 0 {
+        return;
+    }
+    let half = window / 2;
+    let window_range = |row: usize| row.saturating_sub(half)..(row + half + 1).min(nrows);
+
+    let wows = (0..data.shape()[1])
+        .into_par_iter()
+        .map(|col| {
+            let values = data.column(col).to_vec();
+            match statistic {
+                Statistic::Mean => running_mean(&values, window_range),
+                Statistic::Median => {
+                    let mut buffer = Vec::<T>::with_capacity(2 * half + 1);
+                    (0..nrows)
+                        .map(|row| {
+                            buffer.clear();
+                            buffer.extend_from_slice(&values[window_range(row)]);
+                            median_inplace(&mut buffer)
+                        })
+                        .collect::<Vec<T>>()
+                }
+            }
+        })
+        .collect::<Vec<Vec<T>>>();
+
+    for (mut column, wow) in data.columns_mut().into_iter().zip(wows) {
+        for (value, wow) in column.iter_mut().zip(wow) {
+            *value = *value - wow;
+        }
+    }
+}
+
+/// The amplitude measure that AGC normalizes by
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AgcMethod {
+    /// The root mean square amplitude
+    Rms,
+    /// The mean absolute amplitude
+    MeanAbs,
+}
+
+impl std::str::FromStr for AgcMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rms" => Ok(Self::Rms),
+            "mean_abs" => Ok(Self::MeanAbs),
+            other => Err(format!("Unknown AGC method: {other}")),
+        }
+    }
+}
+
+impl std::fmt::Display for AgcMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Rms => write!(f, "rms"),
+            Self::MeanAbs => write!(f, "mean_abs"),
+        }
+    }
+}
+
+/// Apply automatic gain control (AGC) by normalizing every sample by the amplitude around it
+///
+/// The amplitude is measured in a window that is centered on each sample, and truncated at the
+/// top and bottom of the trace. Samples in windows without any signal are left as they are.
+///
+/// # Arguments
+/// - `data`: The data, with one trace per column
+/// - `method`: Whether the amplitude is the RMS or the mean absolute value of the window
+/// - `window`: The number of samples in the window. Even windows are extended by one sample.
+///
+/// # Returns
+/// The gain that each sample was multiplied with. Dividing by it undoes the AGC.
+pub fn agc<T: Float + FromPrimitive + Send + Sync>(
+    data: &mut Array2<T>,
+    method: AgcMethod,
+    window: usize,
+) -> Array2<T> {
+    let half = window / 2;
+    let gains = (0..data.shape()[1])
+        .into_par_iter()
+        .map(|col| {
+            let values: Vec<T> = match method {
+                AgcMethod::Rms => data.column(col).iter().map(|v| v.powi(2)).collect(),
+                AgcMethod::MeanAbs => data.column(col).iter().map(|v| v.abs()).collect(),
+            };
+            let n_values = values.len();
+            running_mean(&values, |row| {
+                row.saturating_sub(half)..(row + half + 1).min(n_values)
+            })
+            .into_iter()
+            .map(|mean| {
+                let amplitude = match method {
+                    AgcMethod::Rms => mean.sqrt(),
+                    AgcMethod::MeanAbs => mean,
+                };
+                match amplitude > T::zero() {
+                    true => amplitude.recip(),
+                    false => T::one(),
+                }
+            })
+            .collect::<Vec<T>>()
+        })
+        .collect::<Vec<Vec<T>>>();
+
+    let mut gain = Array2::<T>::zeros(data.dim());
+    for (col, column_gains) in gains.into_iter().enumerate() {
+        gain.column_mut(col)
+            .assign(&ndarray::Array1::from_vec(column_gains));
+    }
+    data.zip_mut_with(&gain, |value, gain| *value = *value * *gain);
+    gain
+}
+
+/// The mean of the values in the window (a range of indices) around each value
+///
+/// The running sums are kept in f64, since the difference of two large f32 sums loses weak values
+/// that follow strong ones.
+fn running_mean<T: Float + FromPrimitive>(
+    values: &[T],
+    window: impl Fn(usize) -> Range<usize>,
+) -> Vec<T> {
+    let mut cumulative = Vec::<f64>::with_capacity(values.len() + 1);
+    cumulative.push(0.);
+    for value in values {
+        cumulative.push(cumulative.last().unwrap() + value.to_f64().unwrap());
+    }
+    (0..values.len())
+        .map(|i| {
+            let Range { start, end } = window(i);
+            T::from_f64((cumulative[end] - cumulative[start]) / (end - start) as f64).unwrap()
+        })
+        .collect()
+}
+
+/// The median of a non-empty slice. The slice is partially reordered in the process.
+fn median_inplace<T: Float + FromPrimitive>(values: &mut [T]) -> T {
+    let cmp = |a: &T, b: &T| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal);
+    let mid = values.len() / 2;
+    let is_even = values.len().is_multiple_of(2);
+    let (lower, upper, _) = values.select_nth_unstable_by(mid, cmp);
+    match is_even {
+        false => *upper,
+        true => {
+            let lower_max = lower.iter().copied().fold(T::neg_infinity(), T::max);
+            (lower_max + *upper) / T::from_f32(2.).unwrap()
+        }
+    }
+}
+
 pub fn window_subset_vec<T>(mut v: Vec<T>, window: usize) -> Vec<T> {
     assert!(window >= 1, "window must be >= 1");
 
@@ -176,6 +437,113 @@ mod tests {
             Err("Window size (0) needs to be >= 2".to_string())
         );
     }
+    #[test]
+    fn test_background_removal() {
+        use super::Statistic;
+
+        // A horizontal band (the background) with one deviating trace
+        let mut data = Array2::<f32>::from_elem((4, 5), 1.);
+        data.row_mut(2).fill(3.);
+        data[[1, 2]] = 6.;
+        data[[2, 2]] = 8.;
+
+        let mut median = data.clone();
+        super::background_removal(&mut median, Statistic::Median, 0, 0..4);
+        let mut expected = Array2::<f32>::zeros((4, 5));
+        expected[[1, 2]] = 5.;
+        expected[[2, 2]] = 5.;
+        assert_eq!(median, expected);
+
+        // The mean is affected by the deviating trace
+        let mut mean = data.clone();
+        super::background_removal(&mut mean, Statistic::Mean, 0, 0..4);
+        assert_eq!(mean[[1, 0]], -1.);
+        assert_eq!(mean[[1, 2]], 4.);
+        assert_eq!(mean[[0, 0]], 0.);
+
+        // Only the given rows should be modified
+        let mut partial = data.clone();
+        super::background_removal(&mut partial, Statistic::Median, 0, 2..4);
+        assert_eq!(partial.row(1), data.row(1));
+        assert_eq!(partial.row(2), expected.row(2));
+
+        // With a sliding window, a slowly changing background is also removed
+        let mut ramp = Array2::<f32>::from_shape_fn((2, 9), |(_, col)| col as f32);
+        super::background_removal(&mut ramp, Statistic::Mean, 3, 0..2);
+        assert_eq!(ramp[[0, 4]], 0.);
+        assert_eq!(ramp[[0, 0]], -1.);
+        assert_eq!(ramp[[0, 8]], 1.);
+
+        let mut even = ndarray::arr2(&[[1_f32, 2., 4., 10.]]);
+        super::background_removal(&mut even, Statistic::Median, 4, 0..1);
+        assert_eq!(even, ndarray::arr2(&[[-2_f32, -1., 1., 7.]]));
+    }
+
+    #[test]
+    fn test_dewow() {
+        use super::Statistic;
+
+        // A slow linear "wow" with a spike. The running mean of a linear trend is the trend itself
+        let mut data = Array2::<f32>::from_shape_fn((20, 3), |(row, _)| row as f32 * 0.5);
+        data[[10, 1]] += 5.;
+
+        let mut mean = data.clone();
+        super::dewow(&mut mean, Statistic::Mean, 5);
+        assert_eq!(mean[[5, 0]], 0.);
+        // The tail is also processed, with a truncated window
+        assert_eq!(mean[[19, 0]], 0.5);
+        assert_eq!(mean[[0, 0]], -0.5);
+        assert_eq!(mean[[10, 1]], 4.);
+
+        // The median is less affected by the spike
+        let mut median = data.clone();
+        super::dewow(&mut median, Statistic::Median, 5);
+        assert_eq!(median[[10, 1]], 4.5);
+        assert_eq!(median[[9, 1]], 0.);
+        assert_eq!(median.column(0), median.column(2));
+
+        // Even windows are extended to be centered
+        let mut even = data.clone();
+        super::dewow(&mut even, Statistic::Mean, 4);
+        assert_eq!(even, mean);
+    }
+
+    #[test]
+    fn test_agc() {
+        use super::AgcMethod;
+
+        // A trace that decays strongly with depth, and an empty trace
+        let mut data = Array2::<f32>::from_shape_fn((12, 2), |(row, col)| {
+            (col == 0) as u8 as f32 * 1024. * 0.5_f32.powi(row as i32) * [1., -1.][row % 2]
+        });
+        let original = data.clone();
+
+        let gain = super::agc(&mut data, AgcMethod::MeanAbs, 1);
+        // A window of one sample leaves only the sign
+        assert_eq!(data.column(0).iter().map(|v| v.abs()).sum::<f32>(), 12.);
+        assert_eq!(data.column(1), original.column(1));
+        assert_eq!(&data / &gain, original);
+
+        let mut rms = original.clone();
+        super::agc(&mut rms, AgcMethod::Rms, 5);
+        let amplitudes = rms.column(0).mapv(f32::abs);
+        let max = amplitudes.fold(0_f32, |a, b| a.max(*b));
+        let min = amplitudes.fold(f32::INFINITY, |a, b| a.min(*b));
+        // The decay of 2^11 is reduced to a small range
+        assert!(max / min < 5., "{max} {min}");
+        assert!(rms[[0, 0]] > 0. && rms[[1, 0]] < 0.);
+
+        // A strong early arrival followed by weak late samples
+        let mut late = Array2::<f32>::from_shape_fn((2100, 1), |(row, _)| match row < 50 {
+            true => 1e4,
+            false => 1e-2,
+        });
+        let gain = super::agc(&mut late, AgcMethod::Rms, 21);
+        for row in [500, 1000, 2000] {
+            assert!((gain[[row, 0]] - 100.).abs() < 1., "{}", gain[[row, 0]]);
+        }
+    }
+
     #[test]
     fn test_window_subset_vec() {
         // Helper to keep the assertions readable
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::error::Error;
 /// Functions to process GPR data
 use std::path::{Path, PathBuf};
@@ -8,17 +9,14 @@ use std::time::{Duration, SystemTime};
 use ndarray::{Array1, Array2, Axis, Slice};
 use rayon::prelude::*;
 
+use crate::horizons::{self, BedPick, Horizon, Phase};
+use crate::hyperbola::{Diffraction, DiffractionSearch};
+use crate::provenance::{self, Provenance, ProvenanceSource, ProvenanceStep};
+use crate::steps::{SkipFirst, Step, STEPS};
+use crate::velocity::VelocityModel;
 use crate::{dem, filters, io, tools};
 
 const DEFAULT_ZERO_CORR_THRESHOLD_MULTIPLIER: f32 = 1.0;
-const DEFAULT_EMPTY_TRACE_STRENGTH: f32 = 1.0;
-const DEFAULT_DEWOW_WINDOW: u32 = 5;
-const DEFAULT_NORMALIZE_HORIZONTAL_MAGNITUDES_CUTOFF: f32 = 0.3;
-const DEFAULT_AUTOGAIN_N_BINS: usize = 100;
-const DEFAULT_BANDPASS_LOW_CUTOFF: f32 = 0.1;
-const DEFAULT_BANDPASS_HIGH_CUTOFF: f32 = 0.9;
-const DEFAULT_BANDPASS_Q: f32 = 0.707;
-const DEFAULT_SIGLOG_MINVAL_LOG10: f32 = -1.;
 
 /// Metadata associated with a GPR dataset
 ///
@@ -32,6 +30,9 @@ pub struct GPRMeta {
     pub frequency_steps: u32,
     /// The interval between traces (s)
     pub time_interval: f32,
+    /// The interval between traces of distance-triggered data (m), which is then the default step
+    /// of `equidistant_traces`
+    pub distance_interval: Option<f32>,
     /// The name of the antenna
     pub antenna: String,
     /// The frequency of the antenna (MHz)
@@ -46,15 +47,61 @@ pub struct GPRMeta {
     pub data_filepath: PathBuf,
     /// The velocity of the medium (m / ns)
     pub medium_velocity: f32,
+    /// A layered velocity model, which is used instead of `medium_velocity` to convert return
+    /// times to depths if it is given
+    pub velocity_model: Option<VelocityModel>,
+    /// The (zero-based) channel of the data file, for formats that may have multiple channels
+    pub channel: usize,
+    /// The along-track and cross-track (positive to the left) offset of the channel relative to
+    /// the positioning antenna (m)
+    pub channel_offset: [f32; 2],
 }
 
 impl GPRMeta {
+    /// The average velocity of the medium down to the depth of a return time
+    ///
+    /// This is the medium velocity, unless a velocity model is given.
+    ///
+    /// # Arguments
+    /// - `return_time`: The two-way return time (ns)
+    ///
+    /// # Returns
+    /// The average velocity (m/ns)
+    pub fn average_velocity(&self, return_time: f32) -> f32 {
+        match &self.velocity_model {
+            Some(model) => model.average_velocity(return_time),
+            None => self.medium_velocity,
+        }
+    }
+
     /// Find a ".cor" file based on the location of the ".rd3" file
     ///
+    /// For Malå multichannel (MIRA) sets, where each channel has an "_A<channel>" suffix, the
+    /// ".cor" file may be shared by the set. If the channel has no ".cor" file of its own, one
+    /// without the suffix or of another channel in the set is used instead.
+    ///
     /// # Arguments
     /// - `projected_crs`: The CRS to project coordinates into
     pub fn find_cor(&self, projected_crs: Option<&String>) -> Result<GPRLocation, Box<dyn Error>> {
-        io::load_cor(&self.data_filepath.with_extension("cor"), projected_crs)
+        let cor_filepath = self.data_filepath.with_extension("cor");
+
+        if !cor_filepath.is_file() && io::mala_channel(&self.data_filepath).is_some() {
+            let stem = self.data_filepath.file_stem().unwrap().to_string_lossy();
+            let set_stem = stem.rsplit_once("_A").unwrap().0;
+            let set_cor = cor_filepath.with_file_name(format!("{set_stem}.cor"));
+            if set_cor.is_file() {
+                return io::load_cor(&set_cor, projected_crs);
+            }
+            let pattern =
+                cor_filepath.with_file_name(format!("{}_A*.cor", glob::Pattern::escape(set_stem)));
+            if let Some(channel_cor) = glob::glob(&pattern.to_string_lossy())?
+                .filter_map(|p| p.ok())
+                .min()
+            {
+                return io::load_cor(&channel_cor, projected_crs);
+            }
+        }
+        io::load_cor(&cor_filepath, projected_crs)
     }
 }
 
@@ -70,22 +117,29 @@ Samples (height):\t{}
 Traces (width):\t\t{}
 Time window:\t\t{} ns
 Max depth:\t\t{:.1} m
-Medium velocity:\t{} m/ns
+Medium velocity:\t{}
 Sampling frequency:\t{} MHz
 Time between traces:\t{} s
 Antenna:\t\t{}
 Antenna separation:\t{} m
+Channel:\t\t{}
+Channel offset:\t\t{:?} m
 ",
             self.data_filepath,
             self.samples,
             self.last_trace,
             self.time_window,
-            0.5 * self.time_window * self.medium_velocity,
-            self.medium_velocity,
+            0.5 * self.time_window * self.average_velocity(self.time_window),
+            match &self.velocity_model {
+                Some(model) => model.to_string(),
+                None => format!("{} m/ns", self.medium_velocity),
+            },
             self.frequency,
             self.time_interval,
             self.antenna,
             self.antenna_separation,
+            self.channel,
+            self.channel_offset,
         )
     }
 }
@@ -110,6 +164,15 @@ impl CorPoint {
     }
 }
 
+/// A marker set during the survey, e.g. at a flag or a crossing
+#[derive(Debug, Clone, PartialEq)]
+pub struct Marker {
+    /// The (zero-based) trace index of the marker
+    pub trace: usize,
+    /// The comment of the marker (may be empty)
+    pub comment: String,
+}
+
 #[derive(Debug, Clone)]
 pub enum LocationCorrection {
     None,
@@ -226,12 +289,55 @@ impl GPRLocation {
         let mut dist = offsets
             .slice_axis(Axis(1), Slice::new(1, None, 1))
             .mapv(|f| f.powi(2))
-            .sum_axis(Axis(1));
+            .sum_axis(Axis(1))
+            .mapv(f64::sqrt);
         dist.accumulate_axis_inplace(Axis(0), |prev, cur| *cur += prev);
 
         dist
     }
 
+    /// Shift the track horizontally, for channels that are offset from the positioning antenna
+    ///
+    /// The direction of travel at each point is estimated from its neighbours. Points where the
+    /// track is stationary use the last known direction.
+    ///
+    /// # Arguments
+    /// - `along_track`: The offset in the direction of travel (m)
+    /// - `cross_track`: The offset perpendicular to the direction of travel, positive to the left (m)
+    pub fn offset_horizontally(&mut self, along_track: f64, cross_track: f64) {
+        let n_points = self.cor_points.len();
+        if n_points < 2 {
+            return;
+        }
+        let mut directions: Vec<Option<(f64, f64)>> = (0..n_points)
+            .map(|i| {
+                let prev = &self.cor_points[i.saturating_sub(1)];
+                let next = &self.cor_points[(i + 1).min(n_points - 1)];
+                let (dx, dy) = (next.easting - prev.easting, next.northing - prev.northing);
+                let length = (dx.powi(2) + dy.powi(2)).sqrt();
+                match length > 0. {
+                    true => Some((dx / length, dy / length)),
+                    false => None,
+                }
+            })
+            .collect();
+
+        // Fill stationary points with the last (or for the start, the first) known direction
+        let Some(first_direction) = directions.iter().flatten().next().copied() else {
+            return;
+        };
+        let mut last_direction = first_direction;
+        for direction in directions.iter_mut() {
+            last_direction = *direction.get_or_insert(last_direction);
+        }
+
+        for (point, direction) in self.cor_points.iter_mut().zip(directions) {
+            let (dx, dy) = direction.unwrap();
+            point.easting += along_track * dx - cross_track * dy;
+            point.northing += along_track * dy + cross_track * dx;
+        }
+    }
+
     fn range_fill(&self, start_trace: u32, end_trace: u32) -> GPRLocation {
         let mut new_points: Vec<CorPoint> = Vec::new();
 
@@ -254,7 +360,18 @@ impl GPRLocation {
         }
     }
 
-    pub fn get_dem_elevations(&mut self, dem_path: &Path) -> Result<(), String> {
+    /// Sample the first band of a raster (e.g. a DEM) at each point
+    ///
+    /// # Arguments
+    /// - `raster_path`: The path to a raster that GDAL can read
+    ///
+    /// # Returns
+    /// The value of the raster at each point
+    ///
+    /// # Errors
+    /// - The coordinates could not be converted to WGS84
+    /// - The raster could not be sampled at every point
+    pub fn sample_raster(&self, raster_path: &Path) -> Result<Vec<f32>, String> {
         let coords = self
             .cor_points
             .iter()
@@ -266,7 +383,11 @@ impl GPRLocation {
 
         let coords_wgs84 =
             crate::coords::to_wgs84(&coords, &crate::coords::Crs::from_user_input(&self.crs)?)?;
-        let elev = dem::sample_dem(dem_path, &coords_wgs84)?;
+        dem::sample_dem(raster_path, &coords_wgs84)
+    }
+
+    pub fn get_dem_elevations(&mut self, dem_path: &Path) -> Result<(), String> {
+        let elev = self.sample_raster(dem_path)?;
 
         for (i, point) in self.cor_points.iter_mut().enumerate() {
             if let Some(e) = elev.get(i) {
@@ -278,14 +399,38 @@ impl GPRLocation {
         Ok(())
     }
 
-    pub fn to_csv(&self, filepath: &Path) -> Result<(), std::io::Error> {
-        let mut output = "trace_n,easting,northing,altitude\n".to_string();
+    /// Write the points to a CSV file
+    ///
+    /// # Arguments
+    /// - `filepath`: The path of the CSV file
+    /// - `bed_picks`: The ice thickness, bed elevation and quality of each point to add as
+    ///   columns. Points without a pick are left empty.
+    pub fn to_csv(
+        &self,
+        filepath: &Path,
+        bed_picks: Option<&[Option<BedPick>]>,
+    ) -> Result<(), std::io::Error> {
+        let mut output = "trace_n,easting,northing,altitude".to_string();
+        if bed_picks.is_some() {
+            output += ",ice_thickness,bed_elevation,bed_quality";
+        }
+        output += "\n";
 
-        for point in &self.cor_points {
+        for (i, point) in self.cor_points.iter().enumerate() {
             output += &format!(
-                "{},{},{},{}\n",
+                "{},{},{},{}",
                 point.trace_n, point.easting, point.northing, point.altitude
             );
+            if let Some(bed_picks) = bed_picks {
+                output += &match bed_picks.get(i).copied().flatten() {
+                    Some(pick) => format!(
+                        ",{},{},{}",
+                        pick.ice_thickness, pick.bed_elevation, pick.quality
+                    ),
+                    None => ",,,".to_string(),
+                };
+            }
+            output += "\n";
         }
 
         std::fs::write(filepath, output)
@@ -368,193 +513,304 @@ pub struct GPR {
     pub metadata: GPRMeta,
     /// Processing log. Each line is one processing step
     pub log: Vec<String>,
+    /// Machine-readable record of the source data and of each step run with `process`
+    pub provenance: Provenance,
+    /// Markers set during the survey. The trace indices follow the data through all processing steps
+    pub markers: Vec<Marker>,
     /// The horizontal component of the signal distance (m). Defaults to the antenna separation if no correction has been made.
-    horizontal_signal_distance: f32,
+    pub(crate) horizontal_signal_distance: f32,
     /// The calculated zero-point (ns). It represents the delay between the transmitter and the receiver.
-    zero_point_ns: f32,
+    pub(crate) zero_point_ns: f32,
+    /// The gain of the last AGC run with `save=true`, so that it can be undone. It has the same
+    /// shape as `data`, and is discarded by steps that move traces or samples (except subset).
+    pub(crate) agc_gain: Option<Array2<f32>>,
+    /// Picked horizons, with one pick per trace. They are discarded by steps that move traces or
+    /// samples (except subset and the zero corrections, which move them too).
+    pub horizons: Vec<Horizon>,
+    /// Fitted diffraction hyperbolae. They are discarded by steps that move traces or samples
+    /// (except subset and the zero corrections, which move them too).
+    pub diffractions: Vec<Diffraction>,
 }
 
 impl GPR {
-    pub fn process(&mut self, step_name: &str) -> Result<(), Box<dyn Error>> {
-        if step_name.contains("dewow") {
-            let window = tools::parse_option::<u32>(step_name, 0)?.unwrap_or(DEFAULT_DEWOW_WINDOW);
-
-            self.dewow(window);
-        } else if step_name.contains("zero_corr_max_peak") {
-            self.zero_corr_max_peak();
-        } else if step_name.contains("zero_corr") {
-            let threshold_multiplier = tools::parse_option::<f32>(step_name, 0)?;
-
-            self.zero_corr(threshold_multiplier);
-        } else if step_name.contains("equidistant_traces") {
-            let step = tools::parse_option::<f32>(step_name, 0)?;
-            self.make_equidistant(step);
-        } else if step_name.contains("normalize_horizontal_magnitudes") {
-            // Try to parse the argument as an integer. If that doesn't work, try to parse it as a
-            // float and assume it's the fraction of the height
-            let skip_first: isize =
-                match tools::parse_option::<isize>(step_name, 0) {
-                    Ok(v) => Ok(v.unwrap_or(0)),
-                    Err(e) => match e.contains("Could not parse argument 0 as value") {
-                        true => tools::parse_option::<f32>(step_name, 0).and_then(|fraction| {
-                            match (fraction.unwrap() >= 1.0) & (fraction.unwrap() < 0.) {
-                                true => Err(format!(
-                                    "Invalid fraction: {:?}. Must be between 0.0 and 1.0.",
-                                    fraction
-                                )),
-                                false => {
-                                    Ok((self.height() as f32 * fraction.unwrap_or(0.)) as isize)
-                                }
-                            }
-                        }),
-                        false => Err(e),
-                    },
-                }?;
-            self.normalize_horizontal_magnitudes(Some(skip_first));
-        } else if step_name.contains("kirchhoff_migration2d") {
-            self.kirchhoff_migration2d();
-        } else if step_name.contains("auto_gain") {
-            let n_bins =
-                tools::parse_option::<usize>(step_name, 0)?.unwrap_or(DEFAULT_AUTOGAIN_N_BINS);
-            self.auto_gain(n_bins);
-        } else if step_name.contains("gain") {
-            let factor = match tools::parse_option::<f32>(step_name, 0)? {
-                Some(v) => Ok(v),
-                None => Err(
-                    "The gain factor must be specified when applying gain. E.g. gain(0.1)"
-                        .to_string(),
-                ),
-            }?;
-            self.gain(factor);
-        } else if step_name.contains("subset") {
-            let min_trace: Option<u32> = match tools::parse_option::<u32>(step_name, 0)? {
-                Some(v) => Ok(Some(v)),
-                None => Err("Indices must be given when subsetting, e.g. subset(0, -1, 0, 500)"),
-            }?;
-
-            let max_trace: Option<u32> = match tools::parse_option::<isize>(step_name, 1) {
-                Ok(v) => Ok(v.and_then(|v2| if v2 == -1 { None } else { Some(v2 as u32) })),
-                Err(e) => match e.contains("out of bounds") {
-                    true => Ok(None),
-                    false => Err(e),
-                },
-            }?;
-            let min_sample: Option<u32> = match tools::parse_option::<u32>(step_name, 2) {
-                Ok(v) => Ok(v),
-                Err(e) => match e.contains("out of bounds") {
-                    true => Ok(None),
-                    false => Err(e),
-                },
-            }?;
-            let max_sample: Option<u32> = match tools::parse_option::<isize>(step_name, 3) {
-                Ok(v) => Ok(v.and_then(|v2| if v2 == -1 { None } else { Some(v2 as u32) })),
-                Err(e) => match e.contains("out of bounds") {
-                    true => Ok(None),
-                    false => Err(e),
-                },
-            }?;
-            *self = self.subset(min_trace, max_trace, min_sample, max_sample)?;
-        } else if step_name.contains("average_traces") {
-            let window: usize = tools::parse_option(step_name, 0)?
-                .ok_or("Must provide an averaging window to average_traces".to_string())?;
-
-            self.average_traces(window)?;
-        } else if step_name.contains("unphase") {
-            self.unphase();
-        } else if step_name.contains("abslog") {
-            self.abslog()
-        } else if step_name.contains("siglog") {
-            let minval =
-                tools::parse_option::<f32>(step_name, 0)?.unwrap_or(DEFAULT_SIGLOG_MINVAL_LOG10);
-            self.siglog(minval);
-        } else if step_name.contains("correct_topography") {
-            self.correct_topography();
-        } else if step_name.contains("correct_antenna_separation") {
-            self.correct_antenna_separation();
-        } else if step_name.contains("remove_traces") {
-            let mut traces = Vec::<usize>::new();
-            for i in 0..self.width() {
-                // Try to parse the i:th option as an usize.
-                // If that doesn't work, it's either a range (e.g. 1-3) or it's poorly formatted
-                if let Some(trace) = tools::parse_option::<usize>(step_name, i).ok().flatten() {
-                    traces.push(trace);
-                } else {
-                    // Extract the option as a string. If i is out of bounds, this one will fail (and break the loop)
-                    if let Some(token) = tools::parse_option::<String>(step_name, i).ok().flatten()
-                    {
-                        // Start trying to parse it as a range (e.g. 1-3), or else give helpful messages.
-                        if !token.contains("-") {
-                            return Err(
-                                format!("Error reading 'remove_traces' argument: {token}").into()
-                            );
-                        }
-                        let mut new_traces = Vec::<usize>::new();
-                        let parts: Vec<&str> = token.split('-').collect();
-                        if let (Some(start_str), Some(end_str)) = (parts.first(), parts.get(1)) {
-                            if let (Ok(start), Ok(end)) =
-                                (start_str.parse::<usize>(), end_str.parse::<usize>())
-                            {
-                                for value in start..=end {
-                                    new_traces.push(value);
-                                }
-                            }
-                        }
-                        if new_traces.is_empty() {
-                            return Err(
-                                format!("Error reading 'remove_traces' argument: {token}").into()
-                            );
-                        };
-                        traces.append(&mut new_traces);
-                    } else {
-                        break;
-                    }
+    /// Run a processing step and record it in the provenance
+    ///
+    /// # Arguments
+    /// - `step`: The step to run
+    ///
+    /// # Errors
+    /// If the step fails, for example because of invalid arguments for the data.
+    pub fn process(&mut self, step: &Step) -> Result<(), Box<dyn Error>> {
+        let start_time = SystemTime::now();
+        let input_shape = [self.height(), self.width()];
+        // Automatically determined arguments are filled in as they are found
+        let mut resolved = step.clone();
+        let mut derived = BTreeMap::<String, f64>::new();
+
+        match step {
+            Step::Dewow {
+                window_ns,
+                periods,
+                statistic,
+            } => {
+                let window_ns = self.dewow(*window_ns, *periods, *statistic)?;
+                resolved = Step::Dewow {
+                    window_ns: Some(window_ns),
+                    periods: *periods,
+                    statistic: *statistic,
+                };
+            }
+            Step::BackgroundRemoval {
+                statistic,
+                window,
+                min_sample,
+                max_sample,
+            } => self.background_removal(*statistic, *window, *min_sample, *max_sample)?,
+            Step::ZeroCorrMaxPeak => self.zero_corr_max_peak(),
+            Step::ZeroCorr {
+                threshold_multiplier,
+            } => self.zero_corr(Some(*threshold_multiplier)),
+            Step::FftBandpass {
+                low_cut,
+                low_pass,
+                high_pass,
+                high_cut,
+            } => self.fft_bandpass([*low_cut, *low_pass, *high_pass, *high_cut])?,
+            Step::Notch {
+                frequency,
+                width,
+                harmonics,
+            } => {
+                let width = self.notch(*frequency, *width, *harmonics)?;
+                resolved = Step::Notch {
+                    frequency: *frequency,
+                    width: Some(width),
+                    harmonics: *harmonics,
+                };
+            }
+            Step::FkVelocityFilter {
+                min_velocity,
+                max_velocity,
+                mode,
+                taper,
+            } => self.fk_velocity_filter(*min_velocity, *max_velocity, *mode, *taper)?,
+            Step::FkDipFilter {
+                min_dip,
+                max_dip,
+                mode,
+                taper,
+            } => self.fk_dip_filter(*min_dip, *max_dip, *mode, *taper)?,
+            Step::EquidistantTraces { step } => {
+                let step = self.make_equidistant(*step)?;
+                resolved = Step::EquidistantTraces { step: Some(step) };
+            }
+            Step::NormalizeHorizontalMagnitudes { skip_first } => {
+                let skip_first = match skip_first {
+                    SkipFirst::Samples(samples) => *samples,
+                    SkipFirst::Fraction(fraction) => (self.height() as f32 * fraction) as isize,
+                };
+                self.normalize_horizontal_magnitudes(Some(skip_first));
+                resolved = Step::NormalizeHorizontalMagnitudes {
+                    skip_first: SkipFirst::Samples(skip_first),
+                };
+            }
+            Step::KirchhoffMigration2d => self.kirchhoff_migration2d(),
+            Step::StoltMigration { velocity } => {
+                let velocity = self.stolt_migration(*velocity)?;
+                resolved = Step::StoltMigration {
+                    velocity: Some(velocity),
+                };
+            }
+            Step::GazdagMigration { layers } => {
+                let layers = self.gazdag_migration(layers)?;
+                resolved = Step::GazdagMigration { layers };
+            }
+            Step::AutoGain { n_bins } => {
+                let factor = self.auto_gain(*n_bins);
+                derived.insert("gain_factor".to_string(), factor as f64);
+            }
+            Step::Gain { factor } => self.gain(*factor),
+            Step::SecGain { attenuation } => self.sec_gain(*attenuation),
+            Step::PowerGain { exponent } => self.power_gain(*exponent),
+            Step::SphericalDivergence { exponent } => self.spherical_divergence(*exponent),
+            Step::Agc {
+                window_ns,
+                method,
+                save,
+            } => self.agc(*window_ns, *method, *save)?,
+            Step::UndoAgc => self.undo_agc()?,
+            Step::Subset {
+                min_trace,
+                max_trace,
+                min_sample,
+                max_sample,
+            } => {
+                *self =
+                    self.subset(Some(*min_trace), *max_trace, Some(*min_sample), *max_sample)?;
+            }
+            Step::AverageTraces { window } => self.average_traces(*window)?,
+            Step::Unphase => self.unphase(),
+            Step::Envelope => self.envelope(),
+            Step::InstantaneousPhase => self.instantaneous_phase(),
+            Step::InstantaneousFrequency => self.instantaneous_frequency(),
+            Step::Abslog => self.abslog(),
+            Step::Siglog { minval_log10 } => self.siglog(*minval_log10),
+            Step::CorrectTopography => self.correct_topography(),
+            Step::CorrectAntennaSeparation => self.correct_antenna_separation(),
+            Step::RemoveTraces { traces } => self.remove_traces(traces, true)?,
+            Step::RemoveEmptyTraces { strength } => self.remove_empty_traces(*strength)?,
+            Step::BandpassMhz {
+                low_cutoff,
+                high_cutoff,
+                q,
+                zero_phase,
+            } => self.bandpass(*low_cutoff, *high_cutoff, *q, false, *zero_phase)?,
+            Step::PickHorizon {
+                horizon,
+                seeds,
+                phase,
+                window_ns,
+                max_gap,
+                threshold,
+            } => {
+                let (window_ns, n_picked) =
+                    self.pick_horizon(horizon, seeds, *phase, *window_ns, *max_gap, *threshold)?;
+                resolved = Step::PickHorizon {
+                    horizon: horizon.clone(),
+                    seeds: seeds.clone(),
+                    phase: *phase,
+                    window_ns: Some(window_ns),
+                    max_gap: *max_gap,
+                    threshold: *threshold,
+                };
+                derived.insert("n_picked".to_string(), n_picked as f64);
+            }
+            Step::DetectBed {
+                min_depth,
+                max_jump_ns,
+                prior,
+                prior_tolerance,
+            } => {
+                let (max_jump_ns, n_picked) =
+                    self.detect_bed(*min_depth, *max_jump_ns, prior.as_deref(), *prior_tolerance)?;
+                resolved = Step::DetectBed {
+                    min_depth: *min_depth,
+                    max_jump_ns: Some(max_jump_ns),
+                    prior: prior.clone(),
+                    prior_tolerance: *prior_tolerance,
+                };
+                derived.insert("n_picked".to_string(), n_picked as f64);
+                let thicknesses = self
+                    .bed_picks()
+                    .unwrap_or_default()
+                    .iter()
+                    .flatten()
+                    .map(|pick| pick.ice_thickness)
+                    .collect::<Vec<f32>>();
+                if !thicknesses.is_empty() {
+                    derived.insert(
+                        "mean_ice_thickness".to_string(),
+                        (thicknesses.iter().sum::<f32>() / thicknesses.len() as f32) as f64,
+                    );
                 }
             }
-            if traces.is_empty() {
-                return Err(
-                    "Indices must be given when calling remove_traces, e.g. remove_traces(0 1 5)"
-                        .into(),
-                );
-            };
-            self.remove_traces(&traces, true)?;
-        } else if step_name.contains("remove_empty_traces") {
-            let strength =
-                tools::parse_option::<f32>(step_name, 0)?.unwrap_or(DEFAULT_EMPTY_TRACE_STRENGTH);
-
-            self.remove_empty_traces(strength)?;
-        } else if step_name.contains("bandpass_mhz") {
-            let error_msg =
-                "Must provide lower and upper cutoff frequencies (e.g. bandpass_mhz(50 150)";
-            let low_cutoff = tools::parse_option(step_name, 0)?.ok_or(error_msg)?;
-            let high_cutoff = tools::parse_option(step_name, 1)?.ok_or(error_msg)?;
-            let q: f32 = tools::parse_option(step_name, 2)
-                .ok()
-                .flatten()
-                .unwrap_or(DEFAULT_BANDPASS_Q);
-            self.bandpass(low_cutoff, high_cutoff, q, false)?;
-        } else if step_name.contains("bandpass") {
-            let low_cutoff =
-                tools::parse_option(step_name, 0)?.unwrap_or(DEFAULT_BANDPASS_LOW_CUTOFF);
-            let high_cutoff =
-                tools::parse_option(step_name, 1)?.unwrap_or(DEFAULT_BANDPASS_HIGH_CUTOFF);
-            let q: f32 = tools::parse_option(step_name, 2)
-                .ok()
-                .flatten()
-                .unwrap_or(DEFAULT_BANDPASS_Q);
-            self.bandpass(low_cutoff, high_cutoff, q, true)?;
-        } else {
-            return Err(format!("Step name not recognized: {}", step_name).into());
+            Step::FitHyperbolae {
+                apexes,
+                aperture_m,
+                max_count,
+            } => {
+                let n_diffractions = self.fit_hyperbolae(apexes, *aperture_m, *max_count)?;
+                derived.insert("n_diffractions".to_string(), n_diffractions as f64);
+                if n_diffractions > 0 {
+                    derived.insert(
+                        "mean_velocity".to_string(),
+                        self.diffractions
+                            .iter()
+                            .map(|d| d.velocity as f64)
+                            .sum::<f64>()
+                            / n_diffractions as f64,
+                    );
+                }
+            }
+            Step::Bandpass {
+                low_cutoff,
+                high_cutoff,
+                q,
+                zero_phase,
+            } => self.bandpass(*low_cutoff, *high_cutoff, *q, true, *zero_phase)?,
+        }
+
+        // A saved AGC gain, picks and diffractions no longer correspond to the samples if the
+        // samples or traces were moved, even if the shape is the same
+        let moves_samples = matches!(
+            step,
+            Step::ZeroCorr { .. }
+                | Step::ZeroCorrMaxPeak
+                | Step::EquidistantTraces { .. }
+                | Step::AverageTraces { .. }
+                | Step::RemoveTraces { .. }
+                | Step::RemoveEmptyTraces { .. }
+                | Step::KirchhoffMigration2d
+                | Step::StoltMigration { .. }
+                | Step::GazdagMigration { .. }
+                | Step::CorrectAntennaSeparation
+        );
+        if moves_samples
+            || self
+                .agc_gain
+                .as_ref()
+                .is_some_and(|gain| gain.dim() != self.data.dim())
+        {
+            self.agc_gain = None;
+        }
+        // The zero corrections and subset move the picks and diffractions with the samples they
+        // remove instead
+        let moves_picks = matches!(
+            step,
+            Step::Subset { .. } | Step::ZeroCorr { .. } | Step::ZeroCorrMaxPeak
+        );
+        let reshaped = input_shape != [self.height(), self.width()];
+        if ((moves_samples || reshaped) && !moves_picks)
+            || self.horizons.iter().any(|h| h.picks.len() != self.width())
+        {
+            self.horizons.clear();
+            self.diffractions.clear();
         }
 
+        self.provenance.steps.push(ProvenanceStep {
+            step: step.to_string(),
+            resolved: resolved.to_string(),
+            derived,
+            input_shape,
+            output_shape: [self.height(), self.width()],
+            duration_seconds: SystemTime::now()
+                .duration_since(start_time)
+                .unwrap()
+                .as_secs_f32(),
+            radal_version: provenance::radal_version(),
+        });
+
         Ok(())
     }
 
+    /// Apply a bandpass Butterworth filter to each trace
+    ///
+    /// # Arguments
+    /// - `low_cutoff`: The low cutoff frequency (MHz, or normalized to the Nyquist frequency)
+    /// - `high_cutoff`: The high cutoff frequency (MHz, or normalized to the Nyquist frequency)
+    /// - `q`: The damping of the filter sections
+    /// - `normalized`: Whether the cutoffs are normalized (0-1) instead of in MHz
+    /// - `zero_phase`: Filter forward and backward, so that reflections are not shifted in time
+    ///
+    /// # Errors
+    /// If the cutoffs or `q` are invalid.
     pub fn bandpass(
         &mut self,
         low_cutoff: f32,
         high_cutoff: f32,
         q: f32,
         normalized: bool,
+        zero_phase: bool,
     ) -> Result<(), String> {
         let start_time = SystemTime::now();
 
@@ -583,8 +839,12 @@ impl GPR {
             ));
         }
 
+        let filter = match zero_phase {
+            true => filters::bandpass::bandpass_zero_phase,
+            false => filters::bandpass::bandpass_hpf_then_lpf,
+        };
         for mut col in self.data.columns_mut() {
-            filters::bandpass::bandpass_hpf_then_lpf(
+            filter(
                 &mut col,
                 low_cutoff,
                 high_cutoff,
@@ -594,12 +854,16 @@ impl GPR {
             )?;
         }
 
+        let phase = match zero_phase {
+            true => "zero-phase ",
+            false => "",
+        };
         if normalized {
             self.log_event(
                 "bandpass",
                 &format!(
-                    "Applied a normalized bandpass Butterworth filter ({:.3}-{:.3}, q={:.3})",
-                    low_cutoff, high_cutoff, q,
+                    "Applied a normalized {}bandpass Butterworth filter ({:.3}-{:.3}, q={:.3})",
+                    phase, low_cutoff, high_cutoff, q,
                 ),
                 start_time,
             );
@@ -607,8 +871,8 @@ impl GPR {
             self.log_event(
                 "bandpass_mhz",
                 &format!(
-                    "Applied a bandpass Butterworth filter ({:.3}-{:.3} MHz, q={:.3})",
-                    low_cutoff, high_cutoff, q
+                    "Applied a {}bandpass Butterworth filter ({:.3}-{:.3} MHz, q={:.3})",
+                    phase, low_cutoff, high_cutoff, q
                 ),
                 start_time,
             );
@@ -617,90 +881,796 @@ impl GPR {
         Ok(())
     }
 
-    pub fn subset(
-        &self,
-        min_trace: Option<u32>,
-        max_trace: Option<u32>,
-        min_sample: Option<u32>,
-        max_sample: Option<u32>,
-    ) -> Result<GPR, String> {
+    /// Apply a bandpass filter with cosine-tapered corners in the frequency domain
+    ///
+    /// # Arguments
+    /// - `corners`: The low cut, low pass, high pass and high cut frequencies (MHz)
+    ///
+    /// # Errors
+    /// If the corners are negative, not in ascending order, or the pass band is empty.
+    pub fn fft_bandpass(&mut self, corners: [f32; 4]) -> Result<(), String> {
         let start_time = SystemTime::now();
-        let min_trace_ = min_trace.unwrap_or(0);
-        let max_trace_ = match max_trace {
-            Some(x) => x,
-            None => self.width() as u32,
-        };
-        let min_sample_ = min_sample.unwrap_or(0);
-        let max_sample_ = match max_sample {
-            Some(x) => x,
-            None => self.height() as u32,
-        };
 
-        let checks = [
-            (min_trace_, self.width(), "min trace number"),
-            (max_trace_, self.width(), "max trace number"),
-            (min_sample_, self.height(), "min sample number"),
-            (max_sample_, self.height(), "max sample number"),
-        ];
-
-        for (val, maxval, desc) in checks {
-            if val > maxval as u32 {
-                return Err(format!(
-                    "Subset failed: {desc} ({val}) out of the dataset bounds ({maxval})"
-                ));
-            }
+        if corners[0] < 0. || corners.windows(2).any(|w| w[0] > w[1]) || corners[1] >= corners[2] {
+            return Err(format!(
+                "The fft_bandpass corners need to be non-negative and in ascending order, with a pass band (provided: {corners:?} MHz)"
+            ));
         }
+        filters::fft::apply_frequency_response(&mut self.data, self.metadata.frequency, |f| {
+            filters::fft::tukey_bandpass_response(f, corners)
+        });
 
-        let data_subset = self
-            .data
-            .slice(ndarray::s![
-                min_sample_ as isize..max_sample_ as isize,
-                min_trace_ as isize..max_trace_ as isize
-            ])
-            .to_owned();
+        self.log_event(
+            "fft_bandpass",
+            &format!(
+                "Applied a tapered FFT bandpass filter ({}-{}-{}-{} MHz)",
+                corners[0], corners[1], corners[2], corners[3]
+            ),
+            start_time,
+        );
+        Ok(())
+    }
 
-        let location_subset = GPRLocation {
-            cor_points: self.location.cor_points[min_trace_ as usize..max_trace_ as usize]
-                .to_owned(),
-            correction: self.location.correction.clone(),
-            crs: self.location.crs.clone(),
+    /// Remove a frequency and optionally its harmonics with a notch filter in the frequency domain
+    ///
+    /// # Arguments
+    /// - `frequency`: The frequency to remove (MHz)
+    /// - `width`: The full width of the notch (MHz). If None, four times the spectral resolution
+    /// - `harmonics`: The number of multiples of the frequency to remove, including itself
+    ///
+    /// # Returns
+    /// The width of the notch that was used (MHz)
+    ///
+    /// # Errors
+    /// If the frequency or width is not positive, or harmonics is 0.
+    pub fn notch(
+        &mut self,
+        frequency: f32,
+        width: Option<f32>,
+        harmonics: usize,
+    ) -> Result<f32, String> {
+        let start_time = SystemTime::now();
+
+        let width = width.unwrap_or(4. * self.metadata.frequency / self.height() as f32);
+        if frequency <= 0. || width <= 0. || harmonics == 0 {
+            return Err(format!(
+                "The notch frequency ({frequency} MHz), width ({width} MHz) and harmonics ({harmonics}) need to be positive"
+            ));
+        }
+        filters::fft::apply_frequency_response(&mut self.data, self.metadata.frequency, |f| {
+            filters::fft::notch_response(f, frequency, width, harmonics)
+        });
+
+        let harmonics_text = match harmonics {
+            1 => String::new(),
+            n => format!(" and its harmonics up to {n}x"),
         };
+        self.log_event(
+            "notch",
+            &format!("Removed {frequency} MHz{harmonics_text} with a {width} MHz wide notch"),
+            start_time,
+        );
+        Ok(width)
+    }
 
-        let mut metadata = self.metadata.clone();
+    /// The mean amplitude spectrum of the traces
+    ///
+    /// # Returns
+    /// The frequencies (MHz) and the mean amplitude of the traces at each frequency
+    pub fn spectrum(&self) -> (Array1<f32>, Array1<f32>) {
+        filters::fft::mean_amplitude_spectrum(&self.data, self.metadata.frequency)
+    }
 
-        metadata.last_trace = max_trace_;
-        metadata.time_window *= (max_sample_ - min_sample_) as f32 / metadata.samples as f32;
-        metadata.samples = max_sample_ - min_sample_;
+    /// Reject or pass events by their apparent velocity with an f-k (frequency-wavenumber) filter
+    ///
+    /// The apparent velocity of an event is 2 / its slope in two-way time (ns/m), so that the limbs
+    /// of diffraction hyperbolae have the velocity of the medium.
+    ///
+    /// # Arguments
+    /// - `min_velocity`: The minimum apparent velocity of the zone (m/ns)
+    /// - `max_velocity`: The maximum apparent velocity of the zone (m/ns). May be infinite.
+    /// - `mode`: Whether to keep or remove the events in the zone
+    /// - `taper`: The width of the tapered edges as a fraction of their slope
+    ///
+    /// # Errors
+    /// If the velocities are not positive and ascending, or if the traces are not equidistant.
+    pub fn fk_velocity_filter(
+        &mut self,
+        min_velocity: f32,
+        max_velocity: f32,
+        mode: filters::fft::FkMode,
+        taper: f32,
+    ) -> Result<(), String> {
+        if min_velocity <= 0. || min_velocity >= max_velocity {
+            return Err(format!(
+                "The f-k velocities need to be positive and ascending (provided: {min_velocity}-{max_velocity} m/ns)"
+            ));
+        }
+        self.fk_filter(
+            "fk_velocity_filter",
+            [2. / max_velocity, 2. / min_velocity],
+            mode,
+            taper,
+            &format!("apparent velocities of {min_velocity}-{max_velocity} m/ns"),
+        )
+    }
 
-        let log = self.log.clone();
+    /// Reject or pass events by their dip in the medium with an f-k (frequency-wavenumber) filter
+    ///
+    /// A reflector with the dip θ has the slope 2 sin(θ) / v in two-way time, where v is the
+    /// velocity of the medium.
+    ///
+    /// # Arguments
+    /// - `min_dip`: The minimum dip of the zone (degrees from horizontal)
+    /// - `max_dip`: The maximum dip of the zone (degrees from horizontal)
+    /// - `mode`: Whether to keep or remove the events in the zone
+    /// - `taper`: The width of the tapered edges as a fraction of their slope
+    ///
+    /// # Errors
+    /// If the dips are not ascending between 0 and 90 degrees, or if the traces are not equidistant.
+    pub fn fk_dip_filter(
+        &mut self,
+        min_dip: f32,
+        max_dip: f32,
+        mode: filters::fft::FkMode,
+        taper: f32,
+    ) -> Result<(), String> {
+        if min_dip < 0. || max_dip > 90. || min_dip >= max_dip {
+            return Err(format!(
+                "The f-k dips need to be ascending between 0 and 90 degrees (provided: {min_dip}-{max_dip})"
+            ));
+        }
+        let slope = |dip: f32| 2. * dip.to_radians().sin() / self.metadata.medium_velocity;
+        self.fk_filter(
+            "fk_dip_filter",
+            [slope(min_dip), slope(max_dip)],
+            mode,
+            taper,
+            &format!("dips of {min_dip}-{max_dip} degrees"),
+        )
+    }
 
-        let mut new_gpr = GPR {
-            data: data_subset,
-            location: location_subset,
-            metadata,
-            log,
-            topo_data: self.topo_data.clone(),
-            horizontal_signal_distance: self.horizontal_signal_distance,
-            zero_point_ns: self.zero_point_ns,
+    /// Run an f-k filter on a zone of slopes (ns/m) in two-way time and log it
+    ///
+    /// # Arguments
+    /// - `step_name`: The name of the step to log
+    /// - `slopes`: The minimum and maximum slope of the zone (ns/m)
+    /// - `mode`: Whether to keep or remove the events in the zone
+    /// - `taper`: The width of the tapered edges as a fraction of their slope
+    /// - `zone_description`: A description of the zone for the log, e.g. "dips of 0-30 degrees"
+    ///
+    /// # Errors
+    /// If the taper is negative, or if `equidistant_traces` has not been run.
+    fn fk_filter(
+        &mut self,
+        step_name: &str,
+        slopes: [f32; 2],
+        mode: filters::fft::FkMode,
+        taper: f32,
+        zone_description: &str,
+    ) -> Result<(), String> {
+        let start_time = SystemTime::now();
+
+        if taper < 0. {
+            return Err(format!(
+                "The f-k taper needs to be non-negative (provided: {taper})"
+            ));
+        }
+        let trace_spacing = self.equidistant_trace_spacing(step_name)?;
+
+        filters::fft::fk_filter(
+            &mut self.data,
+            self.metadata.frequency,
+            trace_spacing,
+            slopes,
+            mode,
+            taper,
+        );
+
+        let action = match mode {
+            filters::fft::FkMode::Pass => "Passed",
+            filters::fft::FkMode::Reject => "Rejected",
         };
-        new_gpr.log_event(
-            "subset",
+        self.log_event(
+            step_name,
             &format!(
-                "Subset data from {:?} to ({}:{}, {}:{})",
-                self.data.shape(),
-                min_sample_,
-                max_sample_,
-                min_trace_,
-                max_trace_
+                "{action} events with {zone_description} ({}-{} ns/m) with an f-k filter and a trace spacing of {trace_spacing:.3} m",
+                slopes[0], slopes[1]
             ),
             start_time,
         );
-
-        Ok(new_gpr)
+        Ok(())
     }
 
-    pub fn vertical_resolution_ns(&self) -> f32 {
-        self.metadata.time_window / self.metadata.samples as f32
+    /// The distance between traces, for steps that require equidistant traces
+    ///
+    /// The wavenumbers of the f-k steps are only valid if the traces are spaced uniformly, as
+    /// after `equidistant_traces`. Every distance between neighbouring traces has to be within
+    /// 10% of the mean, which also catches traces that were removed or merged afterwards.
+    ///
+    /// # Arguments
+    /// - `step_name`: The name of the step, for the error message
+    ///
+    /// # Returns
+    /// The mean distance between traces (m)
+    ///
+    /// # Errors
+    /// If the traces are not spaced uniformly, or not spaced apart.
+    fn equidistant_trace_spacing(&self, step_name: &str) -> Result<f32, String> {
+        let trace_spacing = self.trace_spacing();
+        if trace_spacing <= 0. {
+            return Err(format!(
+                "{step_name} requires a positive trace spacing (found: {trace_spacing} m)"
+            ));
+        }
+        let irregular = self
+            .location
+            .cor_points
+            .windows(2)
+            .map(|pair| {
+                ((pair[1].easting - pair[0].easting).powi(2)
+                    + (pair[1].northing - pair[0].northing).powi(2))
+                .sqrt() as f32
+            })
+            .enumerate()
+            .find(|(_, spacing)| (spacing - trace_spacing).abs() > 0.1 * trace_spacing);
+        if let Some((i, spacing)) = irregular {
+            return Err(format!(
+                "{step_name} requires equidistant traces, but traces {i} and {} are {spacing} m apart (mean: {trace_spacing} m). Run equidistant_traces first.",
+                i + 1
+            ));
+        }
+        Ok(trace_spacing)
+    }
+
+    /// Migrate the data with Stolt (f-k) migration and a constant velocity
+    ///
+    /// # Arguments
+    /// - `velocity`: The velocity of the medium (m/ns). If None, the medium velocity is used
+    ///
+    /// # Returns
+    /// The velocity that was used (m/ns)
+    ///
+    /// # Errors
+    /// If the velocity is not positive, the traces are not equidistant, or if the topography has
+    /// been corrected.
+    pub fn stolt_migration(&mut self, velocity: Option<f32>) -> Result<f32, String> {
+        let start_time = SystemTime::now();
+        let velocity = velocity.unwrap_or(self.metadata.medium_velocity);
+
+        if velocity <= 0. {
+            return Err(format!(
+                "The migration velocity needs to be positive (provided: {velocity} m/ns)"
+            ));
+        }
+        if self.topo_data.is_some() {
+            return Err(
+                "stolt_migration has to be run before correct_topography, since it assumes a flat surface".to_string(),
+            );
+        }
+        let trace_spacing = self.equidistant_trace_spacing("stolt_migration")?;
+
+        filters::migration::stolt(
+            &mut self.data,
+            self.metadata.frequency,
+            trace_spacing,
+            velocity,
+        );
+
+        self.log_event(
+            "stolt_migration",
+            &format!("Ran Stolt (f-k) migration with a velocity of {velocity} m/ns"),
+            start_time,
+        );
+        Ok(velocity)
+    }
+
+    /// Migrate the data with Gazdag phase-shift migration and a layered velocity model
+    ///
+    /// # Arguments
+    /// - `layers`: The top depth (m) and velocity (m/ns) of each layer, sorted by depth. The first
+    ///   layer has to start at 0 m and the velocities have to be positive. If empty, the layers of the velocity model
+    ///   (or the medium velocity) are used.
+    ///
+    /// # Returns
+    /// The layers that were used
+    ///
+    /// # Errors
+    /// If the layers are invalid, the traces are not equidistant, or if the topography has been
+    /// corrected.
+    pub fn gazdag_migration(&mut self, layers: &[(f32, f32)]) -> Result<Vec<(f32, f32)>, String> {
+        let start_time = SystemTime::now();
+        let layers = match (layers.is_empty(), &self.metadata.velocity_model) {
+            (false, _) => VelocityModel::from_depth_layers(layers)
+                .map_err(|e| format!("Invalid migration layers: {e}"))?
+                .layers()
+                .to_vec(),
+            (true, Some(model)) => model.layers().to_vec(),
+            (true, None) => vec![(0., self.metadata.medium_velocity)],
+        };
+        if layers.iter().any(|(_, velocity)| *velocity <= 0.) {
+            return Err("The migration velocities need to be positive".to_string());
+        }
+        if self.topo_data.is_some() {
+            return Err(
+                "gazdag_migration has to be run before correct_topography, since it assumes a flat surface".to_string(),
+            );
+        }
+        let trace_spacing = self.equidistant_trace_spacing("gazdag_migration")?;
+
+        // The velocity of each sample, found by stepping downward from the surface
+        let sample_interval = 1e3 / self.metadata.frequency;
+        let mut depth = 0_f32;
+        let velocities = (0..self.height())
+            .map(|_| {
+                let velocity = layers
+                    .iter()
+                    .rev()
+                    .find(|(top, _)| *top <= depth)
+                    .map(|(_, velocity)| *velocity)
+                    .unwrap_or(layers[0].1);
+                depth += velocity * sample_interval / 2.;
+                velocity
+            })
+            .collect::<Vec<f32>>();
+
+        filters::migration::phase_shift(
+            &mut self.data,
+            self.metadata.frequency,
+            trace_spacing,
+            &velocities,
+        );
+
+        let layer_text = layers
+            .iter()
+            .map(|(top, velocity)| format!("{velocity} m/ns from {top} m"))
+            .collect::<Vec<String>>()
+            .join(", ");
+        self.log_event(
+            "gazdag_migration",
+            &format!("Ran Gazdag phase-shift migration with velocities of {layer_text}"),
+            start_time,
+        );
+        Ok(layers)
+    }
+
+    /// The mean horizontal distance between consecutive traces (m)
+    fn trace_spacing(&self) -> f32 {
+        let points = &self.location.cor_points;
+        if points.len() < 2 {
+            return 0.;
+        }
+        let length = points
+            .windows(2)
+            .map(|pair| {
+                ((pair[1].easting - pair[0].easting).powi(2)
+                    + (pair[1].northing - pair[0].northing).powi(2))
+                .sqrt()
+            })
+            .sum::<f64>();
+        (length / (points.len() - 1) as f64) as f32
+    }
+
+    /// Pick a horizon by tracking a reflection from seed picks
+    ///
+    /// See [`horizons::track`] for how the reflection is tracked. A horizon with the same name is
+    /// replaced.
+    ///
+    /// # Arguments
+    /// - `name`: The name of the horizon
+    /// - `seeds`: The trace index and return time (ns) of each seed
+    /// - `phase`: The phase of the reflection to follow
+    /// - `window_ns`: The height of the search window (ns). If None, one period of the antenna
+    ///   center frequency
+    /// - `max_gap`: The number of consecutive traces without a pick before the tracking stops
+    /// - `threshold`: The minimum strength of a pick relative to its seed (0-1)
+    ///
+    /// # Returns
+    /// The window that was used (ns) and the number of picked traces
+    ///
+    /// # Errors
+    /// - No seeds were given, the window is not positive, or the threshold is not between 0 and 1
+    /// - A seed is outside of the data, or the phase was not found close to it
+    pub fn pick_horizon(
+        &mut self,
+        name: &str,
+        seeds: &[(usize, f32)],
+        phase: Phase,
+        window_ns: Option<f32>,
+        max_gap: usize,
+        threshold: f32,
+    ) -> Result<(f32, usize), String> {
+        let start_time = SystemTime::now();
+        if seeds.is_empty() {
+            return Err("At least one seed is needed to pick a horizon".to_string());
+        }
+        if !(0. ..=1.).contains(&threshold) {
+            return Err(format!(
+                "The pick threshold needs to be between 0 and 1, not {threshold}"
+            ));
+        }
+        let window_ns = window_ns.unwrap_or(1000. / self.metadata.antenna_mhz);
+        if !window_ns.is_finite() || window_ns <= 0. {
+            return Err(format!(
+                "The pick window needs to be a positive number of ns, not {window_ns}"
+            ));
+        }
+        let resolution = self.vertical_resolution_ns();
+        let half_window = ((window_ns / 2. / resolution).round() as usize).max(1);
+
+        let sample_seeds = seeds
+            .iter()
+            .map(|(trace, time)| (*trace, time / resolution))
+            .collect::<Vec<(usize, f32)>>();
+        let picks = horizons::track(
+            &self.data,
+            &sample_seeds,
+            phase,
+            half_window,
+            max_gap,
+            threshold,
+        )?
+        .into_iter()
+        .map(|pick| pick.map(|(sample, quality)| (sample * resolution, quality)))
+        .collect::<Vec<Option<(f32, f32)>>>();
+
+        let horizon = Horizon::new(name, &picks);
+        let n_picked = horizon.n_picked();
+        self.horizons.retain(|h| h.name != name);
+        self.horizons.push(horizon);
+
+        self.log_event(
+            "pick_horizon",
+            &format!(
+                "Picked the {phase} phase of horizon '{name}' in {n_picked} of {} traces from {} seed(s), with a window of {window_ns} ns",
+                self.width(),
+                seeds.len()
+            ),
+            start_time,
+        );
+        Ok((window_ns, n_picked))
+    }
+
+    /// Detect the bed as the strongest continuous reflector below a minimum depth
+    ///
+    /// The reflector is found in the energy of the trace envelopes, see
+    /// [`horizons::detect_continuous`]. With a raster of the expected ice thickness, only depths
+    /// within a tolerance of it are searched, except where the raster has no positive value. The
+    /// bed is saved as the horizon "bed", which the ice thickness and bed elevation are derived
+    /// from (see [`GPR::bed_picks`]).
+    ///
+    /// # Arguments
+    /// - `min_depth`: The shallowest depth to search (m)
+    /// - `max_jump_ns`: The largest change in return time between neighbouring traces (ns). If
+    ///   None, one period of the antenna center frequency
+    /// - `prior`: A raster of the expected ice thickness (m)
+    /// - `prior_tolerance`: How far from the expected ice thickness to search (m)
+    ///
+    /// # Returns
+    /// The maximum jump that was used (ns) and the number of picked traces
+    ///
+    /// # Errors
+    /// - The minimum depth is below the deepest sample
+    /// - The prior raster could not be sampled
+    pub fn detect_bed(
+        &mut self,
+        min_depth: f32,
+        max_jump_ns: Option<f32>,
+        prior: Option<&Path>,
+        prior_tolerance: f32,
+    ) -> Result<(f32, usize), String> {
+        let start_time = SystemTime::now();
+        let depths = self.depths();
+        let height = self.height();
+        let first_sample_below =
+            |depth: f32| depths.iter().position(|d| *d >= depth).unwrap_or(height);
+        let min_sample = first_sample_below(min_depth);
+        if min_sample >= height {
+            return Err(format!(
+                "The minimum bed depth ({min_depth} m) is below the deepest sample ({} m)",
+                depths.last().unwrap_or(&0.)
+            ));
+        }
+
+        let expected = match prior {
+            Some(path) => Some(self.location.sample_raster(path)?),
+            None => None,
+        };
+        let windows = (0..self.width())
+            .map(|trace| {
+                match expected
+                    .as_ref()
+                    .and_then(|thicknesses| thicknesses.get(trace))
+                    .filter(|thickness| thickness.is_finite() && **thickness > 0.)
+                {
+                    Some(thickness) => {
+                        let start = first_sample_below(thickness - prior_tolerance).max(min_sample);
+                        start..first_sample_below(thickness + prior_tolerance).max(start)
+                    }
+                    None => min_sample..height,
+                }
+            })
+            .collect::<Vec<std::ops::Range<usize>>>();
+
+        let max_jump_ns = max_jump_ns.unwrap_or(1000. / self.metadata.antenna_mhz);
+        let resolution = self.vertical_resolution_ns();
+        let max_jump = ((max_jump_ns / resolution).round() as usize).max(1);
+        let energy = filters::hilbert::envelope(&self.data).mapv(|value| value.powi(2));
+        let picks = horizons::detect_continuous(&energy, &windows, max_jump)
+            .into_iter()
+            .map(|pick| pick.map(|(sample, quality)| (sample * resolution, quality)))
+            .collect::<Vec<Option<(f32, f32)>>>();
+
+        let horizon = Horizon::new(horizons::BED_HORIZON, &picks);
+        let n_picked = horizon.n_picked();
+        self.horizons.retain(|h| h.name != horizons::BED_HORIZON);
+        self.horizons.push(horizon);
+
+        self.log_event(
+            "detect_bed",
+            &format!(
+                "Detected the bed in {n_picked} of {} traces below {min_depth} m, with a maximum jump of {max_jump_ns} ns between traces{}",
+                self.width(),
+                match prior {
+                    Some(path) => format!(" and a prior ice thickness from {path:?} (±{prior_tolerance} m)"),
+                    None => String::new(),
+                }
+            ),
+            start_time,
+        );
+        Ok((max_jump_ns, n_picked))
+    }
+
+    /// The ice thickness, bed elevation and quality of each trace, from the "bed" horizon
+    ///
+    /// # Returns
+    /// The bed of each trace (None where it was not picked), or None if there is no bed horizon
+    pub fn bed_picks(&self) -> Option<Vec<Option<BedPick>>> {
+        let bed = self
+            .horizons
+            .iter()
+            .find(|h| h.name == horizons::BED_HORIZON)?;
+        Some(
+            bed.picks
+                .iter()
+                .zip(&bed.quality)
+                .zip(&self.location.cor_points)
+                .map(|((pick, quality), point)| {
+                    let (time, quality) = pick.zip(*quality)?;
+                    let ice_thickness = self.depth_at(time);
+                    Some(BedPick {
+                        ice_thickness,
+                        bed_elevation: point.altitude - ice_thickness as f64,
+                        quality,
+                    })
+                })
+                .collect(),
+        )
+    }
+
+    /// Fit diffraction hyperbolae to estimate the velocity of the medium
+    ///
+    /// See [`DiffractionSearch::fit`] for how a hyperbola is fitted to the envelope of the data.
+    /// Without apexes, diffractions are found automatically with [`DiffractionSearch::scan`]. The
+    /// fitted diffractions replace any previous ones.
+    ///
+    /// # Arguments
+    /// - `apexes`: The approximate trace index and return time (ns) of each apex. If empty, the
+    ///   diffractions are found automatically.
+    /// - `aperture_m`: The largest horizontal distance from an apex to fit (m). If None, the depth
+    ///   of the apex at the medium velocity, but at least five traces.
+    /// - `max_count`: The maximum number of diffractions to find automatically
+    ///
+    /// # Returns
+    /// The number of fitted diffractions
+    ///
+    /// # Errors
+    /// - The aperture is not positive
+    /// - An apex is outside of the data, or no hyperbola could be fitted to it
+    pub fn fit_hyperbolae(
+        &mut self,
+        apexes: &[(usize, f32)],
+        aperture_m: Option<f32>,
+        max_count: usize,
+    ) -> Result<usize, String> {
+        let start_time = SystemTime::now();
+        if let Some(aperture_m) = aperture_m.filter(|a| !a.is_finite() || *a <= 0.) {
+            return Err(format!(
+                "The hyperbola aperture needs to be a positive number of m, not {aperture_m}"
+            ));
+        }
+        let envelope = filters::hilbert::envelope(&self.data);
+        let distances = self.location.distances().mapv(|d| d as f32).to_vec();
+        let search = DiffractionSearch {
+            envelope: &envelope,
+            distances: &distances,
+            sample_interval: self.vertical_resolution_ns(),
+            antenna_separation: self.horizontal_signal_distance,
+            expected_velocity: self.metadata.medium_velocity,
+            window: 1000. / self.metadata.antenna_mhz,
+        };
+
+        self.diffractions = match apexes.is_empty() {
+            true => search.scan(aperture_m, max_count),
+            false => apexes
+                .iter()
+                .map(|(trace, time)| search.fit(*trace, *time, aperture_m))
+                .collect::<Result<Vec<Diffraction>, String>>()?,
+        };
+
+        let fits = self
+            .diffractions
+            .iter()
+            .map(|d| {
+                format!(
+                    "{:.4} ± {:.4} m/ns at trace {} ({} ns, {:.2} m)",
+                    d.velocity,
+                    d.uncertainty,
+                    d.trace,
+                    d.return_time,
+                    d.depth(self.horizontal_signal_distance)
+                )
+            })
+            .collect::<Vec<String>>();
+        self.log_event(
+            "fit_hyperbolae",
+            &format!(
+                "Fitted {} diffraction hyperbola(e){}{}",
+                fits.len(),
+                match apexes.is_empty() {
+                    true => " found automatically".to_string(),
+                    false => format!(" from {} apex(es)", apexes.len()),
+                },
+                match fits.is_empty() {
+                    true => String::new(),
+                    false => format!(": {}", fits.join(", ")),
+                }
+            ),
+            start_time,
+        );
+        Ok(self.diffractions.len())
+    }
+
+    pub fn subset(
+        &self,
+        min_trace: Option<u32>,
+        max_trace: Option<u32>,
+        min_sample: Option<u32>,
+        max_sample: Option<u32>,
+    ) -> Result<GPR, String> {
+        let start_time = SystemTime::now();
+        let min_trace_ = min_trace.unwrap_or(0);
+        let max_trace_ = match max_trace {
+            Some(x) => x,
+            None => self.width() as u32,
+        };
+        let min_sample_ = min_sample.unwrap_or(0);
+        let max_sample_ = match max_sample {
+            Some(x) => x,
+            None => self.height() as u32,
+        };
+
+        let checks = [
+            (min_trace_, self.width(), "min trace number"),
+            (max_trace_, self.width(), "max trace number"),
+            (min_sample_, self.height(), "min sample number"),
+            (max_sample_, self.height(), "max sample number"),
+        ];
+
+        for (val, maxval, desc) in checks {
+            if val > maxval as u32 {
+                return Err(format!(
+                    "Subset failed: {desc} ({val}) out of the dataset bounds ({maxval})"
+                ));
+            }
+        }
+
+        let data_subset = self
+            .data
+            .slice(ndarray::s![
+                min_sample_ as isize..max_sample_ as isize,
+                min_trace_ as isize..max_trace_ as isize
+            ])
+            .to_owned();
+
+        let location_subset = GPRLocation {
+            cor_points: self.location.cor_points[min_trace_ as usize..max_trace_ as usize]
+                .to_owned(),
+            correction: self.location.correction.clone(),
+            crs: self.location.crs.clone(),
+        };
+
+        let mut metadata = self.metadata.clone();
+
+        metadata.last_trace = max_trace_;
+        metadata.time_window *= (max_sample_ - min_sample_) as f32 / metadata.samples as f32;
+        metadata.samples = max_sample_ - min_sample_;
+
+        let log = self.log.clone();
+        let provenance = self.provenance.clone();
+
+        let markers = self
+            .markers
+            .iter()
+            .filter(|m| (min_trace_ as usize..max_trace_ as usize).contains(&m.trace))
+            .map(|m| Marker {
+                trace: m.trace - min_trace_ as usize,
+                comment: m.comment.clone(),
+            })
+            .collect();
+
+        // The picks are shifted to the new first sample, and picks outside the samples are removed
+        let time_offset = min_sample_ as f32 * self.vertical_resolution_ns();
+        let max_time = (max_sample_ - min_sample_) as f32 * self.vertical_resolution_ns();
+        let horizons = self
+            .horizons
+            .iter()
+            .map(|horizon| {
+                let traces = min_trace_ as usize..max_trace_ as usize;
+                let picks = horizon.picks[traces.clone()]
+                    .iter()
+                    .zip(&horizon.quality[traces])
+                    .map(|(pick, quality)| {
+                        pick.map(|time| time - time_offset)
+                            .filter(|time| (0. ..max_time).contains(time))
+                            .zip(*quality)
+                    })
+                    .collect::<Vec<Option<(f32, f32)>>>();
+                Horizon::new(&horizon.name, &picks)
+            })
+            .collect();
+        let diffractions = self
+            .diffractions
+            .iter()
+            .filter(|d| {
+                (min_trace_ as usize..max_trace_ as usize).contains(&d.trace)
+                    && (time_offset..time_offset + max_time).contains(&d.return_time)
+            })
+            .map(|d| Diffraction {
+                trace: d.trace - min_trace_ as usize,
+                return_time: d.return_time - time_offset,
+                ..*d
+            })
+            .collect();
+
+        let mut new_gpr = GPR {
+            data: data_subset,
+            location: location_subset,
+            metadata,
+            log,
+            provenance,
+            markers,
+            topo_data: self.topo_data.clone(),
+            horizontal_signal_distance: self.horizontal_signal_distance,
+            zero_point_ns: self.zero_point_ns,
+            agc_gain: self.agc_gain.as_ref().map(|gain| {
+                gain.slice(ndarray::s![
+                    min_sample_ as isize..max_sample_ as isize,
+                    min_trace_ as isize..max_trace_ as isize
+                ])
+                .to_owned()
+            }),
+            horizons,
+            diffractions,
+        };
+        new_gpr.log_event(
+            "subset",
+            &format!(
+                "Subset data from {:?} to ({}:{}, {}:{})",
+                self.data.shape(),
+                min_sample_,
+                max_sample_,
+                min_trace_,
+                max_trace_
+            ),
+            start_time,
+        );
+
+        Ok(new_gpr)
+    }
+
+    pub fn vertical_resolution_ns(&self) -> f32 {
+        self.metadata.time_window / self.metadata.samples as f32
     }
 
     pub fn from_meta_and_loc(
@@ -712,11 +1682,22 @@ impl GPR {
                 &metadata.data_filepath,
                 metadata.samples as usize,
             )?),
+            Some("rd7") => Ok(io::load_rd7(
+                &metadata.data_filepath,
+                metadata.samples as usize,
+            )?),
             Some("dt1") => Ok(io::load_pe_dt1(
                 &metadata.data_filepath,
                 metadata.samples as usize,
                 metadata.last_trace as usize,
             )?),
+            Some(ext) if ["sgy", "segy"].contains(&ext.to_lowercase().as_str()) => {
+                Ok(io::load_segy_data(&metadata.data_filepath)?)
+            }
+            Some(ext) if ext.eq_ignore_ascii_case("dzt") => Ok(io::load_dzt_data(
+                &metadata.data_filepath,
+                metadata.channel,
+            )?),
             _ => Err(format!("Unknown filetype: {:?}", metadata.data_filepath)),
         }?;
 
@@ -726,14 +1707,27 @@ impl GPR {
         };
         let horizontal_signal_distance = metadata.antenna_separation;
 
+        // Malå markers are saved in a separate ".mrk" file
+        let mrk_filepath = metadata.data_filepath.with_extension("mrk");
+        let mut markers = match mrk_filepath.is_file() {
+            true => io::load_mrk(&mrk_filepath)?,
+            false => Vec::new(),
+        };
+        markers.retain(|m| m.trace < data.shape()[1]);
+
         Ok(GPR {
             data,
             location: location_data,
             metadata,
             log: Vec::new(),
+            provenance: Provenance::default(),
+            markers,
             topo_data: None,
             horizontal_signal_distance,
             zero_point_ns: 0.,
+            agc_gain: None,
+            horizons: Vec::new(),
+            diffractions: Vec::new(),
         })
     }
 
@@ -800,7 +1794,9 @@ impl GPR {
 
         self.zero_point_ns = self.metadata.time_window * (positive_peaks.mean().unwrap() as f32)
             / self.height() as f32;
+        let removed_ns = positive_peaks.mapv(|peak| peak as f32 * self.vertical_resolution_ns());
         self.update_data(new_data);
+        self.shift_picks(removed_ns.as_slice().unwrap());
         self.log_event(
             "zero_corr_max_peak",
             &format!(
@@ -812,6 +1808,34 @@ impl GPR {
         );
     }
 
+    /// Move the horizon picks and diffractions after samples were removed from the top of the traces
+    ///
+    /// As in `subset`, picks that end up outside the samples are removed.
+    ///
+    /// # Arguments
+    /// - `removed_ns`: The time that was removed from the top of each trace (ns)
+    fn shift_picks(&mut self, removed_ns: &[f32]) {
+        let max_time = self.metadata.time_window;
+        for horizon in self.horizons.iter_mut() {
+            let picks = horizon
+                .picks
+                .iter()
+                .zip(&horizon.quality)
+                .zip(removed_ns)
+                .map(|((pick, quality), removed)| {
+                    pick.map(|time| time - removed)
+                        .filter(|time| (0. ..max_time).contains(time))
+                        .zip(*quality)
+                })
+                .collect::<Vec<Option<(f32, f32)>>>();
+            *horizon = Horizon::new(&horizon.name, &picks);
+        }
+        self.diffractions.retain_mut(|d| {
+            d.return_time -= removed_ns[d.trace];
+            (0. ..max_time).contains(&d.return_time)
+        });
+    }
+
     fn update_data(&mut self, data: Array2<f32>) {
         self.data = data;
 
@@ -871,20 +1895,56 @@ impl GPR {
         );
     }
 
-    pub fn vertical_resolution_m(&self) -> f32 {
-        let depths = self.depths();
+    /// Replace the data with their envelope (instantaneous amplitude)
+    pub fn envelope(&mut self) {
+        let start_time = SystemTime::now();
 
-        let mut diffs = Array1::<f32>::zeros((depths.shape()[0] - 1,));
-        for i in 1..depths.shape()[0] {
-            diffs[i - 1] = depths[i] - depths[i - 1];
-        }
-        tools::quantiles(&diffs, &[0.8], None)[0]
+        self.data = filters::hilbert::envelope(&self.data);
+        self.log_event(
+            "envelope",
+            "Replaced the data with their envelope (instantaneous amplitude)",
+            start_time,
+        );
     }
 
-    pub fn correct_topography(&mut self) {
+    /// Replace the data with their instantaneous phase (radians)
+    pub fn instantaneous_phase(&mut self) {
         let start_time = SystemTime::now();
 
-        let mut altitudes = Array1::<f32>::from_iter(
+        self.data = filters::hilbert::instantaneous_phase(&self.data);
+        self.log_event(
+            "instantaneous_phase",
+            "Replaced the data with their instantaneous phase (radians)",
+            start_time,
+        );
+    }
+
+    /// Replace the data with their instantaneous frequency (MHz)
+    pub fn instantaneous_frequency(&mut self) {
+        let start_time = SystemTime::now();
+
+        self.data = filters::hilbert::instantaneous_frequency(&self.data, self.metadata.frequency);
+        self.log_event(
+            "instantaneous_frequency",
+            "Replaced the data with their instantaneous frequency (MHz)",
+            start_time,
+        );
+    }
+
+    pub fn vertical_resolution_m(&self) -> f32 {
+        let depths = self.depths();
+
+        let mut diffs = Array1::<f32>::zeros((depths.shape()[0] - 1,));
+        for i in 1..depths.shape()[0] {
+            diffs[i - 1] = depths[i] - depths[i - 1];
+        }
+        tools::quantiles(&diffs, &[0.8], None)[0]
+    }
+
+    pub fn correct_topography(&mut self) {
+        let start_time = SystemTime::now();
+
+        let mut altitudes = Array1::<f32>::from_iter(
             self.location
                 .cor_points
                 .iter()
@@ -895,10 +1955,19 @@ impl GPR {
 
         let max_depth = tools::return_time_to_depth(
             self.metadata.time_window,
-            self.metadata.medium_velocity,
+            self.metadata.average_velocity(self.metadata.time_window),
             self.metadata.antenna_separation,
         );
 
+        // With a velocity model, the samples are not evenly spaced in depth, so the traces are
+        // first resampled to even depth intervals
+        let resampled = self
+            .metadata
+            .velocity_model
+            .as_ref()
+            .map(|_| self.resample_to_depth(max_depth));
+        let data = resampled.as_ref().unwrap_or(&self.data);
+
         let sample_per_meter = self.height() as f32 / max_depth;
 
         let start_indices = altitudes.mapv(|altitude| (altitude * sample_per_meter) as isize);
@@ -908,7 +1977,7 @@ impl GPR {
             self.width(),
         ));
 
-        for (i, col) in self.data.columns().into_iter().enumerate() {
+        for (i, col) in data.columns().into_iter().enumerate() {
             topo_data
                 .column_mut(i)
                 .slice_axis_mut(
@@ -926,11 +1995,54 @@ impl GPR {
 
         self.log_event(
             "correct_topography",
-            "Generated a profile that is corrected for topography (topo_data).",
+            &match &self.metadata.velocity_model {
+                Some(model) => format!("Generated a profile that is corrected for topography (topo_data), using a velocity model of {model}."),
+                None => "Generated a profile that is corrected for topography (topo_data).".to_string(),
+            },
             start_time,
         );
     }
 
+    /// Resample the traces to even depth intervals from the surface to the maximum depth
+    ///
+    /// # Arguments
+    /// - `max_depth`: The depth of the last sample (m)
+    ///
+    /// # Returns
+    /// The resampled data, with the same shape as the data
+    fn resample_to_depth(&self, max_depth: f32) -> Array2<f32> {
+        let height = self.height();
+        let depths = self.depths().into_raw_vec();
+
+        // The fractional sample index of each even depth
+        let positions = (0..height)
+            .map(|row| {
+                let depth = row as f32 * max_depth / height as f32;
+                let upper = depths.partition_point(|d| *d < depth);
+                match upper {
+                    0 => 0.,
+                    _ if upper >= height => (height - 1) as f32,
+                    _ => {
+                        let interval = depths[upper] - depths[upper - 1];
+                        (upper - 1) as f32
+                            + (depth - depths[upper - 1]) / interval.max(f32::EPSILON)
+                    }
+                }
+            })
+            .collect::<Vec<f32>>();
+
+        let mut resampled = Array2::<f32>::zeros(self.data.dim());
+        for (mut resampled_col, col) in resampled.columns_mut().into_iter().zip(self.data.columns())
+        {
+            for (value, position) in resampled_col.iter_mut().zip(&positions) {
+                let lower = position.floor() as usize;
+                let weight = position - lower as f32;
+                *value = col[lower] * (1. - weight) + col[(lower + 1).min(height - 1)] * weight;
+            }
+        }
+        resampled
+    }
+
     pub fn unphase(&mut self) {
         let start_time = SystemTime::now();
 
@@ -986,34 +2098,132 @@ impl GPR {
             .unwrap();
 
         self.zero_point_ns = self.metadata.time_window * (first_rise as f32) / self.height() as f32;
+        let removed_ns = vec![first_rise as f32 * self.vertical_resolution_ns(); self.width()];
         self.update_data(
             self.data
                 .slice_axis(Axis(0), Slice::new(first_rise, None, 1))
                 .to_owned(),
         );
         self.data -= mean_silent_val;
+        self.shift_picks(&removed_ns);
 
         self.log_event("zero_corr", &format!("Applied a global zero-corr by removing the first {} rows (threshold multiplier: {:?})", first_rise, threshold_multiplier), start_time);
     }
 
-    pub fn dewow(&mut self, window: u32) {
+    /// Subtract a centered running mean or median from every trace (dewow)
+    ///
+    /// # Arguments
+    /// - `window_ns`: The length of the window in ns. If None, it is derived from the antenna period
+    /// - `periods`: The number of antenna periods in the window, if `window_ns` is None
+    /// - `statistic`: Whether the running mean or the running median is subtracted
+    ///
+    /// # Returns
+    /// The length of the window that was used (ns)
+    ///
+    /// # Errors
+    /// - `window_ns` is None and the antenna frequency is unknown
+    /// - The window is shorter than two samples
+    pub fn dewow(
+        &mut self,
+        window_ns: Option<f32>,
+        periods: f32,
+        statistic: filters::Statistic,
+    ) -> Result<f32, String> {
         let start_time = SystemTime::now();
 
-        let height = self.height() as u32;
+        let window_ns = match window_ns {
+            Some(w) => w,
+            None if self.metadata.antenna_mhz > 0. => periods * 1000. / self.metadata.antenna_mhz,
+            None => {
+                return Err(format!(
+                    "The dewow window cannot be derived from an antenna frequency of {} MHz. Set it with window_ns",
+                    self.metadata.antenna_mhz
+                ))
+            }
+        };
+        let window = (window_ns / self.vertical_resolution_ns()).round() as usize;
+        if !window_ns.is_finite() || window < 2 {
+            return Err(format!(
+                "The dewow window of {window_ns} ns is shorter than two samples ({} ns)",
+                2. * self.vertical_resolution_ns()
+            ));
+        }
+        // The window is centered, so it needs an odd number of samples
+        let window = window | 1;
 
-        for i in (0..(height - window)).step_by(window as usize) {
-            let mut view = self.data.slice_axis_mut(
-                Axis(0),
-                ndarray::Slice::new(i as isize, Some((i + window) as isize), 1_isize),
-            );
+        filters::dewow(&mut self.data, statistic, window);
 
-            view -= view.mean().unwrap();
-        }
         self.log_event(
             "dewow",
-            &format!("Ran dewow with a window size of {}", window),
+            &format!(
+                "Subtracted the running {statistic} of a {window_ns} ns ({window} samples) window"
+            ),
+            start_time,
+        );
+        Ok(window_ns)
+    }
+
+    /// Subtract the mean or median trace (the background) from every trace
+    ///
+    /// # Arguments
+    /// - `statistic`: Whether the background is the mean or the median of the traces
+    /// - `window`: The number of traces in a sliding window around each trace. 0 means all traces
+    /// - `min_sample`: The first sample where the background is removed
+    /// - `max_sample`: The sample after the last where the background is removed. None means the end
+    ///
+    /// # Errors
+    /// - The topography has already been corrected. The background is not horizontal in
+    ///   `topo_data`, so the two would no longer be consistent.
+    /// - The window is 1, which would remove all data
+    /// - The sample range is empty
+    pub fn background_removal(
+        &mut self,
+        statistic: filters::Statistic,
+        window: usize,
+        min_sample: u32,
+        max_sample: Option<u32>,
+    ) -> Result<(), String> {
+        let start_time = SystemTime::now();
+        if self.topo_data.is_some() {
+            return Err(
+                "background_removal has to be run before correct_topography, since the background is not horizontal in the topographically corrected data".to_string(),
+            );
+        }
+        if window == 1 {
+            return Err(
+                "The background_removal window has to be 0 (all traces) or larger than 1"
+                    .to_string(),
+            );
+        }
+        let max_sample = max_sample
+            .map(|m| m as usize)
+            .unwrap_or(self.height())
+            .min(self.height());
+        if min_sample as usize >= max_sample {
+            return Err(format!(
+                "Empty background_removal sample range: {min_sample} to {max_sample}"
+            ));
+        }
+
+        filters::background_removal(
+            &mut self.data,
+            statistic,
+            window,
+            min_sample as usize..max_sample,
+        );
+
+        let traces = match window {
+            0 => "all traces".to_string(),
+            w => format!("a sliding window of {w} traces"),
+        };
+        self.log_event(
+            "background_removal",
+            &format!(
+                "Subtracted the {statistic} trace of {traces} from samples {min_sample} to {max_sample}"
+            ),
             start_time,
         );
+        Ok(())
     }
 
     pub fn normalize_horizontal_magnitudes(&mut self, skip_first: Option<isize>) {
@@ -1035,7 +2245,14 @@ impl GPR {
         );
     }
 
-    pub fn auto_gain(&mut self, n_bins: usize) {
+    /// Measure the attenuation of the signal with depth and apply a gain to correct for it
+    ///
+    /// # Arguments
+    /// - `n_bins`: The number of vertical bins to measure the attenuation in
+    ///
+    /// # Returns
+    /// The gain factor that was applied (dB / ns)
+    pub fn auto_gain(&mut self, n_bins: usize) -> f32 {
         let start_time = SystemTime::now();
 
         let step = ((self.height() / n_bins) as isize).max(1);
@@ -1067,6 +2284,7 @@ impl GPR {
             start_time,
         );
         self.gain(slope);
+        slope
     }
 
     pub fn gain(&mut self, factor: f32) {
@@ -1087,29 +2305,209 @@ impl GPR {
         );
     }
 
-    pub fn make_equidistant(&mut self, step: Option<f32>) {
+    /// The two-way travel time of each sample (ns), counted from the first sample
+    ///
+    /// The first sample is given the time of the second, so that time-dependent gains do not
+    /// zero it out.
+    fn sample_times_ns(&self) -> Array1<f32> {
+        let ns_per_sample = self.vertical_resolution_ns();
+        Array1::range(0., self.height() as f32, 1.).mapv(|i| i.max(1.) * ns_per_sample)
+    }
+
+    /// Multiply every trace by the same gain curve
+    ///
+    /// # Arguments
+    /// - `gain`: The gain of each sample
+    fn apply_sample_gain(&mut self, gain: &Array1<f32>) {
+        self.data *= &gain.view().insert_axis(Axis(1));
+    }
+
+    /// Apply spreading and exponential compensation (SEC) gain
+    ///
+    /// Each sample is multiplied by its two-way travel time `t` (ns) and by `10 ^(a * v * t / 20)`,
+    /// where `a` is the attenuation and `v` is the medium velocity.
+    ///
+    /// # Arguments
+    /// - `attenuation`: The attenuation along the travel path (dB / m)
+    pub fn sec_gain(&mut self, attenuation: f32) {
+        let start_time = SystemTime::now();
+
+        let velocity = self.metadata.medium_velocity;
+        let gain = self
+            .sample_times_ns()
+            .mapv(|t| t * 10_f32.powf(attenuation * velocity * t / 20.));
+        self.apply_sample_gain(&gain);
+
+        self.log_event(
+            "sec_gain",
+            &format!(
+                "Applied SEC gain with an attenuation of {attenuation} dB / m (velocity: {velocity} m / ns)"
+            ),
+            start_time,
+        );
+    }
+
+    /// Multiply each sample by its two-way travel time (ns) to the power of `exponent`
+    ///
+    /// # Arguments
+    /// - `exponent`: The exponent of the travel time
+    pub fn power_gain(&mut self, exponent: f32) {
+        let start_time = SystemTime::now();
+
+        let gain = self.sample_times_ns().mapv(|t| t.powf(exponent));
+        self.apply_sample_gain(&gain);
+
+        self.log_event(
+            "power_gain",
+            &format!("Applied a t ^{exponent} gain (ns)"),
+            start_time,
+        );
+    }
+
+    /// Compensate geometric spreading by multiplying each sample by its depth to the power of `exponent`
+    ///
+    /// Samples above the first sample with a positive depth (in the direct wave) are given the
+    /// gain of that sample.
+    ///
+    /// # Arguments
+    /// - `exponent`: The exponent of the depth. 1 is for spherical and 0.5 for cylindrical spreading.
+    pub fn spherical_divergence(&mut self, exponent: f32) {
+        let start_time = SystemTime::now();
+
+        let depths = self.depths();
+        let min_depth = depths.iter().copied().find(|d| *d > 0.).unwrap_or(1.);
+        let gain = depths.mapv(|d| d.max(min_depth).powf(exponent));
+        self.apply_sample_gain(&gain);
+
+        self.log_event(
+            "spherical_divergence",
+            &format!("Compensated geometric spreading with a depth ^{exponent} gain (m)"),
+            start_time,
+        );
+    }
+
+    /// Apply automatic gain control (AGC) with a sliding window along each trace
+    ///
+    /// # Arguments
+    /// - `window_ns`: The length of the window (ns)
+    /// - `method`: Whether the samples are normalized by the RMS or the mean absolute amplitude
+    /// - `save`: Keep the gain so that it can be undone with `undo_agc`. If a gain is already
+    ///   saved, the two are combined.
+    ///
+    /// # Errors
+    /// - The topography has already been corrected
+    /// - The window is shorter than one sample
+    pub fn agc(
+        &mut self,
+        window_ns: f32,
+        method: filters::AgcMethod,
+        save: bool,
+    ) -> Result<(), String> {
+        let start_time = SystemTime::now();
+        if self.topo_data.is_some() {
+            return Err(
+                "agc has to be run before correct_topography, since the AGC window is along the traces"
+                    .to_string(),
+            );
+        }
+        let window = (window_ns / self.vertical_resolution_ns()).round() as usize;
+        if !window_ns.is_finite() || window < 1 {
+            return Err(format!(
+                "The AGC window of {window_ns} ns is shorter than one sample ({} ns)",
+                self.vertical_resolution_ns()
+            ));
+        }
+        let window = window | 1;
+
+        let gain = filters::agc(&mut self.data, method, window);
+
+        let saved = match save {
+            true => {
+                self.agc_gain = Some(match self.agc_gain.take() {
+                    Some(previous) => previous * gain,
+                    None => gain,
+                });
+                " The gain was saved."
+            }
+            false => "",
+        };
+        self.log_event(
+            "agc",
+            &format!(
+                "Normalized samples by the {method} amplitude of a {window_ns} ns ({window} samples) window.{saved}"
+            ),
+            start_time,
+        );
+        Ok(())
+    }
+
+    /// Undo the AGC gain that was saved by `agc`
+    ///
+    /// # Errors
+    /// - The topography has already been corrected
+    /// - No gain is saved, or it was discarded because traces or samples were moved
+    pub fn undo_agc(&mut self) -> Result<(), String> {
+        let start_time = SystemTime::now();
+        if self.topo_data.is_some() {
+            return Err("undo_agc has to be run before correct_topography, like agc".to_string());
+        }
+        let gain = self.agc_gain.take().ok_or(
+            "No AGC gain is saved. It is saved with agc(save=true), and discarded if traces or samples are moved".to_string(),
+        )?;
+        self.data /= &gain;
+        self.log_event("undo_agc", "Divided by the saved AGC gain", start_time);
+        Ok(())
+    }
+
+    /// Resample the traces to a fixed horizontal distance between them
+    ///
+    /// # Arguments
+    /// - `step`: The distance between traces (m). If None, it is the distance interval of
+    ///   distance-triggered data, or is otherwise determined from the median velocity
+    ///
+    /// # Returns
+    /// The distance between traces that was used (m)
+    ///
+    /// # Errors
+    /// - No step is given and the time interval between traces is unknown
+    /// - The given or determined step is not a positive number
+    pub fn make_equidistant(&mut self, step: Option<f32>) -> Result<f32, String> {
         let start_time = SystemTime::now();
         let distances = self.location.distances().mapv(|v| v as f32);
         let max_distance = distances.max().unwrap();
 
-        let step = step.unwrap_or({
-            let velocities = self.location.velocities().mapv(|v| v as f32);
+        let step = match step.or(self.metadata.distance_interval) {
+            Some(step) => step,
+            None => {
+                if !(self.metadata.time_interval.is_finite() && self.metadata.time_interval > 0.) {
+                    return Err(format!(
+                        "The time interval between traces is unknown ({} s), so the equidistant_traces step has to be given",
+                        self.metadata.time_interval
+                    ));
+                }
+                let velocities = self.location.velocities().mapv(|v| v as f32);
 
-            let normal_velocity = tools::quantiles(&velocities, &[0.5], None)[0];
+                let normal_velocity = tools::quantiles(&velocities, &[0.5], None)[0];
 
-            let mut seconds_moving = 0_f32;
-            for i in 1..self.width() {
-                if velocities[i] < (0.3 * normal_velocity) {
-                    continue;
-                };
-                seconds_moving += self.metadata.time_interval;
-            }
+                let mut seconds_moving = 0_f32;
+                for i in 1..self.width() {
+                    if velocities[i] < (0.3 * normal_velocity) {
+                        continue;
+                    };
+                    seconds_moving += self.metadata.time_interval;
+                }
 
-            let nominal_data_width =
-                (seconds_moving / self.metadata.time_interval).floor() as usize;
+                let nominal_data_width =
+                    (seconds_moving / self.metadata.time_interval).floor() as usize;
 
-            max_distance / (nominal_data_width as f32)
-        });
+                max_distance / (nominal_data_width as f32)
+            }
+        };
+        if !(step.is_finite() && step > 0.) {
+            return Err(format!(
+                "The equidistant_traces step has to be a positive number of meters, not {step}"
+            ));
+        }
 
         if (max_distance / step).round() as usize == self.width() {
             self.log_event(
@@ -1117,9 +2515,16 @@ impl GPR {
                 "Traces were already equidistant.",
                 start_time,
             );
-            return;
+            return Ok(step);
         };
 
+        // The markers are moved to the trace closest to their original distance
+        let marker_distances = self
+            .markers
+            .iter()
+            .map(|m| distances[m.trace] - distances[0])
+            .collect::<Vec<f32>>();
+
         let resampler = tools::Resampler::<f32>::new(distances, step);
         //resampler.resample_along_axis(&mut self.data, tools::Axis2D::Col);
         self.update_data(resampler.resample_along_axis_par(&self.data, tools::Axis2D::Col));
@@ -1181,6 +2586,10 @@ impl GPR {
 
         self.metadata.last_trace = self.data.shape()[1] as u32;
         self.location.cor_points = cor_points;
+        let width = self.width();
+        for (marker, distance) in self.markers.iter_mut().zip(marker_distances) {
+            marker.trace = ((distance / step).round() as usize).min(width - 1);
+        }
         self.log_event(
             "equidistant_traces",
             &format!("Ran equidistant traces with a spacing of {step} m"),
@@ -1209,6 +2618,7 @@ impl GPR {
 
         self.log_event("equidistant_traces", "Ran equidistant traces", start_time);
         */
+        Ok(step)
     }
 
     fn log_event(&mut self, step_name: &str, event: &str, start_time: SystemTime) {
@@ -1408,6 +2818,18 @@ impl GPR {
         }
         self.metadata.last_trace = self.width() as u32;
 
+        // Markers on removed traces are moved to the next remaining trace
+        match traces_to_keep.is_empty() {
+            true => self.markers.clear(),
+            false => {
+                for marker in self.markers.iter_mut() {
+                    marker.trace = traces_to_keep
+                        .partition_point(|t| *t < marker.trace)
+                        .min(traces_to_keep.len() - 1);
+                }
+            }
+        }
+
         if log {
             self.log_event(
                 "remove_traces",
@@ -1432,6 +2854,11 @@ impl GPR {
         self.location.cor_points =
             filters::window_subset_vec(self.location.cor_points.clone(), window);
         self.metadata.time_interval *= window as f32;
+        self.metadata.distance_interval =
+            self.metadata.distance_interval.map(|v| v * window as f32);
+        for marker in self.markers.iter_mut() {
+            marker.trace /= window;
+        }
 
         self.update_data(averaged_data);
 
@@ -1475,25 +2902,39 @@ impl GPR {
     pub fn width(&self) -> usize {
         self.data.shape()[1]
     }
-    pub fn export(&self, nc_filepath: &Path) -> Result<(), Box<dyn Error>> {
-        io::export_netcdf(self, nc_filepath)
+    pub fn export(
+        &self,
+        filepath: &Path,
+        segy_options: &io::SegyOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        io::export(self, filepath, segy_options)
     }
 
+    /// The depth of each sample (m)
+    ///
+    /// The depths are converted from the return times with the velocity model, if given, or
+    /// otherwise with the medium velocity.
     pub fn depths(&self) -> Array1<f32> {
         let time_windows = (Array1::<f32>::range(0., self.height() as f32, 1.)
             / self.height() as f32)
             * self.metadata.time_window;
+        time_windows.mapv(|time| self.depth_at(time))
+    }
+
+    /// The depth (m) of a return time, in the same way as for `depths`
+    ///
+    /// # Arguments
+    /// - `return_time`: The two-way return time (ns)
+    pub fn depth_at(&self, return_time: f32) -> f32 {
         let corr_antenna_separation = (self.horizontal_signal_distance.powi(2)
             - (self.zero_point_ns * self.metadata.medium_velocity).powi(2))
         .max(0.)
         .sqrt();
-        time_windows.mapv(|time| {
-            tools::return_time_to_depth(
-                time,
-                self.metadata.medium_velocity,
-                corr_antenna_separation,
-            )
-        })
+        tools::return_time_to_depth(
+            return_time,
+            self.metadata.average_velocity(return_time),
+            corr_antenna_separation,
+        )
     }
 
     pub fn merge(&mut self, other: &GPR) -> Result<(), String> {
@@ -1508,6 +2949,11 @@ impl GPR {
                 "Antenna frequencies are different: {} vs {}",
                 self.metadata.antenna_mhz, other.metadata.antenna_mhz
             ))
+        } else if self.metadata.channel != other.metadata.channel {
+            Err(format!(
+                "Channels are different: {} vs {}",
+                self.metadata.channel, other.metadata.channel
+            ))
         } else if self.metadata.time_window != other.metadata.time_window {
             Err(format!(
                 "Time windows are different: {} vs {}",
@@ -1518,12 +2964,42 @@ impl GPR {
                 .cor_points
                 .append(other.location.cor_points.clone().as_mut());
 
+            let width = self.width();
+            self.markers.extend(other.markers.iter().map(|m| Marker {
+                trace: m.trace + width,
+                comment: m.comment.clone(),
+            }));
+
+            horizons::append(&mut self.horizons, width, &other.horizons, other.width());
+            self.diffractions
+                .extend(other.diffractions.iter().map(|d| Diffraction {
+                    trace: d.trace + width,
+                    ..*d
+                }));
+
             self.data.append(Axis(1), other.data.view()).unwrap();
 
+            // A saved AGC gain can only be kept if both profiles have one
+            self.agc_gain = match (self.agc_gain.take(), &other.agc_gain) {
+                (Some(mut gain), Some(other_gain)) => {
+                    gain.append(Axis(1), other_gain.view()).unwrap();
+                    Some(gain)
+                }
+                _ => None,
+            };
+
             self.metadata.time_window *= self.height() as f32 / self.metadata.samples as f32;
             self.metadata.samples = self.height() as u32;
             self.metadata.last_trace = self.width() as u32;
 
+            if let (Some(source), Some(other_source)) =
+                (&mut self.provenance.source, &other.provenance.source)
+            {
+                source
+                    .filepaths
+                    .extend(other_source.filepaths.iter().cloned());
+            }
+
             self.log_event(
                 "merge",
                 &format!("Merged {:?}", other.metadata.data_filepath),
@@ -1541,18 +3017,62 @@ pub struct RunParams {
     pub dem_path: Option<PathBuf>,
     pub cor_path: Option<PathBuf>,
     pub medium_velocity: f32,
+    /// A layered velocity model that replaces the medium velocity for depth conversion
+    pub velocity_model: Option<VelocityModel>,
     pub crs: Option<String>,
     pub quiet: bool,
     pub track_path: Option<Option<PathBuf>>,
-    pub steps: Vec<String>,
+    pub spectrum_path: Option<Option<PathBuf>>,
+    pub picks_path: Option<Option<PathBuf>>,
+    pub velocity_table_path: Option<Option<PathBuf>>,
+    pub steps: Vec<Step>,
     pub no_export: bool,
     pub render_path: Option<Option<PathBuf>>,
     pub merge: Option<Duration>,
     pub override_antenna_mhz: Option<f32>,
+    pub segy_options: io::SegyOptions,
 }
 
-pub fn run(params: RunParams) -> Result<Vec<GPR>, Box<dyn Error>> {
-    let empty: Vec<GPR> = Vec::new();
+impl Default for RunParams {
+    /// No inputs, steps or extra outputs, and the medium velocity of ice
+    fn default() -> Self {
+        Self {
+            filepaths: Vec::new(),
+            output_path: None,
+            only_info: false,
+            dem_path: None,
+            cor_path: None,
+            medium_velocity: crate::cli::DEFAULT_MEDIUM_VELOCITY,
+            velocity_model: None,
+            crs: None,
+            quiet: false,
+            track_path: None,
+            spectrum_path: None,
+            picks_path: None,
+            velocity_table_path: None,
+            steps: Vec::new(),
+            no_export: false,
+            render_path: None,
+            merge: None,
+            override_antenna_mhz: None,
+            segy_options: Default::default(),
+        }
+    }
+}
+
+/// Load (and optionally merge) the profiles of a run, without processing them
+///
+/// # Arguments
+/// - `params`: The run parameters. The steps, export and render parameters are not used.
+///
+/// # Returns
+/// The output filepath and profile of each loaded input. If only info was requested, the info is
+/// printed and nothing is returned.
+///
+/// # Errors
+/// - An input could not be found or loaded
+/// - The output directory does not exist
+fn load_run_inputs(params: &RunParams) -> Result<Vec<(PathBuf, GPR)>, Box<dyn Error>> {
     let mut gprs: Vec<(PathBuf, GPR)> = Vec::new();
     for filepath in &params.filepaths {
         let ext = filepath
@@ -1560,7 +3080,15 @@ pub fn run(params: RunParams) -> Result<Vec<GPR>, Box<dyn Error>> {
             .and_then(|s| s.to_str())
             .ok_or(format!("Extension-less filepath: {:?}", filepath).to_string())?;
 
-        let (gpr_meta, mut gpr_locations) = if ["hd", "dt1"].contains(&ext) {
+        // Previously exported NetCDF files are loaded in full, since they have been processed.
+        let mut preloaded: Option<GPR> = None;
+
+        let (mut gpr_metas, mut gpr_locations) = if ext.eq_ignore_ascii_case("nc") {
+            let gpr = io::load_netcdf(filepath, params.override_antenna_mhz)?;
+            let meta_and_loc = (vec![gpr.metadata.clone()], gpr.location.clone());
+            preloaded = Some(gpr);
+            meta_and_loc
+        } else if ["hd", "dt1"].contains(&ext) {
             let hd_filepath = filepath.with_extension("hd");
             // Make sure that it exists
             if !hd_filepath.is_file() {
@@ -1578,16 +3106,73 @@ pub fn run(params: RunParams) -> Result<Vec<GPR>, Box<dyn Error>> {
                 params.override_antenna_mhz,
             )?;
 
-            let gpr_locations =
-                io::load_pe_gp2(&filepath.with_extension("gp2"), params.crs.as_ref())?;
-            (gpr_meta, gpr_locations)
+            // Without a ".gp2" file, the positions in the trace headers are used instead
+            let gp2_filepath = filepath.with_extension("gp2");
+            let gpr_locations = match gp2_filepath.is_file() {
+                true => io::load_pe_gp2(&gp2_filepath, params.crs.as_ref())?,
+                false => io::load_pe_trace_locations(
+                    &gpr_meta.data_filepath,
+                    gpr_meta.samples as usize,
+                    gpr_meta.last_trace as usize,
+                    params.crs.as_ref(),
+                )?,
+            };
+            (vec![gpr_meta], gpr_locations)
+        } else if ["sgy", "segy"].contains(&ext.to_lowercase().as_str()) {
+            let (gpr_meta, segy_locations) = io::load_segy(
+                filepath,
+                params.medium_velocity,
+                params.override_antenna_mhz,
+                params.crs.as_ref(),
+                &params.segy_options,
+            )?;
+            let gpr_locations = match &params.cor_path {
+                Some(fp) => io::load_cor(fp, params.crs.as_ref())?,
+                None => segy_locations,
+            };
+            (vec![gpr_meta], gpr_locations)
+        } else if ext.eq_ignore_ascii_case("dzt") {
+            // GSSI files have the metadata in the data file, with one metadata object per channel
+            let gpr_metas = io::load_dzt(
+                filepath,
+                params.medium_velocity,
+                params.override_antenna_mhz,
+            )?;
+
+            let dzg_filepath = io::with_extension_any_case(filepath, "dzg");
+            let gpr_locations = match &params.cor_path {
+                Some(fp) => io::load_cor(fp, params.crs.as_ref())?,
+                None => match dzg_filepath.is_file() {
+                    true => io::load_dzg(&dzg_filepath, params.crs.as_ref())?,
+                    // Distance-triggered profiles may be positioned by their scans per meter
+                    false => io::load_dzt_distance_locations(filepath, params.crs.as_ref())?,
+                },
+            };
+            (gpr_metas, gpr_locations)
         } else {
             // The given filepath may be ".rd3" or may not have an extension at all
             // Counterintuitively to the user point of view, it's the ".rad" file that should be given
             let rad_filepath = filepath.with_extension("rad");
 
+            // A multichannel (MIRA) set may be given by its name without the "_A<channel>" suffix
+            let rad_filepaths = match rad_filepath.is_file() {
+                true => vec![rad_filepath.clone()],
+                false => {
+                    let pattern = rad_filepath.with_file_name(format!(
+                        "{}_A*.rad",
+                        glob::Pattern::escape(&rad_filepath.file_stem().unwrap().to_string_lossy())
+                    ));
+                    let mut channel_filepaths = glob::glob(&pattern.to_string_lossy())?
+                        .filter_map(|p| p.ok())
+                        .filter(|p| io::mala_channel(p).is_some())
+                        .collect::<Vec<PathBuf>>();
+                    channel_filepaths.sort_by_key(|p| io::mala_channel(p));
+                    channel_filepaths
+                }
+            };
+
             // Make sure that it exists
-            if !rad_filepath.is_file() {
+            if rad_filepaths.is_empty() {
                 if filepath.is_file() {
                     return Err(
                         format!("File found but no '.rad' file found: {:?}", rad_filepath).into(),
@@ -1595,12 +3180,12 @@ pub fn run(params: RunParams) -> Result<Vec<GPR>, Box<dyn Error>> {
                 }
                 return Err(format!("File not found: {:?}", rad_filepath).into());
             };
-            // Load the GPR metadata from the rad file
-            let gpr_meta = io::load_rad(
-                &rad_filepath,
-                params.medium_velocity,
-                params.override_antenna_mhz,
-            )?;
+            // Load the GPR metadata from the rad file(s)
+            let gpr_metas = rad_filepaths
+                .iter()
+                .map(|fp| io::load_rad(fp, params.medium_velocity, params.override_antenna_mhz))
+                .collect::<Result<Vec<GPRMeta>, _>>()?;
+            let gpr_meta = &gpr_metas[0];
 
             // Load the GPR location data
             // If the "--cor" argument was used, load from there. Otherwise, try to find a ".cor" file
@@ -1617,7 +3202,7 @@ pub fn run(params: RunParams) -> Result<Vec<GPR>, Box<dyn Error>> {
                     },
                 }?,
             };
-            (gpr_meta, gpr_locations)
+            (gpr_metas, gpr_locations)
         };
 
         // If a "--dem" was given, substitute elevations using said DEM
@@ -1625,6 +3210,13 @@ pub fn run(params: RunParams) -> Result<Vec<GPR>, Box<dyn Error>> {
             gpr_locations.get_dem_elevations(dem_path)?;
         };
 
+        // A velocity model of the run replaces that of a previously exported profile
+        if let Some(velocity_model) = &params.velocity_model {
+            for gpr_meta in gpr_metas.iter_mut() {
+                gpr_meta.velocity_model = Some(velocity_model.clone());
+            }
+        }
+
         // Construct the output filepath. If one was given, use that.
         // If a path was given and it's a directory, use the file stem + ".nc" of the input
         // filename. If no output path was given, default to the directory of the input.
@@ -1649,32 +3241,89 @@ pub fn run(params: RunParams) -> Result<Vec<GPR>, Box<dyn Error>> {
 
         // If the "--info" argument was given, stop here and just show info.
         if params.only_info {
-            println!("{}", gpr_meta);
+            for gpr_meta in &gpr_metas {
+                println!("{}", gpr_meta);
+            }
             println!("{}", gpr_locations);
             // If the track should be exported, do so.
             if let Some(potential_track_path) = &params.track_path {
                 io::export_locations(
                     &gpr_locations,
+                    None,
                     potential_track_path.into(),
                     &output_filepath,
                     !params.quiet,
                 )?;
             };
+        } else if let Some(mut gpr) = preloaded {
+            if !params.no_export && output_filepath == *filepath {
+                return Err(format!(
+                    "The output filepath is the same as the input: {:?}. Use '--output' to choose another",
+                    filepath
+                )
+                .into());
+            }
+            gpr.location = gpr_locations;
+            if params.velocity_model.is_some() {
+                gpr.metadata.velocity_model = params.velocity_model.clone();
+            }
+            gprs.push((output_filepath, gpr));
         } else {
             // At this point, the data should be processed.
-            let gpr = match GPR::from_meta_and_loc(gpr_locations, gpr_meta) {
-                Ok(g) => g,
-                Err(e) => {
-                    return Err(format!(
-                        "Error loading GPR data from {:?}: {:?}",
-                        filepath.with_extension("rd3"),
-                        e
-                    )
-                    .into())
+            // Multi-channel data are split into separate profiles, with the channel as a suffix.
+            let multichannel = gpr_metas.len() > 1;
+            for gpr_meta in gpr_metas {
+                let output_filepath = match multichannel {
+                    true => output_filepath.with_file_name(format!(
+                        "{}_ch{}.{}",
+                        output_filepath.file_stem().unwrap().to_string_lossy(),
+                        gpr_meta.channel,
+                        output_filepath
+                            .extension()
+                            .map(|e| e.to_string_lossy())
+                            .unwrap_or_default(),
+                    )),
+                    false => output_filepath.clone(),
+                };
+                let data_filepath = gpr_meta.data_filepath.clone();
+                let mut channel_locations = gpr_locations.clone();
+                if gpr_meta.channel_offset != [0., 0.] {
+                    channel_locations.offset_horizontally(
+                        gpr_meta.channel_offset[0] as f64,
+                        gpr_meta.channel_offset[1] as f64,
+                    );
                 }
-            };
+                let channel = gpr_meta.channel;
+                let mut gpr = match GPR::from_meta_and_loc(channel_locations, gpr_meta) {
+                    Ok(g) => g,
+                    Err(e) => {
+                        return Err(format!(
+                            "Error loading GPR data from {:?}: {:?}",
+                            data_filepath, e
+                        )
+                        .into())
+                    }
+                };
 
-            gprs.push((output_filepath, gpr));
+                gpr.provenance.source = Some(ProvenanceSource {
+                    filepaths: vec![
+                        std::path::absolute(filepath).unwrap_or_else(|_| filepath.clone())
+                    ],
+                    channel,
+                    cor_path: params.cor_path.clone(),
+                    dem_path: params.dem_path.clone(),
+                    crs: params.crs.clone(),
+                    medium_velocity: params.medium_velocity,
+                    velocity_model: params.velocity_model.clone(),
+                    override_antenna_mhz: params.override_antenna_mhz,
+                    segy_coordinate_bytes: params.segy_options.coordinate_bytes,
+                    segy_scalar: params.segy_options.scalar,
+                    segy_sample_interval_unit_ns: params.segy_options.sample_interval_unit_ns,
+                    radal_version: provenance::radal_version(),
+                });
+
+                gprs.push((output_filepath, gpr));
+            }
         };
     }
 
@@ -1733,6 +3382,13 @@ pub fn run(params: RunParams) -> Result<Vec<GPR>, Box<dyn Error>> {
         }
     };
 
+    Ok(gprs)
+}
+
+pub fn run(params: RunParams) -> Result<Vec<GPR>, Box<dyn Error>> {
+    let empty: Vec<GPR> = Vec::new();
+    let gprs = load_run_inputs(&params)?;
+
     for (output_filepath, mut gpr) in gprs {
         // Record the starting time to show "t+XX" times
         let start_time = SystemTime::now();
@@ -1767,7 +3423,7 @@ pub fn run(params: RunParams) -> Result<Vec<GPR>, Box<dyn Error>> {
             if !params.quiet {
                 println!("Exporting to {:?}", output_filepath);
             };
-            match gpr.export(&output_filepath) {
+            match gpr.export(&output_filepath, &params.segy_options) {
                 Ok(_) => (),
                 Err(e) => return Err(format!("Error exporting data: {:?}", e).into()),
             }
@@ -1798,53 +3454,212 @@ pub fn run(params: RunParams) -> Result<Vec<GPR>, Box<dyn Error>> {
         if let Some(potential_track_path) = &params.track_path {
             io::export_locations(
                 &gpr.location,
+                gpr.bed_picks().as_deref(),
                 potential_track_path.into(),
                 &output_filepath,
                 !params.quiet,
             )?;
         };
+
+        // If "--spectrum" was given, export the mean amplitude spectrum.
+        if let Some(potential_spectrum_path) = &params.spectrum_path {
+            io::export_spectrum(
+                &gpr,
+                potential_spectrum_path.into(),
+                &output_filepath,
+                !params.quiet,
+            )?;
+        };
+
+        // If "--picks" was given, export the picked horizons.
+        if let Some(potential_picks_path) = &params.picks_path {
+            io::export_picks(
+                &gpr,
+                potential_picks_path.into(),
+                &output_filepath,
+                !params.quiet,
+            )?;
+        };
+
+        // If "--velocity-table" was given, export the fitted diffractions as a velocity model.
+        if let Some(potential_velocity_table_path) = &params.velocity_table_path {
+            io::export_velocity_table(
+                &gpr,
+                potential_velocity_table_path.into(),
+                &output_filepath,
+                !params.quiet,
+            )?;
+        };
     }
 
     Ok(empty)
 }
 
-pub fn all_available_steps() -> Vec<[&'static str; 2]> {
-    vec![
-        ["subset", "Subset the data in x (traces) and/or y (samples). Examples: Clip to the first 500 samples: subset(0 -1 0 500). Clip to the first 300 traces, subset(0 300)"],
-        ["remove_traces", "Manually remove trace indices, for example in case they are visually deemed bad. Example: Remove the first two traces: remove_traces(0 1)"],
-        ["remove_empty_traces", "Remove all traces that appear empty. Recommended to be run as the first filter if required!. The strength threshold (mean absolute trace value) can be tweaked. Example: 'remove_empty_traces(2)'. Default: 1."],
-        ["average_traces", "Average traces in a given window. The coordinate information is picked from the middle averaged trace. Example: 'average_traces(3)'."],
-        ["zero_corr_max_peak", "Shift the location of the zero return time by finding the maximum row value. The peak is found for each trace individually."],
-        ["zero_corr", "Shift the location of the zero return time by finding the first row where data appear. The correction can be tweaked to allow more or less data, e.g. 'zero_corr(0.9)'. Default: 1.0"],
-        ["bandpass", "Apply a bandpass Butterworth filter to each trace individually. The given frequencies are normalized (0: 0Hz, 1: Nyquist). An optional strength (q) can be provided as a third argument (default 0.707). Default: bandpass(0.1 0.9)"],
-        ["bandpass_mhz", "Apply a bandpass Butterworth filter to each trace individually. An optional strength (q) can be provided as a third argument (default 0.707). The given frequencies are assumed to be in MHz."],
-        ["equidistant_traces", "Make all traces equidistant by averaging them in a fixed horizontal grid. The step size is determined from the median moving velocity. Other step sizes in m can be given, e.g. 'equidistant_traces(2.)' for 2 m. Default: auto"],
-        ["normalize_horizontal_magnitudes", "Normalize the magnitudes of the traces in the horizontal axis. This removes or reduces horizontal banding. The uppermost samples of the trace can be excluded, either by sample number (integer; e.g. 'normalize_horizontal_magnitudes(300)') or by a fraction of the trace (float; e.g. 'normalize_horizontal_magnitudes(0.3)'). Default: 0.3"],
-        ["dewow", "Subtract the horizontal moving average magnitude for each trace. This reduces artefacts that are consistent among every trace. The averaging window can be set, e.g. 'dewow(10)'. Default: 5"],
-        ["auto_gain", "Automatically determine the best gain factor and apply it. The data are binned vertically and the mean absolute deviation of the values is used as a proxy for signal attenuation. The median attenuation in decibel volts is given to the gain filter. The amounts of bins can be given, e.g. 'auto_gain(100). Default: 100"],
-        ["gain", "Multiply the magnitude as a function of depth. This is most often used to correct for signal attenuation with time/distance. Gain is applied as: '10 ^(gain * twt / 20)' (dB / ns) where gain is the given gain factor and twt is the two-way travel time of the signal. Examples: gain(0.002). No default value."],
-        ["kirchhoff_migration2d", "Migrate sample magnitudes in the horizontal and vertical distance dimension to correct hyperbolae in the data. The correction is needed because the GPR does not observe only what is directly below it, but rather in a cone that is determined by the dominant antenna frequency. Thus, without migration, each trace is the sum of a cone beneath it. Topographic Kirchhoff migration (in 2D) corrects for this in two dimensions."],
-        ["abslog", "Run a log10 operation on the absolute values (log10(abs(data))), converting it to a logarithmic scale. This is useful for visualisation. Before conversion, the data are added with the 1st percentile (absolute) value in the dataset to avoid log10(0) == inf."],
-        ["siglog", "Run a log10 operation on absolute values and then account for the sign. Values smaller than the set minimum magnitude are truncated to zero. E.g. with an exponent offset of 0: 1000 -> 3, -1000 -> -3, 0.001 -> 0. The argument specifies the exponent offset to apply to allow for values smaller than +-1 (e.g. 10e-1). Default: -1"],
-        ["unphase", "Combine the positive and negative phases of the signal into one positive magntiude. The assumption is made that the positive magnitude of the signal comes first, followed by an offset negative component. The distance between the positive and negative peaks are found, and then the negative part is shifted accordingly."],
-        ["correct_topography", "Make a copy of the data and topographically correct it. In the output, the data will be called \"topo_data\". Note that the copying means any step run after this will not be reflected in \"topo_data\". This is thus recommended to run last."],
-        ["correct_antenna_separation", "Correct for the separation between the antenna transmitter and receiver. The consequence is that depths are slightly over-exaggerated at low return-times before correction. This step averages samples so that each sample represents a consistent depth interval."],
-    ]
-}
+/// Re-run the recorded processing of an exported profile on its original data
+///
+/// The raw data are loaded with the recorded settings, each recorded step is run again with its
+/// resolved (automatically determined) arguments, and the result is compared with the data of the
+/// exported profile.
+///
+/// # Arguments
+/// - `filepath`: The processed NetCDF file
+/// - `raw_filepath`: The raw file to use instead of the recorded one, e.g. if it has been moved
+/// - `quiet`: Suppress progress messages
+///
+/// # Returns
+/// The maximum absolute difference between the exported and the replayed data
+///
+/// # Errors
+/// - The file has no recorded source (e.g. from an older version) or is a merged profile
+/// - The raw data cannot be found or loaded
+/// - A step fails
+/// - The replayed data do not match the exported data
+pub fn replay(
+    filepath: &Path,
+    raw_filepath: Option<&Path>,
+    quiet: bool,
+) -> Result<f32, Box<dyn Error>> {
+    let processed = io::load_netcdf(filepath, None)?;
+    let source = processed.provenance.source.clone().ok_or(format!(
+        "No source is recorded in {:?}. Was it made by an older version of radal?",
+        filepath
+    ))?;
+    if source.filepaths.len() != 1 {
+        return Err(format!(
+            "Replaying merged profiles is not supported ({} sources)",
+            source.filepaths.len()
+        )
+        .into());
+    }
+
+    // If the raw file is not found where it was, it may have been moved together with the output
+    let mut candidates = match raw_filepath {
+        Some(fp) => vec![fp.to_path_buf()],
+        None => vec![source.filepaths[0].clone()],
+    };
+    if raw_filepath.is_none() {
+        if let Some(filename) = source.filepaths[0].file_name() {
+            candidates.push(filepath.with_file_name(filename));
+        }
+    }
 
-pub fn default_processing_profile() -> Vec<String> {
-    vec![
-        "remove_empty_traces".to_string(),
-        format!("zero_corr_max_peak"),
-        "equidistant_traces".to_string(),
-        "correct_antenna_separation".to_string(),
-        format!(
-            "normalize_horizontal_magnitudes({})",
-            DEFAULT_NORMALIZE_HORIZONTAL_MAGNITUDES_CUTOFF
+    let mut loaded: Result<Vec<(PathBuf, GPR)>, Box<dyn Error>> = Err("No raw file".into());
+    for candidate in &candidates {
+        loaded = load_run_inputs(&RunParams {
+            filepaths: vec![candidate.clone()],
+            dem_path: source.dem_path.clone(),
+            cor_path: source.cor_path.clone(),
+            medium_velocity: source.medium_velocity,
+            velocity_model: source.velocity_model.clone(),
+            crs: source.crs.clone(),
+            quiet,
+            no_export: true,
+            override_antenna_mhz: source.override_antenna_mhz,
+            segy_options: source.segy_options(),
+            ..Default::default()
+        });
+        if loaded.is_ok() {
+            break;
+        }
+    }
+    let mut gpr = loaded
+        .map_err(|e| {
+            format!("Could not load the raw data (use '--raw' to give its filepath): {e}")
+        })?
+        .into_iter()
+        .map(|(_, gpr)| gpr)
+        .find(|gpr| gpr.metadata.channel == source.channel)
+        .ok_or(format!(
+            "Channel {} not found in the raw data",
+            source.channel
+        ))?;
+
+    let n_steps = processed.provenance.steps.len();
+    for (i, recorded) in processed.provenance.steps.iter().enumerate() {
+        // The resolved step is run, so that automatically determined arguments are not determined
+        // again, possibly differently
+        let step = recorded.resolved.parse::<Step>()?;
+        if !quiet {
+            println!("{}/{}, Running step {}", i + 1, n_steps, step);
+            if recorded.radal_version != provenance::radal_version() {
+                println!(
+                    "\tThe step was run with radal version {} (this is {})",
+                    recorded.radal_version,
+                    provenance::radal_version()
+                );
+            }
+        }
+        gpr.process(&step)
+            .map_err(|e| format!("Error on step {}: {:?}", step, e))?;
+    }
+
+    let mut max_difference = 0_f32;
+    for (name, replayed, exported) in [
+        ("data", Some(&gpr.data), Some(&processed.data)),
+        (
+            "topo_data",
+            gpr.topo_data.as_ref(),
+            processed.topo_data.as_ref(),
         ),
-        format!("dewow({})", DEFAULT_DEWOW_WINDOW),
-        format!("auto_gain({})", DEFAULT_AUTOGAIN_N_BINS),
-    ]
+    ] {
+        let (replayed, exported) = match (replayed, exported) {
+            (Some(r), Some(e)) => (r, e),
+            (None, None) => continue,
+            _ => return Err(format!("The replayed {name} differ in whether they exist").into()),
+        };
+        if replayed.shape() != exported.shape() {
+            return Err(format!(
+                "The replayed {name} have a different shape: {:?} vs. {:?}",
+                replayed.shape(),
+                exported.shape()
+            )
+            .into());
+        }
+        let scale = exported
+            .iter()
+            .filter(|v| v.is_finite())
+            .fold(0_f32, |max, v| max.max(v.abs()));
+        let difference = replayed
+            .iter()
+            .zip(exported.iter())
+            .map(|(r, e)| match (r.is_finite(), e.is_finite()) {
+                (true, true) => (r - e).abs(),
+                (false, false) => 0.,
+                _ => f32::INFINITY,
+            })
+            .fold(0_f32, f32::max);
+        // Allow for small differences from the order of parallel operations
+        if difference > scale * 1e-5 {
+            return Err(format!(
+                "The replayed {name} differ from the exported: maximum difference {difference} (maximum value {scale})"
+            )
+            .into());
+        }
+        max_difference = max_difference.max(difference);
+    }
+
+    if !quiet {
+        println!(
+            "Replayed {} steps. The result matches {:?} (maximum difference: {})",
+            n_steps, filepath, max_difference
+        );
+    }
+    Ok(max_difference)
+}
+
+/// List the signatures and descriptions of all available steps
+///
+/// # Returns
+/// Pairs of the step signature (e.g. "average_traces(window=5)") and its description
+pub fn all_available_steps() -> Vec<[String; 2]> {
+    STEPS
+        .iter()
+        .map(|spec| [spec.signature(), spec.description.to_string()])
+        .collect()
+}
+
+pub fn default_processing_profile() -> Vec<Step> {
+    Step::default_profile()
 }
 
 #[cfg(test)]
@@ -1907,6 +3722,7 @@ mod tests {
             frequency: 5000.,
             frequency_steps: 0,
             time_interval: 0.2,
+            distance_interval: None,
             antenna: "500MHz".to_string(),
             antenna_mhz: 500.,
             antenna_separation: 1.,
@@ -1914,6 +3730,9 @@ mod tests {
             last_trace: n_traces as u32,
             data_filepath: std::path::PathBuf::new(),
             medium_velocity: 0.167,
+            channel: 0,
+            channel_offset: [0., 0.],
+            velocity_model: None,
         };
 
         let mut data = ndarray::Array2::<f32>::zeros((n_samples, n_traces));
@@ -1928,8 +3747,13 @@ mod tests {
             data,
             topo_data: None,
             zero_point_ns: 0.,
+            agc_gain: None,
             horizontal_signal_distance: 1.,
             log: Vec::new(),
+            provenance: Default::default(),
+            markers: Vec::new(),
+            horizons: Vec::new(),
+            diffractions: Vec::new(),
         }
     }
 
@@ -1966,6 +3790,12 @@ mod tests {
         let distances = gpr_location.distances();
         assert_eq!(distances[0], 0.);
         assert_eq!(distances[9], 9.);
+
+        // The steps are not squared with other spacings
+        let distances = make_gpr_location(10, Some(0.5), None, None).distances();
+        assert_eq!(distances[9], 4.5);
+        let distances = make_gpr_location(10, Some(2.), None, None).distances();
+        assert_eq!(distances[9], 18.);
     }
 
     #[test]
@@ -1998,6 +3828,7 @@ mod tests {
             frequency: 8000.,
             frequency_steps: 1,
             time_interval: 1000.,
+            distance_interval: None,
             antenna: "800MHz".into(),
             antenna_mhz: 800.,
             antenna_separation: 2.,
@@ -2005,6 +3836,9 @@ mod tests {
             last_trace: width.unwrap_or(2048) as u32,
             data_filepath: PathBuf::new(),
             medium_velocity: 0.168,
+            channel: 0,
+            channel_offset: [0., 0.],
+            velocity_model: None,
         }
     }
 
@@ -2030,8 +3864,13 @@ mod tests {
             location: gpr_location,
             metadata: meta,
             log: Vec::new(),
+            provenance: Default::default(),
+            markers: Vec::new(),
             horizontal_signal_distance: antenna_separation,
             zero_point_ns: 0.,
+            agc_gain: None,
+            horizons: Vec::new(),
+            diffractions: Vec::new(),
         }
     }
 
@@ -2046,6 +3885,465 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_background_removal() {
+        use crate::filters::Statistic;
+
+        // The test data are horizontal bands, so they are entirely background
+        let mut gpr = make_test_gpr(Some(10), Some(20));
+        gpr.process(&"background_removal(median max_sample=15)".parse().unwrap())
+            .unwrap();
+        assert_eq!(gpr.data[[14, 3]], 0.);
+        assert_eq!(gpr.data[[15, 3]], 15.);
+        assert!(gpr.log.last().unwrap().contains("median"));
+
+        assert!(gpr.background_removal(Statistic::Mean, 1, 0, None).is_err());
+        assert!(gpr
+            .background_removal(Statistic::Mean, 0, 20, None)
+            .is_err());
+
+        // The background is not horizontal in topographically corrected data
+        gpr.topo_data = Some(gpr.data.clone());
+        assert!(gpr
+            .background_removal(Statistic::Mean, 0, 0, None)
+            .unwrap_err()
+            .contains("correct_topography"));
+    }
+
+    #[test]
+    fn test_dewow() {
+        use crate::filters::Statistic;
+
+        // 25 ns per sample. The test data are linear ramps, so only the truncated edges remain
+        let mut gpr = make_test_gpr(Some(4), Some(20));
+        gpr.dewow(Some(75.), 1., Statistic::Mean).unwrap();
+        assert_eq!(gpr.data[[0, 0]], -0.5);
+        assert_eq!(gpr.data[[10, 3]], 0.);
+        assert_eq!(gpr.data[[19, 3]], 0.5);
+        assert!(gpr.log.last().unwrap().contains("3 samples"));
+
+        // The antenna period of 1.25 ns is shorter than a sample
+        assert!(gpr.dewow(None, 1., Statistic::Mean).is_err());
+        assert!(gpr.dewow(Some(25.), 1., Statistic::Mean).is_err());
+
+        // The window is derived from the antenna period and recorded
+        gpr.metadata.antenna_mhz = 10.;
+        gpr.process(&"dewow(statistic=median)".parse().unwrap())
+            .unwrap();
+        assert_eq!(
+            gpr.provenance.steps.last().unwrap().resolved,
+            "dewow(window_ns=100 periods=1 statistic=median)"
+        );
+        gpr.metadata.antenna_mhz = 0.;
+        assert!(gpr.process(&"dewow".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_bandpass_zero_phase() {
+        // A wavelet at sample 100 of every trace (sampled at 8000 MHz)
+        let make_gpr = || {
+            let mut gpr = make_test_gpr(Some(3), Some(256));
+            gpr.data = ndarray::Array2::from_shape_fn((256, 3), |(row, _)| {
+                let t = (row as f32 - 100.) / 4.;
+                (1. - 2. * t.powi(2)) * (-t.powi(2)).exp()
+            });
+            gpr
+        };
+        let peak_row = |gpr: &super::GPR| {
+            gpr.data
+                .column(0)
+                .iter()
+                .enumerate()
+                .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+                .unwrap()
+                .0
+        };
+
+        let mut causal = make_gpr();
+        causal
+            .process(&"bandpass_mhz(200 1500)".parse().unwrap())
+            .unwrap();
+        assert!(peak_row(&causal) > 100);
+
+        let mut gpr = make_gpr();
+        gpr.process(&"bandpass_mhz(200 1500 zero_phase=true)".parse().unwrap())
+            .unwrap();
+        assert_eq!(peak_row(&gpr), 100);
+        assert!(gpr.log.last().unwrap().contains("zero-phase"));
+    }
+
+    #[test]
+    fn test_fft_filters() {
+        // 250 and 1000 MHz sines, sampled at 8000 MHz (a spectral resolution of 31.25 MHz)
+        let make_gpr = || {
+            let mut gpr = make_test_gpr(Some(3), Some(256));
+            gpr.data = ndarray::Array2::from_shape_fn((256, 3), |(row, _)| {
+                let t = row as f32 / 8000.;
+                (2. * std::f32::consts::PI * 250. * t).sin()
+                    + (2. * std::f32::consts::PI * 1000. * t).sin()
+            });
+            gpr
+        };
+        let amplitude_at = |gpr: &super::GPR, frequency: f32| {
+            let (frequencies, amplitudes) = gpr.spectrum();
+            let bin = frequencies.iter().position(|f| *f == frequency).unwrap();
+            amplitudes[bin]
+        };
+        // The largest difference to the given sines away from the edges, where narrow notches
+        // ring on the start and end of the truncated sines
+        let interior_error = |gpr: &super::GPR, frequencies: &[f32]| {
+            (64..192)
+                .map(|row| {
+                    let t = row as f32 / 8000.;
+                    let expected = frequencies
+                        .iter()
+                        .map(|f| (2. * std::f32::consts::PI * f * t).sin())
+                        .sum::<f32>();
+                    (gpr.data[[row, 1]] - expected).abs()
+                })
+                .fold(0_f32, f32::max)
+        };
+
+        let gpr = make_gpr();
+        assert_eq!(gpr.spectrum().0.len(), 129);
+        assert!((amplitude_at(&gpr, 250.) - 0.5).abs() < 1e-3);
+        assert!((amplitude_at(&gpr, 1000.) - 0.5).abs() < 1e-3);
+
+        let mut bandpassed = make_gpr();
+        bandpassed
+            .process(&"fft_bandpass(500 800 1500 2000)".parse().unwrap())
+            .unwrap();
+        assert!(amplitude_at(&bandpassed, 250.) < 0.02);
+        assert!(amplitude_at(&bandpassed, 1000.) > 0.45);
+        assert!(interior_error(&bandpassed, &[1000.]) < 0.05);
+        assert!(bandpassed
+            .log
+            .last()
+            .unwrap()
+            .contains("500-800-1500-2000 MHz"));
+
+        let mut notched = make_gpr();
+        notched.process(&"notch(250)".parse().unwrap()).unwrap();
+        assert!(amplitude_at(&notched, 250.) < 0.1);
+        assert!(interior_error(&notched, &[1000.]) < 0.1);
+        // The automatic width (four frequency bins) is recorded in the provenance
+        assert_eq!(
+            notched.provenance.steps.last().unwrap().resolved,
+            "notch(frequency=250 width=125 harmonics=1)"
+        );
+
+        // The fourth harmonic of 250 MHz is 1000 MHz
+        let mut notched = make_gpr();
+        notched
+            .process(&"notch(250 harmonics=4)".parse().unwrap())
+            .unwrap();
+        assert!(amplitude_at(&notched, 1000.) < 0.1);
+
+        for invalid in [
+            "fft_bandpass(800 500 1500 2000)",
+            "fft_bandpass(500 800 800 2000)",
+            "notch(0)",
+            "notch(250 harmonics=0)",
+        ] {
+            assert!(make_gpr().process(&invalid.parse().unwrap()).is_err());
+        }
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let output_path = temp_dir.path().join("gpr.nc");
+        crate::io::export_spectrum(&gpr, None, &output_path, false).unwrap();
+        let content = std::fs::read_to_string(temp_dir.path().join("gpr_spectrum.csv")).unwrap();
+        let lines = content.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], "frequency_mhz,amplitude");
+        assert_eq!(lines.len(), 130);
+        assert!(lines[9].starts_with("250,"));
+    }
+
+    #[test]
+    fn test_fk_filters() {
+        // Traces every 0.05 m with a flat 200 MHz event and an event with the slope of the air wave
+        // (2 / 0.3 m/ns), both in a Gaussian window to avoid sharp edges
+        let event = |gpr: &super::GPR, start_ns: f32, slope: f32| {
+            let width = gpr.width() as f32;
+            ndarray::Array2::from_shape_fn(gpr.data.dim(), |(row, col)| {
+                let t = row as f32 / 8. - start_ns - slope * col as f32 * 0.05;
+                let x = col as f32 - width / 2.;
+                (2. * std::f32::consts::PI * 0.2 * t).sin()
+                    * (-(t / 5.).powi(2)).exp()
+                    * (-(x / (width / 5.)).powi(2)).exp()
+            })
+        };
+        let make_gpr = || {
+            let mut gpr = make_test_gpr(Some(256), Some(1024));
+            gpr.location = make_gpr_location(256, Some(0.05), None, None);
+            gpr.make_equidistant(None).unwrap();
+            let flat = event(&gpr, 40., 0.);
+            let dipping = event(&gpr, 20., 2. / 0.3);
+            gpr.data = &flat + &dipping;
+            (gpr, flat, dipping)
+        };
+        let rms = |values: &ndarray::Array2<f32>| values.mapv(|v| v.powi(2)).mean().unwrap().sqrt();
+
+        for step in [
+            "fk_velocity_filter(0.25 0.35)",
+            "fk_dip_filter(20 60)",
+            "fk_dip_filter(0 10 mode=pass)",
+        ] {
+            let (mut gpr, flat, dipping) = make_gpr();
+            gpr.process(&step.parse().unwrap()).unwrap();
+            assert!(rms(&(&gpr.data - &flat)) < 0.15 * rms(&dipping), "{step}");
+        }
+        let (mut gpr, flat, dipping) = make_gpr();
+        gpr.process(&"fk_velocity_filter(0.25 0.35 mode=pass)".parse().unwrap())
+            .unwrap();
+        assert!(rms(&(&gpr.data - &dipping)) < 0.15 * rms(&flat));
+        assert!(gpr.log.last().unwrap().contains("Passed events"));
+
+        // The traces have to be equidistant, which they no longer are if traces are removed
+        let mut gpr = make_gpr().0;
+        gpr.remove_traces(&[100], false).unwrap();
+        assert!(gpr
+            .fk_velocity_filter(0.25, 0.35, crate::filters::fft::FkMode::Reject, 0.2)
+            .unwrap_err()
+            .contains("traces 99 and 100"));
+        // Uniformly spaced traces do not need to be resampled
+        let mut gpr = make_test_gpr(Some(256), Some(1024));
+        assert!(gpr
+            .fk_velocity_filter(0.25, 0.35, crate::filters::fft::FkMode::Reject, 0.2)
+            .is_ok());
+
+        for invalid in [
+            "fk_velocity_filter(0.35 0.25)",
+            "fk_velocity_filter(0 0.3)",
+            "fk_dip_filter(10 100)",
+            "fk_dip_filter(10 60 taper=-1)",
+        ] {
+            assert!(make_gpr().0.process(&invalid.parse().unwrap()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_migration() {
+        // A point diffractor below the middle of 64 traces spaced 1 m apart, recorded with a
+        // 25 MHz wavelet and 2 ns samples
+        let (width, height) = (64, 256);
+        let (apex_trace, apex_row) = (32_usize, 119_usize);
+        let apex_time = apex_row as f32 * 2.;
+        // The diffraction hyperbola has the two-way time t0 at the apex and the given RMS velocity
+        let make_gpr = |rms_velocity: f32| {
+            let mut gpr = make_test_gpr(Some(width), Some(height));
+            gpr.metadata.frequency = 500.;
+            gpr.metadata.time_window = 2. * height as f32;
+            for point in gpr.location.cor_points.iter_mut() {
+                point.altitude = 0.;
+            }
+            gpr.make_equidistant(Some(1.)).unwrap();
+            gpr.data = ndarray::Array2::from_shape_fn((height, width), |(row, col)| {
+                let distance = col as f32 - apex_trace as f32;
+                let time = (apex_time.powi(2) + (2. * distance / rms_velocity).powi(2)).sqrt();
+                // A Ricker wavelet, decaying with the spherical spreading
+                let t = std::f32::consts::PI * 0.025 * (row as f32 * 2. - time);
+                (1. - 2. * t.powi(2)) * (-t.powi(2)).exp() * apex_time / time
+            });
+            gpr
+        };
+        // The fraction of the energy within 5 traces and 10 samples of the apex, and its location
+        let focus = |gpr: &super::GPR| {
+            let energy = gpr.data.mapv(|v| v.powi(2));
+            let near = energy
+                .slice(ndarray::s![
+                    apex_row - 10..apex_row + 10,
+                    apex_trace - 5..apex_trace + 6
+                ])
+                .sum();
+            let peak = energy
+                .indexed_iter()
+                .max_by(|a, b| a.1.total_cmp(b.1))
+                .unwrap()
+                .0;
+            (near / energy.sum(), peak)
+        };
+        let migrate = |rms_velocity: f32, step: &str| {
+            let mut gpr = make_gpr(rms_velocity);
+            gpr.process(&step.parse().unwrap()).unwrap();
+            gpr
+        };
+
+        // The f-k migrations should focus the diffraction at least as well as Kirchhoff migration
+        let (raw_focus, _) = focus(&make_gpr(0.168));
+        let (kirchhoff_focus, kirchhoff_peak) = focus(&migrate(0.168, "kirchhoff_migration2d"));
+        assert!(kirchhoff_focus > 2. * raw_focus);
+        for step in ["stolt_migration", "gazdag_migration"] {
+            let gpr = migrate(0.168, step);
+            let (fraction, peak) = focus(&gpr);
+            assert!(
+                fraction > kirchhoff_focus,
+                "{step}: {fraction} {kirchhoff_focus}"
+            );
+            assert!(peak.0.abs_diff(kirchhoff_peak.0) <= 3, "{step}: {peak:?}");
+            assert!(peak.1.abs_diff(apex_trace) <= 1, "{step}: {peak:?}");
+            assert_eq!(gpr.width(), width);
+            assert_eq!(gpr.height(), height);
+        }
+        assert_eq!(
+            migrate(0.168, "stolt_migration")
+                .provenance
+                .steps
+                .last()
+                .unwrap()
+                .resolved,
+            "stolt_migration(velocity=0.168)"
+        );
+
+        // With 10 m of firn (0.2 m/ns) over ice, the layered model focuses better
+        let firn_time = 2. * 10. / 0.2;
+        let rms_velocity = ((0.2_f32.powi(2) * firn_time
+            + 0.168_f32.powi(2) * (apex_time - firn_time))
+            / apex_time)
+            .sqrt();
+        let (constant_focus, _) = focus(&migrate(rms_velocity, "gazdag_migration(0:0.168)"));
+        let gpr = migrate(rms_velocity, "gazdag_migration(0:0.2 10:0.168)");
+        let (layered_focus, _) = focus(&gpr);
+        assert!(layered_focus > constant_focus + 0.05);
+        assert!(gpr.log.last().unwrap().contains("0.2 m/ns from 0 m"));
+
+        // The traces have to be equidistant, the surface flat, and the layers start at the surface
+        let mut gpr = make_gpr(0.168);
+        gpr.remove_traces(&[apex_trace], false).unwrap();
+        assert!(gpr.stolt_migration(None).is_err());
+        let mut gpr = make_gpr(0.168);
+        gpr.topo_data = Some(gpr.data.clone());
+        assert!(gpr.stolt_migration(None).is_err());
+        assert!(make_gpr(0.168).gazdag_migration(&[(5., 0.168)]).is_err());
+        // The layers have to be sorted by depth, with positive velocities
+        let err = make_gpr(0.168)
+            .gazdag_migration(&[(0., 0.2), (10., 0.168), (5., 0.1)])
+            .unwrap_err();
+        assert!(err.contains("increasing"), "{err}");
+        let err = make_gpr(0.168)
+            .gazdag_migration(&[(0., 0.2), (10., -0.1)])
+            .unwrap_err();
+        assert!(err.contains("positive"), "{err}");
+
+        // The reflectors move, so picks and diffractions are discarded
+        for step in [
+            "kirchhoff_migration2d",
+            "stolt_migration",
+            "gazdag_migration",
+        ] {
+            let mut gpr = make_gpr(0.168);
+            gpr.horizons.push(crate::horizons::Horizon::new(
+                "bed",
+                &vec![Some((apex_time, 1.)); gpr.width()],
+            ));
+            gpr.diffractions.push(crate::hyperbola::Diffraction {
+                trace: apex_trace,
+                return_time: apex_time,
+                velocity: 0.168,
+                uncertainty: 0.01,
+                n_picks: 10,
+                coherence: 0.8,
+            });
+            gpr.process(&step.parse().unwrap()).unwrap();
+            assert!(gpr.horizons.is_empty(), "{step}");
+            assert!(gpr.diffractions.is_empty(), "{step}");
+        }
+    }
+
+    #[test]
+    fn test_physical_gains() {
+        // 25 ns per sample and ones everywhere, so the data become the gain curves
+        let make_gpr = || {
+            let mut gpr = make_test_gpr(Some(3), Some(20));
+            gpr.data.fill(1.);
+            gpr
+        };
+
+        let mut gpr = make_gpr();
+        gpr.process(&"power_gain(2)".parse().unwrap()).unwrap();
+        assert_eq!(gpr.data[[0, 0]], 625.);
+        assert_eq!(gpr.data[[1, 0]], 625.);
+        assert_eq!(gpr.data[[4, 2]], 10000.);
+
+        // Without attenuation, SEC is a linear gain
+        let mut gpr = make_gpr();
+        gpr.sec_gain(0.);
+        assert_eq!(gpr.data[[4, 1]], 100.);
+        // 0.1 dB/m along 4.2 m of travel path (at 0.168 m/ns) is 0.42 dB
+        let mut gpr = make_gpr();
+        gpr.sec_gain(0.1);
+        let expected = 25. * 10_f32.powf(0.42 / 20.);
+        assert!((gpr.data[[1, 0]] - expected).abs() < 1e-3);
+        assert!(gpr.log.last().unwrap().contains("0.1 dB / m"));
+
+        let mut gpr = make_gpr();
+        let depths = gpr.depths();
+        gpr.process(&"spherical_divergence".parse().unwrap())
+            .unwrap();
+        assert_eq!(gpr.data[[10, 0]], depths[10]);
+        assert!(gpr.data.iter().all(|v| *v > 0.));
+        // The gain increases monotonically with depth
+        assert!(gpr
+            .data
+            .column(0)
+            .windows(2)
+            .into_iter()
+            .all(|w| w[1] >= w[0]));
+    }
+
+    #[test]
+    fn test_agc() {
+        use crate::filters::AgcMethod;
+
+        // 25 ns per sample
+        let mut gpr = make_test_gpr(Some(10), Some(20));
+        let original = gpr.data.clone();
+
+        gpr.process(&"agc(75 save=true)".parse().unwrap()).unwrap();
+        assert!(gpr.log.last().unwrap().contains("3 samples"));
+        assert!(gpr.data[[15, 0]] < 1.5);
+        assert!(gpr.agc_gain.is_some());
+
+        // The saved gain follows a subset, and can then be undone
+        gpr.process(&"subset(2 8 5 15)".parse().unwrap()).unwrap();
+        gpr.process(&"undo_agc".parse().unwrap()).unwrap();
+        let expected = original.slice(ndarray::s![5..15, 2..8]);
+        assert!(gpr
+            .data
+            .iter()
+            .zip(expected.iter())
+            .all(|(a, b)| (a - b).abs() < 1e-4));
+        assert!(gpr.undo_agc().is_err());
+
+        // The gain is discarded if the shape changes
+        gpr.agc(75., AgcMethod::MeanAbs, true).unwrap();
+        gpr.process(&"average_traces(2)".parse().unwrap()).unwrap();
+        assert!(gpr.agc_gain.is_none());
+
+        // ... or if the samples are moved within the same shape
+        gpr.agc(75., AgcMethod::MeanAbs, true).unwrap();
+        let shape = gpr.data.dim();
+        gpr.process(&"kirchhoff_migration2d".parse().unwrap())
+            .unwrap();
+        assert_eq!(gpr.data.dim(), shape);
+        assert!(gpr.agc_gain.is_none());
+
+        // It cannot be undone after correcting the topography
+        gpr.agc(75., AgcMethod::MeanAbs, true).unwrap();
+        gpr.topo_data = Some(gpr.data.clone());
+        assert!(gpr.undo_agc().is_err());
+        assert!(gpr.agc_gain.is_some());
+        gpr.topo_data = None;
+        gpr.agc_gain = None;
+
+        // Without saving, there is nothing to undo
+        gpr.agc(75., AgcMethod::Rms, false).unwrap();
+        assert!(gpr.agc_gain.is_none());
+
+        assert!(gpr.agc(5., AgcMethod::Rms, false).is_err());
+        gpr.topo_data = Some(gpr.data.clone());
+        assert!(gpr.agc(75., AgcMethod::Rms, false).is_err());
+    }
+
     #[test]
     fn test_correct_antenna_separation() {
         let mut gpr = make_test_gpr(Some(10), Some(1024));
@@ -2066,6 +4364,292 @@ mod tests {
         assert!(gpr.data[[10, 0]] > 10.);
     }
 
+    #[test]
+    fn test_velocity_model() {
+        use crate::tools;
+        use crate::velocity::VelocityModel;
+        use ndarray_stats::QuantileExt;
+
+        let mut gpr = make_test_gpr(Some(10), Some(500));
+        gpr.data.fill(0.);
+        gpr.data.row_mut(300).fill(1.);
+        let constant_depths = gpr.depths();
+
+        // 10 m of firn over ice, where the firn takes 100 ns (two-way) to pass
+        gpr.metadata.velocity_model =
+            Some(VelocityModel::from_depth_layers(&[(0., 0.2), (10., 0.168)]).unwrap());
+        let depths = gpr.depths();
+        assert_eq!(depths[50], tools::return_time_to_depth(50., 0.2, 2.));
+        assert!((depths[100] - tools::return_time_to_depth(100., 0.2, 2.)).abs() < 1e-4);
+        assert!(depths[400] > constant_depths[400]);
+        // Below the firn, the depth increases with the velocity of ice
+        assert!(
+            ((depths[400] - depths[300]) - (constant_depths[400] - constant_depths[300])).abs()
+                < 0.01
+        );
+
+        // The reflector is moved to its depth in the topographically corrected data
+        gpr.correct_topography();
+        assert!(gpr
+            .log
+            .last()
+            .unwrap()
+            .contains("velocity model of 0.2 m/ns from 0 m"));
+        let max_depth = tools::return_time_to_depth(500., 2. * 43.6 / 500., 2.);
+        let expected_row = depths[300] / max_depth * 500.;
+        // The last trace is the highest, so it is not shifted
+        let row = gpr.topo_data.as_ref().unwrap().column(9).argmax().unwrap();
+        assert!(
+            (row as f32 - expected_row).abs() <= 1.,
+            "{row} {expected_row}"
+        );
+        assert!(row > 300);
+    }
+
+    #[test]
+    fn test_pick_horizon() {
+        use crate::horizons::Phase;
+
+        // A 100 MHz reflection (with 2.5 ns samples) that dips by 1 ns per trace
+        let mut gpr = make_test_gpr(Some(60), Some(200));
+        gpr.data = ndarray::Array2::from_shape_fn((200, 60), |(row, col)| {
+            let t = row as f32 - (80. + 0.4 * col as f32);
+            (2. * std::f32::consts::PI * 0.25 * t).cos() * (-(t / 4.).powi(2)).exp()
+        });
+
+        gpr.process(&"pick_horizon(bed 10:210)".parse().unwrap())
+            .unwrap();
+        let provenance_step = gpr.provenance.steps.last().unwrap();
+        assert_eq!(
+            provenance_step.resolved,
+            "pick_horizon(bed 10:210 phase=max window_ns=1.25 max_gap=10 threshold=0.3)"
+        );
+        assert_eq!(provenance_step.derived["n_picked"], 60.);
+        let picks = &gpr.horizons[0].picks;
+        assert!((picks[10].unwrap() - 210.).abs() < 0.5, "{:?}", picks[10]);
+        assert!((picks[59].unwrap() - 259.).abs() < 0.5, "{:?}", picks[59]);
+
+        // Picking again replaces the horizon of the same name
+        gpr.pick_horizon("bed", &[(0, 200.)], Phase::Max, None, 10, 0.3)
+            .unwrap();
+        gpr.pick_horizon("trough", &[(0, 205.)], Phase::Min, Some(5.), 10, 0.3)
+            .unwrap();
+        assert_eq!(
+            gpr.horizons
+                .iter()
+                .map(|h| h.name.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["bed", "trough"]
+        );
+        assert!((gpr.horizons[1].picks[0].unwrap() - 205.).abs() < 0.5);
+        assert!(gpr
+            .pick_horizon("bed", &[(0, 200.)], Phase::Max, None, 10, 1.5)
+            .is_err());
+        assert!(gpr
+            .pick_horizon("bed", &[(60, 200.)], Phase::Max, None, 10, 0.3)
+            .is_err());
+        for window_ns in [0., -1., f32::INFINITY, f32::NAN] {
+            assert!(gpr
+                .pick_horizon("bed", &[(0, 200.)], Phase::Max, Some(window_ns), 10, 0.3)
+                .is_err());
+        }
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        crate::io::export_picks(&gpr, None, &temp_dir.path().join("gpr.nc"), false).unwrap();
+        let content = std::fs::read_to_string(temp_dir.path().join("gpr_picks.csv")).unwrap();
+        let lines = content.lines().collect::<Vec<&str>>();
+        assert_eq!(
+            lines[0],
+            "horizon,trace,return_time_ns,depth_m,easting,northing,elevation,quality"
+        );
+        assert_eq!(lines.len(), 121);
+        let fields = lines[1].split(',').collect::<Vec<&str>>();
+        assert_eq!(fields[..2], ["bed", "0"]);
+        let (time, depth) = (
+            fields[2].parse::<f32>().unwrap(),
+            fields[3].parse::<f32>().unwrap(),
+        );
+        assert_eq!(depth, gpr.depth_at(time));
+
+        // The picks follow the subset, and are discarded if the traces change otherwise
+        gpr.process(&"subset(10 -1 20 -1)".parse().unwrap())
+            .unwrap();
+        assert_eq!(gpr.horizons[0].picks.len(), 50);
+        assert!((gpr.horizons[0].picks[0].unwrap() - 160.).abs() < 0.5);
+
+        // They move up with the samples that a zero correction removes, ...
+        // as do the diffractions
+        gpr.diffractions.push(crate::hyperbola::Diffraction {
+            trace: 5,
+            return_time: 300.,
+            velocity: 0.168,
+            uncertainty: 0.01,
+            n_picks: 10,
+            coherence: 0.8,
+        });
+        let height = gpr.height();
+        gpr.process(&"zero_corr".parse().unwrap()).unwrap();
+        let removed_ns = (height - gpr.height()) as f32 * gpr.vertical_resolution_ns();
+        assert!(removed_ns > 0.);
+        assert!((gpr.horizons[0].picks[0].unwrap() - (160. - removed_ns)).abs() < 0.5);
+        assert_eq!(gpr.diffractions[0].return_time, 300. - removed_ns);
+
+        // ... but are discarded if the samples are resampled
+        gpr.process(&"correct_antenna_separation".parse().unwrap())
+            .unwrap();
+        assert!(gpr.horizons.is_empty());
+        gpr.horizons.push(crate::horizons::Horizon::new(
+            "bed",
+            &vec![Some((100., 1.)); gpr.width()],
+        ));
+        gpr.process(&"average_traces(2)".parse().unwrap()).unwrap();
+        assert!(gpr.horizons.is_empty());
+    }
+
+    #[test]
+    fn test_hilbert_attributes() {
+        // An 800 MHz wavelet, sampled at 8000 MHz
+        let mut gpr = make_test_gpr(Some(4), Some(256));
+        gpr.data = ndarray::Array2::from_shape_fn((256, 4), |(row, _)| {
+            let t = row as f32 - 128.;
+            (2. * std::f32::consts::PI * 0.1 * t).cos() * (-(t / 30.).powi(2)).exp()
+        });
+        let data = gpr.data.clone();
+
+        gpr.process(&"envelope".parse().unwrap()).unwrap();
+        assert!((gpr.data[[128, 0]] - 1.).abs() < 0.01);
+        assert!(gpr.data.iter().all(|v| *v >= 0.));
+
+        gpr.data = data.clone();
+        gpr.process(&"instantaneous_frequency".parse().unwrap())
+            .unwrap();
+        assert!(
+            (gpr.data[[128, 2]] - 800.).abs() < 5.,
+            "{}",
+            gpr.data[[128, 2]]
+        );
+
+        gpr.data = data;
+        gpr.process(&"instantaneous_phase".parse().unwrap())
+            .unwrap();
+        assert!(gpr.data[[128, 1]].abs() < 0.01);
+        assert!(gpr.data.iter().all(|v| v.abs() <= std::f32::consts::PI));
+        assert!(gpr.log.last().unwrap().contains("instantaneous phase"));
+    }
+
+    #[test]
+    fn test_fit_hyperbolae() {
+        // A diffraction at trace 30 and 250 ns in a medium of 0.1 m/ns, with 1 m trace spacing
+        let mut gpr = make_test_gpr(Some(60), Some(400));
+        let apex_time = 250.;
+        let depth = ((0.1_f32 * apex_time / 2.).powi(2) - 1.).sqrt();
+        gpr.data = ndarray::Array2::from_shape_fn((400, 60), |(row, col)| {
+            let offset = col as f32 - 30.;
+            let time = (((offset - 1.).powi(2) + depth.powi(2)).sqrt()
+                + ((offset + 1.).powi(2) + depth.powi(2)).sqrt())
+                / 0.1;
+            let t = row as f32 - time / 1.25;
+            (2. * std::f32::consts::PI * 0.2 * t).cos() * (-(t / 4.).powi(2)).exp()
+        });
+
+        gpr.process(&"fit_hyperbolae(30:245)".parse().unwrap())
+            .unwrap();
+        assert_eq!(gpr.diffractions.len(), 1);
+        let diffraction = gpr.diffractions[0];
+        assert_eq!(diffraction.trace, 30);
+        assert!(
+            (diffraction.velocity - 0.1).abs() < 0.002,
+            "{diffraction:?}"
+        );
+        let provenance_step = gpr.provenance.steps.last().unwrap();
+        assert_eq!(provenance_step.derived["n_diffractions"], 1.);
+        assert!(gpr.log.last().unwrap().contains("at trace 30"));
+
+        // The diffraction is found automatically and written as a velocity model
+        gpr.process(&"fit_hyperbolae".parse().unwrap()).unwrap();
+        assert_eq!(gpr.diffractions.len(), 1);
+        assert!((gpr.diffractions[0].velocity - 0.1).abs() < 0.002);
+        let temp_dir = tempfile::tempdir().unwrap();
+        crate::io::export_velocity_table(&gpr, None, &temp_dir.path().join("gpr.nc"), false)
+            .unwrap();
+        let model =
+            crate::velocity::VelocityModel::from_csv(&temp_dir.path().join("gpr_velocity.csv"))
+                .unwrap();
+        assert!((model.average_velocity(apex_time) - gpr.diffractions[0].velocity).abs() < 1e-5);
+
+        // The diffractions follow a subset, and are discarded if the traces change otherwise
+        gpr.process(&"subset(10 50)".parse().unwrap()).unwrap();
+        assert_eq!(gpr.diffractions[0].trace, 20);
+        gpr.process(&"average_traces(2)".parse().unwrap()).unwrap();
+        assert!(gpr.diffractions.is_empty());
+        assert!(gpr.fit_hyperbolae(&[(5, 600.)], None, 1).is_err());
+        for aperture_m in [0., -5., f32::INFINITY, f32::NAN] {
+            assert!(gpr.fit_hyperbolae(&[], Some(aperture_m), 1).is_err());
+        }
+    }
+
+    #[test]
+    fn test_detect_bed() {
+        // A strong direct wave, a weak internal layer and a dipping bed (in 1.25 ns samples)
+        let mut gpr = make_test_gpr(Some(60), Some(400));
+        let wavelet =
+            |t: f32| (2. * std::f32::consts::PI * 0.2 * t).cos() * (-(t / 4.).powi(2)).exp();
+        gpr.data = ndarray::Array2::from_shape_fn((400, 60), |(row, col)| {
+            let row = row as f32;
+            5. * wavelet(row - 10.)
+                + 0.2 * wavelet(row - 150.)
+                + 0.5 * wavelet(row - (300. - 0.5 * col as f32))
+        });
+
+        gpr.process(&"detect_bed".parse().unwrap()).unwrap();
+        let provenance_step = gpr.provenance.steps.last().unwrap();
+        assert_eq!(
+            provenance_step.resolved,
+            "detect_bed(min_depth=10 max_jump_ns=1.25 prior_tolerance=50)"
+        );
+        assert_eq!(provenance_step.derived["n_picked"], 60.);
+
+        let bed_picks = gpr.bed_picks().unwrap();
+        for (trace, (pick, time)) in bed_picks.iter().zip(&gpr.horizons[0].picks).enumerate() {
+            let (pick, time) = (pick.unwrap(), time.unwrap());
+            let expected = (300. - 0.5 * trace as f32) * gpr.vertical_resolution_ns();
+            assert!((time - expected).abs() < 1., "{trace}: {time} {expected}");
+            assert_eq!(pick.ice_thickness, gpr.depth_at(time));
+            assert_eq!(
+                pick.bed_elevation,
+                gpr.location.cor_points[trace].altitude - pick.ice_thickness as f64
+            );
+            assert!(pick.quality > 0.5, "{trace}: {}", pick.quality);
+        }
+
+        // The ice thickness is added to the track file
+        let temp_dir = tempfile::tempdir().unwrap();
+        crate::io::export_locations(
+            &gpr.location,
+            bed_picks.as_slice().into(),
+            None,
+            &temp_dir.path().join("gpr.nc"),
+            false,
+        )
+        .unwrap();
+        let content = std::fs::read_to_string(temp_dir.path().join("gpr_track.csv")).unwrap();
+        let lines = content.lines().collect::<Vec<&str>>();
+        assert_eq!(
+            lines[0],
+            "trace_n,easting,northing,altitude,ice_thickness,bed_elevation,bed_quality"
+        );
+        assert_eq!(lines.len(), 61);
+        assert_eq!(
+            lines[1].split(',').nth(4).unwrap().parse::<f32>().unwrap(),
+            bed_picks[0].unwrap().ice_thickness
+        );
+
+        // Without a minimum depth, the direct wave is stronger than the bed
+        gpr.detect_bed(0., None, None, 50.).unwrap();
+        assert!(gpr.horizons[0].picks[0].unwrap() < 20.);
+        assert!(gpr.detect_bed(1000., None, None, 50.).is_err());
+    }
+
     #[test]
     fn test_equidistant_traces() {
         let width = 128;
@@ -2085,7 +4669,7 @@ mod tests {
             point.altitude = first.altitude;
         }
         assert_eq!(gpr.width(), width);
-        gpr.make_equidistant(None);
+        gpr.make_equidistant(None).unwrap();
         // Now, the N stationary points should be coerced into one
         assert_eq!(gpr.width(), width - (n_stationary - 1));
     }
@@ -2129,6 +4713,51 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_markers() {
+        let make_markers = |traces: &[usize]| {
+            traces
+                .iter()
+                .map(|t| super::Marker {
+                    trace: *t,
+                    comment: format!("Marker {t}"),
+                })
+                .collect::<Vec<super::Marker>>()
+        };
+        let marker_traces =
+            |gpr: &super::GPR| gpr.markers.iter().map(|m| m.trace).collect::<Vec<usize>>();
+
+        let mut gpr = make_dummy_gpr(20, 30, Some(1.));
+        gpr.markers = make_markers(&[0, 5, 12, 19]);
+
+        // Markers on removed traces should move to the next remaining trace
+        gpr.remove_traces(&[5, 6], true).unwrap();
+        assert_eq!(marker_traces(&gpr), [0, 5, 10, 17]);
+        assert_eq!(gpr.markers[1].comment, "Marker 5");
+
+        // Markers outside of the subset should be removed
+        let mut gpr = gpr.subset(Some(2), Some(15), None, None).unwrap();
+        assert_eq!(marker_traces(&gpr), [3, 8]);
+
+        gpr.average_traces(2).unwrap();
+        assert_eq!(marker_traces(&gpr), [1, 4]);
+
+        // The markers of the other profile should be offset by the width of the first
+        let mut first = make_dummy_gpr(20, 30, Some(1.));
+        let mut second = make_dummy_gpr(20, 30, Some(1.));
+        first.markers = make_markers(&[4]);
+        second.markers = make_markers(&[2]);
+        first.merge(&second).unwrap();
+        assert_eq!(marker_traces(&first), [4, 22]);
+
+        // Markers should be moved to the closest trace of the new spacing
+        let mut gpr = make_dummy_gpr(20, 30, Some(1.));
+        gpr.markers = make_markers(&[0, 5, 19]);
+        gpr.make_equidistant(Some(2.)).unwrap();
+        assert_eq!(marker_traces(&gpr), [0, 3, 10]);
+        assert!(gpr.markers.iter().all(|m| m.trace < gpr.width()));
+    }
+
     #[test]
     fn test_remove_empty_traces() {
         let mut gpr = make_dummy_gpr(20, 30, Some(1.));
@@ -2146,4 +4775,636 @@ mod tests {
 
         assert_eq!(gpr.width(), 17);
     }
+
+    /// Write a fake Malå dataset (".rad", ".rd3" and ".cor") to a directory
+    ///
+    /// The data are a ramp of values that increase with the trace number.
+    fn write_fake_mala_files(
+        directory: &std::path::Path,
+        n_traces: usize,
+        n_samples: usize,
+    ) -> PathBuf {
+        write_fake_mala_files_with(directory, n_traces, n_samples, |trace, _| trace as i16)
+    }
+
+    /// Write fake Malå files like `write_fake_mala_files`, with the value of each trace and sample
+    fn write_fake_mala_files_with(
+        directory: &std::path::Path,
+        n_traces: usize,
+        n_samples: usize,
+        value: impl Fn(usize, usize) -> i16,
+    ) -> PathBuf {
+        let rad_path = directory.join("profile.rad");
+        let rad_text = [
+            format!("SAMPLES:{n_samples}"),
+            "FREQUENCY: 5000.".to_string(),
+            "FREQUENCY STEPS: 1".to_string(),
+            "TIME INTERVAL: 0.1".to_string(),
+            "ANTENNAS: 500 MHz shielded".to_string(),
+            "ANTENNA SEPARATION: 0.2".to_string(),
+            format!("TIMEWINDOW:{}", n_samples as f32 * 0.2),
+            format!("LAST TRACE: {n_traces}"),
+        ]
+        .join("\r\n");
+        std::fs::write(&rad_path, rad_text).unwrap();
+
+        let mut bytes = Vec::<u8>::new();
+        for trace in 0..n_traces {
+            for sample in 0..n_samples {
+                bytes.extend_from_slice(&value(trace, sample).to_le_bytes());
+            }
+        }
+        std::fs::write(rad_path.with_extension("rd3"), bytes).unwrap();
+
+        let cor_text = (0..n_traces)
+            .map(|i| {
+                format!(
+                    "{}\t2022-01-01\t00:00:{:02}\t78.{:04}\tN\t16.0\tE\t100.0\tM\t1",
+                    i + 1,
+                    i,
+                    i
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\r\n");
+        std::fs::write(rad_path.with_extension("cor"), cor_text).unwrap();
+
+        rad_path
+    }
+
+    #[test]
+    fn test_translate() {
+        use clap::Parser;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let rad_path = write_fake_mala_files(temp_dir.path(), 10, 16);
+        let out_path = temp_dir.path().join("translated.nc");
+
+        let args = crate::cli::Args::parse_from([
+            "radal",
+            "translate",
+            rad_path.to_str().unwrap(),
+            out_path.to_str().unwrap(),
+            "--quiet",
+        ]);
+        let params = match crate::cli::args_to_action(&args) {
+            crate::cli::CliAction::Run(params) => *params,
+            _ => panic!("Expected a run action"),
+        };
+        assert!(params.steps.is_empty());
+
+        super::run(params).unwrap();
+        assert!(out_path.is_file());
+
+        // Unknown output formats should be caught before any data are loaded
+        let args = crate::cli::Args::parse_from([
+            "radal",
+            "translate",
+            rad_path.to_str().unwrap(),
+            temp_dir.path().join("translated.xyz").to_str().unwrap(),
+        ]);
+        match crate::cli::args_to_action(&args) {
+            crate::cli::CliAction::Error(e) => assert!(e.contains("Unsupported output format")),
+            _ => panic!("Expected an error"),
+        };
+    }
+
+    #[test]
+    fn test_offset_horizontally() {
+        let mut location = make_gpr_location(6, None, None, None);
+        // Make the track go straight east, with one stationary point
+        for (i, point) in location.cor_points.iter_mut().enumerate() {
+            point.easting = 100. + i.min(3) as f64;
+            point.northing = 200.;
+        }
+        let original = location.clone();
+
+        location.offset_horizontally(1., 0.5);
+
+        for (point, orig) in location.cor_points.iter().zip(&original.cor_points) {
+            // Forward is east, and left is north
+            assert!((point.easting - (orig.easting + 1.)).abs() < 1e-9);
+            assert!((point.northing - (orig.northing + 0.5)).abs() < 1e-9);
+            assert_eq!(point.altitude, orig.altitude);
+        }
+    }
+
+    #[test]
+    fn test_run_mala_multichannel() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let rad_path = write_fake_mala_files(temp_dir.path(), 10, 16);
+        let rad_text = std::fs::read_to_string(&rad_path).unwrap();
+
+        // Make a two-channel set with a shared ".cor" file, with the second channel offset to the left
+        for (channel, y_offset) in [(1, 0.), (2, 0.5)] {
+            let channel_path = temp_dir.path().join(format!("profile_A{channel}.rad"));
+            std::fs::write(
+                &channel_path,
+                format!("{rad_text}\r\nCH_X_OFFSET:0.0\r\nCH_Y_OFFSET:{y_offset}"),
+            )
+            .unwrap();
+            std::fs::copy(
+                rad_path.with_extension("rd3"),
+                channel_path.with_extension("rd3"),
+            )
+            .unwrap();
+        }
+        std::fs::remove_file(&rad_path).unwrap();
+        std::fs::remove_file(rad_path.with_extension("rd3")).unwrap();
+
+        super::run(super::RunParams {
+            filepaths: vec![rad_path.clone()],
+            crs: Some("EPSG:32633".to_string()),
+            quiet: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let gprs = (0..2)
+            .map(|channel| {
+                crate::io::load_netcdf(
+                    &temp_dir.path().join(format!("profile_ch{channel}.nc")),
+                    None,
+                )
+                .unwrap()
+            })
+            .collect::<Vec<super::GPR>>();
+
+        for (p0, p1) in gprs[0]
+            .location
+            .cor_points
+            .iter()
+            .zip(&gprs[1].location.cor_points)
+        {
+            let distance =
+                ((p1.easting - p0.easting).powi(2) + (p1.northing - p0.northing).powi(2)).sqrt();
+            assert!((distance - 0.5).abs() < 1e-3);
+        }
+
+        // Channels should not be merged with each other
+        let mut gprs = gprs;
+        let second = gprs.pop().unwrap();
+        assert!(gprs[0].merge(&second).unwrap_err().contains("Channels"));
+    }
+
+    #[test]
+    fn test_netcdf_roundtrip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let nc_path = temp_dir.path().join("roundtrip.nc");
+
+        let mut gpr = make_test_gpr(Some(40), Some(30));
+        gpr.zero_point_ns = 2.5;
+        gpr.horizontal_signal_distance = 1.5;
+        gpr.topo_data = Some(ndarray::Array2::<f32>::ones((35, 40)));
+        gpr.agc_gain = Some(ndarray::Array2::<f32>::from_elem((30, 40), 0.5));
+        gpr.log.push("first step".to_string());
+        gpr.log.push("second step".to_string());
+        gpr.metadata.data_filepath = temp_dir.path().join("roundtrip.rd3");
+        gpr.metadata.velocity_model = Some(
+            crate::velocity::VelocityModel::from_depth_layers(&[(0., 0.2), (15., 0.168)]).unwrap(),
+        );
+        gpr.horizons = vec![crate::horizons::Horizon::new(
+            "bed",
+            &(0..40)
+                .map(|i| (i % 3 != 0).then_some((i as f32 * 2.5, 0.75)))
+                .collect::<Vec<Option<(f32, f32)>>>(),
+        )];
+        gpr.markers = vec![
+            super::Marker {
+                trace: 3,
+                comment: "Flag 1".to_string(),
+            },
+            super::Marker {
+                trace: 20,
+                comment: String::new(),
+            },
+        ];
+
+        gpr.export(&nc_path, &Default::default()).unwrap();
+
+        let loaded = crate::io::load_netcdf(&nc_path, None).unwrap();
+
+        assert_eq!(loaded.data, gpr.data);
+        assert_eq!(loaded.topo_data, gpr.topo_data);
+        assert_eq!(loaded.agc_gain, gpr.agc_gain);
+        assert_eq!(loaded.log, gpr.log);
+        assert_eq!(loaded.markers, gpr.markers);
+        assert_eq!(loaded.horizons, gpr.horizons);
+        // The ice thickness is derived from the bed horizon
+        let file = netcdf::open(&nc_path).unwrap();
+        let ice_thickness = file
+            .variable("ice_thickness")
+            .unwrap()
+            .get_values::<f32, _>(..)
+            .unwrap();
+        assert!(ice_thickness[0].is_nan());
+        assert_eq!(ice_thickness[1], gpr.depth_at(2.5));
+        assert!(file.variable("bed_elevation").is_some());
+        assert!(file.variable("bed_quality").is_some());
+        assert_eq!(loaded.zero_point_ns, 2.5);
+        assert_eq!(loaded.horizontal_signal_distance, 1.5);
+        assert_eq!(loaded.location.crs, gpr.location.crs);
+        assert_eq!(loaded.location.cor_points.len(), 40);
+        for (a, b) in loaded
+            .location
+            .cor_points
+            .iter()
+            .zip(gpr.location.cor_points.iter())
+        {
+            assert_eq!(a.trace_n, b.trace_n);
+            assert_eq!(a.time_seconds, b.time_seconds);
+            assert_eq!(a.easting, b.easting);
+            assert_eq!(a.northing, b.northing);
+            assert_eq!(a.altitude, b.altitude);
+        }
+        assert_eq!(loaded.metadata.samples, 30);
+        assert_eq!(loaded.metadata.last_trace, 40);
+        assert_eq!(loaded.metadata.antenna, gpr.metadata.antenna);
+        assert_eq!(loaded.metadata.antenna_mhz, gpr.metadata.antenna_mhz);
+        assert_eq!(loaded.metadata.time_window, gpr.metadata.time_window);
+        assert_eq!(loaded.metadata.time_interval, gpr.metadata.time_interval);
+        assert_eq!(loaded.metadata.frequency, gpr.metadata.frequency);
+        assert_eq!(
+            loaded.metadata.medium_velocity,
+            gpr.metadata.medium_velocity
+        );
+        assert_eq!(loaded.metadata.velocity_model, gpr.metadata.velocity_model);
+        assert_eq!(loaded.depths(), gpr.depths());
+
+        let loaded = crate::io::load_netcdf(&nc_path, Some(100.)).unwrap();
+        assert_eq!(loaded.metadata.antenna_mhz, 100.);
+    }
+
+    #[test]
+    fn test_netcdf_equidistant_traces() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let nc_path = temp_dir.path().join("intermediate.nc");
+
+        let roundtrip = |time_interval: f32, distance_interval: Option<f32>| {
+            let mut gpr = make_test_gpr(Some(20), Some(16));
+            gpr.location = make_gpr_location(20, Some(0.5), None, None);
+            gpr.metadata.data_filepath = temp_dir.path().join("intermediate.rd3");
+            gpr.metadata.time_interval = time_interval;
+            gpr.metadata.distance_interval = distance_interval;
+            gpr.export(&nc_path, &Default::default()).unwrap();
+
+            let mut loaded = crate::io::load_netcdf(&nc_path, None).unwrap();
+            assert_eq!(loaded.metadata.distance_interval, distance_interval);
+            let result = loaded.process(&super::Step::EquidistantTraces { step: None });
+            (loaded, result)
+        };
+
+        // The step is found from the time interval of time-triggered data
+        let (loaded, result) = roundtrip(0.1, None);
+        result.unwrap();
+        assert_eq!(loaded.metadata.time_interval, 0.1);
+        assert_eq!(loaded.width(), 20);
+
+        // Distance-triggered data keep their distance interval as the default step
+        let (loaded, result) = roundtrip(f32::NAN, Some(0.25));
+        result.unwrap();
+        assert!(loaded.metadata.time_interval.is_nan());
+        assert_eq!(loaded.width(), 39);
+
+        // Without either interval, the step has to be given instead of collapsing the profile
+        let (loaded, result) = roundtrip(f32::NAN, None);
+        let err_msg = result.unwrap_err().to_string();
+        assert!(err_msg.contains("step has to be given"), "Got: {err_msg:?}");
+        assert_eq!(loaded.width(), 20);
+    }
+
+    #[test]
+    fn test_run_netcdf_input() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let rad_path = write_fake_mala_files(temp_dir.path(), 10, 16);
+        let nc_path = rad_path.with_extension("nc");
+
+        let make_params =
+            |filepath: PathBuf, output_path: Option<PathBuf>, step: &str| super::RunParams {
+                filepaths: vec![filepath],
+                output_path,
+                quiet: true,
+                steps: vec![step.parse().unwrap()],
+                ..Default::default()
+            };
+
+        super::run(make_params(rad_path, None, "subset(0 -1 0 8)")).unwrap();
+        assert!(nc_path.is_file());
+
+        // Continue processing from the intermediate result
+        let out_path = temp_dir.path().join("continued.nc");
+        super::run(make_params(
+            nc_path.clone(),
+            Some(out_path.clone()),
+            "subset(0 5)",
+        ))
+        .unwrap();
+
+        let continued = crate::io::load_netcdf(&out_path, None).unwrap();
+        assert_eq!(continued.height(), 8);
+        assert_eq!(continued.width(), 5);
+        assert_eq!(continued.log.len(), 2);
+
+        // The input should not be overwritten by default
+        let result = super::run(make_params(nc_path, None, "subset(0 5)"));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_replay() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        // A slight wave along the traces, so that the filters have something to work on
+        let wave = |trace: usize, sample: usize| trace as i16 + [0, 3, 1, -2][sample % 4];
+        let rad_path = write_fake_mala_files_with(temp_dir.path(), 20, 16, wave);
+        let nc_path = rad_path.with_extension("nc");
+
+        let steps = [
+            "subset(1)",
+            "equidistant_traces",
+            "normalize_horizontal_magnitudes",
+            "dewow(window_ns=0.6)",
+            "auto_gain(4)",
+        ];
+        super::run(super::RunParams {
+            filepaths: vec![rad_path.clone()],
+            quiet: true,
+            steps: steps.iter().map(|s| s.parse().unwrap()).collect(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let processed = crate::io::load_netcdf(&nc_path, None).unwrap();
+        let provenance = &processed.provenance;
+        let source = provenance.source.as_ref().unwrap();
+        assert_eq!(source.filepaths, vec![rad_path.clone()]);
+        assert_eq!(source.medium_velocity, 0.168);
+        assert_eq!(provenance.steps.len(), steps.len());
+        assert_eq!(provenance.steps[0].input_shape, [16, 20]);
+        assert_eq!(provenance.steps[0].output_shape, [16, 19]);
+        // Automatically determined values should be recorded
+        assert!(provenance.steps[1]
+            .resolved
+            .starts_with("equidistant_traces(step="));
+        assert_eq!(
+            provenance.steps[2].resolved,
+            "normalize_horizontal_magnitudes(skip_first=4)"
+        );
+        assert!(provenance.steps[4].derived.contains_key("gain_factor"));
+
+        assert_eq!(super::replay(&nc_path, None, true).unwrap(), 0.);
+
+        // The raw data may be given explicitly, e.g. if they were moved
+        let moved_dir = temp_dir.path().join("moved");
+        std::fs::create_dir(&moved_dir).unwrap();
+        let moved_path = write_fake_mala_files_with(&moved_dir, 20, 16, wave);
+        std::fs::remove_file(rad_path.with_extension("rd3")).unwrap();
+        assert!(super::replay(&nc_path, None, true)
+            .unwrap_err()
+            .to_string()
+            .contains("--raw"));
+        assert!(super::replay(&nc_path, Some(&moved_path), true).is_ok());
+
+        // A result that does not match should be an error
+        let mut modified = processed;
+        modified.data *= 2.;
+        let modified_path = temp_dir.path().join("modified.nc");
+        modified
+            .export(&modified_path, &Default::default())
+            .unwrap();
+        assert!(super::replay(&modified_path, Some(&moved_path), true)
+            .unwrap_err()
+            .to_string()
+            .contains("differ"));
+
+        // The resolved steps are replayed, not the requested ones
+        let mut modified = crate::io::load_netcdf(&nc_path, None).unwrap();
+        modified.provenance.steps[1].step = "equidistant_traces(step=100)".into();
+        modified
+            .export(&modified_path, &Default::default())
+            .unwrap();
+        assert!(super::replay(&modified_path, Some(&moved_path), true).is_ok());
+    }
+
+    #[test]
+    fn test_export_segy() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let segy_path = temp_dir.path().join("profile.sgy");
+
+        let (width, height) = (12, 30);
+        let mut gpr = make_test_gpr(Some(width), Some(height));
+        gpr.metadata.data_filepath = temp_dir.path().join("profile.rd3");
+        gpr.log
+            .push("dewow (duration: 0.01s):\tA very long log line ".repeat(4));
+
+        gpr.export(&segy_path, &Default::default()).unwrap();
+
+        let bytes = std::fs::read(&segy_path).unwrap();
+        let trace_bytes = 240 + 4 * height;
+        assert_eq!(bytes.len(), 3600 + width * trace_bytes);
+
+        let i16_at = |i: usize| i16::from_be_bytes([bytes[i], bytes[i + 1]]);
+        let i32_at =
+            |i: usize| i32::from_be_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+
+        // The textual header should be EBCDIC, starting with "C 1 " and ending with "END EBCDIC"
+        assert_eq!(bytes[0..4], [0xC3, 0x40, 0xF1, 0x40]);
+        assert_eq!(
+            bytes[39 * 80..39 * 80 + 14],
+            [0xC3, 0xF4, 0xF0, 0x40, 0xC5, 0xD5, 0xC4, 0x40, 0xC5, 0xC2, 0xC3, 0xC4, 0xC9, 0xC3]
+        );
+
+        // Binary header, with the exact interval in microseconds in the revision 2 fields
+        let interval_ps = (gpr.vertical_resolution_ns() * 1000.).round() as i16;
+        assert_eq!(i16_at(3216), interval_ps);
+        assert_eq!(i16_at(3220), height as i16);
+        assert_eq!(i16_at(3224), 5);
+        assert_eq!(bytes[3500..3502], [2, 0]);
+        assert_eq!(i32_at(3296), 0x0102_0304);
+        assert_eq!(
+            f64::from_be_bytes(bytes[3272..3280].try_into().unwrap()),
+            gpr.vertical_resolution_ns() as f64 / 1000.
+        );
+
+        // Trace headers and samples
+        for i in [0, width - 1] {
+            let start = 3600 + i * trace_bytes;
+            let point = gpr.location.cor_points[i];
+            assert_eq!(i32_at(start), i as i32 + 1);
+            assert_eq!(i16_at(start + 70), -100);
+            assert_eq!(i32_at(start + 72), (point.easting * 100.).round() as i32);
+            assert_eq!(i32_at(start + 76), (point.northing * 100.).round() as i32);
+            assert_eq!(i32_at(start + 40), (point.altitude * 100.).round() as i32);
+            assert_eq!(i16_at(start + 114), height as i16);
+
+            let last_sample = start + 240 + 4 * (height - 1);
+            assert_eq!(
+                f32::from_be_bytes(bytes[last_sample..last_sample + 4].try_into().unwrap()),
+                gpr.data[[height - 1, i]]
+            );
+        }
+
+        // Revision 1 has no extended fields
+        let options = crate::io::SegyOptions {
+            revision: 1,
+            ..Default::default()
+        };
+        gpr.export(&segy_path, &options).unwrap();
+        let bytes = std::fs::read(&segy_path).unwrap();
+        assert_eq!(bytes[3500..3502], [1, 0]);
+        assert!(bytes[3268..3300].iter().all(|b| *b == 0));
+
+        let options = crate::io::SegyOptions {
+            revision: 3,
+            ..Default::default()
+        };
+        assert!(gpr.export(&segy_path, &options).is_err());
+    }
+
+    #[test]
+    fn test_segy_roundtrip() {
+        use clap::Parser;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let segy_path = temp_dir.path().join("profile.sgy");
+
+        let (width, height) = (12, 30);
+        let mut gpr = make_test_gpr(Some(width), Some(height));
+        gpr.metadata.data_filepath = temp_dir.path().join("profile.rd3");
+        // Round the time window to make the picosecond interval exact
+        gpr.metadata.time_window = 300.;
+        gpr.export(&segy_path, &Default::default()).unwrap();
+
+        let (meta, location) = crate::io::load_segy(
+            &segy_path,
+            0.168,
+            None,
+            Some(&gpr.location.crs),
+            &Default::default(),
+        )
+        .unwrap();
+
+        assert_eq!(meta.samples, height as u32);
+        assert_eq!(meta.last_trace, width as u32);
+        assert_eq!(meta.time_window, 300.);
+        assert_eq!(meta.antenna_mhz, gpr.metadata.antenna_mhz);
+        assert_eq!(meta.antenna_separation, gpr.metadata.antenna_separation);
+        // The traces are one second apart
+        assert_eq!(meta.time_interval, 1.);
+        assert_eq!(location.crs, gpr.location.crs);
+        for (a, b) in location
+            .cor_points
+            .iter()
+            .zip(gpr.location.cor_points.iter())
+        {
+            assert!((a.easting - b.easting).abs() < 0.01);
+            assert!((a.northing - b.northing).abs() < 0.01);
+            assert!((a.altitude - b.altitude).abs() < 0.01);
+            assert_eq!(a.time_seconds, b.time_seconds.floor());
+        }
+
+        let loaded = super::GPR::from_meta_and_loc(location, meta).unwrap();
+        assert_eq!(loaded.data, gpr.data);
+
+        // Projected coordinates need a CRS
+        assert!(crate::io::load_segy(&segy_path, 0.168, None, None, &Default::default()).is_err());
+
+        // Revision 1 files only have the picosecond fields, which are detected from the header
+        let rev1_path = temp_dir.path().join("profile_rev1.sgy");
+        let options = crate::io::SegyOptions {
+            revision: 1,
+            ..Default::default()
+        };
+        gpr.export(&rev1_path, &options).unwrap();
+        let (meta, _) = crate::io::load_segy(
+            &rev1_path,
+            0.168,
+            None,
+            Some(&gpr.location.crs),
+            &Default::default(),
+        )
+        .unwrap();
+        assert_eq!(meta.time_window, 300.);
+
+        // The same should work from the CLI, with the SEG-Y options parsed
+        let out_path = temp_dir.path().join("translated.nc");
+        let args = crate::cli::Args::parse_from([
+            "radal",
+            "translate",
+            segy_path.to_str().unwrap(),
+            out_path.to_str().unwrap(),
+            "--crs",
+            &gpr.location.crs,
+            "--segy-coord-bytes",
+            "181,185",
+            "--segy-scalar",
+            "-100",
+            "--segy-interval-unit",
+            "ps",
+            "--quiet",
+        ]);
+        let params = match crate::cli::args_to_action(&args) {
+            crate::cli::CliAction::Run(params) => *params,
+            _ => panic!("Expected a run action"),
+        };
+        assert_eq!(params.segy_options.coordinate_bytes, [181, 185, 41]);
+        assert_eq!(params.segy_options.scalar, Some(-100.));
+        super::run(params).unwrap();
+        assert!(out_path.is_file());
+
+        let args = crate::cli::Args::parse_from([
+            "radal",
+            "translate",
+            segy_path.to_str().unwrap(),
+            out_path.to_str().unwrap(),
+            "--segy-interval-unit",
+            "fortnights",
+        ]);
+        assert!(matches!(
+            crate::cli::args_to_action(&args),
+            crate::cli::CliAction::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_run_segy_default_profile() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let segy_path = temp_dir.path().join("profile.sgy");
+
+        let (width, height) = (20, 64);
+        let mut gpr = make_test_gpr(Some(width), Some(height));
+        gpr.metadata.data_filepath = temp_dir.path().join("profile.rd3");
+        gpr.metadata.time_window = 640.;
+        // A decaying wave along the traces, so that the default profile has something to work on
+        gpr.data = ndarray::Array2::from_shape_fn((height, width), |(row, _)| {
+            1000. * [0., 3., 1., -2.][row % 4] * (-(row as f32) / 16.).exp()
+        });
+        gpr.export(&segy_path, &Default::default()).unwrap();
+
+        let out_path = temp_dir.path().join("processed.nc");
+        super::run(super::RunParams {
+            filepaths: vec![segy_path],
+            output_path: Some(out_path.clone()),
+            crs: Some(gpr.location.crs.clone()),
+            quiet: true,
+            steps: super::default_processing_profile(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        // The trace interval is found from the trace times, so equidistant_traces keeps the 1 m
+        // spacing instead of collapsing the profile
+        let processed = crate::io::load_netcdf(&out_path, None).unwrap();
+        assert_eq!(processed.metadata.time_interval, 1.);
+        assert_eq!(processed.width(), width);
+
+        // Without the trace times, the step cannot be determined
+        let mut gpr = make_test_gpr(Some(width), Some(height));
+        gpr.metadata.time_interval = f32::NAN;
+        let err_msg = gpr
+            .process(&super::Step::EquidistantTraces { step: None })
+            .unwrap_err()
+            .to_string();
+        assert!(err_msg.contains("step has to be given"), "Got: {err_msg:?}");
+        assert_eq!(gpr.width(), width);
+    }
 }
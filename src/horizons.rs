@@ -0,0 +1,592 @@
+/// Semi-automatic picking of horizons (reflectors) along a profile
+///
+/// A horizon is tracked from one or more seed picks. From each seed, the tracker moves trace by
+/// trace in both directions, and looks for the same phase of the signal (a peak, a trough or a
+/// zero crossing) in a window centered on the previous pick. Traces where the phase is not found
+/// are left as gaps, and the tracking stops after too many consecutive gaps.
+///
+/// The bed can also be detected automatically, as the strongest continuous reflector below a
+/// minimum depth.
+use std::ops::Range;
+use std::str::FromStr;
+
+use ndarray::{Array2, ArrayView1};
+
+/// The name of the horizon that the ice thickness is derived from
+pub const BED_HORIZON: &str = "bed";
+
+/// How much a jump between neighbouring traces costs when detecting the bed, relative to the
+/// strongest energy of one trace
+const BED_RESTART_PENALTY: f32 = 1.;
+
+/// Which phase of the reflection to follow
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    /// The positive peak
+    Max,
+    /// The negative trough
+    Min,
+    /// The zero crossing with the same polarity as at the seed
+    ZeroCrossing,
+}
+
+impl FromStr for Phase {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "max" => Ok(Phase::Max),
+            "min" => Ok(Phase::Min),
+            "zero_crossing" => Ok(Phase::ZeroCrossing),
+            _ => Err(format!(
+                "Unknown phase: {s:?}. Choose from \"max\", \"min\" or \"zero_crossing\""
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Phase::Max => write!(f, "max"),
+            Phase::Min => write!(f, "min"),
+            Phase::ZeroCrossing => write!(f, "zero_crossing"),
+        }
+    }
+}
+
+/// A named horizon with one optional pick per trace
+#[derive(Debug, Clone, PartialEq)]
+pub struct Horizon {
+    pub name: String,
+    /// The two-way return time (ns) of each trace, or None where the horizon was not found
+    pub picks: Vec<Option<f32>>,
+    /// The quality (0-1) of each pick, or None where the horizon was not found
+    pub quality: Vec<Option<f32>>,
+}
+
+impl Horizon {
+    /// Create a horizon from picks with a quality
+    ///
+    /// # Arguments
+    /// - `name`: The name of the horizon
+    /// - `picks`: The return time (ns) and quality (0-1) of each trace, or None for gaps
+    pub fn new(name: &str, picks: &[Option<(f32, f32)>]) -> Self {
+        Horizon {
+            name: name.to_string(),
+            picks: picks.iter().map(|p| p.map(|(time, _)| time)).collect(),
+            quality: picks
+                .iter()
+                .map(|p| p.map(|(_, quality)| quality))
+                .collect(),
+        }
+    }
+
+    /// The number of traces that have a pick
+    pub fn n_picked(&self) -> usize {
+        self.picks.iter().filter(|p| p.is_some()).count()
+    }
+}
+
+/// The ice thickness and bed elevation at a trace, from the bed horizon
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BedPick {
+    /// The depth of the bed below the surface (m)
+    pub ice_thickness: f32,
+    /// The elevation of the bed (m a.s.l.)
+    pub bed_elevation: f64,
+    /// The quality of the pick (0-1)
+    pub quality: f32,
+}
+
+/// A phase found in a trace
+#[derive(Debug, Clone, Copy)]
+struct Pick {
+    /// The (fractional) sample index
+    position: f32,
+    /// How strong the phase is, to compare with the seed
+    strength: f32,
+    /// Whether a zero crossing goes from negative to positive values
+    rising: bool,
+}
+
+/// Find the phase in a window of a trace
+///
+/// Peaks and troughs are refined to a fractional sample with a parabola through the neighbouring
+/// samples, and zero crossings are interpolated linearly.
+///
+/// # Arguments
+/// - `trace`: The trace
+/// - `center`: The (fractional) sample index that the window is centered on
+/// - `half_window`: The number of samples on each side of the center to search
+/// - `phase`: The phase to look for
+/// - `rising`: For zero crossings, the required polarity, if any
+///
+/// # Returns
+/// The strongest peak or trough, or the zero crossing closest to the center. None if there is no
+/// such phase within the window.
+fn find_phase(
+    trace: ArrayView1<f32>,
+    center: f32,
+    half_window: usize,
+    phase: Phase,
+    rising: Option<bool>,
+) -> Option<Pick> {
+    let n_samples = trace.len();
+    if n_samples < 3 {
+        return None;
+    }
+    let center_index = (center.round().max(0.) as usize).min(n_samples - 1);
+    let start = center_index.saturating_sub(half_window);
+    let end = center_index.saturating_add(half_window).min(n_samples - 1);
+
+    match phase {
+        Phase::Max | Phase::Min => {
+            let sign = match phase {
+                Phase::Max => 1.,
+                _ => -1.,
+            };
+            // Only local extremes within the window count, so that a pick at the edge of the
+            // window (on the flank of another reflection) is not accepted
+            (start.max(1)..end.min(n_samples - 2) + 1)
+                .filter(|i| {
+                    let value = sign * trace[*i];
+                    value > 0. && value >= sign * trace[i - 1] && value > sign * trace[i + 1]
+                })
+                .map(|i| {
+                    let (before, value, after) =
+                        (sign * trace[i - 1], sign * trace[i], sign * trace[i + 1]);
+                    let curvature = before - 2. * value + after;
+                    let offset = match curvature < 0. {
+                        true => (0.5 * (before - after) / curvature).clamp(-0.5, 0.5),
+                        false => 0.,
+                    };
+                    Pick {
+                        position: i as f32 + offset,
+                        strength: value,
+                        rising: false,
+                    }
+                })
+                .max_by(|a, b| {
+                    a.strength.total_cmp(&b.strength).then(
+                        (b.position - center)
+                            .abs()
+                            .total_cmp(&(a.position - center).abs()),
+                    )
+                })
+        }
+        Phase::ZeroCrossing => {
+            let strength = trace
+                .slice(ndarray::s![start..=end])
+                .iter()
+                .fold(0_f32, |max, v| max.max(v.abs()));
+            (start..end)
+                .filter_map(|i| {
+                    let (value, next) = (trace[i], trace[i + 1]);
+                    let is_rising = value < 0. && next >= 0.;
+                    let is_falling = value > 0. && next <= 0.;
+                    if !(is_rising || is_falling) || rising.is_some_and(|r| r != is_rising) {
+                        return None;
+                    }
+                    Some(Pick {
+                        position: i as f32 + value / (value - next),
+                        strength,
+                        rising: is_rising,
+                    })
+                })
+                .min_by(|a, b| {
+                    (a.position - center)
+                        .abs()
+                        .total_cmp(&(b.position - center).abs())
+                })
+        }
+    }
+}
+
+/// Track a horizon from seed picks
+///
+/// The seeds are tracked in order, and a seed does not overwrite picks of an earlier seed. A
+/// phase is only accepted if it is at least `threshold` times as strong as at its seed, and the
+/// quality of a pick is its strength relative to the seed (at most 1).
+///
+/// # Arguments
+/// - `data`: The data, with one trace per column
+/// - `seeds`: The trace index and (fractional) sample index of each seed
+/// - `phase`: The phase to follow
+/// - `half_window`: The number of samples above and below the previous pick to search
+/// - `max_gap`: The number of consecutive traces without a pick before the tracking stops
+/// - `threshold`: The minimum strength relative to the seed (0-1)
+///
+/// # Returns
+/// The (fractional) sample index and quality of each trace, or None where the horizon was not
+/// found
+///
+/// # Errors
+/// - A seed is outside of the data
+/// - The phase is not found close to a seed
+pub fn track(
+    data: &Array2<f32>,
+    seeds: &[(usize, f32)],
+    phase: Phase,
+    half_window: usize,
+    max_gap: usize,
+    threshold: f32,
+) -> Result<Vec<Option<(f32, f32)>>, String> {
+    let (n_samples, n_traces) = data.dim();
+    let mut picks = vec![None::<(f32, f32)>; n_traces];
+
+    for (seed_trace, seed_sample) in seeds {
+        if *seed_trace >= n_traces || *seed_sample < 0. || *seed_sample >= n_samples as f32 {
+            return Err(format!(
+                "The seed at trace {seed_trace} and sample {seed_sample} is outside of the data ({n_traces} traces, {n_samples} samples)"
+            ));
+        }
+        if picks[*seed_trace].is_some() {
+            continue;
+        }
+        let seed = find_phase(
+            data.column(*seed_trace),
+            *seed_sample,
+            half_window,
+            phase,
+            None,
+        )
+        .ok_or(format!(
+            "No {phase} phase was found within {half_window} samples of the seed at trace {seed_trace} and sample {seed_sample}"
+        ))?;
+        picks[*seed_trace] = Some((seed.position, 1.));
+        let min_strength = threshold * seed.strength;
+        let rising = Some(seed.rising).filter(|_| phase == Phase::ZeroCrossing);
+
+        for step in [-1_isize, 1] {
+            let mut previous = seed.position;
+            let mut gap = 0;
+            let mut trace = *seed_trace as isize + step;
+            while (0..n_traces as isize).contains(&trace) && picks[trace as usize].is_none() {
+                match find_phase(
+                    data.column(trace as usize),
+                    previous,
+                    half_window,
+                    phase,
+                    rising,
+                )
+                .filter(|pick| pick.strength >= min_strength)
+                {
+                    Some(pick) => {
+                        let quality = (pick.strength / seed.strength).min(1.);
+                        picks[trace as usize] = Some((pick.position, quality));
+                        previous = pick.position;
+                        gap = 0;
+                    }
+                    None => {
+                        gap += 1;
+                        if gap > max_gap {
+                            break;
+                        }
+                    }
+                }
+                trace += step;
+            }
+        }
+    }
+    Ok(picks)
+}
+
+/// Detect the strongest continuous reflector, such as the bed
+///
+/// The path through the profile with the largest sum of energy is found with dynamic programming,
+/// where the energy of each trace is normalized by its strongest sample in the window. Between
+/// neighbouring traces, the path may move by at most `max_jump` samples, or jump anywhere at a
+/// cost of one trace of full energy. Traces with an empty window (or without energy) are left as
+/// gaps, and the path starts anew after them.
+///
+/// The quality of a pick is one minus the ratio of the median energy in the window to the energy
+/// of the pick, so a pick that clearly stands out from the rest of the trace is close to 1.
+///
+/// # Arguments
+/// - `energy`: The energy (e.g. the squared envelope) of the data, with one trace per column
+/// - `windows`: The range of samples to search in each trace
+/// - `max_jump`: The largest change (samples) between neighbouring traces without a penalty
+///
+/// # Returns
+/// The (fractional) sample index and quality of each trace, or None where nothing was found
+pub fn detect_continuous(
+    energy: &Array2<f32>,
+    windows: &[Range<usize>],
+    max_jump: usize,
+) -> Vec<Option<(f32, f32)>> {
+    let (n_samples, n_traces) = energy.dim();
+    let windows = (0..n_traces)
+        .map(|trace| match windows.get(trace) {
+            Some(window) => window.start.min(n_samples)..window.end.min(n_samples),
+            None => 0..0,
+        })
+        .collect::<Vec<Range<usize>>>();
+    let scores = windows
+        .iter()
+        .enumerate()
+        .map(|(trace, window)| {
+            let values = energy.slice(ndarray::s![window.clone(), trace]);
+            let max = values.fold(0_f32, |max, v| max.max(*v));
+            match max > 0. {
+                true => values.iter().map(|v| v / max).collect(),
+                false => Vec::new(),
+            }
+        })
+        .collect::<Vec<Vec<f32>>>();
+    let argmax = |values: &[f32]| {
+        values
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(i, total)| (i, *total))
+    };
+
+    // The best total score of a path that ends at each sample of the window, and the sample of the
+    // previous trace (relative to its window) that the path came from
+    let mut totals = Vec::<Vec<f32>>::with_capacity(n_traces);
+    let mut origins = Vec::<Vec<Option<usize>>>::with_capacity(n_traces);
+    for (trace, score) in scores.iter().enumerate() {
+        let previous = trace
+            .checked_sub(1)
+            .and_then(|p| argmax(&totals[p]).map(|best| (p, best)));
+        let (trace_totals, trace_origins) = match previous {
+            None => (score.clone(), vec![None; score.len()]),
+            Some((p, (best, best_total))) => {
+                let previous_window = &windows[p];
+                let restart = best_total - BED_RESTART_PENALTY;
+                score
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| {
+                        let sample = windows[trace].start + i;
+                        let continued = (sample.saturating_sub(max_jump).max(previous_window.start)
+                            ..(sample + max_jump + 1).min(previous_window.end))
+                            .map(|j| {
+                                (
+                                    j - previous_window.start,
+                                    totals[p][j - previous_window.start],
+                                )
+                            })
+                            .max_by(|a, b| a.1.total_cmp(&b.1))
+                            .filter(|(_, total)| *total >= restart);
+                        match continued {
+                            Some((j, total)) => (value + total, Some(j)),
+                            None => (value + restart, Some(best)),
+                        }
+                    })
+                    .unzip()
+            }
+        };
+        totals.push(trace_totals);
+        origins.push(trace_origins);
+    }
+
+    // Follow the best paths back from their ends
+    let mut picks = vec![None::<(f32, f32)>; n_traces];
+    let mut current = None::<usize>;
+    for trace in (0..n_traces).rev() {
+        current = current.or_else(|| argmax(&totals[trace]).map(|(i, _)| i));
+        let Some(i) = current else {
+            continue;
+        };
+        let sample = windows[trace].start + i;
+        let column = energy.column(trace);
+        let value = column[sample];
+        let offset = match (sample.checked_sub(1), column.get(sample + 1)) {
+            (Some(before), Some(after)) => {
+                let curvature = column[before] - 2. * value + after;
+                match curvature < 0. {
+                    true => (0.5 * (column[before] - after) / curvature).clamp(-0.5, 0.5),
+                    false => 0.,
+                }
+            }
+            _ => 0.,
+        };
+        let mut window_values = column.slice(ndarray::s![windows[trace].clone()]).to_vec();
+        window_values.sort_by(f32::total_cmp);
+        let median = window_values[window_values.len() / 2];
+        let quality = match value > 0. {
+            true => (1. - median / value).clamp(0., 1.),
+            false => 0.,
+        };
+        picks[trace] = Some((sample as f32 + offset, quality));
+        current = origins[trace][i];
+    }
+    picks
+}
+
+/// Append the horizons of a profile to the horizons of the profile before it
+///
+/// Horizons with the same name are joined. Horizons that only exist in one of the profiles are
+/// left as gaps in the other.
+///
+/// # Arguments
+/// - `horizons`: The horizons of the first profile
+/// - `width`: The number of traces of the first profile
+/// - `other`: The horizons of the second profile
+/// - `other_width`: The number of traces of the second profile
+pub fn append(horizons: &mut Vec<Horizon>, width: usize, other: &[Horizon], other_width: usize) {
+    for horizon in horizons.iter_mut() {
+        match other.iter().find(|h| h.name == horizon.name) {
+            Some(other_horizon) => {
+                horizon.picks.extend(&other_horizon.picks);
+                horizon.quality.extend(&other_horizon.quality);
+            }
+            None => {
+                horizon.picks.resize(width + other_width, None);
+                horizon.quality.resize(width + other_width, None);
+            }
+        }
+    }
+    for other_horizon in other {
+        if !horizons.iter().any(|h| h.name == other_horizon.name) {
+            let mut picks = vec![None; width];
+            picks.extend(&other_horizon.picks);
+            let mut quality = vec![None; width];
+            quality.extend(&other_horizon.quality);
+            horizons.push(Horizon {
+                name: other_horizon.name.clone(),
+                picks,
+                quality,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{append, detect_continuous, track, Horizon, Phase};
+    use ndarray::Array2;
+
+    /// The sample indices of picks, without their quality
+    fn positions(picks: Vec<Option<(f32, f32)>>) -> Vec<Option<f32>> {
+        picks
+            .into_iter()
+            .map(|p| p.map(|(position, _)| position))
+            .collect()
+    }
+
+    /// A dipping 100 MHz reflection (sampled at 1 ns) that fades out between two traces
+    fn make_dipping_reflection(n_traces: usize) -> Array2<f32> {
+        Array2::from_shape_fn((200, n_traces), |(row, col)| {
+            let t = row as f32 - (80. + 0.4 * col as f32);
+            let amplitude = match (30..35).contains(&col) {
+                true => 0.,
+                false => 1.,
+            };
+            amplitude * (2. * std::f32::consts::PI * 0.1 * t).cos() * (-(t / 8.).powi(2)).exp()
+        })
+    }
+
+    #[test]
+    fn test_track() {
+        let data = make_dipping_reflection(60);
+
+        let picks = positions(track(&data, &[(10, 85.)], Phase::Max, 3, 10, 0.3).unwrap());
+        for (trace, pick) in picks.iter().enumerate() {
+            match (30..35).contains(&trace) {
+                // The gap is left empty, but the tracking continues on the other side
+                true => assert_eq!(*pick, None),
+                false => {
+                    let expected = 80. + 0.4 * trace as f32;
+                    let pick = pick.unwrap();
+                    assert!((pick - expected).abs() < 0.2, "{trace}: {pick} {expected}");
+                }
+            }
+        }
+
+        // The trough follows about half a period (5 ns) later, pulled in a little by the envelope
+        let picks = positions(track(&data, &[(10, 89.)], Phase::Min, 3, 10, 0.3).unwrap());
+        assert!((84. ..85.).contains(&picks[0].unwrap()), "{:?}", picks[0]);
+        assert!((picks[10].unwrap() - picks[0].unwrap() - 4.).abs() < 0.2);
+
+        // The zero crossing between the peak and the trough
+        let picks = positions(track(&data, &[(0, 82.)], Phase::ZeroCrossing, 3, 10, 0.3).unwrap());
+        assert!((picks[0].unwrap() - 82.5).abs() < 0.2, "{:?}", picks[0]);
+        assert!((picks[50].unwrap() - 102.5).abs() < 0.2, "{:?}", picks[50]);
+
+        // With a short maximum gap, the tracking stops at the gap, unless there is another seed
+        let picks = positions(track(&data, &[(10, 85.)], Phase::Max, 3, 2, 0.3).unwrap());
+        assert_eq!(picks.iter().filter(|p| p.is_some()).count(), 30);
+        let picks =
+            positions(track(&data, &[(10, 85.), (50, 100.)], Phase::Max, 3, 2, 0.3).unwrap());
+        assert_eq!(picks.iter().filter(|p| p.is_some()).count(), 55);
+
+        // The seed has full quality, and the faint traces next to it less
+        let mut faint = data.clone();
+        faint.column_mut(11).mapv_inplace(|v| v * 0.5);
+        let picks = track(&faint, &[(10, 85.)], Phase::Max, 3, 10, 0.3).unwrap();
+        assert_eq!(picks[10].unwrap().1, 1.);
+        assert!((picks[11].unwrap().1 - 0.5).abs() < 0.05);
+        assert!(picks[12].unwrap().1 > 0.95);
+
+        assert!(track(&data, &[(60, 85.)], Phase::Max, 3, 2, 0.3).is_err());
+        // There is no reflection at the seed
+        assert!(track(&data, &[(10, 180.)], Phase::Max, 3, 2, 0.3).is_err());
+    }
+
+    #[test]
+    fn test_detect_continuous() {
+        // A weak continuous reflector at 150 samples, a strong one at 100 samples that only
+        // covers a few traces, and a spike of noise
+        let mut energy = Array2::<f32>::from_elem((200, 40), 0.01);
+        for col in 0..40 {
+            energy[[150 + col / 10, col]] = 0.5;
+        }
+        for col in 18..21 {
+            energy[[100, col]] = 1.;
+        }
+        energy[[20, 5]] = 2.;
+        let windows = vec![50..200; 40];
+
+        let picks = detect_continuous(&energy, &windows, 2);
+        for (col, pick) in picks.iter().enumerate() {
+            let (position, quality) = pick.unwrap();
+            assert!(
+                (position - (150 + col / 10) as f32).abs() < 0.5,
+                "{col}: {position}"
+            );
+            assert!(quality > 0.9);
+        }
+
+        // Without a window, a trace is left as a gap. The spike is not picked even if it is
+        // searched, since jumping to it costs more than it gains
+        let mut windows = windows;
+        windows[3] = 0..0;
+        windows[5] = 0..200;
+        let picks = detect_continuous(&energy, &windows, 2);
+        assert_eq!(picks[3], None);
+        assert_eq!(picks[4].unwrap().0, 150.);
+        assert_eq!(picks[5].unwrap().0, 150.);
+        // Traces without any energy are gaps as well
+        assert_eq!(
+            detect_continuous(&Array2::zeros((10, 2)), &[0..10, 0..10], 1),
+            vec![None; 2]
+        );
+    }
+
+    #[test]
+    fn test_append() {
+        let horizon = |name: &str, picks: &[Option<f32>]| Horizon {
+            name: name.to_string(),
+            picks: picks.to_vec(),
+            quality: picks.iter().map(|p| p.map(|_| 1.)).collect(),
+        };
+        let mut horizons = vec![horizon("bed", &[Some(1.), None])];
+        append(
+            &mut horizons,
+            2,
+            &[horizon("layer", &[Some(3.)]), horizon("bed", &[Some(2.)])],
+            1,
+        );
+        assert_eq!(
+            horizons,
+            vec![
+                horizon("bed", &[Some(1.), None, Some(2.)]),
+                horizon("layer", &[None, None, Some(3.)])
+            ]
+        );
+        assert_eq!(horizons[0].n_picked(), 2);
+    }
+}
@@ -0,0 +1,512 @@
+/// Velocity estimation from diffraction hyperbolae
+///
+/// A point reflector (such as a boulder, a crevasse tip or an englacial channel) shows up as a
+/// hyperbola in a profile, whose shape only depends on the velocity of the medium above it. The
+/// velocity is estimated in two stages: first, the velocity whose hyperbola has the most energy
+/// along it is found with a scan. Then, the hyperbola is picked trace by trace around the scanned
+/// one, and the velocity is fitted to the picks with least squares, which also gives its
+/// uncertainty.
+use ndarray::Array2;
+
+use crate::velocity::VelocityModel;
+
+/// The slowest velocity to scan (m/ns)
+const MIN_VELOCITY: f32 = 0.01;
+/// The fastest velocity to scan (m/ns), close to the speed of light
+const MAX_VELOCITY: f32 = 0.3;
+/// The velocity step of the scan (m/ns)
+const VELOCITY_STEP: f32 = 0.001;
+/// The minimum envelope of a pick along the hyperbola, relative to the envelope at the apex
+const MIN_PICK_STRENGTH: f32 = 0.25;
+/// The minimum number of picks (besides the apex) to fit a velocity to
+const MIN_PICKS: usize = 3;
+/// The minimum envelope of a candidate apex in a scan, relative to the strongest envelope
+const MIN_APEX_STRENGTH: f32 = 0.1;
+/// The minimum coherence of a diffraction found in a scan
+const MIN_COHERENCE: f32 = 0.6;
+
+/// A fitted diffraction hyperbola
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Diffraction {
+    /// The trace index of the apex
+    pub trace: usize,
+    /// The two-way return time of the apex (ns)
+    pub return_time: f32,
+    /// The (root-mean-square) velocity of the medium above the apex (m/ns)
+    pub velocity: f32,
+    /// The standard error of the velocity (m/ns)
+    pub uncertainty: f32,
+    /// The number of traces that the hyperbola was picked in, including the apex
+    pub n_picks: usize,
+    /// The mean envelope along the fitted hyperbola within the aperture, relative to the apex
+    pub coherence: f32,
+}
+
+impl Diffraction {
+    /// The depth of the point reflector (m)
+    ///
+    /// # Arguments
+    /// - `antenna_separation`: The separation between the transmitter and receiver (m)
+    pub fn depth(&self, antenna_separation: f32) -> f32 {
+        apex_depth(self.return_time, self.velocity, antenna_separation)
+    }
+}
+
+/// The depth of a point reflector from the return time at its apex
+///
+/// # Arguments
+/// - `return_time`: The two-way return time at the apex (ns)
+/// - `velocity`: The velocity of the medium (m/ns)
+/// - `antenna_separation`: The separation between the transmitter and receiver (m)
+fn apex_depth(return_time: f32, velocity: f32, antenna_separation: f32) -> f32 {
+    ((velocity * return_time / 2.).powi(2) - (antenna_separation / 2.).powi(2))
+        .max(0.)
+        .sqrt()
+}
+
+/// The two-way return time of a point reflector at a horizontal offset from its apex
+///
+/// # Arguments
+/// - `offset`: The horizontal distance between the antenna midpoint and the apex (m)
+/// - `apex_time`: The two-way return time at the apex (ns)
+/// - `velocity`: The velocity of the medium (m/ns)
+/// - `antenna_separation`: The separation between the transmitter and receiver (m)
+fn travel_time(offset: f32, apex_time: f32, velocity: f32, antenna_separation: f32) -> f32 {
+    let depth = apex_depth(apex_time, velocity, antenna_separation);
+    let half_separation = antenna_separation / 2.;
+    (((offset - half_separation).powi(2) + depth.powi(2)).sqrt()
+        + ((offset + half_separation).powi(2) + depth.powi(2)).sqrt())
+        / velocity
+}
+
+/// The offset (-0.5 to 0.5) of the top of a parabola through three neighbouring values
+fn parabola_offset(before: f32, value: f32, after: f32) -> f32 {
+    let curvature = before - 2. * value + after;
+    match curvature < 0. {
+        true => (0.5 * (before - after) / curvature).clamp(-0.5, 0.5),
+        false => 0.,
+    }
+}
+
+/// A profile to search for diffraction hyperbolae in
+pub struct DiffractionSearch<'a> {
+    /// The envelope of the data, with one trace per column
+    pub envelope: &'a Array2<f32>,
+    /// The distance of each trace along the profile (m)
+    pub distances: &'a [f32],
+    /// The time between samples (ns)
+    pub sample_interval: f32,
+    /// The separation between the transmitter and receiver (m)
+    pub antenna_separation: f32,
+    /// The expected velocity (m/ns), to choose an aperture with
+    pub expected_velocity: f32,
+    /// The height of the window around the scanned hyperbola to pick it in (ns)
+    pub window: f32,
+}
+
+impl DiffractionSearch<'_> {
+    /// Half of the window, in samples
+    fn half_window(&self) -> usize {
+        ((self.window / 2. / self.sample_interval).round() as usize).max(1)
+    }
+
+    /// The envelope at a fractional sample, interpolated linearly and 0 outside of the data
+    fn envelope_at(&self, trace: usize, sample: f32) -> f32 {
+        let n_samples = self.envelope.nrows();
+        if sample < 0. || sample > (n_samples - 1) as f32 {
+            return 0.;
+        }
+        let lower = sample.floor() as usize;
+        let weight = sample - lower as f32;
+        let upper = (lower + 1).min(n_samples - 1);
+        self.envelope[[lower, trace]] * (1. - weight) + self.envelope[[upper, trace]] * weight
+    }
+
+    /// The strongest sample of the envelope of a trace within half a window of a sample
+    ///
+    /// # Returns
+    /// The (fractional) sample and its envelope, or None if the window is outside of the data
+    fn strongest(&self, trace: usize, center: f32) -> Option<(f32, f32)> {
+        let n_samples = self.envelope.nrows();
+        let center = center.round();
+        let half_window = self.half_window() as f32;
+        if center + half_window < 0. || center - half_window > (n_samples - 1) as f32 {
+            return None;
+        }
+        let start = (center - half_window).max(0.) as usize;
+        let end = ((center + half_window) as usize).min(n_samples - 1);
+        let column = self.envelope.column(trace);
+        let sample = (start..=end).max_by(|a, b| column[*a].total_cmp(&column[*b]))?;
+        let offset = match (sample.checked_sub(1), column.get(sample + 1)) {
+            (Some(before), Some(after)) => parabola_offset(column[before], column[sample], *after),
+            _ => 0.,
+        };
+        Some((sample as f32 + offset, column[sample]))
+    }
+
+    /// The traces within an aperture around an apex, with their offsets from it (m)
+    ///
+    /// # Arguments
+    /// - `trace`: The trace of the apex
+    /// - `return_time`: The return time of the apex (ns)
+    /// - `aperture`: The largest offset (m). If None, the depth of the apex at the expected
+    ///   velocity (where the hyperbola is at 45°), but at least five traces.
+    fn aperture_traces(
+        &self,
+        trace: usize,
+        return_time: f32,
+        aperture: Option<f32>,
+    ) -> Vec<(usize, f32)> {
+        let n_traces = self.distances.len();
+        let spacing = match n_traces > 1 {
+            true => (self.distances[n_traces - 1] - self.distances[0]) / (n_traces - 1) as f32,
+            false => 0.,
+        };
+        let aperture = aperture.unwrap_or(
+            apex_depth(return_time, self.expected_velocity, self.antenna_separation)
+                .max(5. * spacing),
+        );
+        let apex_distance = self.distances[trace];
+        self.distances
+            .iter()
+            .enumerate()
+            .map(|(i, distance)| (i, distance - apex_distance))
+            .filter(|(_, offset)| offset.abs() <= aperture)
+            .collect()
+    }
+
+    /// Fit the velocity of a diffraction hyperbola
+    ///
+    /// # Arguments
+    /// - `trace`: The trace of the apex
+    /// - `return_time`: The approximate return time of the apex (ns). It is moved to the
+    ///   strongest envelope within half a window.
+    /// - `aperture`: The largest offset from the apex to fit (m). If None, the depth of the apex
+    ///   at the expected velocity, but at least five traces.
+    ///
+    /// # Errors
+    /// - The apex is outside of the data
+    /// - The scan finds the fastest or slowest velocity, so there is no hyperbola
+    /// - The hyperbola was picked in too few traces
+    pub fn fit(
+        &self,
+        trace: usize,
+        return_time: f32,
+        aperture: Option<f32>,
+    ) -> Result<Diffraction, String> {
+        let (n_samples, n_traces) = self.envelope.dim();
+        let time_window = n_samples as f32 * self.sample_interval;
+        if trace >= n_traces || !(0. ..time_window).contains(&return_time) {
+            return Err(format!(
+                "The apex at trace {trace} and {return_time} ns is outside of the data ({n_traces} traces, {time_window} ns)"
+            ));
+        }
+        let (apex_sample, apex_strength) = self
+            .strongest(trace, return_time / self.sample_interval)
+            .filter(|(_, strength)| *strength > 0.)
+            .ok_or(format!(
+                "There is no signal at the apex at trace {trace} and {return_time} ns"
+            ))?;
+        let apex_time = apex_sample * self.sample_interval;
+        let traces = self.aperture_traces(trace, apex_time, aperture);
+        let predicted_sample = |offset: f32, velocity: f32| {
+            travel_time(offset, apex_time, velocity, self.antenna_separation) / self.sample_interval
+        };
+
+        // Scan for the velocity with the most energy along its hyperbola
+        let n_velocities = ((MAX_VELOCITY - MIN_VELOCITY) / VELOCITY_STEP).round() as usize + 1;
+        let (best, _) = (0..n_velocities)
+            .map(|i| {
+                let velocity = MIN_VELOCITY + i as f32 * VELOCITY_STEP;
+                let energy = traces
+                    .iter()
+                    .map(|(t, offset)| self.envelope_at(*t, predicted_sample(*offset, velocity)))
+                    .sum::<f32>();
+                (i, energy)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap_or((0, 0.));
+        if best == 0 || best == n_velocities - 1 {
+            return Err(format!(
+                "No hyperbola was found at trace {trace} and {apex_time} ns"
+            ));
+        }
+        let scanned_velocity = MIN_VELOCITY + best as f32 * VELOCITY_STEP;
+
+        // Pick the hyperbola around the scanned one
+        let picks = traces
+            .iter()
+            .filter_map(|(t, offset)| {
+                let (sample, strength) =
+                    self.strongest(*t, predicted_sample(*offset, scanned_velocity))?;
+                (strength >= MIN_PICK_STRENGTH * apex_strength)
+                    .then_some((*offset, sample * self.sample_interval))
+            })
+            .collect::<Vec<(f32, f32)>>();
+        let n_offset_picks = picks.iter().filter(|(offset, _)| *offset != 0.).count();
+        if n_offset_picks < MIN_PICKS {
+            return Err(format!(
+                "The hyperbola at trace {trace} and {apex_time} ns was only found in {n_offset_picks} traces beside the apex (at least {MIN_PICKS} are needed)"
+            ));
+        }
+
+        // Fit the velocity to the picks by least squares, with a golden-section search
+        let misfit = |velocity: f32| {
+            picks
+                .iter()
+                .map(|(offset, time)| {
+                    (time - travel_time(*offset, apex_time, velocity, self.antenna_separation))
+                        .powi(2)
+                })
+                .sum::<f32>()
+        };
+        let ratio = (5_f32.sqrt() - 1.) / 2.;
+        let (mut low, mut high) = (
+            (scanned_velocity - 10. * VELOCITY_STEP).max(MIN_VELOCITY),
+            (scanned_velocity + 10. * VELOCITY_STEP).min(MAX_VELOCITY),
+        );
+        while high - low > 1e-6 {
+            let lower = high - ratio * (high - low);
+            let upper = low + ratio * (high - low);
+            match misfit(lower) < misfit(upper) {
+                true => high = upper,
+                false => low = lower,
+            }
+        }
+        let velocity = (low + high) / 2.;
+
+        // The standard error follows from the residuals and the sensitivity of the times
+        let step = 1e-4;
+        let sensitivity = picks
+            .iter()
+            .map(|(offset, _)| {
+                let faster =
+                    travel_time(*offset, apex_time, velocity + step, self.antenna_separation);
+                let slower =
+                    travel_time(*offset, apex_time, velocity - step, self.antenna_separation);
+                ((faster - slower) / (2. * step)).powi(2)
+            })
+            .sum::<f32>();
+        let residual_variance = misfit(velocity) / (picks.len() - 1) as f32;
+        let coherence = traces
+            .iter()
+            .map(|(t, offset)| self.envelope_at(*t, predicted_sample(*offset, velocity)))
+            .sum::<f32>()
+            / (traces.len() as f32 * apex_strength);
+
+        Ok(Diffraction {
+            trace,
+            return_time: apex_time,
+            velocity,
+            uncertainty: (residual_variance / sensitivity).sqrt(),
+            n_picks: picks.len(),
+            coherence,
+        })
+    }
+
+    /// Find diffractions automatically and fit their velocities
+    ///
+    /// Candidate apexes are local maxima of the envelope that are at least twice as strong as
+    /// the envelope at the same return time half an aperture to either side, which excludes
+    /// horizontal reflectors. The strongest candidates are fitted first. Candidates that do not
+    /// fit a coherent hyperbola (such as crossing flanks of other hyperbolae), or that are within
+    /// half an aperture and window of a fitted one, are skipped.
+    ///
+    /// # Arguments
+    /// - `aperture`: The largest offset from an apex to fit (m). If None, see `fit`.
+    /// - `max_count`: The maximum number of diffractions to fit
+    ///
+    /// # Returns
+    /// The fitted diffractions, from the strongest to the weakest
+    pub fn scan(&self, aperture: Option<f32>, max_count: usize) -> Vec<Diffraction> {
+        let (n_samples, n_traces) = self.envelope.dim();
+        let half_window = self.half_window();
+        let min_strength =
+            MIN_APEX_STRENGTH * self.envelope.iter().fold(0_f32, |max, v| max.max(*v));
+
+        let mut candidates = Vec::<(usize, usize, f32)>::new();
+        for trace in 0..n_traces {
+            for sample in 0..n_samples {
+                let value = self.envelope[[sample, trace]];
+                if value < min_strength || value <= 0. {
+                    continue;
+                }
+                let is_local_max = (trace.saturating_sub(1)..(trace + 2).min(n_traces)).all(|t| {
+                    (sample.saturating_sub(half_window)..(sample + half_window + 1).min(n_samples))
+                        .all(|s| (t, s) == (trace, sample) || self.envelope[[s, t]] < value)
+                });
+                if !is_local_max {
+                    continue;
+                }
+                let time = sample as f32 * self.sample_interval;
+                let traces = self.aperture_traces(trace, time, aperture);
+                let (first, last) = (traces[0].0, traces[traces.len() - 1].0);
+                let is_point = [(first + trace) / 2, (trace + last).div_ceil(2)]
+                    .iter()
+                    .filter(|t| **t != trace)
+                    .all(|t| self.envelope[[sample, *t]] < 0.5 * value);
+                if is_point {
+                    candidates.push((trace, sample, value));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| b.2.total_cmp(&a.2));
+
+        let mut diffractions = Vec::<Diffraction>::new();
+        for (trace, sample, _) in candidates {
+            if diffractions.len() >= max_count {
+                break;
+            }
+            let time = sample as f32 * self.sample_interval;
+            let traces = self.aperture_traces(trace, time, aperture);
+            let half_aperture = (traces[traces.len() - 1].0 - traces[0].0) / 4;
+            let is_near_fitted = diffractions.iter().any(|d| {
+                d.trace.abs_diff(trace) <= half_aperture.max(1)
+                    && (d.return_time - time).abs() <= self.window
+            });
+            if is_near_fitted {
+                continue;
+            }
+            match self.fit(trace, time, aperture) {
+                Ok(diffraction) if diffraction.coherence >= MIN_COHERENCE => {
+                    diffractions.push(diffraction)
+                }
+                _ => (),
+            }
+        }
+        diffractions
+    }
+}
+
+/// Write the fitted diffractions as a velocity model CSV, which can be given to `--velocity-model`
+///
+/// The diffractions are listed as comments, followed by a layered model from their velocities
+/// (see [`VelocityModel::from_rms_velocities`]).
+///
+/// # Arguments
+/// - `diffractions`: The fitted diffractions
+/// - `antenna_separation`: The separation between the transmitter and receiver (m)
+///
+/// # Errors
+/// - There are no diffractions
+pub fn velocity_table(
+    diffractions: &[Diffraction],
+    antenna_separation: f32,
+) -> Result<String, String> {
+    if diffractions.is_empty() {
+        return Err(
+            "No diffraction hyperbolae were fitted (see the \"fit_hyperbolae\" step)".to_string(),
+        );
+    }
+    let model = VelocityModel::from_rms_velocities(
+        &diffractions
+            .iter()
+            .map(|d| (d.return_time, d.velocity))
+            .collect::<Vec<(f32, f32)>>(),
+    )?;
+
+    let mut text =
+        "# Diffraction hyperbolae: trace,return_time_ns,depth_m,velocity,uncertainty,n_picks\n"
+            .to_string();
+    for d in diffractions {
+        text += &format!(
+            "# {},{},{},{},{},{}\n",
+            d.trace,
+            d.return_time,
+            d.depth(antenna_separation),
+            d.velocity,
+            d.uncertainty,
+            d.n_picks
+        );
+    }
+    Ok(text + &model.to_csv_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{travel_time, velocity_table, DiffractionSearch};
+    use crate::velocity::VelocityModel;
+    use ndarray::Array2;
+
+    /// Point reflectors (trace, apex time) in 1 ns samples and 0.1 m trace spacing
+    fn make_diffractions(apexes: &[(usize, f32)], velocity: f32) -> Array2<f32> {
+        Array2::from_shape_fn((300, 200), |(row, col)| {
+            apexes
+                .iter()
+                .map(|(trace, apex_time)| {
+                    let offset = (col as f32 - *trace as f32) * 0.1;
+                    let t = row as f32 - travel_time(offset, *apex_time, velocity, 0.5);
+                    (-(t / 3.).powi(2)).exp()
+                })
+                .sum()
+        })
+    }
+
+    fn make_search<'a>(envelope: &'a Array2<f32>, distances: &'a [f32]) -> DiffractionSearch<'a> {
+        DiffractionSearch {
+            envelope,
+            distances,
+            sample_interval: 1.,
+            antenna_separation: 0.5,
+            expected_velocity: 0.168,
+            window: 8.,
+        }
+    }
+
+    #[test]
+    fn test_fit() {
+        let envelope = make_diffractions(&[(100, 120.)], 0.15);
+        let distances = (0..200).map(|i| i as f32 * 0.1).collect::<Vec<f32>>();
+        let search = make_search(&envelope, &distances);
+
+        // The apex is found from a rough guess
+        let diffraction = search.fit(101, 118., None).unwrap();
+        assert_eq!(diffraction.trace, 101);
+        assert!((diffraction.return_time - 120.).abs() < 0.5);
+        assert!(
+            (diffraction.velocity - 0.15).abs() < 0.003,
+            "{diffraction:?}"
+        );
+        assert!(diffraction.uncertainty < 0.003, "{diffraction:?}");
+        assert!(diffraction.n_picks > 20);
+        // 0.15 m/ns × 60 ns, minus a little for the antenna separation
+        assert!((diffraction.depth(0.5) - 9.).abs() < 0.1);
+
+        assert!(search.fit(200, 120., None).is_err());
+        assert!(search.fit(0, 400., None).is_err());
+        // There is no signal far from the hyperbola
+        assert!(search.fit(100, 280., None).is_err());
+
+        // A horizontal reflector is not a hyperbola
+        let flat = Array2::from_shape_fn((300, 200), |(row, _)| {
+            (-((row as f32 - 100.) / 3.).powi(2)).exp()
+        });
+        assert!(make_search(&flat, &distances).fit(100, 100., None).is_err());
+    }
+
+    #[test]
+    fn test_scan() {
+        let envelope = make_diffractions(&[(50, 100.), (140, 180.)], 0.17);
+        let distances = (0..200).map(|i| i as f32 * 0.1).collect::<Vec<f32>>();
+        let mut diffractions = make_search(&envelope, &distances).scan(None, 10);
+        diffractions.sort_by_key(|d| d.trace);
+
+        assert_eq!(diffractions.len(), 2, "{diffractions:?}");
+        assert_eq!(diffractions[0].trace, 50);
+        assert_eq!(diffractions[1].trace, 140);
+        for diffraction in &diffractions {
+            assert!(
+                (diffraction.velocity - 0.17).abs() < 0.003,
+                "{diffraction:?}"
+            );
+        }
+        assert_eq!(make_search(&envelope, &distances).scan(None, 1).len(), 1);
+
+        // The table is a velocity model with the diffractions as comments
+        let table = velocity_table(&diffractions, 0.5).unwrap();
+        assert_eq!(table.lines().filter(|l| l.starts_with('#')).count(), 3);
+        let model = VelocityModel::from_csv_str(&table).unwrap();
+        assert!((model.average_velocity(100.) - diffractions[0].velocity).abs() < 1e-5);
+        assert!(velocity_table(&[], 0.5).is_err());
+    }
+}
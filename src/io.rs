@@ -5,7 +5,17 @@ use std::collections::HashMap;
 use std::error::Error;
 use std::path::{Path, PathBuf};
 
-use crate::{gpr, tools};
+use crate::horizons::{BedPick, Horizon};
+use crate::provenance::Provenance;
+use crate::velocity::VelocityModel;
+use crate::{gpr, hyperbola, tools};
+
+/// File extensions of the formats that GPR data can be loaded from.
+pub const IMPORT_EXTENSIONS: &[&str] =
+    &["rad", "rd3", "rd7", "hd", "dt1", "dzt", "nc", "sgy", "segy"];
+
+/// File extensions of the formats that GPR data can be exported to.
+pub const EXPORT_EXTENSIONS: &[&str] = &["nc", "sgy", "segy"];
 
 /// Load and parse a Malå metadata file (.rad)
 ///
@@ -17,10 +27,18 @@ use crate::{gpr, tools};
 /// # Returns
 /// A gpr::GPRMeta instance.
 ///
+/// The data file is either a 16 bit ".rd3" file or a 32 bit ".rd7" file. The samples are scaled
+/// with the amplitude range of the ".rad" file when the data are loaded (see `load_rd3`).
+///
+/// Multichannel (MIRA) arrays write one file set per channel, with an "_A<channel>" suffix (e.g.
+/// "DAT_001_A3.rad"). The channel number is parsed from the suffix, and the position of the
+/// channel relative to the positioning antenna is read from the "CH_X_OFFSET" (along-track) and
+/// "CH_Y_OFFSET" (cross-track) keys.
+///
 /// # Errors
 /// - The file could not be read
 /// - The contents could not be parsed correctly
-/// - The associated ".rd3" file does not exist.
+/// - No associated ".rd3" or ".rd7" file exists.
 pub fn load_rad(
     filepath: &Path,
     medium_velocity: f32,
@@ -32,11 +50,21 @@ pub fn load_rad(
     // Collect all rows into a hashmap, assuming a "KEY:VALUE" structure.
     let data: HashMap<&str, &str> = content.lines().filter_map(|s| s.split_once(':')).collect();
 
-    let rd3_filepath = filepath.with_extension("rd3");
-    if !rd3_filepath.is_file() {
-        return Err(format!("File not found: {rd3_filepath:?}").into());
+    let rd3_filepath = match ["rd3", "rd7"]
+        .iter()
+        .map(|ext| filepath.with_extension(ext))
+        .find(|fp| fp.is_file())
+    {
+        Some(fp) => fp,
+        None => return Err(format!("File not found: {:?}", filepath.with_extension("rd3")).into()),
     };
 
+    let channel_offset = ["CH_X_OFFSET", "CH_Y_OFFSET"].map(|key| {
+        data.get(key)
+            .and_then(|v| v.trim().parse::<f32>().ok())
+            .unwrap_or(0.)
+    });
+
     // Extract and parse all required metadata into a new GPRMeta object.
     let antenna = data
         .get("ANTENNAS")
@@ -75,6 +103,7 @@ pub fn load_rad(
             .ok_or("No 'TIME INTERVAL' key in metadata")?
             .replace(' ', "")
             .parse()?,
+        distance_interval: None,
         antenna_mhz,
         antenna,
         antenna_separation: data
@@ -92,11 +121,53 @@ pub fn load_rad(
             .ok_or("No 'LAST TRACE' key in metadata")?
             .trim()
             .parse()?,
+        channel: mala_channel(&rd3_filepath).unwrap_or(0),
         data_filepath: rd3_filepath,
         medium_velocity,
+        channel_offset,
+        velocity_model: None,
     })
 }
 
+/// Load and parse a Malå marker file (.mrk)
+///
+/// Each row has the (one-based) trace number of a marker, optionally followed by a comment. Rows
+/// that do not start with a trace number are skipped.
+///
+/// # Arguments
+/// - `filepath`: The path to the file to read.
+///
+/// # Returns
+/// The markers, sorted by trace.
+///
+/// # Errors
+/// - The file could not be found/read
+pub fn load_mrk(filepath: &Path) -> Result<Vec<gpr::Marker>, Box<dyn Error>> {
+    let bytes = std::fs::read(filepath)?;
+    let content = String::from_utf8_lossy(&bytes);
+
+    let mut markers = content
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            let (trace_str, comment) = line
+                .split_once(|c: char| c.is_whitespace() || c == ',')
+                .unwrap_or((line, ""));
+            // The ".mrk"-files are 1-indexed whereas this is 0-indexed
+            let trace = trace_str.parse::<usize>().ok()?.checked_sub(1)?;
+            Some(gpr::Marker {
+                trace,
+                comment: comment
+                    .trim_matches(|c: char| c.is_whitespace() || c == ',')
+                    .to_string(),
+            })
+        })
+        .collect::<Vec<gpr::Marker>>();
+    markers.sort_by_key(|m| m.trace);
+
+    Ok(markers)
+}
+
 /// Load and parse a Malå ".cor" location file
 ///
 /// # Arguments
@@ -214,8 +285,60 @@ pub fn load_cor(
     }
 }
 
+/// Parse the channel of a Malå multichannel (MIRA) file from its "_A<channel>" suffix
+///
+/// # Returns
+/// The zero-based channel, or None if the filename has no channel suffix.
+pub fn mala_channel(filepath: &Path) -> Option<usize> {
+    let stem = filepath.file_stem()?.to_str()?;
+    let (_, suffix) = stem.rsplit_once("_A")?;
+    suffix.parse::<usize>().ok()?.checked_sub(1)
+}
+
+/// The voltage range of Malå data if the ".rad" file has none (mV)
+///
+/// Malå does not document the voltage of the integer range, and most ".rad" files do not state
+/// it, so the 50V (50000mV) of RGPR is used to keep the amplitudes comparable with earlier output.
+/// https://github.com/emanuelhuber/RGPR/blob/d78ff7745c83488111f9e63047680a30da8f825d/R/readMala.R#L8
+const DEFAULT_MALA_AMPLITUDE_RANGE_MV: f64 = 50000.;
+
+/// The factor to convert the samples of a Malå data file to millivolts
+///
+/// The voltage range of the full integer range is read from the "AMPLITUDE RANGE (mV)" key of
+/// the associated ".rad" file if it is there, and is otherwise `DEFAULT_MALA_AMPLITUDE_RANGE_MV`.
+///
+/// # Arguments
+/// - `filepath`: The path of the data file
+/// - `max_value`: The largest sample value of the bit depth
+///
+/// # Errors
+/// - The amplitude range is not a positive number
+fn mala_bits_to_millivolt(filepath: &Path, max_value: f64) -> Result<f64, Box<dyn Error>> {
+    let content = std::fs::read(filepath.with_extension("rad")).unwrap_or_default();
+    let content = String::from_utf8_lossy(&content);
+    let amplitude_range_mv = match content
+        .lines()
+        .filter_map(|s| s.split_once(':'))
+        .find(|(key, _)| key.trim() == "AMPLITUDE RANGE (mV)")
+    {
+        Some((_, v)) => match v.trim().parse::<f64>() {
+            Ok(range) if range > 0. => range,
+            _ => {
+                return Err(
+                    format!("Invalid 'AMPLITUDE RANGE (mV)' value for {filepath:?}: {v:?}").into(),
+                )
+            }
+        },
+        None => DEFAULT_MALA_AMPLITUDE_RANGE_MV,
+    };
+    Ok(amplitude_range_mv / max_value)
+}
+
 /// Load a Malå data (.rd3) file
 ///
+/// The samples are scaled to millivolts with the amplitude range of the ".rad" file (see
+/// `mala_bits_to_millivolt`).
+///
 /// # Arguments
 /// - `filepath`: The path of the file to read.
 /// - `height`: The expected height of the data. The width is parsed automatically.
@@ -225,72 +348,353 @@ pub fn load_cor(
 ///
 /// # Errors
 /// - The file cannot be read
+/// - The amplitude range of the ".rad" file is invalid
 /// - The length does not work with the expected shape
 pub fn load_rd3(filepath: &Path, height: usize) -> Result<Array2<f32>, Box<dyn std::error::Error>> {
     let bytes = std::fs::read(filepath)?;
 
-    let mut data: Vec<f32> = Vec::new();
-
-    // It's 50V (50000mV) in RGPR https://github.com/emanuelhuber/RGPR/blob/d78ff7745c83488111f9e63047680a30da8f825d/R/readMala.R#L8
-    let bits_to_millivolt = 50000. / i16::MAX as f32;
+    let bits_to_millivolt = mala_bits_to_millivolt(filepath, i16::MAX as f64)? as f32;
 
     // The values are read as 16 bit little endian signed integers, and are converted to millivolts
-    for byte_pair in bytes.chunks_exact(2) {
-        let value = i16::from_le_bytes([byte_pair[0], byte_pair[1]]);
-        data.push(value as f32 * bits_to_millivolt);
-    }
+    let data = bytes
+        .chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 * bits_to_millivolt)
+        .collect::<Vec<f32>>();
 
     let width: usize = data.len() / height;
 
     Ok(ndarray::Array2::from_shape_vec((width, height), data)?.reversed_axes())
 }
 
-pub fn load_pe_dt1(
+/// Load a 32 bit Malå data (.rd7) file
+///
+/// The format is the same as for ".rd3" files, but with 32 bit samples. The same voltage range
+/// is spread over the larger integer range.
+///
+/// # Arguments
+/// - `filepath`: The path of the file to read.
+/// - `height`: The expected height of the data. The width is parsed automatically.
+///
+/// # Returns
+/// A 2D array of 32 bit floating point values in the shape (height, width).
+///
+/// # Errors
+/// - The file cannot be read
+/// - The amplitude range of the ".rad" file is invalid
+/// - The length does not work with the expected shape
+pub fn load_rd7(filepath: &Path, height: usize) -> Result<Array2<f32>, Box<dyn std::error::Error>> {
+    let bytes = std::fs::read(filepath)?;
+
+    let bits_to_millivolt = mala_bits_to_millivolt(filepath, i32::MAX as f64)?;
+
+    // The values are read as 32 bit little endian signed integers, and are converted to millivolts
+    let data = bytes
+        .chunks_exact(4)
+        .map(|b| (i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64 * bits_to_millivolt) as f32)
+        .collect::<Vec<f32>>();
+
+    let width: usize = data.len() / height;
+
+    Ok(ndarray::Array2::from_shape_vec((width, height), data)?.reversed_axes())
+}
+
+/// The size of the header preceding each trace in a pulseEKKO data file (.dt1)
+const PE_TRACE_HEADER_BYTES: usize = 25 * 4 + 28; // 128
+
+/// The amplitude window to assume if the ".hd" file has none (from one header)
+const DEFAULT_PE_AMPLITUDE_WINDOW_MV: f32 = 104.12;
+
+/// The header of one trace in a pulseEKKO data file (.dt1)
+///
+/// The header consists of 25 little endian four byte words (of which the GPS position takes up six
+/// as 64 bit floats) and a 28 byte comment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeTraceHeader {
+    /// The trace number, as written by the control unit (one-based)
+    pub trace_n: u32,
+    /// The position along the line, in the position units of the ".hd" file
+    pub position: f32,
+    /// The number of samples in the trace
+    pub samples: u32,
+    /// The elevation of the trace, if entered
+    pub topography: f32,
+    /// The number of bytes per sample. Two for 16 bit data and four for the 32 bit variant
+    pub bytes_per_sample: usize,
+    /// The number of stacks of the trace
+    pub stacks: u32,
+    /// The GPS position (x, y, z) of the trace, if one was recorded
+    pub gps: Option<[f64; 3]>,
+    /// The time of the trace (seconds after midnight)
+    pub time_of_day: f32,
+}
+
+impl PeTraceHeader {
+    /// Parse a trace header from the (at least) 128 bytes preceding a trace
+    fn from_bytes(bytes: &[u8]) -> PeTraceHeader {
+        let f32_at =
+            |word: usize| f32::from_le_bytes(bytes[word * 4..word * 4 + 4].try_into().unwrap());
+        let f64_at =
+            |word: usize| f64::from_le_bytes(bytes[word * 4..word * 4 + 8].try_into().unwrap());
+
+        let gps = [f64_at(8), f64_at(10), f64_at(12)];
+
+        PeTraceHeader {
+            trace_n: f32_at(0) as u32,
+            position: f32_at(1),
+            samples: f32_at(2) as u32,
+            topography: f32_at(3),
+            bytes_per_sample: f32_at(5) as usize,
+            stacks: f32_at(7) as u32,
+            gps: match gps[0] != 0. || gps[1] != 0. {
+                true => Some(gps),
+                false => None,
+            },
+            time_of_day: f32_at(23),
+        }
+    }
+}
+
+/// Parse the "KEY = VALUE" rows of a pulseEKKO metadata file (.hd)
+fn parse_pe_hd(content: &str) -> HashMap<&str, &str> {
+    content
+        .lines()
+        .filter_map(|s| s.split_once('='))
+        .map(|(key, value)| (key.trim(), value.trim()))
+        .collect()
+}
+
+/// Read the trace headers of a pulseEKKO data file (.dt1)
+///
+/// # Arguments
+/// - `filepath`: The path of the ".dt1" file.
+/// - `height`: The expected number of samples per trace.
+/// - `width`: The expected number of traces.
+///
+/// # Returns
+/// One header per trace.
+///
+/// # Errors
+/// - The file cannot be read
+/// - The file is shorter than the headers claim
+pub fn load_pe_trace_headers(
     filepath: &Path,
     height: usize,
     width: usize,
-) -> Result<Array2<f32>, Box<dyn std::error::Error>> {
+) -> Result<Vec<PeTraceHeader>, Box<dyn Error>> {
     let bytes = std::fs::read(filepath)?;
+    Ok(pe_trace_offsets(&bytes, filepath, height, width)?
+        .into_iter()
+        .map(|(_, header)| header)
+        .collect())
+}
 
-    const TRACE_HEADER_BYTES: usize = 25 * 4 + 28; // 128
+/// Find the byte offset and header of each trace in a pulseEKKO data file (.dt1)
+///
+/// The sample size is taken from each trace header, or from the "DATA TYPE" key of the ".hd" file
+/// if the trace header value is not valid.
+///
+/// # Errors
+/// - The file is shorter than the headers claim
+fn pe_trace_offsets(
+    bytes: &[u8],
+    filepath: &Path,
+    height: usize,
+    width: usize,
+) -> Result<Vec<(usize, PeTraceHeader)>, Box<dyn Error>> {
+    let hd_content =
+        std::fs::read_to_string(with_extension_any_case(filepath, "hd")).unwrap_or_default();
+    let hd_bytes_per_sample = match parse_pe_hd(&hd_content).get("DATA TYPE") {
+        Some(&"I*4") => 4,
+        _ => 2,
+    };
 
-    // Based on one header. Should probably be set from the header itself.
-    // Also, it's a bit unclear if it should be halved or not...
-    let bits_to_millivolt = 104.12 / i16::MAX as f32;
+    let mut traces = Vec::<(usize, PeTraceHeader)>::with_capacity(width);
+    let mut offset: usize = 0;
+    for _ in 0..width {
+        if bytes.len() < offset + PE_TRACE_HEADER_BYTES {
+            return Err(format!(
+                "File too short: got {} bytes, expected at least {} bytes",
+                bytes.len(),
+                offset + PE_TRACE_HEADER_BYTES
+            )
+            .into());
+        }
+        let mut header = PeTraceHeader::from_bytes(&bytes[offset..]);
+        if ![2, 4].contains(&header.bytes_per_sample) {
+            header.bytes_per_sample = hd_bytes_per_sample;
+        }
+        offset += PE_TRACE_HEADER_BYTES;
+
+        let end = offset + height * header.bytes_per_sample;
+        if bytes.len() < end {
+            return Err(format!(
+                "File too short: got {} bytes, expected at least {} bytes",
+                bytes.len(),
+                end
+            )
+            .into());
+        }
+        traces.push((offset, header));
+        offset = end;
+    }
+    Ok(traces)
+}
 
-    let bytes_per_trace = TRACE_HEADER_BYTES + height * 2;
-    let expected_len = width * bytes_per_trace;
+/// Load a pulseEKKO data file (.dt1)
+///
+/// The samples are 16 or 32 bit little endian signed integers, which are converted to millivolts
+/// using the "AMPLITUDE WINDOW (mV)" of the associated ".hd" file.
+///
+/// # Arguments
+/// - `filepath`: The path of the file to read.
+/// - `height`: The expected height of the data.
+/// - `width`: The expected width of the data.
+///
+/// # Returns
+/// A 2D array of 32 bit floating point values in the shape (height, width).
+///
+/// # Errors
+/// - The file cannot be read
+/// - The file is shorter than the expected shape
+pub fn load_pe_dt1(
+    filepath: &Path,
+    height: usize,
+    width: usize,
+) -> Result<Array2<f32>, Box<dyn std::error::Error>> {
+    let bytes = std::fs::read(filepath)?;
 
-    if bytes.len() < expected_len {
-        return Err(format!(
-            "File too short: got {} bytes, expected at least {} bytes",
-            bytes.len(),
-            expected_len
-        )
-        .into());
-    }
+    // It's a bit unclear if the amplitude window should be halved or not...
+    let hd_content =
+        std::fs::read_to_string(with_extension_any_case(filepath, "hd")).unwrap_or_default();
+    let amplitude_window_mv = parse_pe_hd(&hd_content)
+        .get("AMPLITUDE WINDOW (mV)")
+        .and_then(|v| v.parse::<f32>().ok())
+        .unwrap_or(DEFAULT_PE_AMPLITUDE_WINDOW_MV);
 
     let mut data: Vec<f32> = Vec::with_capacity(height * width);
-    let mut offset: usize = 0;
-
-    for _ in 0..width {
-        offset += TRACE_HEADER_BYTES;
 
-        let end = offset + height * 2;
-        let slice = &bytes[offset..end];
+    for (offset, header) in pe_trace_offsets(&bytes, filepath, height, width)? {
+        let slice = &bytes[offset..offset + height * header.bytes_per_sample];
 
-        for j in 0..height {
-            let k = j * 2;
-            let v = i16::from_le_bytes([slice[k], slice[k + 1]]);
-            data.push(v as f32 * bits_to_millivolt);
+        match header.bytes_per_sample {
+            4 => {
+                let bits_to_millivolt = amplitude_window_mv as f64 / i32::MAX as f64;
+                data.extend(slice.chunks_exact(4).map(|b| {
+                    (i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64 * bits_to_millivolt) as f32
+                }));
+            }
+            _ => {
+                let bits_to_millivolt = amplitude_window_mv / i16::MAX as f32;
+                data.extend(
+                    slice
+                        .chunks_exact(2)
+                        .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 * bits_to_millivolt),
+                );
+            }
         }
-
-        offset = end;
     }
 
     Ok(Array2::from_shape_vec((width, height), data)?.reversed_axes())
 }
 
+/// Create a location track from the trace headers of a pulseEKKO data file (.dt1)
+///
+/// This is a fallback for when no ".gp2" file exists. If all traces have GPS positions and the
+/// ".hd" file says that they are in latitude and longitude ("LatLong"), they are projected like the
+/// ".gp2" coordinates. Other GPS positions are assumed to be in the given CRS. Without GPS
+/// positions, the position along the line is used as the easting, with a "local" CRS unless one is
+/// given.
+///
+/// # Arguments
+/// - `filepath`: The path of the ".dt1" file.
+/// - `height`: The number of samples per trace.
+/// - `width`: The number of traces.
+/// - `projected_crs`: The CRS to project coordinates into.
+///
+/// # Errors
+/// - The ".dt1" or ".hd" files cannot be read
+/// - The date in the ".hd" file cannot be parsed
+/// - GPS positions are not in latitude and longitude and no CRS was given
+pub fn load_pe_trace_locations(
+    filepath: &Path,
+    height: usize,
+    width: usize,
+    projected_crs: Option<&String>,
+) -> Result<gpr::GPRLocation, Box<dyn Error>> {
+    let headers = load_pe_trace_headers(filepath, height, width)?;
+    if headers.is_empty() {
+        return Err(format!("No traces in file: {filepath:?}").into());
+    }
+    let hd_content = std::fs::read_to_string(with_extension_any_case(filepath, "hd"))?;
+
+    // The date is on the third line, e.g. "2025-Apr-04"
+    let date_str = hd_content.lines().nth(2).unwrap_or_default().trim();
+    let midnight = chrono::NaiveDate::parse_from_str(date_str, "%Y-%b-%d")
+        .map_err(|e| format!("Could not parse date {date_str:?} in the '.hd' file: {e}"))?
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc()
+        .timestamp() as f64;
+
+    let mut points = headers
+        .iter()
+        .enumerate()
+        .map(|(i, header)| gpr::CorPoint {
+            trace_n: i as u32,
+            time_seconds: midnight + header.time_of_day as f64,
+            easting: header.position as f64,
+            northing: 0.,
+            altitude: header.topography as f64,
+        })
+        .collect::<Vec<gpr::CorPoint>>();
+
+    let gps = headers
+        .iter()
+        .map(|h| h.gps)
+        .collect::<Option<Vec<[f64; 3]>>>();
+
+    let crs = match gps {
+        Some(gps) if hd_content.contains("LatLong") => {
+            let coords = gps
+                .iter()
+                .map(|g| crate::coords::Coord { x: g[0], y: g[1] })
+                .collect::<Vec<crate::coords::Coord>>();
+            let projected_crs = match projected_crs {
+                Some(s) => s.to_string(),
+                None => crate::coords::UtmCrs::optimal_crs(&coords[0]).to_epsg_str(),
+            };
+            let projected = crate::coords::from_wgs84(
+                &coords,
+                &crate::coords::Crs::from_user_input(&projected_crs)?,
+            )?;
+            for ((point, coord), g) in points.iter_mut().zip(projected).zip(&gps) {
+                point.easting = coord.x;
+                point.northing = coord.y;
+                point.altitude = g[2];
+            }
+            projected_crs
+        }
+        Some(gps) => {
+            let projected_crs = projected_crs.ok_or(
+                "The trace header GPS positions are not in latitude and longitude. Specify the CRS with '--crs'",
+            )?;
+            for (point, g) in points.iter_mut().zip(&gps) {
+                point.easting = g[0];
+                point.northing = g[1];
+                point.altitude = g[2];
+            }
+            projected_crs.to_string()
+        }
+        None => projected_crs.cloned().unwrap_or("local".to_string()),
+    };
+
+    Ok(gpr::GPRLocation {
+        cor_points: points,
+        correction: gpr::LocationCorrection::None,
+        crs,
+    })
+}
+
 pub fn load_pe_hd(
     filepath: &Path,
     medium_velocity: f32,
@@ -298,11 +702,7 @@ pub fn load_pe_hd(
 ) -> Result<gpr::GPRMeta, Box<dyn Error>> {
     let content = std::fs::read_to_string(filepath)?;
 
-    // Collect all rows into a hashmap, assuming a "KEY:VALUE" structure.
-    let mut data = HashMap::<&str, &str>::new();
-    for (key, value) in content.lines().filter_map(|s| s.split_once('=')) {
-        data.insert(key.trim(), value.trim());
-    }
+    let data = parse_pe_hd(&content);
     let samples: u32 = data
         .get("NUMBER OF PTS/TRC")
         .ok_or("No 'NUMBER OF PTS/TRC' key in metadata")?
@@ -342,6 +742,7 @@ pub fn load_pe_hd(
             .ok_or("No 'TRACE INTERVAL (s)' key in metadata")?
             .replace(' ', "")
             .parse()?,
+        distance_interval: None,
         antenna_mhz,
         antenna: data
             .get("NOMINAL FREQUENCY")
@@ -362,9 +763,92 @@ pub fn load_pe_hd(
             .parse()?,
         data_filepath: dt1_filepath,
         medium_velocity,
+        channel: 0,
+        channel_offset: [0., 0.],
+        velocity_model: None,
+    })
+}
+
+/// Load a common-midpoint (CMP) or wide-angle (WARR) gather from a pulseEKKO sounding
+///
+/// The antenna separation of each trace is its position in the trace header, or it is given by a
+/// spacing. Time zero is at the "TIMEZERO AT POINT" sample of the ".hd" file, or at the first
+/// sample if it is missing.
+///
+/// # Arguments
+/// - `filepath`: The path of the ".hd" file.
+/// - `spacing`: The increase in antenna separation between traces (m). If None, the trace
+///   positions are used.
+/// - `first_offset`: The antenna separation of the first trace with a spacing (m). If None, the
+///   "ANTENNA SEPARATION" of the ".hd" file.
+/// - `override_antenna_mhz`: Override the antenna center frequency (MHz)
+///
+/// # Errors
+/// - The ".hd" or ".dt1" files cannot be read
+/// - No spacing was given and the trace positions are not in metres or do not change
+pub fn load_pe_gather(
+    filepath: &Path,
+    spacing: Option<f32>,
+    first_offset: Option<f32>,
+    override_antenna_mhz: Option<f32>,
+) -> Result<crate::cmp::Gather, Box<dyn Error>> {
+    let meta = load_pe_hd(filepath, 0., override_antenna_mhz)?;
+    let (height, width) = (meta.samples as usize, meta.last_trace as usize);
+    let data = load_pe_dt1(&meta.data_filepath, height, width)?;
+    let hd_content = std::fs::read_to_string(filepath)?;
+    let hd = parse_pe_hd(&hd_content);
+
+    let offsets = match spacing {
+        Some(spacing) => {
+            let first_offset = first_offset.unwrap_or(meta.antenna_separation);
+            (0..width)
+                .map(|i| first_offset + i as f32 * spacing)
+                .collect::<Vec<f32>>()
+        }
+        None => {
+            if let Some(unit) = hd.get("POSITION UNITS").filter(|u| **u != "m") {
+                return Err(format!("The trace positions are in {unit:?}, not metres. Give the antenna separation step with '--spacing'").into());
+            }
+            let offsets = load_pe_trace_headers(&meta.data_filepath, height, width)?
+                .iter()
+                .map(|header| header.position)
+                .collect::<Vec<f32>>();
+            if offsets.iter().all(|offset| *offset == offsets[0]) {
+                return Err("The trace positions do not change, so they are not antenna separations. Give the antenna separation step with '--spacing'".into());
+            }
+            offsets
+        }
+    };
+
+    let sample_interval = meta.time_window / meta.samples as f32;
+    let time_zero_sample = hd
+        .get("TIMEZERO AT POINT")
+        .and_then(|v| v.parse::<f32>().ok())
+        .unwrap_or(0.);
+
+    Ok(crate::cmp::Gather {
+        data,
+        offsets,
+        sample_interval,
+        start_time: -time_zero_sample * sample_interval,
+        antenna_mhz: meta.antenna_mhz,
     })
 }
 
+/// Read the UTC date of an NMEA RMC string as "YYYY-MM-DD"
+///
+/// # Returns
+/// The date, or None if the string is not an RMC string or has no valid date
+fn read_rmc_date(rmc_str: &str) -> Option<String> {
+    if !rmc_str.trim().starts_with('$') || rmc_str.trim().get(3..7) != Some("RMC,") {
+        return None;
+    }
+    let date = rmc_str.split(',').nth(9)?;
+    chrono::NaiveDate::parse_from_str(date, "%d%m%y")
+        .ok()
+        .map(|d| d.format("%Y-%m-%d").to_string())
+}
+
 fn read_gga(gga_str: &str, date: &str) -> Result<(f64, crate::coords::Coord, f64), Box<dyn Error>> {
     let months = [
         "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
@@ -496,90 +980,1090 @@ pub fn load_pe_gp2(
     }
 }
 
-/// Export a GPR profile and its metadata to a NetCDF (".nc") file.
-///
-/// It will overwrite any file that already exists with the same filename.
+/// The size of one GSSI DZT header block. Multi-channel files have one block per channel.
+const DZT_HEADER_BYTES: usize = 1024;
+
+/// Nominal center frequencies (MHz) of GSSI antenna models, as written in DZT headers.
+const GSSI_ANTENNA_MHZ: &[(&str, f32)] = &[
+    ("3207", 100.),
+    ("3207AP", 100.),
+    ("5106", 200.),
+    ("5106A", 200.),
+    ("50270", 270.),
+    ("50270S", 270.),
+    ("50300", 300.),
+    ("D50300", 300.),
+    ("350", 350.),
+    ("350HS", 350.),
+    ("D400HS", 350.),
+    ("5103", 400.),
+    ("5103A", 400.),
+    ("50400", 400.),
+    ("50400S", 400.),
+    ("800", 800.),
+    ("D50800", 800.),
+    ("3101", 900.),
+    ("3101A", 900.),
+    ("51600", 1600.),
+    ("51600S", 1600.),
+    ("62000", 2000.),
+    ("62000-003", 2000.),
+    ("62300", 2300.),
+    ("62300XT", 2300.),
+    ("52600", 2600.),
+    ("52600S", 2600.),
+];
+
+/// The parts of a GSSI DZT header that are needed to read the file.
+#[derive(Debug, Clone)]
+struct DztHeader {
+    /// The number of samples per scan (trace)
+    samples: u32,
+    /// The number of bits per sample (8, 16 or 32)
+    bits: u16,
+    /// The number of scans per second
+    scans_per_second: f32,
+    /// The number of scans per meter, which is set for distance-triggered surveys
+    scans_per_meter: f32,
+    /// The return time window of each scan (ns)
+    range_ns: f32,
+    /// The antenna name of each channel
+    antennas: Vec<String>,
+    /// The byte offset of the first scan
+    data_offset: usize,
+    /// The number of scans (traces) per channel
+    n_scans: usize,
+    /// The creation date of the file as "YYYY-MM-DD", if it was set
+    date: Option<String>,
+}
+
+/// Read the header of a GSSI data file (.dzt)
 ///
 /// # Arguments
-/// - `gpr`: The GPR object to export
-/// - `nc_filepath`: The filepath of the output NetCDF file
+/// - `filepath`: The filepath of the ".dzt" file
 ///
 /// # Errors
-/// - If the file already exists and cannot be removed.
-/// - If a dimension, attribute or variable could not be created in the NetCDF file
-/// - If data could not be written to the file
-pub fn export_netcdf(gpr: &gpr::GPR, nc_filepath: &Path) -> Result<(), Box<dyn std::error::Error>> {
-    // Remove any previously existing file. If this is not added, netcdf will throw a useless
-    // error!
-    if nc_filepath.is_file() {
-        std::fs::remove_file(nc_filepath)?;
-    };
-    // Create a new NetCDF file
-    let mut file = netcdf::create(nc_filepath)?;
+/// - The file could not be read
+/// - The header is invalid or describes an unsupported sample format
+fn read_dzt_header(filepath: &Path) -> Result<DztHeader, Box<dyn Error>> {
+    use std::io::Read;
 
-    // Add the x/y dimensions for the data
-    file.add_dimension("x", gpr.width())?;
-    file.add_dimension("y", gpr.height())?;
+    let mut file = std::fs::File::open(filepath)?;
+    let file_len = file.metadata()?.len() as usize;
 
-    // Add global attributes to the file
-    file.add_attribute(
-        "start-datetime",
-        chrono::DateTime::from_timestamp(gpr.location.cor_points[0].time_seconds as i64, 0)
-            .unwrap()
-            .to_rfc3339(),
-    )?;
-    file.add_attribute(
-        "stop-datetime",
-        chrono::DateTime::from_timestamp(
-            gpr.location.cor_points[gpr.location.cor_points.len() - 1].time_seconds as i64,
-            0,
+    let mut bytes = Vec::<u8>::new();
+    file.by_ref()
+        .take(DZT_HEADER_BYTES as u64)
+        .read_to_end(&mut bytes)?;
+
+    if bytes.len() < DZT_HEADER_BYTES {
+        return Err(format!(
+            "File too short for a DZT header: got {} bytes, expected at least {DZT_HEADER_BYTES} bytes",
+            bytes.len()
         )
-        .unwrap()
-        .to_rfc3339(),
-    )?;
-    file.add_attribute("processing-datetime", chrono::Local::now().to_rfc3339())?;
-    file.add_attribute("antenna", gpr.metadata.antenna.clone())?;
-    file.add_attribute("antenna-separation", gpr.metadata.antenna_separation)?;
-    file.add_attribute("frequency-steps", gpr.metadata.frequency_steps)?;
-    file.add_attribute("vertical-sampling-frequency", gpr.metadata.frequency)?;
-    if gpr.metadata.time_interval.is_finite() {
-        file.add_attribute("time-interval", gpr.metadata.time_interval)?;
+        .into());
     }
 
-    file.add_attribute("processing-log", gpr.log.join("\n"))?;
-    file.add_attribute(
-        "original-filename",
-        gpr.metadata
-            .data_filepath
-            .file_name()
-            .unwrap()
-            .to_str()
-            .unwrap(),
-    )?;
+    let u16_at = |b: &[u8], i: usize| u16::from_le_bytes([b[i], b[i + 1]]);
+    let f32_at = |b: &[u8], i: usize| f32::from_le_bytes([b[i], b[i + 1], b[i + 2], b[i + 3]]);
 
-    file.add_attribute("medium-velocity", gpr.metadata.medium_velocity)?;
-    file.add_attribute("medium-velocity-unit", "m / ns")?;
+    // The lower byte of the header tag is always 0xFF
+    if u16_at(&bytes, 0) & 0xFF != 0xFF {
+        return Err(format!("Invalid DZT header tag in {filepath:?}").into());
+    }
 
-    file.add_attribute(
-        "elevation-correction",
-        match gpr.location.correction.clone() {
-            gpr::LocationCorrection::None => "None".to_string(),
-            gpr::LocationCorrection::Dem(fp) => format!(
-                "DEM-corrected: {:?}",
-                fp.as_path().file_name().unwrap().to_str().unwrap()
-            ),
-        },
-    )?;
+    let samples = u16_at(&bytes, 4) as u32;
+    let bits = u16_at(&bytes, 6);
+    let scans_per_second = f32_at(&bytes, 10);
+    let scans_per_meter = f32_at(&bytes, 14);
+    let range_ns = f32_at(&bytes, 26);
+    let n_channels = (u16_at(&bytes, 52) as usize).max(1);
 
-    file.add_attribute("crs", gpr.location.crs.clone())?;
-    let distance_vec = gpr.location.distances().into_raw_vec();
-    file.add_attribute("total-distance", distance_vec[distance_vec.len() - 1])?;
-    file.add_attribute("total-distance-unit", "m")?;
+    if samples == 0 {
+        return Err("The DZT header has zero samples per scan".into());
+    }
+    if ![8, 16, 32].contains(&bits) {
+        return Err(format!("Unsupported DZT sample size: {bits} bits").into());
+    }
+    if range_ns.is_nan() || range_ns <= 0. {
+        return Err(format!("Invalid DZT time range: {range_ns} ns").into());
+    }
 
-    file.add_attribute(
-        "program-version",
-        format!(
-            "{} version {}, © {}",
+    // Older files give the data offset in header blocks, newer give it in bytes.
+    let rh_data = u16_at(&bytes, 2) as usize;
+    let data_offset = match rh_data < DZT_HEADER_BYTES {
+        true => DZT_HEADER_BYTES * rh_data,
+        false => DZT_HEADER_BYTES * n_channels,
+    }
+    .max(DZT_HEADER_BYTES * n_channels);
+
+    // Each channel has its own header block, which contains the antenna name of that channel.
+    file.by_ref()
+        .take((DZT_HEADER_BYTES * (n_channels - 1)) as u64)
+        .read_to_end(&mut bytes)?;
+    let antennas = (0..n_channels)
+        .map(|channel| {
+            let start = 98 + DZT_HEADER_BYTES * channel;
+            bytes
+                .get(start..start + 14)
+                .map(|name| {
+                    String::from_utf8_lossy(name)
+                        .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+                        .to_string()
+                })
+                .unwrap_or_default()
+        })
+        .collect::<Vec<String>>();
+
+    if data_offset > file_len {
+        return Err(format!(
+            "The DZT data offset ({data_offset} bytes) is beyond the end of {filepath:?} ({file_len} bytes)"
+        )
+        .into());
+    }
+    let scan_bytes = samples as usize * (bits as usize / 8) * n_channels;
+    let n_scans = (file_len - data_offset) / scan_bytes;
+    if n_scans == 0 {
+        return Err(format!("No complete scans in {filepath:?}").into());
+    }
+
+    // The creation date is a bitfield: 5 bits of seconds / 2, 6 of minutes, 5 of hours, 5 of
+    // days, 4 of months, and 7 of years since 1980.
+    let created = u32::from_le_bytes([bytes[32], bytes[33], bytes[34], bytes[35]]);
+    let date = chrono::NaiveDate::from_ymd_opt(
+        1980 + (created >> 25) as i32,
+        (created >> 21) & 0x0F,
+        (created >> 16) & 0x1F,
+    )
+    .filter(|_| created != 0)
+    .map(|d| d.format("%Y-%m-%d").to_string());
+
+    Ok(DztHeader {
+        samples,
+        bits,
+        scans_per_second,
+        scans_per_meter,
+        range_ns,
+        antennas,
+        data_offset,
+        n_scans,
+        date,
+    })
+}
+
+/// Get the nominal antenna frequency (MHz) from an antenna name
+///
+/// Known GSSI model numbers are looked up first, then names like "400MHz" or "400 MHz" are parsed.
+fn antenna_mhz_from_name(antenna: &str) -> Option<f32> {
+    let antenna = antenna.trim();
+    if let Some((_, mhz)) = GSSI_ANTENNA_MHZ
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(antenna))
+    {
+        return Some(*mhz);
+    }
+
+    let lowercase = antenna.to_lowercase();
+    let (before, _) = lowercase.split_once("mhz")?;
+    let digits = before
+        .chars()
+        .rev()
+        .skip_while(|c| c.is_whitespace())
+        .take_while(|c| c.is_ascii_digit())
+        .collect::<String>();
+
+    digits.chars().rev().collect::<String>().parse().ok()
+}
+
+/// Find a sibling file with the given extension, accepting either lowercase or uppercase.
+///
+/// GSSI units write uppercase extensions (".DZT", ".DZG"), which are often renamed.
+///
+/// # Returns
+/// The filepath of the file that exists, or the lowercase variant if neither exist.
+pub fn with_extension_any_case(filepath: &Path, extension: &str) -> PathBuf {
+    let uppercase = filepath.with_extension(extension.to_uppercase());
+    match uppercase.is_file() {
+        true => uppercase,
+        false => filepath.with_extension(extension.to_lowercase()),
+    }
+}
+
+/// Load and parse the metadata of a GSSI data file (.dzt)
+///
+/// GSSI files contain a binary header, so the data and metadata are in the same file.
+///
+/// # Arguments
+/// - `filepath`: The filepath of the ".dzt" file
+/// - `medium_velocity`: The velocity of the portrayed medium to assign the GPR data
+/// - `override_antenna_mhz`: Optional antenna frequency override (will not read from metadata).
+///
+/// # Returns
+/// One gpr::GPRMeta instance per channel in the file.
+///
+/// # Errors
+/// - The file could not be read
+/// - The header is invalid or describes an unsupported sample format
+/// - The antenna frequency could not be found from the antenna name
+pub fn load_dzt(
+    filepath: &Path,
+    medium_velocity: f32,
+    override_antenna_mhz: Option<f32>,
+) -> Result<Vec<gpr::GPRMeta>, Box<dyn Error>> {
+    let header = read_dzt_header(filepath)?;
+
+    // The scans per second is zero for distance-triggered surveys, which instead have a distance
+    // between scans.
+    let (time_interval, distance_interval) = match header.scans_per_second > 0. {
+        true => (1. / header.scans_per_second, None),
+        false => (
+            f32::NAN,
+            Some(1. / header.scans_per_meter)
+                .filter(|interval| interval.is_finite() && *interval > 0.),
+        ),
+    };
+
+    header
+        .antennas
+        .iter()
+        .enumerate()
+        .map(|(channel, antenna)| {
+            let antenna_mhz = match override_antenna_mhz {
+                Some(v) => v,
+                None => antenna_mhz_from_name(antenna).ok_or(format!(
+                    "Could not read frequency from the antenna name ({antenna:?}). Try using the antenna MHz override"
+                ))?,
+            };
+            Ok(gpr::GPRMeta {
+                samples: header.samples,
+                frequency: 1000. * header.samples as f32 / header.range_ns,
+                frequency_steps: 0,
+                time_interval,
+                distance_interval,
+                antenna: antenna.clone(),
+                antenna_mhz,
+                // The antenna separation is not part of the DZT header
+                antenna_separation: 0.,
+                time_window: header.range_ns,
+                last_trace: header.n_scans as u32,
+                data_filepath: filepath.to_path_buf(),
+                medium_velocity,
+                channel,
+                channel_offset: [0., 0.],
+                velocity_model: None,
+            })
+        })
+        .collect()
+}
+
+/// Load the data of one channel in a GSSI data file (.dzt)
+///
+/// The values are unscaled, but the offset of unsigned (8 and 16 bit) samples is removed so that
+/// they are centered around zero.
+///
+/// # Arguments
+/// - `filepath`: The path of the file to read.
+/// - `channel`: The (zero-based) channel to read.
+///
+/// # Returns
+/// A 2D array of 32 bit floating point values in the shape (samples, scans).
+///
+/// # Errors
+/// - The file cannot be read
+/// - The header is invalid
+/// - The channel does not exist in the file
+pub fn load_dzt_data(filepath: &Path, channel: usize) -> Result<Array2<f32>, Box<dyn Error>> {
+    let header = read_dzt_header(filepath)?;
+    let n_channels = header.antennas.len();
+    if channel >= n_channels {
+        return Err(format!(
+            "Channel {channel} requested but the file has {n_channels} channel(s)"
+        )
+        .into());
+    }
+
+    let bytes = std::fs::read(filepath)?;
+
+    let height = header.samples as usize;
+    let trace_bytes = height * header.bits as usize / 8;
+
+    let mut data: Vec<f32> = Vec::with_capacity(height * header.n_scans);
+
+    // The scans of each channel are interleaved
+    for scan in bytes[header.data_offset..].chunks_exact(trace_bytes * n_channels) {
+        let trace = &scan[channel * trace_bytes..(channel + 1) * trace_bytes];
+        match header.bits {
+            8 => data.extend(trace.iter().map(|v| *v as f32 - 128.)),
+            16 => data.extend(
+                trace
+                    .chunks_exact(2)
+                    .map(|b| u16::from_le_bytes([b[0], b[1]]) as f32 - 32768.),
+            ),
+            _ => data.extend(
+                trace
+                    .chunks_exact(4)
+                    .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32),
+            ),
+        }
+    }
+
+    Ok(Array2::from_shape_vec((header.n_scans, height), data)?.reversed_axes())
+}
+
+/// Load and parse a GSSI ".dzg" location file
+///
+/// The file consists of "$GSSIS,<scan>,<time>" lines, each followed by NMEA strings. The
+/// positions are read from GGA strings, which only contain the UTC time of day. The UTC date is
+/// taken from the latest RMC string (or the first one for positions before it). Without RMC
+/// strings, the creation date of the associated ".dzt" file is used, which assumes that the clock
+/// of the control unit was set to UTC; otherwise, the date may be one day off close to midnight.
+///
+/// # Arguments
+/// - `filepath`: The path to the file to read.
+/// - `projected_crs`: Any projected CRS understood by PROJ to project the coordinates into
+///
+/// # Returns
+/// The parsed location points in a GPRLocation object.
+///
+/// # Errors
+/// - The file or its associated ".dzt" file could not be found/read
+/// - The file has no RMC strings and the ".dzt" file has no creation date
+/// - `projected_crs` is not understood by PROJ
+/// - No location could be parsed from the file.
+pub fn load_dzg(
+    filepath: &Path,
+    projected_crs: Option<&String>,
+) -> Result<gpr::GPRLocation, Box<dyn Error>> {
+    let content = std::fs::read_to_string(filepath)?;
+
+    let mut date = match content.lines().find_map(read_rmc_date) {
+        Some(date) => date,
+        None => {
+            let dzt_filepath = with_extension_any_case(filepath, "dzt");
+            read_dzt_header(&dzt_filepath)?.date.ok_or(format!(
+                "No RMC strings in {filepath:?} and no creation date in the header of {dzt_filepath:?}"
+            ))?
+        }
+    };
+
+    let mut trace_n: Option<u32> = None;
+
+    let mut coords = Vec::<crate::coords::Coord>::new();
+    let mut points: Vec<gpr::CorPoint> = Vec::new();
+    for line in content.lines().map(|l| l.trim()) {
+        if let Some(gssis) = line.strip_prefix("$GSSIS,") {
+            trace_n = gssis.split(',').next().and_then(|s| s.trim().parse().ok());
+            continue;
+        }
+
+        // Any talker ID is accepted ("$GPGGA", "$GNGGA" etc.)
+        if !line.starts_with('$') {
+            continue;
+        }
+        if line.get(3..7) == Some("RMC,") {
+            if let Some(rmc_date) = read_rmc_date(line) {
+                date = rmc_date;
+            }
+            continue;
+        }
+        if line.get(3..7) != Some("GGA,") {
+            continue;
+        }
+        let Some(trace_n) = trace_n.take() else {
+            continue;
+        };
+        if points.last().map(|p| p.trace_n == trace_n) == Some(true) {
+            continue;
+        }
+
+        // Strings without a position fix have empty fields
+        if line.split(',').nth(2).is_none_or(|lat| lat.is_empty()) {
+            continue;
+        }
+        let Ok((datetime, coord, altitude)) = read_gga(line, &date) else {
+            continue;
+        };
+
+        coords.push(coord);
+        // Coordinates are 0 right now. That's fixed right below
+        points.push(gpr::CorPoint {
+            trace_n,
+            time_seconds: datetime,
+            easting: 0.,
+            northing: 0.,
+            altitude,
+        });
+    }
+    if points.is_empty() {
+        return Err(format!("Could not parse location data from: {:?}", filepath).into());
+    }
+
+    let projected_crs = match projected_crs {
+        Some(s) => s.to_string(),
+        None => crate::coords::UtmCrs::optimal_crs(&coords[0]).to_epsg_str(),
+    };
+    for (i, coord) in crate::coords::from_wgs84(
+        &coords,
+        &crate::coords::Crs::from_user_input(&projected_crs)?,
+    )?
+    .iter()
+    .enumerate()
+    {
+        points[i].easting = coord.x;
+        points[i].northing = coord.y;
+    }
+
+    Ok(gpr::GPRLocation {
+        cor_points: points,
+        correction: gpr::LocationCorrection::None,
+        crs: projected_crs,
+    })
+}
+
+/// Create locations along a straight profile from the scans per meter of a GSSI data file (.dzt)
+///
+/// This is for distance-triggered (survey wheel) profiles without a ".dzg" file. The easting is
+/// the distance along the profile and the northing is zero.
+///
+/// # Arguments
+/// - `filepath`: The filepath of the ".dzt" file
+/// - `projected_crs`: The CRS to assign the locations. If None, they are "local".
+///
+/// # Returns
+/// The locations of the first and last scan in a GPRLocation object.
+///
+/// # Errors
+/// - The file could not be read, or its header is invalid
+/// - The header has no scans per meter
+pub fn load_dzt_distance_locations(
+    filepath: &Path,
+    projected_crs: Option<&String>,
+) -> Result<gpr::GPRLocation, Box<dyn Error>> {
+    let header = read_dzt_header(filepath)?;
+    if !(header.scans_per_meter > 0. && header.scans_per_meter.is_finite()) {
+        return Err(format!(
+            "No GPS ('.dzg') file found and no scans per meter in the header of {filepath:?}"
+        )
+        .into());
+    }
+    let midnight = header
+        .date
+        .as_ref()
+        .and_then(|date| chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+        .map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp() as f64)
+        .unwrap_or(0.);
+
+    let mut scans = vec![0_u32];
+    if header.n_scans > 1 {
+        scans.push(header.n_scans as u32 - 1);
+    }
+    let cor_points = scans
+        .into_iter()
+        .map(|trace_n| gpr::CorPoint {
+            trace_n,
+            time_seconds: match header.scans_per_second > 0. {
+                true => midnight + (trace_n as f32 / header.scans_per_second) as f64,
+                false => midnight,
+            },
+            easting: (trace_n as f32 / header.scans_per_meter) as f64,
+            northing: 0.,
+            altitude: 0.,
+        })
+        .collect::<Vec<gpr::CorPoint>>();
+
+    Ok(gpr::GPRLocation {
+        cor_points,
+        correction: gpr::LocationCorrection::None,
+        crs: projected_crs.cloned().unwrap_or("local".to_string()),
+    })
+}
+
+/// The EBCDIC (code page 037) bytes of the printable ASCII characters (0x20 to 0x7E).
+const EBCDIC_PRINTABLE: [u8; 95] = [
+    0x40, 0x5A, 0x7F, 0x7B, 0x5B, 0x6C, 0x50, 0x7D, 0x4D, 0x5D, 0x5C, 0x4E, 0x6B, 0x60, 0x4B, 0x61,
+    0xF0, 0xF1, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8, 0xF9, 0x7A, 0x5E, 0x4C, 0x7E, 0x6E, 0x6F,
+    0x7C, 0xC1, 0xC2, 0xC3, 0xC4, 0xC5, 0xC6, 0xC7, 0xC8, 0xC9, 0xD1, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6,
+    0xD7, 0xD8, 0xD9, 0xE2, 0xE3, 0xE4, 0xE5, 0xE6, 0xE7, 0xE8, 0xE9, 0xBA, 0xE0, 0xBB, 0xB0, 0x6D,
+    0x79, 0x81, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x91, 0x92, 0x93, 0x94, 0x95, 0x96,
+    0x97, 0x98, 0x99, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7, 0xA8, 0xA9, 0xC0, 0x4F, 0xD0, 0xA1,
+];
+
+/// User settings for reading and writing SEG-Y files, since the header usage varies between data
+/// sources.
+#[derive(Debug, Clone)]
+pub struct SegyOptions {
+    /// The one-based trace header byte positions of the x, y and elevation values (32 bit integers)
+    pub coordinate_bytes: [usize; 3],
+    /// A scalar to apply to the coordinates and elevations instead of the trace header scalars.
+    /// As in the SEG-Y standard, negative values are divisors.
+    pub scalar: Option<f64>,
+    /// The unit of the 16 bit sample interval fields (ns). If it is not given, it is picoseconds
+    /// for files written by radal (see `SEGY_PS_INTERVAL_MARKER`) and otherwise the standard
+    /// microseconds, but many other GPR writers use picoseconds too. The extended sample interval
+    /// of revision 2 files takes precedence and is always in microseconds.
+    pub sample_interval_unit_ns: Option<f32>,
+    /// The SEG-Y revision (1 or 2) of exported files
+    pub revision: u8,
+}
+
+impl Default for SegyOptions {
+    fn default() -> Self {
+        Self {
+            coordinate_bytes: [73, 77, 41],
+            scalar: None,
+            sample_interval_unit_ns: None,
+            revision: 2,
+        }
+    }
+}
+
+/// The textual header line that marks 16 bit sample interval fields in picoseconds in files
+/// written by radal
+const SEGY_PS_INTERVAL_MARKER: &str = "Interval fields in ps";
+
+/// The parts of a SEG-Y file header that are needed to read the traces.
+#[derive(Debug, Clone)]
+struct SegyHeader {
+    /// The textual header, converted to ASCII if it was EBCDIC-encoded
+    text: String,
+    /// The sample interval, in the (unknown) unit of the file
+    interval: u16,
+    /// The extended sample interval of revision 2 files (µs), if it is set
+    extended_interval_us: Option<f64>,
+    /// The number of samples per trace
+    samples: usize,
+    /// The data sample format code (1: IBM float, 2: i32, 3: i16, 5: IEEE float, 8: i8)
+    format_code: i16,
+    /// Whether the file is little-endian (non-standard, but written by some GPR software)
+    little_endian: bool,
+    /// The byte offset of the first trace header
+    data_offset: usize,
+    /// The number of traces in the file
+    n_traces: usize,
+}
+
+impl SegyHeader {
+    fn bytes_per_sample(&self) -> usize {
+        match self.format_code {
+            3 => 2,
+            8 => 1,
+            _ => 4,
+        }
+    }
+
+    fn trace_bytes(&self) -> usize {
+        240 + self.samples * self.bytes_per_sample()
+    }
+
+    fn i16_at(&self, bytes: &[u8], position: usize) -> i16 {
+        let value = [bytes[position], bytes[position + 1]];
+        match self.little_endian {
+            true => i16::from_le_bytes(value),
+            false => i16::from_be_bytes(value),
+        }
+    }
+
+    fn f64_at(&self, bytes: &[u8], position: usize) -> f64 {
+        let value: [u8; 8] = bytes[position..position + 8].try_into().unwrap();
+        match self.little_endian {
+            true => f64::from_le_bytes(value),
+            false => f64::from_be_bytes(value),
+        }
+    }
+
+    fn i32_at(&self, bytes: &[u8], position: usize) -> i32 {
+        let value = [
+            bytes[position],
+            bytes[position + 1],
+            bytes[position + 2],
+            bytes[position + 3],
+        ];
+        match self.little_endian {
+            true => i32::from_le_bytes(value),
+            false => i32::from_be_bytes(value),
+        }
+    }
+}
+
+/// Convert an IBM System/360 single precision float to an IEEE float
+fn ibm_to_f32(bits: u32) -> f32 {
+    let sign = match bits >> 31 {
+        1 => -1_f64,
+        _ => 1_f64,
+    };
+    let exponent = ((bits >> 24) & 0x7F) as i32 - 64;
+    let mantissa = (bits & 0x00FF_FFFF) as f64 / (1 << 24) as f64;
+
+    (sign * mantissa * 16_f64.powi(exponent)) as f32
+}
+
+/// Parse the textual and binary headers of a SEG-Y file
+///
+/// # Errors
+/// - The file is too short
+/// - The sample format is not supported or the endianness could not be determined
+/// - The file has a variable number of extended textual headers
+fn read_segy_header(bytes: &[u8]) -> Result<SegyHeader, Box<dyn Error>> {
+    if bytes.len() < 3600 {
+        return Err(format!(
+            "File too short for a SEG-Y header: got {} bytes, expected at least 3600 bytes",
+            bytes.len()
+        )
+        .into());
+    }
+
+    // The textual header starts with "C" in either EBCDIC or ASCII
+    let text = match bytes[0] {
+        0xC3 => ebcdic_to_ascii(&bytes[..3200]),
+        _ => String::from_utf8_lossy(&bytes[..3200]).to_string(),
+    }
+    .chars()
+    .collect::<Vec<char>>()
+    .chunks(80)
+    .map(|line| line.iter().collect::<String>().trim_end().to_string())
+    .collect::<Vec<String>>()
+    .join("\n");
+
+    // The format code is used to find out if the file is big-endian (standard) or little-endian
+    let supported_formats = [1, 2, 3, 5, 8];
+    let little_endian = match i16::from_be_bytes([bytes[3224], bytes[3225]]) {
+        code if supported_formats.contains(&code) => false,
+        _ => match i16::from_le_bytes([bytes[3224], bytes[3225]]) {
+            code if supported_formats.contains(&code) => true,
+            _ => {
+                return Err(format!(
+                    "Unsupported SEG-Y sample format code: {}",
+                    i16::from_be_bytes([bytes[3224], bytes[3225]])
+                )
+                .into())
+            }
+        },
+    };
+
+    let mut header = SegyHeader {
+        text,
+        interval: 0,
+        extended_interval_us: None,
+        samples: 0,
+        format_code: 0,
+        little_endian,
+        data_offset: 3600,
+        n_traces: 0,
+    };
+    header.format_code = header.i16_at(bytes, 3224);
+    header.interval = header.i16_at(bytes, 3216) as u16;
+    header.samples = header.i16_at(bytes, 3220) as u16 as usize;
+    // The extended sample interval is only defined from revision 2, whose major revision is a byte
+    if bytes[3500] >= 2 {
+        header.extended_interval_us = Some(header.f64_at(bytes, 3272))
+            .filter(|interval| interval.is_finite() && *interval > 0.);
+    }
+
+    let n_extended_headers = header.i16_at(bytes, 3504);
+    if n_extended_headers < 0 {
+        return Err(
+            "SEG-Y files with a variable number of extended textual headers are not supported"
+                .into(),
+        );
+    }
+    header.data_offset += 3200 * n_extended_headers as usize;
+
+    // Some writers only give the interval and samples in the trace headers
+    if bytes.len() >= header.data_offset + 240 {
+        if header.interval == 0 {
+            header.interval = header.i16_at(bytes, header.data_offset + 116) as u16;
+        }
+        if header.samples == 0 {
+            header.samples = header.i16_at(bytes, header.data_offset + 114) as u16 as usize;
+        }
+    }
+    if header.samples == 0 {
+        return Err("The SEG-Y headers have zero samples per trace".into());
+    }
+    if header.interval == 0 && header.extended_interval_us.is_none() {
+        return Err("The SEG-Y headers have a zero sample interval".into());
+    }
+
+    header.n_traces = bytes.len().saturating_sub(header.data_offset) / header.trace_bytes();
+
+    Ok(header)
+}
+
+/// Load and parse the metadata and trace locations of a SEG-Y file (.sgy/.segy)
+///
+/// The antenna frequency and separation are read from the textual header if they are written
+/// there (as in files exported by radal). The coordinate units and scalars are read from each
+/// trace header. Coordinates given in degrees or arcseconds (coordinate units 3 and 2) are assumed
+/// to be WGS84 and are projected like other formats. Other coordinates are assumed to be in the
+/// given `projected_crs`. The interval between traces is averaged from the trace header times, and
+/// is NaN if some traces have no time or all are in the same second.
+///
+/// # Arguments
+/// - `filepath`: The filepath of the SEG-Y file
+/// - `medium_velocity`: The velocity of the portrayed medium to assign the GPR data
+/// - `override_antenna_mhz`: Optional antenna frequency override (will not read from metadata).
+/// - `projected_crs`: The CRS to project geographic coordinates into, or the CRS of projected coordinates.
+/// - `options`: Settings for the sample interval unit and where the coordinates are stored.
+///
+/// # Returns
+/// The metadata and the location of each trace.
+///
+/// # Errors
+/// - The file could not be read or the headers are invalid
+/// - The antenna frequency could not be found
+/// - The coordinate header bytes are out of range
+/// - The coordinates are projected but no CRS was given
+/// - Some traces have geographic coordinates and others do not
+pub fn load_segy(
+    filepath: &Path,
+    medium_velocity: f32,
+    override_antenna_mhz: Option<f32>,
+    projected_crs: Option<&String>,
+    options: &SegyOptions,
+) -> Result<(gpr::GPRMeta, gpr::GPRLocation), Box<dyn Error>> {
+    let bytes = std::fs::read(filepath)?;
+    let header = read_segy_header(&bytes)?;
+
+    if header.n_traces == 0 {
+        return Err(format!("No traces in SEG-Y file: {filepath:?}").into());
+    }
+
+    let interval_ns = match header.extended_interval_us {
+        Some(interval_us) => (interval_us * 1000.) as f32,
+        None => {
+            let unit_ns = options.sample_interval_unit_ns.unwrap_or(
+                match header.text.contains(SEGY_PS_INTERVAL_MARKER) {
+                    true => 0.001,
+                    false => 1000.,
+                },
+            );
+            header.interval as f32 * unit_ns
+        }
+    };
+    let time_window = interval_ns * header.samples as f32;
+
+    let text_lowercase = header.text.to_lowercase();
+    let antenna_mhz = match override_antenna_mhz {
+        Some(v) => v,
+        None => text_lowercase
+            .lines()
+            .filter(|l| l.contains("antenna"))
+            .find_map(antenna_mhz_from_name)
+            .ok_or("Could not read the antenna frequency from the SEG-Y textual header. Try using the antenna MHz override")?,
+    };
+    let antenna_separation = text_lowercase
+        .split_once("antenna separation:")
+        .and_then(|(_, after)| after.split_whitespace().next())
+        .and_then(|v| v.parse::<f32>().ok())
+        .unwrap_or(0.);
+
+    for byte in options.coordinate_bytes {
+        if !(1..=237).contains(&byte) {
+            return Err(
+                format!("SEG-Y coordinate header byte out of range (1-237): {byte}").into(),
+            );
+        }
+    }
+    let [x_byte, y_byte, z_byte] = options.coordinate_bytes.map(|b| b - 1);
+
+    // SEG-Y scalars are multipliers if positive and divisors if negative. Zero means no scaling.
+    let apply_scalar = |value: i32, scalar: f64| match scalar {
+        s if s < 0. => value as f64 / -s,
+        s if s > 0. => value as f64 * s,
+        _ => value as f64,
+    };
+
+    let mut coords = Vec::<crate::coords::Coord>::new();
+    let mut points: Vec<gpr::CorPoint> = Vec::new();
+    let mut n_geographic = 0_usize;
+    let mut n_timed = 0_usize;
+    for i in 0..header.n_traces {
+        let trace_header = &bytes[header.data_offset + i * header.trace_bytes()..][..240];
+
+        let coordinate_scalar = options
+            .scalar
+            .unwrap_or(header.i16_at(trace_header, 70) as f64);
+        let elevation_scalar = options
+            .scalar
+            .unwrap_or(header.i16_at(trace_header, 68) as f64);
+
+        let mut coord = crate::coords::Coord {
+            x: apply_scalar(header.i32_at(trace_header, x_byte), coordinate_scalar),
+            y: apply_scalar(header.i32_at(trace_header, y_byte), coordinate_scalar),
+        };
+        // Geographic coordinates (2: arcseconds, 3: decimal degrees) are converted to degrees
+        match header.i16_at(trace_header, 88) {
+            2 => {
+                coord.x /= 3600.;
+                coord.y /= 3600.;
+                n_geographic += 1;
+            }
+            3 => n_geographic += 1,
+            _ => (),
+        }
+        coords.push(coord);
+
+        // The time of day and day of the year. Traces without a time are set to the UNIX epoch.
+        let year = header.i16_at(trace_header, 156) as i32;
+        let time_seconds =
+            chrono::NaiveDate::from_yo_opt(year, header.i16_at(trace_header, 158) as u32)
+                .filter(|_| year > 0)
+                .and_then(|date| {
+                    date.and_hms_opt(
+                        header.i16_at(trace_header, 160) as u32,
+                        header.i16_at(trace_header, 162) as u32,
+                        header.i16_at(trace_header, 164) as u32,
+                    )
+                })
+                .map(|datetime| {
+                    n_timed += 1;
+                    datetime.and_utc().timestamp() as f64
+                })
+                .unwrap_or(0.);
+
+        // Coordinates are set right below
+        points.push(gpr::CorPoint {
+            trace_n: i as u32,
+            time_seconds,
+            easting: 0.,
+            northing: 0.,
+            altitude: apply_scalar(header.i32_at(trace_header, z_byte), elevation_scalar),
+        });
+    }
+
+    if !(n_geographic == 0 || n_geographic == header.n_traces) {
+        return Err(format!(
+            "The SEG-Y coordinate units of {n_geographic} of {} traces are geographic, and the rest are not",
+            header.n_traces
+        )
+        .into());
+    }
+    let crs = match n_geographic > 0 {
+        true => {
+            let projected_crs = match projected_crs {
+                Some(s) => s.to_string(),
+                None => crate::coords::UtmCrs::optimal_crs(&coords[0]).to_epsg_str(),
+            };
+            coords = crate::coords::from_wgs84(
+                &coords,
+                &crate::coords::Crs::from_user_input(&projected_crs)?,
+            )?;
+            projected_crs
+        }
+        false => projected_crs
+            .ok_or("The SEG-Y coordinates are projected, so their CRS must be given with '--crs'")?
+            .to_string(),
+    };
+    for (point, coord) in points.iter_mut().zip(coords.iter()) {
+        point.easting = coord.x;
+        point.northing = coord.y;
+    }
+
+    // The trace times are only given in whole seconds, so the interval between traces is averaged
+    // over the profile. It is unknown if some traces have no time or all have the same second.
+    let duration = points[points.len() - 1].time_seconds - points[0].time_seconds;
+    let time_interval = match n_timed == header.n_traces && duration > 0. {
+        true => (duration / (header.n_traces - 1) as f64) as f32,
+        false => f32::NAN,
+    };
+
+    Ok((
+        gpr::GPRMeta {
+            samples: header.samples as u32,
+            frequency: 1000. / interval_ns,
+            frequency_steps: 0,
+            time_interval,
+            distance_interval: None,
+            antenna: format!("{antenna_mhz} MHz"),
+            antenna_mhz,
+            antenna_separation,
+            time_window,
+            last_trace: header.n_traces as u32,
+            data_filepath: filepath.to_path_buf(),
+            medium_velocity,
+            channel: 0,
+            channel_offset: [0., 0.],
+            velocity_model: None,
+        },
+        gpr::GPRLocation {
+            cor_points: points,
+            correction: gpr::LocationCorrection::None,
+            crs,
+        },
+    ))
+}
+
+/// Load the samples of a SEG-Y file (.sgy/.segy)
+///
+/// IBM and IEEE floats, as well as 8, 16 and 32 bit integers, are supported.
+///
+/// # Arguments
+/// - `filepath`: The path of the file to read.
+///
+/// # Returns
+/// A 2D array of 32 bit floating point values in the shape (samples, traces).
+///
+/// # Errors
+/// - The file cannot be read
+/// - The headers are invalid
+pub fn load_segy_data(filepath: &Path) -> Result<Array2<f32>, Box<dyn Error>> {
+    let bytes = std::fs::read(filepath)?;
+    let header = read_segy_header(&bytes)?;
+
+    let mut data: Vec<f32> = Vec::with_capacity(header.samples * header.n_traces);
+
+    let sample_bytes = header.bytes_per_sample();
+    for trace in bytes[header.data_offset..]
+        .chunks_exact(header.trace_bytes())
+        .map(|t| &t[240..])
+    {
+        for sample in trace.chunks_exact(sample_bytes) {
+            let value = match header.format_code {
+                3 => header.i16_at(sample, 0) as f32,
+                8 => sample[0] as i8 as f32,
+                2 => header.i32_at(sample, 0) as f32,
+                code => {
+                    let bits = header.i32_at(sample, 0) as u32;
+                    match code {
+                        1 => ibm_to_f32(bits),
+                        _ => f32::from_bits(bits),
+                    }
+                }
+            };
+            data.push(value);
+        }
+    }
+
+    Ok(Array2::from_shape_vec((header.n_traces, header.samples), data)?.reversed_axes())
+}
+
+/// Export a GPR profile and its metadata, choosing the format from the file extension.
+///
+/// # Arguments
+/// - `gpr`: The GPR object to export
+/// - `filepath`: The filepath of the output file
+/// - `segy_options`: The settings of SEG-Y output (only the revision is used)
+///
+/// # Errors
+/// - If the extension does not correspond to a known format (see `EXPORT_EXTENSIONS`).
+/// - If the format-specific writer fails.
+pub fn export(
+    gpr: &gpr::GPR,
+    filepath: &Path,
+    segy_options: &SegyOptions,
+) -> Result<(), Box<dyn Error>> {
+    match filepath
+        .extension()
+        .and_then(|s| s.to_str())
+        .map(|s| s.to_lowercase())
+        .as_deref()
+    {
+        Some("nc") => export_netcdf(gpr, filepath),
+        Some("sgy" | "segy") => export_segy(gpr, filepath, segy_options.revision),
+        _ => Err(format!(
+            "Unsupported output format: {filepath:?}. Supported extensions: {}",
+            EXPORT_EXTENSIONS.join(", ")
+        )
+        .into()),
+    }
+}
+
+/// Export a GPR profile and its metadata to a NetCDF (".nc") file.
+///
+/// It will overwrite any file that already exists with the same filename.
+///
+/// # Arguments
+/// - `gpr`: The GPR object to export
+/// - `nc_filepath`: The filepath of the output NetCDF file
+///
+/// # Errors
+/// - If the file already exists and cannot be removed.
+/// - If a dimension, attribute or variable could not be created in the NetCDF file
+/// - If data could not be written to the file
+pub fn export_netcdf(gpr: &gpr::GPR, nc_filepath: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    // Remove any previously existing file. If this is not added, netcdf will throw a useless
+    // error!
+    if nc_filepath.is_file() {
+        std::fs::remove_file(nc_filepath)?;
+    };
+    // Create a new NetCDF file
+    let mut file = netcdf::create(nc_filepath)?;
+
+    // Add the x/y dimensions for the data
+    file.add_dimension("x", gpr.width())?;
+    file.add_dimension("y", gpr.height())?;
+
+    // Add global attributes to the file
+    file.add_attribute(
+        "start-datetime",
+        chrono::DateTime::from_timestamp(gpr.location.cor_points[0].time_seconds as i64, 0)
+            .unwrap()
+            .to_rfc3339(),
+    )?;
+    file.add_attribute(
+        "stop-datetime",
+        chrono::DateTime::from_timestamp(
+            gpr.location.cor_points[gpr.location.cor_points.len() - 1].time_seconds as i64,
+            0,
+        )
+        .unwrap()
+        .to_rfc3339(),
+    )?;
+    file.add_attribute("processing-datetime", chrono::Local::now().to_rfc3339())?;
+    file.add_attribute("antenna", gpr.metadata.antenna.clone())?;
+    file.add_attribute("antenna-separation", gpr.metadata.antenna_separation)?;
+    file.add_attribute("antenna-center-frequency", gpr.metadata.antenna_mhz)?;
+    file.add_attribute("horizontal-signal-distance", gpr.horizontal_signal_distance)?;
+    file.add_attribute("zero-point", gpr.zero_point_ns)?;
+    file.add_attribute("time-window", gpr.metadata.time_window)?;
+    file.add_attribute("channel", gpr.metadata.channel as u32)?;
+    file.add_attribute("frequency-steps", gpr.metadata.frequency_steps)?;
+    file.add_attribute("vertical-sampling-frequency", gpr.metadata.frequency)?;
+    if gpr.metadata.time_interval.is_finite() {
+        file.add_attribute("time-interval", gpr.metadata.time_interval)?;
+    }
+    if let Some(distance_interval) = gpr.metadata.distance_interval {
+        file.add_attribute("distance-interval", distance_interval)?;
+    }
+
+    file.add_attribute("processing-log", gpr.log.join("\n"))?;
+    file.add_attribute("processing-provenance", gpr.provenance.to_json()?)?;
+    file.add_attribute(
+        "original-filename",
+        gpr.metadata
+            .data_filepath
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap(),
+    )?;
+
+    file.add_attribute("medium-velocity", gpr.metadata.medium_velocity)?;
+    file.add_attribute("medium-velocity-unit", "m / ns")?;
+    if let Some(model) = &gpr.metadata.velocity_model {
+        file.add_attribute("velocity-model", model.to_csv_string())?;
+    }
+
+    file.add_attribute(
+        "elevation-correction",
+        match gpr.location.correction.clone() {
+            gpr::LocationCorrection::None => "None".to_string(),
+            gpr::LocationCorrection::Dem(fp) => format!(
+                "DEM-corrected: {:?}",
+                fp.as_path().file_name().unwrap().to_str().unwrap()
+            ),
+        },
+    )?;
+
+    file.add_attribute("crs", gpr.location.crs.clone())?;
+    let distance_vec = gpr.location.distances().into_raw_vec();
+    file.add_attribute("total-distance", distance_vec[distance_vec.len() - 1])?;
+    file.add_attribute("total-distance-unit", "m")?;
+
+    file.add_attribute(
+        "program-version",
+        format!(
+            "{} version {}, © {}",
             crate::PROGRAM_NAME,
             crate::PROGRAM_VERSION,
             crate::PROGRAM_AUTHORS
@@ -637,6 +2121,124 @@ pub fn export_netcdf(gpr: &gpr::GPR, nc_filepath: &Path) -> Result<(), Box<dyn s
         data2.put_attribute("unit", "mV")?;
     };
 
+    // Add the saved AGC gain, so that the AGC can be undone after loading the file
+    if let Some(agc_gain) = &gpr.agc_gain {
+        let mut gain = file.add_variable::<f32>("agc_gain", &["y", "x"])?;
+        gain.set_compression(5, true)?;
+        gain.put_values(
+            &agc_gain.iter().map(|v| v.to_owned()).collect::<Vec<f32>>(),
+            ..,
+        )?;
+        gain.put_attribute("coordinates", "distance return-time")?;
+    }
+
+    // Add the markers, with their trace index and comment
+    if !gpr.markers.is_empty() {
+        file.add_dimension("marker", gpr.markers.len())?;
+        let mut marker_trace = file.add_variable::<u32>("marker_trace", &["marker"])?;
+        marker_trace.put_values(
+            &gpr.markers
+                .iter()
+                .map(|m| m.trace as u32)
+                .collect::<Vec<u32>>(),
+            ..,
+        )?;
+        marker_trace.put_attribute("coordinates", "x")?;
+
+        let mut marker_comment = file.add_variable_with_type(
+            "marker_comment",
+            &["marker"],
+            &netcdf::types::NcVariableType::String,
+        )?;
+        for (i, marker) in gpr.markers.iter().enumerate() {
+            marker_comment.put_string(&marker.comment, i)?;
+        }
+    }
+
+    // Add the picked horizons, with NaN for traces without a pick
+    if !gpr.horizons.is_empty() {
+        file.add_dimension("horizon", gpr.horizons.len())?;
+        let mut horizon_name = file.add_variable_with_type(
+            "horizon_name",
+            &["horizon"],
+            &netcdf::types::NcVariableType::String,
+        )?;
+        for (i, horizon) in gpr.horizons.iter().enumerate() {
+            horizon_name.put_string(&horizon.name, i)?;
+        }
+
+        let times = gpr
+            .horizons
+            .iter()
+            .flat_map(|h| h.picks.iter().map(|p| p.unwrap_or(f32::NAN)))
+            .collect::<Vec<f32>>();
+        let depths = times
+            .iter()
+            .map(|time| match time.is_nan() {
+                true => f32::NAN,
+                false => gpr.depth_at(*time),
+            })
+            .collect::<Vec<f32>>();
+        let elevations = depths
+            .chunks(gpr.width().max(1))
+            .flat_map(|row| {
+                row.iter()
+                    .zip(gpr.location.cor_points.iter())
+                    .map(|(depth, point)| point.altitude - *depth as f64)
+            })
+            .collect::<Vec<f64>>();
+
+        let mut horizon_time =
+            file.add_variable::<f32>("horizon_return_time", &["horizon", "x"])?;
+        horizon_time.put_values(&times, ..)?;
+        horizon_time.put_attribute("unit", "ns")?;
+        let mut horizon_depth = file.add_variable::<f32>("horizon_depth", &["horizon", "x"])?;
+        horizon_depth.put_values(&depths, ..)?;
+        horizon_depth.put_attribute("unit", "m")?;
+        let mut horizon_elevation =
+            file.add_variable::<f64>("horizon_elevation", &["horizon", "x"])?;
+        horizon_elevation.put_values(&elevations, ..)?;
+        horizon_elevation.put_attribute("unit", "m a.s.l.")?;
+        let mut horizon_quality = file.add_variable::<f32>("horizon_quality", &["horizon", "x"])?;
+        horizon_quality.put_values(
+            &gpr.horizons
+                .iter()
+                .flat_map(|h| h.quality.iter().map(|q| q.unwrap_or(f32::NAN)))
+                .collect::<Vec<f32>>(),
+            ..,
+        )?;
+    }
+
+    // Add the ice thickness products of the bed horizon, with NaN for traces without a pick
+    if let Some(bed_picks) = gpr.bed_picks() {
+        let mut ice_thickness = file.add_variable::<f32>("ice_thickness", &["x"])?;
+        ice_thickness.put_values(
+            &bed_picks
+                .iter()
+                .map(|pick| pick.map(|p| p.ice_thickness).unwrap_or(f32::NAN))
+                .collect::<Vec<f32>>(),
+            ..,
+        )?;
+        ice_thickness.put_attribute("unit", "m")?;
+        let mut bed_elevation = file.add_variable::<f64>("bed_elevation", &["x"])?;
+        bed_elevation.put_values(
+            &bed_picks
+                .iter()
+                .map(|pick| pick.map(|p| p.bed_elevation).unwrap_or(f64::NAN))
+                .collect::<Vec<f64>>(),
+            ..,
+        )?;
+        bed_elevation.put_attribute("unit", "m a.s.l.")?;
+        let mut bed_quality = file.add_variable::<f32>("bed_quality", &["x"])?;
+        bed_quality.put_values(
+            &bed_picks
+                .iter()
+                .map(|pick| pick.map(|p| p.quality).unwrap_or(f32::NAN))
+                .collect::<Vec<f32>>(),
+            ..,
+        )?;
+    }
+
     // Add the distance variable to the x dimension
     let mut ds = file.add_variable::<f32>("distance", &["x"])?;
     ds.put_values(&distance_vec, ..)?;
@@ -710,9 +2312,494 @@ pub fn export_netcdf(gpr: &gpr::GPR, nc_filepath: &Path) -> Result<(), Box<dyn s
     let mut depth = file.add_variable::<f32>("depth", &["y"])?;
     depth.put_values(&gpr.depths().into_raw_vec(), ..)?;
 
-    depth.put_attribute("unit", "m")?;
+    depth.put_attribute("unit", "m")?;
+
+    Ok(())
+}
+
+/// Read a global attribute from a NetCDF file, if it exists.
+///
+/// # Errors
+/// - The attribute exists but could not be converted to the requested type
+fn netcdf_attribute<T>(file: &netcdf::File, name: &str) -> Result<Option<T>, Box<dyn Error>>
+where
+    T: TryFrom<netcdf::AttributeValue, Error = netcdf::Error>,
+{
+    match file.attribute(name) {
+        Some(attr) => {
+            Ok(Some(T::try_from(attr.value()?).map_err(|e| {
+                format!("Could not read attribute '{name}': {e}")
+            })?))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Read a variable from a NetCDF file as a flat vector.
+///
+/// # Errors
+/// - The variable does not exist
+/// - The values could not be read
+fn netcdf_values<T: netcdf::NcTypeDescriptor + Copy>(
+    file: &netcdf::File,
+    name: &str,
+) -> Result<Vec<T>, Box<dyn Error>> {
+    Ok(file
+        .variable(name)
+        .ok_or(format!("No '{name}' variable in file"))?
+        .get_values::<T, _>(..)?)
+}
+
+/// Load a GPR profile from a NetCDF file written by `export_netcdf`
+///
+/// This allows processing to continue from a saved intermediate result.
+///
+/// # Arguments
+/// - `filepath`: The filepath of the NetCDF file
+/// - `override_antenna_mhz`: Optional antenna frequency override (will not read from metadata).
+///
+/// # Returns
+/// The GPR profile, with the data, location, metadata, processing log and topographically corrected
+/// data (if any).
+///
+/// # Errors
+/// - The file could not be read
+/// - A required variable or attribute is missing
+/// - The antenna frequency could not be found (only in files from older versions of radal)
+pub fn load_netcdf(
+    filepath: &Path,
+    override_antenna_mhz: Option<f32>,
+) -> Result<gpr::GPR, Box<dyn Error>> {
+    let file = netcdf::open(filepath)?;
+
+    let width = file.dimension_len("x").ok_or("No 'x' dimension in file")?;
+    let height = file.dimension_len("y").ok_or("No 'y' dimension in file")?;
+
+    let data = Array2::from_shape_vec((height, width), netcdf_values::<f32>(&file, "data")?)?;
+
+    let topo_data = match file.dimension_len("y2") {
+        Some(topo_height) => Some(Array2::from_shape_vec(
+            (topo_height, width),
+            netcdf_values::<f32>(&file, "data_topographically_corrected")?,
+        )?),
+        None => None,
+    };
+
+    let agc_gain = match file.variable("agc_gain") {
+        Some(_) => Some(Array2::from_shape_vec(
+            (height, width),
+            netcdf_values::<f32>(&file, "agc_gain")?,
+        )?),
+        None => None,
+    };
+
+    let times = netcdf_values::<f64>(&file, "time")?;
+    let eastings = netcdf_values::<f64>(&file, "easting")?;
+    let northings = netcdf_values::<f64>(&file, "northing")?;
+    let elevations = netcdf_values::<f64>(&file, "elevation")?;
+
+    // After loading, there is always one point per trace, so the trace number is the index.
+    let cor_points = (0..width)
+        .map(|i| gpr::CorPoint {
+            trace_n: i as u32,
+            time_seconds: times[i],
+            easting: eastings[i],
+            northing: northings[i],
+            altitude: elevations[i],
+        })
+        .collect::<Vec<gpr::CorPoint>>();
+
+    let correction = match netcdf_attribute::<String>(&file, "elevation-correction")?
+        .as_deref()
+        .and_then(|s| s.strip_prefix("DEM-corrected: "))
+    {
+        Some(dem_name) => gpr::LocationCorrection::Dem(PathBuf::from(dem_name.trim_matches('"'))),
+        None => gpr::LocationCorrection::None,
+    };
+
+    let location = gpr::GPRLocation {
+        cor_points,
+        correction,
+        crs: netcdf_attribute(&file, "crs")?.ok_or("No 'crs' attribute in file")?,
+    };
+
+    let antenna: String =
+        netcdf_attribute(&file, "antenna")?.ok_or("No 'antenna' attribute in file")?;
+
+    // Files from older versions of radal only have the antenna name
+    let antenna_mhz = match override_antenna_mhz {
+        Some(v) => v,
+        None => match netcdf_attribute::<f32>(&file, "antenna-center-frequency")? {
+            Some(v) => v,
+            None => antenna_mhz_from_name(&antenna).ok_or(format!(
+                "Could not read frequency from the antenna field ({antenna:?}). Try using the antenna MHz override"
+            ))?,
+        },
+    };
+
+    // Files from older versions of radal only have the return time of each sample
+    let time_window = match netcdf_attribute::<f32>(&file, "time-window")? {
+        Some(v) => v,
+        None => {
+            let return_time = netcdf_values::<f32>(&file, "return-time")?;
+            match return_time.len() > 1 {
+                true => (return_time[1] - return_time[0]) * height as f32,
+                false => return Err("Could not find the time window in the file".into()),
+            }
+        }
+    };
+
+    let antenna_separation: f32 = netcdf_attribute(&file, "antenna-separation")?
+        .ok_or("No 'antenna-separation' attribute in file")?;
+
+    let metadata = gpr::GPRMeta {
+        samples: height as u32,
+        frequency: netcdf_attribute(&file, "vertical-sampling-frequency")?
+            .ok_or("No 'vertical-sampling-frequency' attribute in file")?,
+        frequency_steps: netcdf_attribute(&file, "frequency-steps")?.unwrap_or(0),
+        time_interval: netcdf_attribute(&file, "time-interval")?.unwrap_or(f32::NAN),
+        distance_interval: netcdf_attribute(&file, "distance-interval")?,
+        antenna,
+        antenna_mhz,
+        antenna_separation,
+        time_window,
+        last_trace: width as u32,
+        data_filepath: filepath.to_path_buf(),
+        medium_velocity: netcdf_attribute(&file, "medium-velocity")?
+            .ok_or("No 'medium-velocity' attribute in file")?,
+        channel: netcdf_attribute::<u32>(&file, "channel")?.unwrap_or(0) as usize,
+        channel_offset: [0., 0.],
+        velocity_model: netcdf_attribute::<String>(&file, "velocity-model")?
+            .map(|text| VelocityModel::from_csv_str(&text))
+            .transpose()?,
+    };
+
+    let log = netcdf_attribute::<String>(&file, "processing-log")?
+        .map(|s| {
+            s.lines()
+                .filter(|l| !l.is_empty())
+                .map(|l| l.to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // Files from older versions have no provenance
+    let provenance = match netcdf_attribute::<String>(&file, "processing-provenance")? {
+        Some(json) => Provenance::from_json(&json)?,
+        None => Provenance::default(),
+    };
+
+    let horizons = match file.variable("horizon_name") {
+        Some(horizon_name) => {
+            let times = netcdf_values::<f32>(&file, "horizon_return_time")?;
+            let quality = netcdf_values::<f32>(&file, "horizon_quality")?;
+            times
+                .chunks(width.max(1))
+                .zip(quality.chunks(width.max(1)))
+                .enumerate()
+                .map(|(i, (time_row, quality_row))| {
+                    let picks = time_row
+                        .iter()
+                        .zip(quality_row)
+                        .map(|(time, quality)| Some((*time, *quality)).filter(|_| !time.is_nan()))
+                        .collect::<Vec<Option<(f32, f32)>>>();
+                    Ok(Horizon::new(&horizon_name.get_string(i)?, &picks))
+                })
+                .collect::<Result<Vec<Horizon>, netcdf::Error>>()?
+        }
+        None => Vec::new(),
+    };
+
+    let markers = match file.variable("marker_trace") {
+        Some(marker_trace) => {
+            let comments = file
+                .variable("marker_comment")
+                .ok_or("No 'marker_comment' variable in file")?;
+            marker_trace
+                .get_values::<u32, _>(..)?
+                .into_iter()
+                .enumerate()
+                .map(|(i, trace)| {
+                    Ok(gpr::Marker {
+                        trace: trace as usize,
+                        comment: comments.get_string(i)?,
+                    })
+                })
+                .collect::<Result<Vec<gpr::Marker>, netcdf::Error>>()?
+        }
+        None => Vec::new(),
+    };
+
+    Ok(gpr::GPR {
+        data,
+        topo_data,
+        location,
+        metadata,
+        log,
+        provenance,
+        markers,
+        horizontal_signal_distance: netcdf_attribute(&file, "horizontal-signal-distance")?
+            .unwrap_or(antenna_separation),
+        zero_point_ns: netcdf_attribute(&file, "zero-point")?.unwrap_or(0.),
+        agc_gain,
+        horizons,
+        diffractions: Vec::new(),
+    })
+}
+
+/// Convert printable ASCII to EBCDIC (code page 037). Other characters become spaces.
+fn ascii_to_ebcdic(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c {
+            ' '..='~' => EBCDIC_PRINTABLE[c as usize - 0x20],
+            _ => 0x40,
+        })
+        .collect()
+}
+
+/// Convert EBCDIC (code page 037) to ASCII. Non-printable characters become spaces.
+fn ebcdic_to_ascii(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| {
+            EBCDIC_PRINTABLE
+                .iter()
+                .position(|e| e == b)
+                .map(|i| (0x20 + i as u8) as char)
+                .unwrap_or(' ')
+        })
+        .collect()
+}
+
+/// Export a GPR profile to a SEG-Y (revision 1 or 2) file.
+///
+/// The textual header is EBCDIC-encoded and contains the metadata and processing log. The samples
+/// are written as big-endian IEEE floats. Coordinates and elevations are written in the projected
+/// CRS of the profile, scaled to centimeters if possible.
+///
+/// The standard sample interval fields (binary header bytes 3217 and 3219, trace header bytes
+/// 117) are in microseconds, which cannot represent GPR sample intervals. They are instead written
+/// in picoseconds, i.e. with all times scaled by 10⁶, which is a common GPR convention. This is
+/// marked in the textual header, so radal reads the files back correctly, but other readers may
+/// need to be told about it (e.g. `--segy-interval-unit ps`). Revision 2 files also have the exact
+/// interval in microseconds in the extended sample interval fields (binary header bytes 3273 and
+/// 3281), which take precedence in readers that support revision 2. The 16 bit fields are zero if
+/// the interval does not fit in them.
+///
+/// Only the data are written, not the topographically corrected data.
+///
+/// It will overwrite any file that already exists with the same filename.
+///
+/// # Arguments
+/// - `gpr`: The GPR object to export
+/// - `filepath`: The filepath of the output SEG-Y file
+/// - `revision`: The SEG-Y revision (1 or 2)
+///
+/// # Errors
+/// - If the revision is not 1 or 2
+/// - If the profile has more samples or a longer sample interval than SEG-Y allows
+/// - If the file could not be written
+pub fn export_segy(gpr: &gpr::GPR, filepath: &Path, revision: u8) -> Result<(), Box<dyn Error>> {
+    use chrono::{Datelike, Timelike};
+
+    const TEXT_HEADER_LINES: usize = 40;
+    const TEXT_HEADER_LINE_LENGTH: usize = 80;
+
+    let height = gpr.height();
+    let samples = u16::try_from(height)
+        .map_err(|_| format!("Too many samples for SEG-Y: {height} (max {})", u16::MAX))?;
+    if ![1, 2].contains(&revision) {
+        return Err(format!("Unsupported SEG-Y revision: {revision}. Choose from 1 or 2").into());
+    }
+    let interval_ns = gpr.vertical_resolution_ns();
+    let interval_ps = (interval_ns * 1000.).round();
+    let interval_ps = match (1. ..=u16::MAX as f32).contains(&interval_ps) {
+        true => interval_ps as u16,
+        // Revision 2 has the extended sample interval instead
+        false if revision == 2 => 0,
+        false => {
+            return Err(
+                format!("Sample interval cannot be written to SEG-Y: {interval_ps} ps").into(),
+            )
+        }
+    };
+
+    // Coordinates are written as integers with a common scalar. Negative scalars are divisors.
+    let max_coordinate = gpr
+        .location
+        .cor_points
+        .iter()
+        .flat_map(|p| [p.easting.abs(), p.northing.abs(), p.altitude.abs()])
+        .fold(0_f64, f64::max);
+    let coordinate_scalar: i16 = [-100_i16, -10, 1]
+        .into_iter()
+        .find(|scalar| max_coordinate * (-*scalar as f64).max(1.) < i32::MAX as f64)
+        .ok_or(format!(
+            "Coordinates too large to be written to SEG-Y: {max_coordinate}"
+        ))?;
+    let scale = |value: f64| (value * (-coordinate_scalar as f64).max(1.)).round() as i32;
+
+    // The textual header has 40 lines of 80 characters, each starting with "C" and the line number
+    let mut text_lines = vec![
+        format!("{} version {}", crate::PROGRAM_NAME, crate::PROGRAM_VERSION),
+        format!(
+            "Original filename: {}",
+            gpr.metadata
+                .data_filepath
+                .file_name()
+                .and_then(|s| s.to_str())
+                .unwrap_or_default()
+        ),
+        format!(
+            "Antenna: {} ({} MHz). Antenna separation: {} m",
+            gpr.metadata.antenna, gpr.metadata.antenna_mhz, gpr.metadata.antenna_separation
+        ),
+        format!(
+            "Samples: {}. Time window: {} ns. Sample interval: {} ns",
+            height, gpr.metadata.time_window, interval_ns
+        ),
+        match revision {
+            2 => format!(
+                "{SEGY_PS_INTERVAL_MARKER} (times scaled by 1e6); exact in the extended field"
+            ),
+            _ => format!("{SEGY_PS_INTERVAL_MARKER} (times scaled by 1e6)"),
+        },
+        format!("Medium velocity: {} m/ns", gpr.metadata.medium_velocity),
+        format!("CRS: {}", gpr.location.crs),
+        format!("Coordinate and elevation scalar: {}", coordinate_scalar),
+        "Processing log:".to_string(),
+    ];
+    for line in &gpr.log {
+        let chars = line.replace('\t', " ").chars().collect::<Vec<char>>();
+        for chunk in chars.chunks(TEXT_HEADER_LINE_LENGTH - 4) {
+            text_lines.push(chunk.iter().collect());
+        }
+    }
+    text_lines.truncate(TEXT_HEADER_LINES - 2);
+    text_lines.resize(TEXT_HEADER_LINES - 2, String::new());
+    text_lines.push(
+        match revision {
+            2 => "SEG-Y_REV2.0",
+            _ => "SEG Y REV1",
+        }
+        .to_string(),
+    );
+    text_lines.push("END EBCDIC".to_string());
+
+    let mut bytes = Vec::<u8>::with_capacity(3600 + gpr.width() * (240 + 4 * height));
+    for (i, line) in text_lines.iter().enumerate() {
+        let mut line = format!("C{:>2} {}", i + 1, line)
+            .chars()
+            .take(TEXT_HEADER_LINE_LENGTH)
+            .collect::<String>();
+        line = format!("{line:<TEXT_HEADER_LINE_LENGTH$}");
+        bytes.extend(ascii_to_ebcdic(&line));
+    }
+
+    // The binary header. All values are big-endian and the positions are zero-based.
+    let mut binary_header = [0_u8; 400];
+    let put = |header: &mut [u8], position: usize, value: &[u8]| {
+        header[position..position + value.len()].copy_from_slice(value);
+    };
+    put(&mut binary_header, 12, &1_i16.to_be_bytes()); // Traces per ensemble
+    put(&mut binary_header, 16, &interval_ps.to_be_bytes()); // Sample interval
+    put(&mut binary_header, 18, &interval_ps.to_be_bytes()); // Original sample interval
+    put(&mut binary_header, 20, &samples.to_be_bytes()); // Samples per trace
+    put(&mut binary_header, 22, &samples.to_be_bytes()); // Original samples per trace
+    put(&mut binary_header, 24, &5_i16.to_be_bytes()); // Sample format: IEEE float
+    put(&mut binary_header, 26, &1_i16.to_be_bytes()); // Ensemble fold
+    put(&mut binary_header, 28, &1_i16.to_be_bytes()); // Trace sorting: as recorded
+    put(&mut binary_header, 54, &1_i16.to_be_bytes()); // Measurement system: meters
+    if revision == 2 {
+        let interval_us = interval_ns as f64 / 1000.;
+        put(&mut binary_header, 68, &(height as i32).to_be_bytes()); // Extended samples per trace
+        put(&mut binary_header, 72, &interval_us.to_be_bytes()); // Extended sample interval
+        put(&mut binary_header, 80, &interval_us.to_be_bytes()); // Extended original interval
+        put(&mut binary_header, 88, &(height as i32).to_be_bytes()); // Extended original samples
+        put(&mut binary_header, 96, &0x0102_0304_i32.to_be_bytes()); // Byte order constant
+    }
+    put(&mut binary_header, 300, &[revision, 0]); // SEG-Y revision
+    put(&mut binary_header, 302, &1_i16.to_be_bytes()); // Fixed length traces
+    put(&mut binary_header, 304, &0_i16.to_be_bytes()); // No extended textual headers
+    bytes.extend(binary_header);
+
+    for (i, (point, trace)) in gpr
+        .location
+        .cor_points
+        .iter()
+        .zip(gpr.data.columns())
+        .enumerate()
+    {
+        let trace_number = (i + 1) as i32;
+        let datetime = chrono::DateTime::from_timestamp(point.time_seconds as i64, 0)
+            .ok_or(format!("Invalid time of trace {i}: {}", point.time_seconds))?;
+
+        let mut trace_header = [0_u8; 240];
+        put(&mut trace_header, 0, &trace_number.to_be_bytes()); // Trace number in line
+        put(&mut trace_header, 4, &trace_number.to_be_bytes()); // Trace number in file
+        put(
+            &mut trace_header,
+            8,
+            &((point.trace_n + 1) as i32).to_be_bytes(),
+        ); // Original trace number
+        put(&mut trace_header, 12, &1_i32.to_be_bytes()); // Trace number in record
+        put(&mut trace_header, 20, &trace_number.to_be_bytes()); // CDP number
+        put(&mut trace_header, 28, &1_i16.to_be_bytes()); // Trace identification: data
+        put(&mut trace_header, 34, &1_i16.to_be_bytes()); // Data use: production
+        put(&mut trace_header, 40, &scale(point.altitude).to_be_bytes()); // Receiver elevation
+        put(&mut trace_header, 44, &scale(point.altitude).to_be_bytes()); // Source elevation
+        put(&mut trace_header, 68, &coordinate_scalar.to_be_bytes()); // Elevation scalar
+        put(&mut trace_header, 70, &coordinate_scalar.to_be_bytes()); // Coordinate scalar
+        for position in [72, 80, 180] {
+            // Source, receiver and CDP coordinates
+            put(
+                &mut trace_header,
+                position,
+                &scale(point.easting).to_be_bytes(),
+            );
+            put(
+                &mut trace_header,
+                position + 4,
+                &scale(point.northing).to_be_bytes(),
+            );
+        }
+        put(&mut trace_header, 88, &1_i16.to_be_bytes()); // Coordinate units: length
+        put(&mut trace_header, 114, &samples.to_be_bytes());
+        put(&mut trace_header, 116, &interval_ps.to_be_bytes());
+        put(
+            &mut trace_header,
+            156,
+            &(datetime.year() as i16).to_be_bytes(),
+        );
+        put(
+            &mut trace_header,
+            158,
+            &(datetime.ordinal() as i16).to_be_bytes(),
+        );
+        put(
+            &mut trace_header,
+            160,
+            &(datetime.hour() as i16).to_be_bytes(),
+        );
+        put(
+            &mut trace_header,
+            162,
+            &(datetime.minute() as i16).to_be_bytes(),
+        );
+        put(
+            &mut trace_header,
+            164,
+            &(datetime.second() as i16).to_be_bytes(),
+        );
+        put(&mut trace_header, 166, &2_i16.to_be_bytes()); // Time basis: UTC
+        bytes.extend(trace_header);
+
+        for value in trace {
+            bytes.extend(value.to_be_bytes());
+        }
+    }
 
-    Ok(())
+    Ok(std::fs::write(filepath, bytes)?)
 }
 
 /// Render an image of the processed GPR data.
@@ -746,7 +2833,7 @@ pub fn render_jpg(gpr: &gpr::GPR, filepath: &Path) -> Result<(), Box<dyn Error>>
     //let logit99 = (0.99_f32 / (1.0_f32 - 0.99_f32)).log(std::f32::consts::E);
 
     // Render the pixels into a grayscale image
-    let pixels: Vec<u8> = data
+    let mut pixels: Vec<u8> = data
         .into_par_iter()
         .map(|f| {
             (255.0 * {
@@ -761,6 +2848,15 @@ pub fn render_jpg(gpr: &gpr::GPR, filepath: &Path) -> Result<(), Box<dyn Error>>
         })
         .collect();
 
+    // Draw the markers as ticks at the top of the image
+    let (height, width) = data_to_render.dim();
+    let tick_height = (height / 50).max(5).min(height);
+    for marker in gpr.markers.iter().filter(|m| m.trace < width) {
+        for row in 0..tick_height {
+            pixels[row * width + marker.trace] = 255;
+        }
+    }
+
     image::save_buffer(
         filepath,
         &pixels,
@@ -772,6 +2868,103 @@ pub fn render_jpg(gpr: &gpr::GPR, filepath: &Path) -> Result<(), Box<dyn Error>>
     Ok(())
 }
 
+/// Render a semblance spectrum as a grayscale image, with the velocity picks as black crosses
+///
+/// The zero-offset return time increases downward and the velocity to the right. The semblance is
+/// scaled from 0 (black) to its maximum (white).
+///
+/// # Arguments
+/// - `semblance`: The semblance spectrum
+/// - `picks`: The zero-offset return time (ns) and RMS velocity (m/ns) of each pick
+/// - `filepath`: The image filepath
+///
+/// # Errors
+/// - The image cannot be saved
+pub fn render_semblance_jpg(
+    semblance: &crate::cmp::Semblance,
+    picks: &[(f32, f32)],
+    filepath: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let (height, width) = semblance.values.dim();
+    let maxval = semblance.values.iter().fold(0_f32, |max, v| max.max(*v));
+    let mut pixels = semblance
+        .values
+        .iter()
+        .map(|v| match maxval > 0. {
+            true => (255. * (v / maxval).clamp(0., 1.)) as u8,
+            false => 0,
+        })
+        .collect::<Vec<u8>>();
+
+    let arm = (width.min(height) / 50).max(3) as isize;
+    for (time, velocity) in picks {
+        let row = ((time - semblance.start_time) / semblance.sample_interval).round() as isize;
+        let Some(col) = semblance
+            .velocities
+            .iter()
+            .enumerate()
+            .min_by(|a, b| (a.1 - velocity).abs().total_cmp(&(b.1 - velocity).abs()))
+            .map(|(col, _)| col as isize)
+        else {
+            continue;
+        };
+        for i in -arm..=arm {
+            for (r, c) in [(row + i, col), (row, col + i)] {
+                if (0..height as isize).contains(&r) && (0..width as isize).contains(&c) {
+                    pixels[r as usize * width + c as usize] = 0;
+                }
+            }
+        }
+    }
+
+    image::save_buffer(
+        filepath,
+        &pixels,
+        width as u32,
+        height as u32,
+        image::ColorType::L8,
+    )?;
+
+    Ok(())
+}
+
+/// Find the filepath of a file that is exported next to the output, such as a track file
+///
+/// # Arguments
+/// - `potential_path`: The given filepath or directory (if provided)
+/// - `output_filepath`: The output filepath to derive a filepath from
+/// - `suffix`: The suffix to add to the file stem of the output filepath, e.g. "_track"
+///
+/// # Returns
+/// The given filepath, or the file stem of the output filepath + the suffix + ".csv" in the given
+/// directory or (if none was given) in the directory of the output filepath.
+pub fn sidecar_csv_filepath(
+    potential_path: Option<&PathBuf>,
+    output_filepath: &Path,
+    suffix: &str,
+) -> PathBuf {
+    let filename = output_filepath
+        .file_stem()
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string()
+        + suffix;
+    match potential_path {
+        // Here is in case a filepath or directory was given
+        Some(fp) => match fp.is_dir() {
+            // In case the filepath points to a directory
+            true => fp.join(filename).with_extension("csv"),
+            // In case it is not a directory (and thereby assumed to be a normal filepath)
+            false => fp.clone(),
+        },
+        // Here is if no filepath was given
+        None => output_filepath
+            .with_file_name(filename)
+            .with_extension("csv"),
+    }
+}
+
 /// Export a "track" file.
 ///
 /// It has its own associated function because the logic may happen in two different places in the
@@ -779,6 +2972,7 @@ pub fn render_jpg(gpr: &gpr::GPR, filepath: &Path) -> Result<(), Box<dyn Error>>
 ///
 /// # Arguments
 /// - `gpr_locations`: The GPRLocation object to export
+/// - `bed_picks`: The ice thickness, bed elevation and quality of each trace to add (if any)
 /// - `potential_track_path`: The output path of the track file or a directory (if provided)
 /// - `output_filepath`: The output filepath to derive a track filepath from in case `potential_track_path` was not provided.
 /// - `verbose`: Print progress?
@@ -787,6 +2981,7 @@ pub fn render_jpg(gpr: &gpr::GPR, filepath: &Path) -> Result<(), Box<dyn Error>>
 /// The exit code of the function
 pub fn export_locations(
     gpr_locations: &gpr::GPRLocation,
+    bed_picks: Option<&[Option<BedPick>]>,
     potential_track_path: Option<&PathBuf>,
     output_filepath: &Path,
     verbose: bool,
@@ -794,48 +2989,121 @@ pub fn export_locations(
     // Determine the output filepath. If one was given, use that. If none was given, use the
     // parent and file stem + "_track.csv" of the output filepath. If a directory was given,
     // use the directory + the file stem of the output filepath + "_track.csv".
-    let track_path: PathBuf = match potential_track_path {
-        // Here is in case a filepath or directory was given
-        Some(fp) => match fp.is_dir() {
-            // In case the filepath points to a directory
-            true => fp
-                .join(
-                    output_filepath
-                        .file_stem()
-                        .unwrap()
-                        .to_str()
-                        .unwrap()
-                        .to_string()
-                        + "_track",
-                )
-                .with_extension("csv"),
-            // In case it is not a directory (and thereby assumed to be a normal filepath)
-            false => fp.clone(),
-        },
-        // Here is if no filepath was given
-        None => output_filepath
-            .with_file_name(
-                output_filepath
-                    .file_stem()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .to_string()
-                    + "_track",
-            )
-            .with_extension("csv"),
-    };
+    let track_path = sidecar_csv_filepath(potential_track_path, output_filepath, "_track");
     if verbose {
         println!("Exporting track to {:?}", track_path);
     };
 
-    Ok(gpr_locations.to_csv(&track_path)?)
+    Ok(gpr_locations.to_csv(&track_path, bed_picks)?)
+}
+
+/// Export the mean amplitude spectrum of a profile to a CSV file
+///
+/// # Arguments
+/// - `gpr`: The profile to export the spectrum of
+/// - `potential_spectrum_path`: The output path of the spectrum file or a directory (if provided)
+/// - `output_filepath`: The output filepath to derive a spectrum filepath from in case
+///   `potential_spectrum_path` was not provided ("_spectrum.csv").
+/// - `verbose`: Print progress?
+pub fn export_spectrum(
+    gpr: &gpr::GPR,
+    potential_spectrum_path: Option<&PathBuf>,
+    output_filepath: &Path,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    let spectrum_path = sidecar_csv_filepath(potential_spectrum_path, output_filepath, "_spectrum");
+    if verbose {
+        println!("Exporting spectrum to {:?}", spectrum_path);
+    };
+
+    let (frequencies, amplitudes) = gpr.spectrum();
+    let mut output = "frequency_mhz,amplitude\n".to_string();
+    for (frequency, amplitude) in frequencies.iter().zip(amplitudes.iter()) {
+        output += &format!("{frequency},{amplitude}\n");
+    }
+    Ok(std::fs::write(spectrum_path, output)?)
+}
+
+/// Export the picked horizons of a profile to a CSV file
+///
+/// Each row is the pick of one horizon in one trace. Traces without a pick are skipped.
+///
+/// # Arguments
+/// - `gpr`: The profile to export the horizons of
+/// - `potential_picks_path`: The output path of the picks file or a directory (if provided)
+/// - `output_filepath`: The output filepath to derive a picks filepath from in case
+///   `potential_picks_path` was not provided ("_picks.csv").
+/// - `verbose`: Print progress?
+pub fn export_picks(
+    gpr: &gpr::GPR,
+    potential_picks_path: Option<&PathBuf>,
+    output_filepath: &Path,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    let picks_path = sidecar_csv_filepath(potential_picks_path, output_filepath, "_picks");
+    if verbose {
+        println!("Exporting picks to {:?}", picks_path);
+    };
+
+    let mut output =
+        "horizon,trace,return_time_ns,depth_m,easting,northing,elevation,quality\n".to_string();
+    for horizon in &gpr.horizons {
+        for (trace, ((pick, quality), point)) in horizon
+            .picks
+            .iter()
+            .zip(&horizon.quality)
+            .zip(gpr.location.cor_points.iter())
+            .enumerate()
+        {
+            if let (Some(time), Some(quality)) = (pick, quality) {
+                let depth = gpr.depth_at(*time);
+                output += &format!(
+                    "{},{trace},{time},{depth},{},{},{},{quality}\n",
+                    horizon.name,
+                    point.easting,
+                    point.northing,
+                    point.altitude - depth as f64
+                );
+            }
+        }
+    }
+    Ok(std::fs::write(picks_path, output)?)
+}
+
+/// Export the fitted diffraction hyperbolae of a profile as a velocity model CSV
+///
+/// See [`hyperbola::velocity_table`] for the format. The file can be given to `--velocity-model`.
+///
+/// # Arguments
+/// - `gpr`: The profile to export the diffractions of
+/// - `potential_velocity_table_path`: The output path of the velocity file or a directory (if
+///   provided)
+/// - `output_filepath`: The output filepath to derive a velocity filepath from in case
+///   `potential_velocity_table_path` was not provided ("_velocity.csv").
+/// - `verbose`: Print progress?
+///
+/// # Errors
+/// - No diffractions were fitted
+pub fn export_velocity_table(
+    gpr: &gpr::GPR,
+    potential_velocity_table_path: Option<&PathBuf>,
+    output_filepath: &Path,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    let table = hyperbola::velocity_table(&gpr.diffractions, gpr.horizontal_signal_distance)?;
+    let velocity_table_path =
+        sidecar_csv_filepath(potential_velocity_table_path, output_filepath, "_velocity");
+    if verbose {
+        println!("Exporting the velocity table to {:?}", velocity_table_path);
+    };
+    Ok(std::fs::write(velocity_table_path, table)?)
 }
 
 #[cfg(test)]
 mod tests {
 
-    use super::{load_cor, load_rad};
+    use super::{load_cor, load_rad, load_rd7, mala_channel};
+    use std::path::Path;
 
     /// Fake some data. One point is in the northern hemisphere and one is in the southern
     fn fake_cor_text() -> String {
@@ -942,6 +3210,89 @@ mod tests {
         assert_eq!(gpr_meta.antenna_mhz, 200.);
     }
 
+    #[test]
+    fn test_load_mrk() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mrk_path = temp_dir.path().join("hello.mrk");
+        std::fs::write(
+            &mrk_path,
+            "120\tRoad crossing\r\n15\r\nMARKERS\r\n\r\n300 Flag 3\r\n",
+        )
+        .unwrap();
+
+        let markers = super::load_mrk(&mrk_path).unwrap();
+
+        // The markers should be zero-indexed and sorted
+        assert_eq!(
+            markers.iter().map(|m| m.trace).collect::<Vec<usize>>(),
+            [14, 119, 299]
+        );
+        assert_eq!(markers[0].comment, "");
+        assert_eq!(markers[1].comment, "Road crossing");
+        assert_eq!(markers[2].comment, "Flag 3");
+    }
+
+    #[test]
+    fn test_load_rd7() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let rad_path = temp_dir.path().join("hello_A02.rad");
+        let rd7_path = rad_path.with_extension("rd7");
+        let rad_text = [
+            "SAMPLES:4",
+            "FREQUENCY: 1000.",
+            "FREQUENCY STEPS: 1",
+            "TIME INTERVAL: 0.1",
+            "ANTENNAS: 400 MHz shielded",
+            "ANTENNA SEPARATION: 0.1",
+            "TIMEWINDOW:4",
+            "LAST TRACE: 3",
+            "CH_X_OFFSET:0.100000",
+            "CH_Y_OFFSET:-0.250000",
+        ]
+        .join("\r\n");
+        std::fs::write(&rad_path, rad_text).unwrap();
+
+        let values: Vec<i32> = (0..12).map(|i| (i - 6) * (i32::MAX / 6)).collect();
+        std::fs::write(
+            &rd7_path,
+            values
+                .iter()
+                .flat_map(|v| v.to_le_bytes())
+                .collect::<Vec<u8>>(),
+        )
+        .unwrap();
+
+        // Without an ".rd3" file, the ".rd7" file should be used
+        let gpr_meta = load_rad(&rad_path, 0.1, None).unwrap();
+        assert_eq!(gpr_meta.data_filepath, rd7_path);
+        assert_eq!(gpr_meta.channel, 1);
+        assert_eq!(gpr_meta.channel_offset, [0.1, -0.25]);
+
+        let data = load_rd7(&rd7_path, gpr_meta.samples as usize).unwrap();
+        assert_eq!(data.shape(), [4, 3]);
+        // The 32 bit range should be scaled to the same 50 V range as the 16 bit data
+        assert!((data[[0, 0]] - -50000.).abs() < 1.);
+        assert!((data[[2, 1]] - 0.).abs() < 1e-3);
+        assert!((data[[0, 2]] - 16666.666).abs() < 1.);
+
+        // The amplitude range of the ".rad" file is used if it is there
+        let rad_text = std::fs::read_to_string(&rad_path).unwrap();
+        std::fs::write(
+            &rad_path,
+            rad_text.clone() + "\r\nAMPLITUDE RANGE (mV):1000",
+        )
+        .unwrap();
+        let data = load_rd7(&rd7_path, gpr_meta.samples as usize).unwrap();
+        assert!((data[[0, 0]] - -1000.).abs() < 0.1);
+        std::fs::write(&rad_path, rad_text + "\r\nAMPLITUDE RANGE (mV):-1").unwrap();
+        assert!(load_rd7(&rd7_path, gpr_meta.samples as usize).is_err());
+
+        assert_eq!(mala_channel(Path::new("DAT_001_A1.rd3")), Some(0));
+        assert_eq!(mala_channel(Path::new("DAT_001_A16.rad")), Some(15));
+        assert_eq!(mala_channel(Path::new("DAT_001.rad")), None);
+        assert_eq!(mala_channel(Path::new("DAT_A0.rad")), None);
+    }
+
     #[test]
     fn test_load_rad_bad_antenna_mhz() {
         // Fake a .rad metadata file
@@ -1042,6 +3393,155 @@ mod tests {
         assert_eq!(gpr_meta.antenna_mhz, 300.);
     }
 
+    /// Fake a pulseEKKO ".hd" and ".dt1" pair with a ramp in each trace
+    fn write_fake_pe_files(
+        directory: &std::path::Path,
+        data_type: &str,
+        gps: Option<[f64; 2]>,
+    ) -> std::path::PathBuf {
+        let hd_path = directory.join("line.hd");
+        let hd_text = [
+            "1234",
+            "fake - pulseEKKO v1.8.1423",
+            "2025-Apr-04",
+            "NUMBER OF TRACES   = 3",
+            "NUMBER OF PTS/TRC  = 4",
+            "TOTAL TIME WINDOW  = 40",
+            "NOMINAL FREQUENCY  = 200",
+            "ANTENNA SEPARATION = 1",
+            "X Y Z POSITIONS ADDED - LatLong",
+            &format!("DATA TYPE          = {data_type}"),
+            "AMPLITUDE WINDOW (mV)= 200",
+            "TRACE INTERVAL (s) = 0.2",
+        ]
+        .join("\r\n");
+        std::fs::write(&hd_path, hd_text).unwrap();
+
+        let bytes_per_sample = if data_type == "I*4" { 4 } else { 2 };
+        let mut bytes = Vec::<u8>::new();
+        for trace in 0..3 {
+            let mut header = [0_u8; super::PE_TRACE_HEADER_BYTES];
+            let mut put_f32 = |word: usize, value: f32| {
+                header[word * 4..word * 4 + 4].copy_from_slice(&value.to_le_bytes())
+            };
+            put_f32(0, (trace + 1) as f32);
+            put_f32(1, trace as f32 * 0.5);
+            put_f32(2, 4.);
+            put_f32(3, 100.);
+            put_f32(5, bytes_per_sample as f32);
+            put_f32(7, 32.);
+            put_f32(23, 3600. + trace as f32);
+            if let Some([x, y]) = gps {
+                for (word, value) in [(8, x), (10, y + trace as f64 * 1e-5), (12, 500.)] {
+                    header[word * 4..word * 4 + 8].copy_from_slice(&value.to_le_bytes());
+                }
+            }
+            bytes.extend_from_slice(&header);
+
+            for sample in 0..4_i32 {
+                match bytes_per_sample {
+                    4 => bytes.extend_from_slice(&(sample * (i32::MAX / 4)).to_le_bytes()),
+                    _ => bytes.extend_from_slice(
+                        &((sample * (i16::MAX as i32 / 4)) as i16).to_le_bytes(),
+                    ),
+                }
+            }
+        }
+        std::fs::write(hd_path.with_extension("dt1"), bytes).unwrap();
+
+        hd_path
+    }
+
+    #[test]
+    fn test_load_pe_dt1() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        for data_type in ["I*2", "I*4"] {
+            let hd_path = write_fake_pe_files(temp_dir.path(), data_type, None);
+            let dt1_path = hd_path.with_extension("dt1");
+
+            let headers = super::load_pe_trace_headers(&dt1_path, 4, 3).unwrap();
+            assert_eq!(headers.len(), 3);
+            assert_eq!(headers[2].trace_n, 3);
+            assert_eq!(headers[2].position, 1.);
+            assert_eq!(headers[2].samples, 4);
+            assert_eq!(headers[2].stacks, 32);
+            assert_eq!(headers[2].time_of_day, 3602.);
+            assert_eq!(headers[2].gps, None);
+            assert_eq!(
+                headers[2].bytes_per_sample,
+                if data_type == "I*4" { 4 } else { 2 }
+            );
+
+            // The samples should be scaled with the amplitude window of the ".hd" file
+            let data = super::load_pe_dt1(&dt1_path, 4, 3).unwrap();
+            assert_eq!(data.shape(), [4, 3]);
+            assert!((data[[2, 1]] - 100.).abs() < 0.1, "{data_type}: {data}");
+
+            // Without GPS positions, the position along the line is used
+            let location = super::load_pe_trace_locations(&dt1_path, 4, 3, None).unwrap();
+            assert_eq!(location.crs, "local");
+            assert_eq!(location.cor_points[2].easting, 1.);
+            assert_eq!(location.cor_points[2].altitude, 100.);
+            assert_eq!(
+                location.cor_points[0].time_seconds,
+                chrono::DateTime::parse_from_rfc3339("2025-04-04T01:00:00+00:00")
+                    .unwrap()
+                    .timestamp() as f64
+            );
+        }
+    }
+
+    #[test]
+    fn test_load_pe_gather() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let hd_path = write_fake_pe_files(temp_dir.path(), "I*2", None);
+
+        // The antenna separations are the trace positions by default
+        let gather = super::load_pe_gather(&hd_path, None, None, None).unwrap();
+        assert_eq!(gather.offsets, vec![0., 0.5, 1.]);
+        assert_eq!(gather.data.shape(), [4, 3]);
+        assert_eq!(gather.sample_interval, 10.);
+        assert_eq!(gather.start_time, 0.);
+        assert_eq!(gather.antenna_mhz, 200.);
+
+        // With a spacing, they start at the antenna separation of the header by default
+        let gather = super::load_pe_gather(&hd_path, Some(0.25), None, None).unwrap();
+        assert_eq!(gather.offsets, vec![1., 1.25, 1.5]);
+        let gather = super::load_pe_gather(&hd_path, Some(0.25), Some(2.), None).unwrap();
+        assert_eq!(gather.offsets, vec![2., 2.25, 2.5]);
+
+        // Positions in other units need a spacing
+        let hd_text = std::fs::read_to_string(&hd_path).unwrap()
+            + "\r\nPOSITION UNITS     = ft\r\nTIMEZERO AT POINT  = 1.5";
+        std::fs::write(&hd_path, hd_text).unwrap();
+        assert!(super::load_pe_gather(&hd_path, None, None, None).is_err());
+        let gather = super::load_pe_gather(&hd_path, Some(0.5), None, None).unwrap();
+        assert_eq!(gather.start_time, -15.);
+    }
+
+    #[test]
+    fn test_load_pe_trace_locations_gps() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let hd_path = write_fake_pe_files(temp_dir.path(), "I*2", Some([15.5, 78.2]));
+
+        let location = super::load_pe_trace_locations(
+            &hd_path.with_extension("dt1"),
+            4,
+            3,
+            Some(&"EPSG:32633".to_string()),
+        )
+        .unwrap();
+
+        assert_eq!(location.crs, "EPSG:32633");
+        assert_eq!(location.cor_points.len(), 3);
+        assert_eq!(location.cor_points[0].altitude, 500.);
+        // 78.2°N 15.5°E is a bit east of the zone 33 central meridian
+        assert!((location.cor_points[0].easting - 510000.).abs() < 10000.);
+        assert!((location.cor_points[0].northing - 8680000.).abs() < 20000.);
+        assert!(location.cor_points[1].northing > location.cor_points[0].northing);
+    }
+
     #[test]
     #[cfg(not(target_os = "windows"))] // Added 2026-02-17 because gdal is hard to install in CI
     fn test_load_pe_gp2() {
@@ -1109,7 +3609,7 @@ mod tests {
             Some(&out_dir),  // In case of a target directory
             None,            // In case of a default name beside the GPR file
         ] {
-            export_locations(&locations, alternative, &dummy_gpr_output_path, false).unwrap();
+            export_locations(&locations, None, alternative, &dummy_gpr_output_path, false).unwrap();
 
             let expected_path = match alternative {
                 Some(p) if p == &out_path => &out_path,
@@ -1139,4 +3639,390 @@ mod tests {
             std::fs::remove_file(expected_path).unwrap();
         }
     }
+
+    /// Fake a 16 bit GSSI ".dzt" file with one header block per channel.
+    ///
+    /// Each sample value is `1000 * channel + scan`, so the channels can be told apart.
+    fn fake_dzt_bytes(samples: usize, n_scans: usize, antennas: &[&str]) -> Vec<u8> {
+        let n_channels = antennas.len();
+        let mut bytes = vec![0_u8; 1024 * n_channels];
+
+        bytes[0..2].copy_from_slice(&0x00FF_u16.to_le_bytes());
+        bytes[2..4].copy_from_slice(&1024_u16.to_le_bytes());
+        bytes[4..6].copy_from_slice(&(samples as u16).to_le_bytes());
+        bytes[6..8].copy_from_slice(&16_u16.to_le_bytes());
+        bytes[10..14].copy_from_slice(&64_f32.to_le_bytes());
+        bytes[26..30].copy_from_slice(&100_f32.to_le_bytes());
+        // 2024-05-17 12:30:20
+        let created: u32 = (44 << 25) | (5 << 21) | (17 << 16) | (12 << 11) | (30 << 5) | 10;
+        bytes[32..36].copy_from_slice(&created.to_le_bytes());
+        bytes[52..54].copy_from_slice(&(n_channels as u16).to_le_bytes());
+
+        for (i, antenna) in antennas.iter().enumerate() {
+            let start = 1024 * i + 98;
+            bytes[start..start + antenna.len()].copy_from_slice(antenna.as_bytes());
+        }
+
+        for scan in 0..n_scans {
+            for channel in 0..n_channels {
+                let value = (32768 + 1000 * channel + scan) as u16;
+                for _ in 0..samples {
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_load_dzt() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dzt_path = temp_dir.path().join("FILE____001.DZT");
+
+        std::fs::write(&dzt_path, fake_dzt_bytes(512, 20, &["5103", "400MHz"])).unwrap();
+
+        let gpr_metas = super::load_dzt(&dzt_path, 0.1, None).unwrap();
+        assert_eq!(gpr_metas.len(), 2);
+
+        for (channel, gpr_meta) in gpr_metas.iter().enumerate() {
+            assert_eq!(gpr_meta.channel, channel);
+            assert_eq!(gpr_meta.samples, 512);
+            assert_eq!(gpr_meta.frequency, 1000. * 512. / 100.);
+            assert_eq!(gpr_meta.time_interval, 1. / 64.);
+            assert_eq!(gpr_meta.antenna_mhz, 400.);
+            assert_eq!(gpr_meta.time_window, 100.);
+            assert_eq!(gpr_meta.last_trace, 20);
+            assert_eq!(gpr_meta.data_filepath, dzt_path);
+        }
+        assert_eq!(gpr_metas[0].antenna, "5103");
+
+        // The unsigned offset should be removed and the channels should be de-interleaved
+        let data = super::load_dzt_data(&dzt_path, 1).unwrap();
+        assert_eq!(data.shape(), [512, 20]);
+        assert_eq!(data[[0, 0]], 1000.);
+        assert_eq!(data[[511, 19]], 1019.);
+
+        assert!(super::load_dzt_data(&dzt_path, 2).is_err());
+
+        // Unknown antennas require the frequency override
+        std::fs::write(&dzt_path, fake_dzt_bytes(512, 20, &["unknown"])).unwrap();
+        let err_msg = super::load_dzt(&dzt_path, 0.1, None)
+            .unwrap_err()
+            .to_string();
+        assert!(err_msg.contains("antenna MHz override"), "Got: {err_msg:?}");
+        let gpr_metas = super::load_dzt(&dzt_path, 0.1, Some(270.)).unwrap();
+        assert_eq!(gpr_metas[0].antenna_mhz, 270.);
+
+        // A file that is not a DZT file should fail
+        std::fs::write(&dzt_path, vec![0_u8; 2048]).unwrap();
+        assert!(super::load_dzt(&dzt_path, 0.1, None).is_err());
+
+        // Truncated files and data offsets beyond the end of the file should fail
+        let bytes = fake_dzt_bytes(512, 20, &["5103"]);
+        std::fs::write(&dzt_path, &bytes[..1024 + 500]).unwrap();
+        let err_msg = super::load_dzt_data(&dzt_path, 0).unwrap_err().to_string();
+        assert!(err_msg.contains("No complete scans"), "Got: {err_msg:?}");
+        let mut bytes = bytes;
+        bytes[2..4].copy_from_slice(&900_u16.to_le_bytes());
+        std::fs::write(&dzt_path, &bytes).unwrap();
+        let err_msg = super::load_dzt_data(&dzt_path, 0).unwrap_err().to_string();
+        assert!(err_msg.contains("beyond the end"), "Got: {err_msg:?}");
+    }
+
+    #[test]
+    fn test_load_dzt_distance_locations() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dzt_path = temp_dir.path().join("hello.dzt");
+
+        let mut bytes = fake_dzt_bytes(128, 21, &["3101"]);
+        std::fs::write(&dzt_path, &bytes).unwrap();
+        assert!(super::load_dzt_distance_locations(&dzt_path, None).is_err());
+
+        // 10 scans per meter
+        bytes[14..18].copy_from_slice(&10_f32.to_le_bytes());
+        std::fs::write(&dzt_path, &bytes).unwrap();
+        let locations = super::load_dzt_distance_locations(&dzt_path, None).unwrap();
+        assert_eq!(locations.crs, "local");
+        assert_eq!(locations.cor_points.len(), 2);
+        assert_eq!(locations.cor_points[1].trace_n, 20);
+        assert_eq!(locations.cor_points[1].easting, 2.);
+        assert_eq!(
+            locations.cor_points[1].time_seconds - locations.cor_points[0].time_seconds,
+            20. / 64.
+        );
+    }
+
+    #[test]
+    fn test_dzt_distance_triggered_equidistant() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dzt_path = temp_dir.path().join("hello.dzt");
+
+        // A distance-triggered file: 10 scans per meter and no scans per second
+        let mut bytes = fake_dzt_bytes(128, 21, &["3101"]);
+        bytes[10..14].copy_from_slice(&0_f32.to_le_bytes());
+        bytes[14..18].copy_from_slice(&10_f32.to_le_bytes());
+        std::fs::write(&dzt_path, &bytes).unwrap();
+
+        let gpr_meta = super::load_dzt(&dzt_path, 0.1, None).unwrap().remove(0);
+        assert!(gpr_meta.time_interval.is_nan());
+        assert_eq!(gpr_meta.distance_interval, Some(0.1));
+
+        // GPS positions (as from a ".dzg" file) that put the scans 0.2 m apart
+        let mut location = super::load_dzt_distance_locations(&dzt_path, None).unwrap();
+        location.cor_points[1].easting = 4.;
+        let mut gpr = crate::gpr::GPR::from_meta_and_loc(location, gpr_meta).unwrap();
+
+        // The default step is the distance between scans, so the profile is not collapsed
+        gpr.process(&crate::steps::Step::EquidistantTraces { step: None })
+            .unwrap();
+        assert_eq!(gpr.width(), 41);
+        assert_eq!(
+            gpr.provenance.steps[0].resolved,
+            "equidistant_traces(step=0.1)"
+        );
+    }
+
+    #[test]
+    fn test_load_dzg() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dzt_path = temp_dir.path().join("hello.dzt");
+        let dzg_path = dzt_path.with_extension("dzg");
+
+        std::fs::write(&dzt_path, fake_dzt_bytes(128, 10, &["3101"])).unwrap();
+
+        let dzg_text = [
+            "$GSSIS,0,0.000",
+            "$GPGGA,123021.00,7719.1908439,N,01522.6497456,E,2,12,0.8,625.490,M,31.466,M,,*40",
+            "",
+            "$GSSIS,4,0.100",
+            "$GPGGA,123021.10,,,,,0,0,,,M,,M,,*40", // No fix; should be skipped
+            "",
+            "$GSSIS,9,1.100",
+            "$GNGGA,123022.10,7719.1918439,N,01522.6497456,E,2,12,0.8,626.490,M,31.466,M,,*40",
+        ]
+        .join("\r\n");
+        std::fs::write(&dzg_path, dzg_text).unwrap();
+
+        let locations = super::load_dzg(&dzg_path, Some(&"EPSG:32633".to_string())).unwrap();
+
+        assert_eq!(locations.cor_points.len(), 2);
+        assert_eq!(locations.cor_points[0].trace_n, 0);
+        assert_eq!(locations.cor_points[1].trace_n, 9);
+        assert_eq!(locations.cor_points[1].altitude, 626.49);
+        // The date is read from the DZT header
+        assert_eq!(
+            locations.cor_points[0].time_seconds,
+            chrono::DateTime::parse_from_rfc3339("2024-05-17T12:30:21+00:00")
+                .unwrap()
+                .timestamp() as f64
+        );
+        assert!(locations.cor_points[0].northing > 8_000_000.);
+        assert!(locations.cor_points[1].northing > locations.cor_points[0].northing);
+
+        // The UTC date of RMC strings is used before that of the DZT header
+        let dzg_text = [
+            "$GSSIS,0,0.000",
+            "$GPGGA,235959.00,7719.1908439,N,01522.6497456,E,2,12,0.8,625.490,M,31.466,M,,*40",
+            "$GPRMC,235959.00,A,7719.1908439,N,01522.6497456,E,0.1,0.0,160524,,,D*40",
+            "$GSSIS,9,1.100",
+            "$GPRMC,000000.10,A,7719.1918439,N,01522.6497456,E,0.1,0.0,170524,,,D*40",
+            "$GPGGA,000000.10,7719.1918439,N,01522.6497456,E,2,12,0.8,626.490,M,31.466,M,,*40",
+        ]
+        .join("\n");
+        std::fs::write(&dzg_path, dzg_text).unwrap();
+        let locations = super::load_dzg(&dzg_path, Some(&"EPSG:32633".to_string())).unwrap();
+        assert_eq!(
+            locations.cor_points[0].time_seconds,
+            chrono::DateTime::parse_from_rfc3339("2024-05-16T23:59:59+00:00")
+                .unwrap()
+                .timestamp() as f64
+        );
+        assert_eq!(
+            locations.cor_points[1].time_seconds,
+            chrono::DateTime::parse_from_rfc3339("2024-05-17T00:00:00+00:00")
+                .unwrap()
+                .timestamp() as f64
+        );
+    }
+
+    #[test]
+    fn test_run_dzt_multichannel() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dzt_path = temp_dir.path().join("hello.dzt");
+
+        std::fs::write(&dzt_path, fake_dzt_bytes(128, 10, &["3101", "3101"])).unwrap();
+        std::fs::write(
+            dzt_path.with_extension("dzg"),
+            [
+                "$GSSIS,0,0.000",
+                "$GPGGA,123021.00,7719.1908439,N,01522.6497456,E,2,12,0.8,625.490,M,31.466,M,,*40",
+                "$GSSIS,9,1.100",
+                "$GPGGA,123022.10,7719.1918439,N,01522.6497456,E,2,12,0.8,626.490,M,31.466,M,,*40",
+            ]
+            .join("\n"),
+        )
+        .unwrap();
+
+        crate::gpr::run(crate::gpr::RunParams {
+            filepaths: vec![dzt_path.clone()],
+            crs: Some("EPSG:32633".to_string()),
+            quiet: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        // Each channel should be exported separately
+        for channel in 0..2 {
+            assert!(temp_dir
+                .path()
+                .join(format!("hello_ch{channel}.nc"))
+                .is_file());
+        }
+        assert!(!temp_dir.path().join("hello.nc").is_file());
+    }
+
+    #[test]
+    fn test_ibm_to_f32() {
+        // Examples from the IBM floating point format description
+        assert_eq!(super::ibm_to_f32(0xC276A000), -118.625);
+        assert_eq!(super::ibm_to_f32(0x42640000), 100.);
+        assert_eq!(super::ibm_to_f32(0x41100000), 1.);
+        assert_eq!(super::ibm_to_f32(0x00000000), 0.);
+    }
+
+    #[test]
+    fn test_load_segy_third_party() {
+        // Fake a little-endian SEG-Y file with an ASCII textual header, IBM floats and geographic
+        // coordinates in arcseconds, stored in non-standard header bytes
+        let temp_dir = tempfile::tempdir().unwrap();
+        let segy_path = temp_dir.path().join("legacy.segy");
+
+        let (n_traces, samples) = (5_usize, 8_usize);
+        let mut bytes = format!("{:<3200}", "C 1 GPR LINE 1. ANTENNA: 250 MHZ").into_bytes();
+        let mut binary_header = [0_u8; 400];
+        binary_header[16..18].copy_from_slice(&100_i16.to_le_bytes()); // 0.1 ns in ps
+        binary_header[20..22].copy_from_slice(&(samples as i16).to_le_bytes());
+        binary_header[24..26].copy_from_slice(&1_i16.to_le_bytes()); // IBM float
+        bytes.extend(binary_header);
+
+        for i in 0..n_traces {
+            let mut trace_header = [0_u8; 240];
+            // Store 16 E, 78 N in arcseconds in the CDP X/Y bytes
+            let x_arcsec = (16. * 3600. + i as f64) as i32;
+            trace_header[180..184].copy_from_slice(&x_arcsec.to_le_bytes());
+            trace_header[184..188].copy_from_slice(&(78 * 3600_i32).to_le_bytes());
+            trace_header[40..44].copy_from_slice(&(1000 + i as i32).to_le_bytes());
+            trace_header[68..70].copy_from_slice(&(-10_i16).to_le_bytes());
+            trace_header[70..72].copy_from_slice(&1_i16.to_le_bytes());
+            trace_header[88..90].copy_from_slice(&2_i16.to_le_bytes()); // Arcseconds
+            trace_header[156..158].copy_from_slice(&2020_i16.to_le_bytes());
+            trace_header[158..160].copy_from_slice(&32_i16.to_le_bytes()); // 1 February
+            trace_header[160..162].copy_from_slice(&12_i16.to_le_bytes());
+            trace_header[164..166].copy_from_slice(&(i as i16).to_le_bytes());
+            bytes.extend(trace_header);
+
+            for _ in 0..samples {
+                bytes.extend(0xC276A000_u32.to_le_bytes()); // -118.625
+            }
+        }
+        std::fs::write(&segy_path, &bytes).unwrap();
+
+        let options = super::SegyOptions {
+            coordinate_bytes: [181, 185, 41],
+            sample_interval_unit_ns: Some(0.001),
+            ..Default::default()
+        };
+        let (meta, location) = super::load_segy(
+            &segy_path,
+            0.168,
+            None,
+            Some(&"EPSG:32633".to_string()),
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(meta.samples, samples as u32);
+        assert_eq!(meta.last_trace, n_traces as u32);
+        assert!((meta.time_window - 0.8).abs() < 1e-6);
+        assert_eq!(meta.antenna_mhz, 250.);
+
+        assert_eq!(location.crs, "EPSG:32633");
+        assert_eq!(location.cor_points.len(), n_traces);
+        assert!(location.cor_points[0].northing > 8_000_000.);
+        assert!(location.cor_points[1].easting > location.cor_points[0].easting);
+        assert_eq!(location.cor_points[2].altitude, 100.2);
+        assert_eq!(
+            location.cor_points[3].time_seconds,
+            chrono::DateTime::parse_from_rfc3339("2020-02-01T12:00:03+00:00")
+                .unwrap()
+                .timestamp() as f64
+        );
+
+        let data = super::load_segy_data(&segy_path).unwrap();
+        assert_eq!(data.shape(), [samples, n_traces]);
+        assert!(data.iter().all(|v| *v == -118.625));
+
+        // A scalar override should be used instead of the trace header scalars
+        let options = super::SegyOptions {
+            coordinate_bytes: [181, 185, 41],
+            scalar: Some(-100.),
+            sample_interval_unit_ns: Some(0.001),
+            ..Default::default()
+        };
+        let (_, location) = super::load_segy(
+            &segy_path,
+            0.168,
+            None,
+            Some(&"EPSG:32633".to_string()),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(location.cor_points[2].altitude, 10.02);
+
+        // The interval is in microseconds by default
+        let options = super::SegyOptions {
+            coordinate_bytes: [181, 185, 41],
+            ..Default::default()
+        };
+        let load = |bytes: &[u8]| {
+            std::fs::write(&segy_path, bytes).unwrap();
+            super::load_segy(
+                &segy_path,
+                0.168,
+                None,
+                Some(&"EPSG:32633".to_string()),
+                &options,
+            )
+        };
+        let (meta, _) = load(&bytes).unwrap();
+        assert_eq!(meta.time_window, 800_000.);
+
+        // The extended interval of revision 2 files takes precedence
+        let mut rev2_bytes = bytes.clone();
+        rev2_bytes[3500] = 2;
+        rev2_bytes[3272..3280].copy_from_slice(&0.0005_f64.to_le_bytes());
+        let (meta, _) = load(&rev2_bytes).unwrap();
+        assert!((meta.time_window - 4.).abs() < 1e-6);
+
+        // The scalars and units are read from each trace
+        let trace_start = |i: usize| 3600 + i * (240 + 4 * samples);
+        let mut mixed_bytes = bytes.clone();
+        let start = trace_start(4);
+        mixed_bytes[start + 40..start + 44].copy_from_slice(&(10_i32).to_le_bytes());
+        mixed_bytes[start + 68..start + 70].copy_from_slice(&(1_i16).to_le_bytes());
+        let (_, location) = load(&mixed_bytes).unwrap();
+        assert_eq!(location.cor_points[4].altitude, 10.);
+        assert_eq!(location.cor_points[3].altitude, 100.3);
+
+        let start = trace_start(1);
+        mixed_bytes[start + 180..start + 184].copy_from_slice(&16_i32.to_le_bytes());
+        mixed_bytes[start + 184..start + 188].copy_from_slice(&78_i32.to_le_bytes());
+        mixed_bytes[start + 88..start + 90].copy_from_slice(&3_i16.to_le_bytes()); // Degrees
+        let (_, location) = load(&mixed_bytes).unwrap();
+        assert!((location.cor_points[1].northing - location.cor_points[0].northing).abs() < 1.);
+
+        mixed_bytes[start + 88..start + 90].copy_from_slice(&1_i16.to_le_bytes()); // Length
+        let err_msg = load(&mixed_bytes).unwrap_err().to_string();
+        assert!(err_msg.contains("4 of 5 traces"), "Got: {err_msg:?}");
+    }
 }
@@ -13,12 +13,19 @@
 use pyo3::prelude::*;
 
 mod cli;
+mod cmp;
 mod coords;
 mod dem;
 mod filters;
 mod gpr;
+mod horizons;
+mod hyperbola;
 mod io;
+mod profile;
+mod provenance;
+mod steps;
 mod tools;
+mod velocity;
 
 #[allow(dead_code)] // For maturin
 const PROGRAM_VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -47,7 +54,9 @@ pub mod radal {
     /// filepath
     ///     Filepath of the header file or a glob pattern of many files
     /// velocity
-    ///     Velocity of the medium in m/ns. Defaults to the typical velocity of ice.
+    ///     Velocity of the medium in m/ns. Defaults to the typical velocity of ice (0.168).
+    /// velocity_model
+    ///     Load a layered velocity model from a CSV file for depth conversion, with the columns "depth" (m) or "twt" (ns), and "velocity" (m/ns) or "density" (kg/m³)
     /// info
     ///     Only show metadata for the file
     /// cor
@@ -58,6 +67,12 @@ pub mod radal {
     ///     Which coordinate reference system to project coordinates in.
     /// track
     ///     Export the location track to a comma separated values (CSV) file. Defaults to the output filename location and stem + "_track.csv"
+    /// spectrum
+    ///     Export the mean amplitude spectrum to a comma separated values (CSV) file. Defaults to the output filename location and stem + "_spectrum.csv"
+    /// picks
+    ///     Export the picked horizons (see the "pick_horizon" step) to a comma separated values (CSV) file. Defaults to the output filename location and stem + "_picks.csv"
+    /// velocity_table
+    ///     Export the fitted diffraction hyperbolae (see the "fit_hyperbolae" step) as a velocity model that can be given to "velocity_model". Defaults to the output filename location and stem + "_velocity.csv"
     /// default
     ///     Process with the default profile. See "--show-default" to list the profile.
     /// default_with_topo
@@ -68,8 +83,10 @@ pub mod radal {
     ///     Show the available steps
     /// steps
     ///     Processing steps to run, separated by commas. Can be a filepath to a newline separated step file.
+    /// profile
+    ///     Processing profile file (".toml", ".yaml" or ".yml") with steps and run settings. Settings given as arguments take precedence over the profile.
     /// output
-    ///     Output filename or directory. Defaults to the input filename with a ".nc" extension
+    ///     Output filename or directory. The format is chosen from the extension (".nc", ".sgy" or ".segy"). Defaults to the input filename with a ".nc" extension
     /// quiet
     ///     Suppress progress messages
     /// render
@@ -80,6 +97,14 @@ pub mod radal {
     ///     Merge profiles closer in time than the given threshold when in batch mode (e.g. "10 min")
     /// override_antenna_mhz
     ///     Override the antenna center frequency (in MHz) of the file metadata
+    /// segy_coord_bytes
+    ///     One-based SEG-Y trace header byte positions of the x, y and elevation values, separated by commas. Default: "73,77,41"
+    /// segy_scalar
+    ///     Scalar to apply to SEG-Y coordinates and elevations instead of the trace header scalars. Negative values are divisors, e.g. -100 for centimeters
+    /// segy_interval_unit
+    ///     Unit of the 16 bit SEG-Y sample interval fields: "ps", "ns" or "us". Many GPR writers use "ps". The extended interval of revision 2 files is always used if set. Default: "ps" for files written by radal, otherwise "us"
+    /// segy_revision
+    ///     SEG-Y revision of exported files: 1 or 2. Revision 2 also has the exact sample interval in the extended header fields. Default: 2
     ///
     /// Returns
     /// -------
@@ -88,44 +113,62 @@ pub mod radal {
     #[pyo3(
         signature = (
             filepath=None,
-            velocity=0.168,
+            velocity=None,
+            velocity_model=None,
             info=false,
             cor=None,
             dem=None,
             crs=None,
             track=None,
+            spectrum=None,
+            picks=None,
+            velocity_table=None,
             default=false,
             default_with_topo=false,
             show_default=false,
             show_all_steps=false,
             steps=None,
+            profile=None,
             output=None,
             quiet=false,
             render=None,
             no_export=false,
             merge=None,
             override_antenna_mhz=None,
+            segy_coord_bytes=None,
+            segy_scalar=None,
+            segy_interval_unit=None,
+            segy_revision=None,
         )
     )]
     fn run_cli(
         filepath: Option<String>,
-        velocity: f32,
+        velocity: Option<f32>,
+        velocity_model: Option<PathBuf>,
         info: bool,
         cor: Option<PathBuf>,
         dem: Option<PathBuf>,
         crs: Option<String>,
         track: Option<PathBuf>,
+        spectrum: Option<PathBuf>,
+        picks: Option<PathBuf>,
+        velocity_table: Option<PathBuf>,
         default: bool,
         default_with_topo: bool,
         show_default: bool,
         show_all_steps: bool,
         steps: Option<Vec<String>>,
+        profile: Option<PathBuf>,
         output: Option<PathBuf>,
         quiet: bool,
         render: Option<PathBuf>,
         no_export: bool,
         merge: Option<String>,
         override_antenna_mhz: Option<f32>,
+        segy_coord_bytes: Option<String>,
+        segy_scalar: Option<f64>,
+        segy_interval_unit: Option<String>,
+        segy_revision: Option<u8>,
         _py: Python<'_>,
     ) -> PyResult<i32> {
         let track_opt: Option<Option<PathBuf>> = match track {
@@ -140,35 +183,301 @@ pub mod radal {
         };
         // Construct the same Args struct the CLI uses
         let args = cli::Args {
+            command: None,
             filepath,
             velocity,
+            velocity_model,
             info,
             cor,
             dem,
             crs,
             track: track_opt,
+            spectrum: spectrum.map(Some),
+            picks: picks.map(Some),
+            velocity_table: velocity_table.map(Some),
             default,
             default_with_topo,
             show_default,
             show_all_steps,
             steps: steps.and_then(|s| Some(s.join(","))),
+            profile,
             output,
             quiet,
             render: render_opt,
             no_export,
             merge,
             override_antenna_mhz,
+            segy_coord_bytes,
+            segy_scalar,
+            segy_interval_unit,
+            segy_revision,
         };
 
-        // Use the shared core logic
-        match cli::args_to_action(&args) {
+        run_action(cli::args_to_action(&args))
+    }
+
+    /// Convert a GPR dataset from one format to another without processing it.
+    ///
+    /// The readers and writers are chosen from the file extensions.
+    ///
+    /// Parameters
+    /// ----------
+    /// input
+    ///     Filepath of the input file
+    /// output
+    ///     Filepath of the output file
+    /// velocity
+    ///     Velocity of the medium in m/ns. Defaults to the typical velocity of ice.
+    /// velocity_model
+    ///     Load a layered velocity model from a CSV file for depth conversion
+    /// cor
+    ///     Load a separate ".cor" file. If not given, it will be searched for automatically
+    /// dem
+    ///     Correct elevation values with a DEM
+    /// crs
+    ///     Which coordinate reference system to project coordinates in.
+    /// quiet
+    ///     Suppress progress messages
+    /// override_antenna_mhz
+    ///     Override the antenna center frequency (in MHz) of the file metadata
+    /// segy_coord_bytes
+    ///     One-based SEG-Y trace header byte positions of the x, y and elevation values, separated by commas. Default: "73,77,41"
+    /// segy_scalar
+    ///     Scalar to apply to SEG-Y coordinates and elevations instead of the trace header scalars. Negative values are divisors, e.g. -100 for centimeters
+    /// segy_interval_unit
+    ///     Unit of the 16 bit SEG-Y sample interval fields: "ps", "ns" or "us". Many GPR writers use "ps". The extended interval of revision 2 files is always used if set. Default: "ps" for files written by radal, otherwise "us"
+    /// segy_revision
+    ///     SEG-Y revision of exported files: 1 or 2. Revision 2 also has the exact sample interval in the extended header fields. Default: 2
+    ///
+    /// Returns
+    /// -------
+    /// The exit code of the CLI.
+    #[pyfunction]
+    #[pyo3(
+        signature = (
+            input,
+            output,
+            velocity=0.168,
+            velocity_model=None,
+            cor=None,
+            dem=None,
+            crs=None,
+            quiet=false,
+            override_antenna_mhz=None,
+            segy_coord_bytes=None,
+            segy_scalar=None,
+            segy_interval_unit=None,
+            segy_revision=None,
+        )
+    )]
+    fn translate(
+        input: PathBuf,
+        output: PathBuf,
+        velocity: f32,
+        velocity_model: Option<PathBuf>,
+        cor: Option<PathBuf>,
+        dem: Option<PathBuf>,
+        crs: Option<String>,
+        quiet: bool,
+        override_antenna_mhz: Option<f32>,
+        segy_coord_bytes: Option<String>,
+        segy_scalar: Option<f64>,
+        segy_interval_unit: Option<String>,
+        segy_revision: Option<u8>,
+        _py: Python<'_>,
+    ) -> PyResult<i32> {
+        let args = cli::Args {
+            command: Some(cli::Command::Translate { input, output }),
+            filepath: None,
+            velocity: Some(velocity),
+            velocity_model,
+            info: false,
+            cor,
+            dem,
+            crs,
+            track: None,
+            spectrum: None,
+            picks: None,
+            velocity_table: None,
+            default: false,
+            default_with_topo: false,
+            show_default: false,
+            show_all_steps: false,
+            steps: None,
+            profile: None,
+            output: None,
+            quiet,
+            render: None,
+            no_export: false,
+            merge: None,
+            override_antenna_mhz,
+            segy_coord_bytes,
+            segy_scalar,
+            segy_interval_unit,
+            segy_revision,
+        };
+
+        run_action(cli::args_to_action(&args))
+    }
+
+    /// Re-run the recorded processing of an exported NetCDF file on its raw data.
+    ///
+    /// Parameters
+    /// ----------
+    /// filepath
+    ///     Filepath of the processed NetCDF file
+    /// raw
+    ///     Filepath of the raw data, if it is not where it was when processed
+    /// quiet
+    ///     Suppress progress messages
+    ///
+    /// Returns
+    /// -------
+    /// The maximum absolute difference between the exported and the replayed data. An error is
+    /// raised if they do not match.
+    #[pyfunction]
+    #[pyo3(signature = (filepath, raw=None, quiet=false))]
+    fn replay(filepath: PathBuf, raw: Option<PathBuf>, quiet: bool) -> PyResult<f32> {
+        gpr::replay(&filepath, raw.as_deref(), quiet)
+            .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(format!("{e}")))
+    }
+
+    /// Estimate velocities from a common-midpoint (CMP) or wide-angle (WARR) pulseEKKO gather.
+    ///
+    /// The semblance of normal moveout hyperbolae is computed for a range of velocities, and the
+    /// picked RMS velocities are exported as interval velocities in a velocity model.
+    ///
+    /// Parameters
+    /// ----------
+    /// filepath
+    ///     Filepath of the ".hd" file of the gather
+    /// spacing
+    ///     The increase in antenna separation between traces (m). If not given, the trace positions of the ".dt1" file are used as antenna separations
+    /// first_offset
+    ///     The antenna separation of the first trace (m) when "spacing" is given. Defaults to the antenna separation of the ".hd" file
+    /// min_velocity
+    ///     The slowest velocity of the semblance spectrum (m/ns)
+    /// max_velocity
+    ///     The fastest velocity of the semblance spectrum (m/ns)
+    /// velocity_step
+    ///     The velocity step of the semblance spectrum (m/ns)
+    /// window_ns
+    ///     The time window that the semblance is summed over (ns). Defaults to one period of the antenna center frequency
+    /// min_semblance
+    ///     The minimum semblance (0-1) of an automatic pick
+    /// velocity_picks
+    ///     RMS velocity picks as zero-offset return time (ns) and velocity (m/ns) pairs. If not given, the semblance maxima are picked automatically
+    /// output
+    ///     Filepath or directory of the velocity model CSV. Defaults to the input filename location and stem + "_velocity.csv"
+    /// render
+    ///     Render the semblance spectrum with the picks and save it to the specified path or directory
+    /// override_antenna_mhz
+    ///     Override the antenna center frequency (in MHz) of the file metadata
+    /// quiet
+    ///     Suppress progress messages
+    ///
+    /// Returns
+    /// -------
+    /// The zero-offset return time (ns) and RMS velocity (m/ns) of each pick.
+    #[pyfunction]
+    #[pyo3(
+        signature = (
+            filepath,
+            spacing=None,
+            first_offset=None,
+            min_velocity=crate::cmp::DEFAULT_MIN_VELOCITY,
+            max_velocity=crate::cmp::DEFAULT_MAX_VELOCITY,
+            velocity_step=crate::cmp::DEFAULT_VELOCITY_STEP,
+            window_ns=None,
+            min_semblance=crate::cmp::DEFAULT_MIN_SEMBLANCE,
+            velocity_picks=None,
+            output=None,
+            render=None,
+            override_antenna_mhz=None,
+            quiet=false,
+        )
+    )]
+    fn cmp(
+        filepath: PathBuf,
+        spacing: Option<f32>,
+        first_offset: Option<f32>,
+        min_velocity: f32,
+        max_velocity: f32,
+        velocity_step: f32,
+        window_ns: Option<f32>,
+        min_semblance: f32,
+        velocity_picks: Option<Vec<(f32, f32)>>,
+        output: Option<PathBuf>,
+        render: Option<PathBuf>,
+        override_antenna_mhz: Option<f32>,
+        quiet: bool,
+    ) -> PyResult<Vec<(f32, f32)>> {
+        crate::cmp::run(crate::cmp::CmpParams {
+            filepath,
+            spacing,
+            first_offset,
+            min_velocity,
+            max_velocity,
+            velocity_step,
+            window_ns,
+            min_semblance,
+            picks: velocity_picks,
+            output_path: output,
+            render_path: render.map(Some),
+            override_antenna_mhz,
+            quiet,
+        })
+        .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(format!("{e}")))
+    }
+
+    /// List the available processing steps.
+    ///
+    /// Returns
+    /// -------
+    /// Pairs of the step signature (e.g. "average_traces(window=5)") and its description.
+    #[pyfunction]
+    fn available_steps() -> Vec<(String, String)> {
+        gpr::all_available_steps()
+            .into_iter()
+            .map(|[signature, description]| (signature, description))
+            .collect()
+    }
+
+    /// List the steps of the default processing profile.
+    ///
+    /// Returns
+    /// -------
+    /// The steps with all arguments written out, which can be given to `run_cli` as `steps`.
+    #[pyfunction]
+    fn default_processing_profile() -> Vec<String> {
+        gpr::default_processing_profile()
+            .iter()
+            .map(|step| step.to_string())
+            .collect()
+    }
+
+    /// Run a parsed CLI action and convert the outcome to a Python result.
+    fn run_action(action: cli::CliAction) -> PyResult<i32> {
+        match action {
             cli::CliAction::Run(params) => {
                 // run the core processing
-                match gpr::run(params) {
+                match gpr::run(*params) {
                     Ok(_) => Ok(0),
                     Err(e) => Err(pyo3::exceptions::PyRuntimeError::new_err(format!("{e:?}"))),
                 }
             }
+            cli::CliAction::Cmp(params) => match crate::cmp::run(*params) {
+                Ok(_) => Ok(0),
+                Err(e) => Err(pyo3::exceptions::PyRuntimeError::new_err(format!("{e}"))),
+            },
+            cli::CliAction::Replay {
+                filepath,
+                raw,
+                quiet,
+            } => match gpr::replay(&filepath, raw.as_deref(), quiet) {
+                Ok(_) => Ok(0),
+                Err(e) => Err(pyo3::exceptions::PyRuntimeError::new_err(format!("{e}"))),
+            },
             cli::CliAction::Done => Ok(0),
             cli::CliAction::Error(msg) => Err(pyo3::exceptions::PyValueError::new_err(msg)),
         }
@@ -12,12 +12,19 @@
 use clap::Parser;
 
 mod cli;
+mod cmp;
 mod coords;
 mod dem;
 mod filters;
 mod gpr;
+mod horizons;
+mod hyperbola;
 mod io;
+mod profile;
+mod provenance;
+mod steps;
 mod tools;
+mod velocity;
 
 const PROGRAM_VERSION: &str = env!("CARGO_PKG_VERSION");
 const PROGRAM_NAME: &str = env!("CARGO_PKG_NAME");
@@ -0,0 +1,489 @@
+/// Processing profiles: steps and run settings stored in a TOML or YAML file
+///
+/// A profile may extend another profile (or the built-in "default" profile), for example:
+/// ```toml
+/// extends = "default"
+/// description = "Glacier profiles, 2023 campaign"
+/// velocity = 0.168
+/// crs = "WGS84 UTM Zone 33N"
+/// render = true
+///
+/// append_steps = [
+///     "correct_topography",
+/// ]
+/// ```
+/// Steps may be written as in "--steps" (e.g. "dewow(window_ns=10)"), or as tables with named
+/// parameters, e.g. `{ name = "dewow", window_ns = 10 }` or (in YAML) `- dewow: {window_ns: 10}`.
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::steps::{self, Step};
+
+/// The names of the built-in profiles that can be extended
+pub const BUILTIN_PROFILES: [&str; 2] = ["default", "default_with_topo"];
+
+/// A parameter value of a step written as a table
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum ParamValue {
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+    Text(String),
+    List(Vec<ParamValue>),
+    Table(BTreeMap<String, ParamValue>),
+}
+
+impl ParamValue {
+    /// Format the value as it would be written in a step, splitting lists into their parts
+    ///
+    /// Text is quoted if needed, so that e.g. a path with spaces stays one value.
+    fn to_parts(&self) -> Vec<String> {
+        match self {
+            ParamValue::Integer(v) => vec![v.to_string()],
+            // Debug formatting keeps the decimal point, so that e.g. 1.0 is not read as an integer
+            ParamValue::Float(v) => vec![format!("{v:?}")],
+            ParamValue::Bool(v) => vec![v.to_string()],
+            ParamValue::Text(s) => vec![steps::quote_value(s)],
+            ParamValue::List(values) => values.iter().flat_map(|v| v.to_parts()).collect(),
+            ParamValue::Table(_) => vec!["{...}".to_string()],
+        }
+    }
+}
+
+/// A step in a profile file, either as text or as a table of parameters
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum StepEntry {
+    Text(String),
+    Table(BTreeMap<String, ParamValue>),
+}
+
+impl StepEntry {
+    /// Convert the entry to the text representation of the step
+    ///
+    /// # Errors
+    /// The entry is a table without a recognizable step name.
+    fn to_text(&self) -> Result<String, String> {
+        let mut table = match self {
+            StepEntry::Text(s) => return Ok(s.clone()),
+            StepEntry::Table(table) => table.clone(),
+        };
+
+        let named = |name: &str, params: &BTreeMap<String, ParamValue>| {
+            let args = params
+                .iter()
+                .flat_map(|(key, value)| {
+                    value
+                        .to_parts()
+                        .into_iter()
+                        .map(move |part| format!("{key}={part}"))
+                })
+                .collect::<Vec<String>>();
+            match args.is_empty() {
+                true => name.to_string(),
+                false => format!("{name}({})", args.join(" ")),
+            }
+        };
+
+        // A table with a "name" key: { name = "dewow", window_ns = 10 }
+        if let Some(name) = table.remove("name") {
+            return match name {
+                ParamValue::Text(name) => Ok(named(&name, &table)),
+                other => Err(format!("Expected a step name, got {:?}", other)),
+            };
+        }
+        // A table with the step name as the only key: { dewow = { window_ns = 10 } } or { gain = 0.002 }
+        if table.len() == 1 {
+            let (name, value) = table.into_iter().next().unwrap();
+            return Ok(match value {
+                ParamValue::Table(params) => named(&name, &params),
+                value => format!("{name}({})", value.to_parts().join(" ")),
+            });
+        }
+        Err(format!(
+            "Could not find the step name among the keys {:?}. Add a \"name\" key",
+            table.keys().collect::<Vec<&String>>()
+        ))
+    }
+}
+
+/// How a rendered image should be saved
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum RenderSetting {
+    /// Render (or not) next to the output file
+    Enabled(bool),
+    /// Render to the given filepath or directory
+    Path(PathBuf),
+}
+
+/// The contents of a profile file, before it is combined with the profile that it extends
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileFile {
+    extends: Option<String>,
+    description: Option<String>,
+    steps: Option<Vec<StepEntry>>,
+    #[serde(default)]
+    append_steps: Vec<StepEntry>,
+    velocity: Option<f32>,
+    velocity_model: Option<PathBuf>,
+    crs: Option<String>,
+    dem: Option<PathBuf>,
+    cor: Option<PathBuf>,
+    output: Option<PathBuf>,
+    render: Option<RenderSetting>,
+    merge: Option<String>,
+    override_antenna_mhz: Option<f32>,
+}
+
+/// A processing profile with all of its steps and run settings
+///
+/// Settings that are not given in the profile (or in any profile it extends) are None.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+    pub description: Option<String>,
+    pub steps: Vec<Step>,
+    /// The velocity of the medium in m/ns
+    pub velocity: Option<f32>,
+    /// The filepath of a layered velocity model (CSV)
+    pub velocity_model: Option<PathBuf>,
+    pub crs: Option<String>,
+    pub dem: Option<PathBuf>,
+    pub cor: Option<PathBuf>,
+    /// The output filename or directory
+    pub output: Option<PathBuf>,
+    /// Whether to render an image, and optionally where
+    pub render: Option<Option<PathBuf>>,
+    /// The merge threshold, e.g. "10 min"
+    pub merge: Option<String>,
+    pub override_antenna_mhz: Option<f32>,
+}
+
+impl Profile {
+    /// Get a built-in profile by name
+    ///
+    /// # Arguments
+    /// - `name`: The name of the profile ("default" or "default_with_topo")
+    ///
+    /// # Returns
+    /// The profile, or None if no built-in profile has the name
+    pub fn builtin(name: &str) -> Option<Profile> {
+        let mut steps = Step::default_profile();
+        match name {
+            "default" => (),
+            "default_with_topo" => steps.push(Step::CorrectTopography),
+            _ => return None,
+        }
+        Some(Profile {
+            steps,
+            ..Default::default()
+        })
+    }
+
+    /// Load a profile from a TOML or YAML file
+    ///
+    /// Relative paths in the profile (including those of extended profiles) are relative to the
+    /// directory of the profile file.
+    ///
+    /// # Arguments
+    /// - `filepath`: The filepath to the profile. The format is chosen from the extension (".toml",
+    ///   ".yaml" or ".yml")
+    ///
+    /// # Errors
+    /// - The file cannot be read or parsed
+    /// - A step cannot be parsed
+    /// - An extended profile does not exist or extends itself
+    pub fn load(filepath: &Path) -> Result<Profile, String> {
+        Self::load_inner(filepath, &mut Vec::new())
+    }
+
+    fn load_inner(filepath: &Path, visited: &mut Vec<PathBuf>) -> Result<Profile, String> {
+        let canonical = filepath
+            .canonicalize()
+            .map_err(|e| format!("Could not read profile {filepath:?}: {e}"))?;
+        if visited.contains(&canonical) {
+            return Err(format!("Profile {filepath:?} extends itself"));
+        }
+        visited.push(canonical);
+
+        let content = std::fs::read_to_string(filepath)
+            .map_err(|e| format!("Could not read profile {filepath:?}: {e}"))?;
+        let file: ProfileFile = match filepath
+            .extension()
+            .and_then(|s| s.to_str())
+            .map(|s| s.to_lowercase())
+            .as_deref()
+        {
+            Some("toml") => toml::from_str(&content).map_err(|e| e.to_string()),
+            Some("yaml") | Some("yml") => serde_yaml::from_str(&content).map_err(|e| e.to_string()),
+            _ => {
+                return Err(format!(
+                    "Unknown profile format: {filepath:?}. Use a \".toml\", \".yaml\" or \".yml\" extension"
+                ))
+            }
+        }
+        .map_err(|e| format!("Error parsing profile {filepath:?}: {e}"))?;
+
+        let directory = filepath.parent().unwrap_or(Path::new("")).to_path_buf();
+        let relative = |path: Option<PathBuf>| path.map(|p| directory.join(p));
+
+        let mut profile = match &file.extends {
+            None => Profile::default(),
+            Some(name) => match Profile::builtin(name) {
+                Some(p) => p,
+                None => Self::load_inner(&directory.join(name), visited).map_err(|e| {
+                    format!(
+                        "{e}\n(extended by {filepath:?}. Built-in profiles are: {})",
+                        BUILTIN_PROFILES.join(", ")
+                    )
+                })?,
+            },
+        };
+
+        let parse_steps = |entries: &[StepEntry], key: &str| {
+            entries
+                .iter()
+                .enumerate()
+                .map(|(i, entry)| {
+                    entry
+                        .to_text()
+                        .and_then(|text| text.parse::<Step>().map_err(|e| e.to_string()))
+                        .map_err(|e| format!("Error in {key}[{i}] of profile {filepath:?}: {e}"))
+                })
+                .collect::<Result<Vec<Step>, String>>()
+        };
+
+        if let Some(steps) = &file.steps {
+            profile.steps = parse_steps(steps, "steps")?;
+        }
+        profile
+            .steps
+            .append(&mut parse_steps(&file.append_steps, "append_steps")?);
+
+        if file.description.is_some() {
+            profile.description = file.description;
+        }
+        if file.velocity.is_some() {
+            profile.velocity = file.velocity;
+        }
+        if file.velocity_model.is_some() {
+            profile.velocity_model = relative(file.velocity_model);
+        }
+        if file.crs.is_some() {
+            profile.crs = file.crs;
+        }
+        if file.dem.is_some() {
+            profile.dem = relative(file.dem);
+        }
+        if file.cor.is_some() {
+            profile.cor = relative(file.cor);
+        }
+        if file.output.is_some() {
+            profile.output = relative(file.output);
+        }
+        match file.render {
+            Some(RenderSetting::Enabled(true)) => profile.render = Some(None),
+            Some(RenderSetting::Enabled(false)) => profile.render = None,
+            Some(RenderSetting::Path(path)) => profile.render = Some(relative(Some(path))),
+            None => (),
+        }
+        if file.merge.is_some() {
+            profile.merge = file.merge;
+        }
+        if file.override_antenna_mhz.is_some() {
+            profile.override_antenna_mhz = file.override_antenna_mhz;
+        }
+
+        Ok(profile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Profile;
+    use crate::filters::Statistic;
+    use crate::steps::Step;
+
+    #[test]
+    fn test_load_profile() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let base_path = temp_dir.path().join("base.toml");
+        std::fs::write(
+            &base_path,
+            r#"
+extends = "default"
+description = "Base"
+velocity = 0.1
+velocity_model = "firn.csv"
+dem = "dem.tif"
+render = true
+append_steps = [
+    "correct_topography",
+]
+            "#,
+        )
+        .unwrap();
+
+        let base = Profile::load(&base_path).unwrap();
+        let mut expected_steps = Step::default_profile();
+        expected_steps.push(Step::CorrectTopography);
+        assert_eq!(base.steps, expected_steps);
+        assert_eq!(base.velocity, Some(0.1));
+        assert_eq!(base.dem, Some(temp_dir.path().join("dem.tif")));
+        assert_eq!(base.velocity_model, Some(temp_dir.path().join("firn.csv")));
+        assert_eq!(base.render, Some(None));
+        assert_eq!(
+            base,
+            Profile::builtin("default_with_topo")
+                .map(|p| Profile {
+                    description: Some("Base".into()),
+                    velocity: Some(0.1),
+                    velocity_model: Some(temp_dir.path().join("firn.csv")),
+                    dem: Some(temp_dir.path().join("dem.tif")),
+                    render: Some(None),
+                    ..p
+                })
+                .unwrap()
+        );
+
+        let child_path = temp_dir.path().join("child.yaml");
+        std::fs::write(
+            &child_path,
+            r#"
+extends: base.toml
+crs: EPSG:32633
+merge: 10 min
+steps:
+  - zero_corr_max_peak
+  - dewow: {window_ns: 10}
+  - name: normalize_horizontal_magnitudes
+    skip_first: 1.0e-1
+  - remove_traces: [0, 5-7]
+  - gain(factor=0.002)
+            "#,
+        )
+        .unwrap();
+
+        let child = Profile::load(&child_path).unwrap();
+        assert_eq!(
+            child.steps,
+            vec![
+                Step::ZeroCorrMaxPeak,
+                Step::Dewow {
+                    window_ns: Some(10.),
+                    periods: 1.,
+                    statistic: Statistic::Mean
+                },
+                "normalize_horizontal_magnitudes(0.1)".parse().unwrap(),
+                Step::RemoveTraces {
+                    traces: vec![0, 5, 6, 7]
+                },
+                Step::Gain { factor: 0.002 },
+            ]
+        );
+        // Settings of the base profile should be inherited
+        assert_eq!(child.velocity, Some(0.1));
+        assert_eq!(child.crs.as_deref(), Some("EPSG:32633"));
+        assert_eq!(child.merge.as_deref(), Some("10 min"));
+        assert_eq!(child.description.as_deref(), Some("Base"));
+    }
+
+    #[test]
+    fn test_profile_quoted_values() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("bed.toml");
+        std::fs::write(
+            &path,
+            r#"steps = [{ name = "detect_bed", prior = "my thickness.tif", min_depth = 5 }]"#,
+        )
+        .unwrap();
+
+        // A path with a space should be one value
+        let steps = Profile::load(&path).unwrap().steps;
+        assert_eq!(
+            steps,
+            vec![r#"detect_bed(min_depth=5, prior="my thickness.tif")"#
+                .parse()
+                .unwrap()]
+        );
+        match &steps[0] {
+            Step::DetectBed { prior, .. } => {
+                assert_eq!(
+                    prior.as_deref(),
+                    Some(std::path::Path::new("my thickness.tif"))
+                )
+            }
+            other => panic!("Expected detect_bed, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_profile_cli_precedence() {
+        use clap::Parser;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let profile_path = temp_dir.path().join("profile.toml");
+        std::fs::write(
+            &profile_path,
+            "extends = \"default\"\nvelocity = 0.1\ncrs = \"EPSG:32633\"\noutput = \"out/\"",
+        )
+        .unwrap();
+
+        let args = crate::cli::Args::parse_from([
+            "radal",
+            "-f",
+            "data.rd3",
+            "--profile",
+            profile_path.to_str().unwrap(),
+            "-v",
+            "0.2",
+        ]);
+        let params = match crate::cli::args_to_action(&args) {
+            crate::cli::CliAction::Run(params) => *params,
+            _ => panic!("Expected a run action"),
+        };
+        assert_eq!(params.medium_velocity, 0.2);
+        assert_eq!(params.crs.as_deref(), Some("EPSG:32633"));
+        assert_eq!(params.output_path, Some(temp_dir.path().join("out/")));
+        assert_eq!(params.steps, Step::default_profile());
+    }
+
+    #[test]
+    fn test_load_profile_errors() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, content: &str| {
+            let path = temp_dir.path().join(name);
+            std::fs::write(&path, content).unwrap();
+            path
+        };
+
+        let path = write(
+            "bad_step.toml",
+            "steps = [\"dewow\", { name = \"gain\", facter = 1 }]",
+        );
+        let error = Profile::load(&path).unwrap_err();
+        assert!(error.contains("steps[1]"), "{error}");
+        assert!(error.contains("Unknown argument 'facter'"), "{error}");
+
+        let path = write("unknown_key.toml", "velocty = 0.1");
+        assert!(Profile::load(&path).unwrap_err().contains("velocty"));
+
+        let path = write("self.yml", "extends: self.yml");
+        assert!(Profile::load(&path).unwrap_err().contains("extends itself"));
+
+        let path = write("missing.toml", "extends = \"nonexistent.toml\"");
+        assert!(Profile::load(&path)
+            .unwrap_err()
+            .contains("Built-in profiles"));
+
+        let path = write("profile.json", "{}");
+        assert!(Profile::load(&path)
+            .unwrap_err()
+            .contains("Unknown profile format"));
+    }
+}
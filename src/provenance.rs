@@ -0,0 +1,130 @@
+/// Machine-readable records of how a profile was loaded and processed
+///
+/// The provenance is stored as JSON in the "processing-provenance" attribute of exported NetCDF
+/// files, next to the human-readable "processing-log". It holds enough information to load the
+/// original data again and re-run the exact chain of steps (see `radal replay`).
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::io::SegyOptions;
+use crate::velocity::VelocityModel;
+
+/// How the raw data of a profile were loaded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProvenanceSource {
+    /// The input filepaths. There are more than one if profiles were merged
+    pub filepaths: Vec<PathBuf>,
+    /// The channel of multichannel data
+    pub channel: usize,
+    pub cor_path: Option<PathBuf>,
+    pub dem_path: Option<PathBuf>,
+    /// The CRS as it was given by the user
+    pub crs: Option<String>,
+    /// The velocity of the medium in m/ns
+    pub medium_velocity: f32,
+    /// The layered velocity model, if one was given. Older provenance records have none.
+    #[serde(default)]
+    pub velocity_model: Option<VelocityModel>,
+    pub override_antenna_mhz: Option<f32>,
+    pub segy_coordinate_bytes: [usize; 3],
+    pub segy_scalar: Option<f64>,
+    /// The unit of the 16 bit SEG-Y sample interval fields, if it was not detected automatically
+    pub segy_sample_interval_unit_ns: Option<f32>,
+    /// The radal version that loaded the data
+    pub radal_version: String,
+}
+
+impl ProvenanceSource {
+    pub fn segy_options(&self) -> SegyOptions {
+        SegyOptions {
+            coordinate_bytes: self.segy_coordinate_bytes,
+            scalar: self.segy_scalar,
+            sample_interval_unit_ns: self.segy_sample_interval_unit_ns,
+            ..Default::default()
+        }
+    }
+}
+
+/// A record of one processing step
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProvenanceStep {
+    /// The step as it was requested, e.g. "equidistant_traces"
+    pub step: String,
+    /// The step with automatically determined arguments filled in, e.g. "equidistant_traces(step=0.52)"
+    pub resolved: String,
+    /// Values that were derived from the data while running the step, e.g. the gain factor of auto_gain
+    pub derived: BTreeMap<String, f64>,
+    /// The shape of the data before the step (samples, traces)
+    pub input_shape: [usize; 2],
+    /// The shape of the data after the step (samples, traces)
+    pub output_shape: [usize; 2],
+    pub duration_seconds: f32,
+    /// The radal version that ran the step
+    pub radal_version: String,
+}
+
+/// The source and all processing steps of a profile
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// How the raw data were loaded. This is unknown for files from older radal versions
+    pub source: Option<ProvenanceSource>,
+    pub steps: Vec<ProvenanceStep>,
+}
+
+impl Provenance {
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+
+    pub fn from_json(json: &str) -> Result<Provenance, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+}
+
+/// The version of radal, to record in the provenance
+pub fn radal_version() -> String {
+    env!("CARGO_PKG_VERSION").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Provenance, ProvenanceSource, ProvenanceStep};
+
+    #[test]
+    fn test_provenance_json() {
+        let provenance = Provenance {
+            source: Some(ProvenanceSource {
+                filepaths: vec!["/data/DAT_001_A1.rad".into()],
+                channel: 0,
+                cor_path: None,
+                dem_path: Some("/data/dem.tif".into()),
+                crs: Some("EPSG:32633".into()),
+                medium_velocity: 0.168,
+                velocity_model: Some(
+                    crate::velocity::VelocityModel::from_depth_layers(&[(0., 0.2), (15., 0.168)])
+                        .unwrap(),
+                ),
+                override_antenna_mhz: None,
+                segy_coordinate_bytes: [73, 77, 41],
+                segy_scalar: Some(-100.),
+                segy_sample_interval_unit_ns: Some(0.001),
+                radal_version: super::radal_version(),
+            }),
+            steps: vec![ProvenanceStep {
+                step: "auto_gain(n_bins=100)".into(),
+                resolved: "auto_gain(n_bins=100)".into(),
+                derived: [("gain_factor".to_string(), 0.0125)].into(),
+                input_shape: [512, 1000],
+                output_shape: [512, 1000],
+                duration_seconds: 0.1,
+                radal_version: super::radal_version(),
+            }],
+        };
+
+        let json = provenance.to_json().unwrap();
+        assert!(json.contains("\"gain_factor\":0.0125"));
+        assert_eq!(Provenance::from_json(&json).unwrap(), provenance);
+    }
+}
@@ -0,0 +1,2099 @@
+/// Processing steps and the parser of their text representation
+///
+/// A step is written as its name, optionally followed by arguments in parentheses. Arguments are
+/// separated by whitespace or commas, and can be given by position or by name. For example,
+/// `gain(0.002)` and `gain(factor=0.002)` are the same step, and `subset(0 -1 0 500)` is the same
+/// as `subset(max_sample=500)`.
+use std::fmt;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::filters::{fft::FkMode, AgcMethod, Statistic};
+use crate::horizons::Phase;
+
+pub const DEFAULT_EMPTY_TRACE_STRENGTH: f32 = 1.0;
+pub const DEFAULT_DEWOW_PERIODS: f32 = 1.0;
+pub const DEFAULT_NORMALIZE_HORIZONTAL_MAGNITUDES_CUTOFF: f32 = 0.3;
+pub const DEFAULT_AUTOGAIN_N_BINS: usize = 100;
+pub const DEFAULT_BANDPASS_LOW_CUTOFF: f32 = 0.1;
+pub const DEFAULT_BANDPASS_HIGH_CUTOFF: f32 = 0.9;
+pub const DEFAULT_BANDPASS_Q: f32 = 0.707;
+pub const DEFAULT_SIGLOG_MINVAL_LOG10: f32 = -1.;
+pub const DEFAULT_FK_TAPER: f32 = 0.2;
+pub const DEFAULT_PICK_MAX_GAP: usize = 10;
+pub const DEFAULT_PICK_THRESHOLD: f32 = 0.3;
+pub const DEFAULT_BED_MIN_DEPTH: f32 = 10.;
+pub const DEFAULT_BED_PRIOR_TOLERANCE: f32 = 50.;
+pub const DEFAULT_HYPERBOLA_MAX_COUNT: usize = 20;
+
+/// The type of value that a step argument takes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArgKind {
+    /// A non-negative integer
+    Unsigned,
+    /// An integer that may be negative
+    Integer,
+    /// A floating point number
+    Float,
+    /// An integer number of samples, or a floating point fraction of the trace
+    SamplesOrFraction,
+    /// Any number of trace indices or ranges of trace indices (e.g. "5-10")
+    TraceRanges,
+    /// Any number of layer top depths (m) and velocities (m/ns), e.g. "15:0.168"
+    VelocityLayers,
+    /// Any number of seed trace indices and return times (ns), e.g. "120:250"
+    Seeds,
+    /// A name of letters, digits and underscores
+    Name,
+    /// A file path
+    Path,
+    /// One of the given words
+    Choice(&'static [&'static str]),
+}
+
+impl ArgKind {
+    fn description(&self) -> String {
+        match self {
+            ArgKind::Unsigned => "a non-negative integer".to_string(),
+            ArgKind::Integer => "an integer".to_string(),
+            ArgKind::Float => "a number".to_string(),
+            ArgKind::SamplesOrFraction => "an integer or a fraction between 0 and 1".to_string(),
+            ArgKind::TraceRanges => "a trace index or range (e.g. 5-10)".to_string(),
+            ArgKind::VelocityLayers => {
+                "a layer top depth (m) and velocity (m/ns), e.g. 15:0.168".to_string()
+            }
+            ArgKind::Seeds => "a trace index and return time (ns), e.g. 120:250".to_string(),
+            ArgKind::Name => "a name of letters, digits and underscores".to_string(),
+            ArgKind::Path => {
+                "a file path (in quotes if it has spaces, commas, parentheses or '=')".to_string()
+            }
+            ArgKind::Choice(choices) => format!("one of: {}", choices.join(", ")),
+        }
+    }
+
+    /// Whether the argument takes any number of values
+    fn is_list(&self) -> bool {
+        matches!(
+            self,
+            ArgKind::TraceRanges | ArgKind::VelocityLayers | ArgKind::Seeds
+        )
+    }
+
+    /// Whether a positional value looks like an item of a list argument
+    ///
+    /// Any other value ends the list, so that the following arguments can still be given by
+    /// position, e.g. "fit_hyperbolae(120:250 900:180 5)".
+    fn is_list_item(&self, value: &str) -> bool {
+        match self {
+            ArgKind::TraceRanges => {
+                value.starts_with(|c: char| c.is_ascii_digit())
+                    && value.chars().all(|c| c.is_ascii_digit() || c == '-')
+            }
+            ArgKind::VelocityLayers | ArgKind::Seeds => value.contains(':'),
+            _ => false,
+        }
+    }
+}
+
+/// The value to use for a step argument that was not given
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArgDefault {
+    /// The argument has to be given
+    Required,
+    /// The value is determined from the data
+    Auto,
+    /// A fixed default value
+    Value(f64),
+    /// A fixed default word of a choice
+    Text(&'static str),
+    /// The argument is not used unless it is given
+    None,
+}
+
+/// The specification of one argument of a step
+#[derive(Debug, Clone, Copy)]
+pub struct StepArg {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub default: ArgDefault,
+}
+
+/// The specification of a step: its name, arguments and description
+#[derive(Debug, Clone, Copy)]
+pub struct StepSpec {
+    pub name: &'static str,
+    pub args: &'static [StepArg],
+    pub description: &'static str,
+}
+
+impl StepSpec {
+    /// Find the specification of a step by its name
+    pub fn from_name(name: &str) -> Option<&'static StepSpec> {
+        STEPS.iter().find(|spec| spec.name == name)
+    }
+
+    /// Show the name and arguments of the step, e.g. "average_traces(window=5)"
+    ///
+    /// Required arguments are shown by their name, optional arguments with their default value.
+    pub fn signature(&self) -> String {
+        if self.args.is_empty() {
+            return self.name.to_string();
+        }
+        let args = self
+            .args
+            .iter()
+            .map(|arg| match (arg.kind, arg.default) {
+                (kind, _) if kind.is_list() => format!("{}...", arg.name),
+                (_, ArgDefault::Required) => arg.name.to_string(),
+                (_, ArgDefault::Auto) => format!("{}=auto", arg.name),
+                (_, ArgDefault::Value(v)) => format!("{}={}", arg.name, v),
+                (_, ArgDefault::Text(t)) => format!("{}={}", arg.name, t),
+                (_, ArgDefault::None) => format!("{}=none", arg.name),
+            })
+            .collect::<Vec<String>>();
+        format!("{}({})", self.name, args.join(" "))
+    }
+}
+
+const fn arg(name: &'static str, kind: ArgKind, default: ArgDefault) -> StepArg {
+    StepArg {
+        name,
+        kind,
+        default,
+    }
+}
+
+/// All available processing steps
+pub const STEPS: &[StepSpec] = &[
+    StepSpec {
+        name: "subset",
+        args: &[
+            arg("min_trace", ArgKind::Unsigned, ArgDefault::Value(0.)),
+            arg("max_trace", ArgKind::Integer, ArgDefault::Value(-1.)),
+            arg("min_sample", ArgKind::Unsigned, ArgDefault::Value(0.)),
+            arg("max_sample", ArgKind::Integer, ArgDefault::Value(-1.)),
+        ],
+        description: "Subset the data in x (traces) and/or y (samples). A maximum of -1 means the end of the data. Examples: Clip to the first 500 samples: subset(0 -1 0 500) or subset(max_sample=500). Clip to the first 300 traces, subset(0 300)",
+    },
+    StepSpec {
+        name: "remove_traces",
+        args: &[arg("traces", ArgKind::TraceRanges, ArgDefault::Required)],
+        description: "Manually remove trace indices, for example in case they are visually deemed bad. Ranges are inclusive. Example: Remove the first two traces: remove_traces(0 1) or remove_traces(0-1)",
+    },
+    StepSpec {
+        name: "remove_empty_traces",
+        args: &[arg(
+            "strength",
+            ArgKind::Float,
+            ArgDefault::Value(DEFAULT_EMPTY_TRACE_STRENGTH as f64),
+        )],
+        description: "Remove all traces that appear empty. Recommended to be run as the first filter if required!. The strength threshold (mean absolute trace value) can be tweaked. Example: 'remove_empty_traces(2)'. Default: 1.",
+    },
+    StepSpec {
+        name: "average_traces",
+        args: &[arg("window", ArgKind::Unsigned, ArgDefault::Required)],
+        description: "Average traces in a given window. The coordinate information is picked from the middle averaged trace. Example: 'average_traces(3)'.",
+    },
+    StepSpec {
+        name: "zero_corr_max_peak",
+        args: &[],
+        description: "Shift the location of the zero return time by finding the maximum row value. The peak is found for each trace individually.",
+    },
+    StepSpec {
+        name: "zero_corr",
+        args: &[arg("threshold_multiplier", ArgKind::Float, ArgDefault::Value(1.))],
+        description: "Shift the location of the zero return time by finding the first row where data appear. The correction can be tweaked to allow more or less data, e.g. 'zero_corr(0.9)'. Default: 1.0",
+    },
+    StepSpec {
+        name: "bandpass",
+        args: &[
+            arg(
+                "low_cutoff",
+                ArgKind::Float,
+                ArgDefault::Value(DEFAULT_BANDPASS_LOW_CUTOFF as f64),
+            ),
+            arg(
+                "high_cutoff",
+                ArgKind::Float,
+                ArgDefault::Value(DEFAULT_BANDPASS_HIGH_CUTOFF as f64),
+            ),
+            arg("q", ArgKind::Float, ArgDefault::Value(DEFAULT_BANDPASS_Q as f64)),
+            arg(
+                "zero_phase",
+                ArgKind::Choice(&["true", "false"]),
+                ArgDefault::Text("false"),
+            ),
+        ],
+        description: "Apply a bandpass Butterworth filter to each trace individually. The given frequencies are normalized (0: 0Hz, 1: Nyquist). An optional strength (q) can be provided as a third argument (default 0.707). The filter delays the signal, unless zero_phase=true, where it is run forward and backward (with twice the attenuation in dB outside of the band). Default: bandpass(0.1 0.9)",
+    },
+    StepSpec {
+        name: "bandpass_mhz",
+        args: &[
+            arg("low_cutoff", ArgKind::Float, ArgDefault::Required),
+            arg("high_cutoff", ArgKind::Float, ArgDefault::Required),
+            arg("q", ArgKind::Float, ArgDefault::Value(DEFAULT_BANDPASS_Q as f64)),
+            arg(
+                "zero_phase",
+                ArgKind::Choice(&["true", "false"]),
+                ArgDefault::Text("false"),
+            ),
+        ],
+        description: "Apply a bandpass Butterworth filter to each trace individually. An optional strength (q) can be provided as a third argument (default 0.707). The given frequencies are assumed to be in MHz. With zero_phase=true, the filter does not shift reflections in time. Example: bandpass_mhz(100 600 zero_phase=true)",
+    },
+    StepSpec {
+        name: "fft_bandpass",
+        args: &[
+            arg("low_cut", ArgKind::Float, ArgDefault::Required),
+            arg("low_pass", ArgKind::Float, ArgDefault::Required),
+            arg("high_pass", ArgKind::Float, ArgDefault::Required),
+            arg("high_cut", ArgKind::Float, ArgDefault::Required),
+        ],
+        description: "Apply a bandpass filter in the frequency domain (FFT) to each trace. The frequencies (MHz) are the four corners of the filter: Nothing below low_cut is kept, the gain rises along a cosine taper to low_pass, everything between low_pass and high_pass is kept, and the gain falls to high_cut. The filter has zero phase. Example: fft_bandpass(50 100 600 800)",
+    },
+    StepSpec {
+        name: "notch",
+        args: &[
+            arg("frequency", ArgKind::Float, ArgDefault::Required),
+            arg("width", ArgKind::Float, ArgDefault::Auto),
+            arg("harmonics", ArgKind::Unsigned, ArgDefault::Value(1.)),
+        ],
+        description: "Remove a narrow band of frequencies (MHz) with a cosine-shaped notch in the frequency domain (FFT). This removes interference lines from e.g. radio transmitters or harmonics of a known source. The width (MHz) defaults to four times the spectral resolution of the traces. With harmonics larger than 1, multiples of the frequency are also removed. Run the step several times for several lines. Examples: notch(156.8), notch(frequency=50 width=5 harmonics=3)",
+    },
+    StepSpec {
+        name: "equidistant_traces",
+        args: &[arg("step", ArgKind::Float, ArgDefault::Auto)],
+        description: "Make all traces equidistant by averaging them in a fixed horizontal grid. The step size is determined from the median moving velocity. Other step sizes in m can be given, e.g. 'equidistant_traces(2.)' for 2 m. Default: auto",
+    },
+    StepSpec {
+        name: "fk_velocity_filter",
+        args: &[
+            arg("min_velocity", ArgKind::Float, ArgDefault::Required),
+            arg("max_velocity", ArgKind::Float, ArgDefault::Required),
+            arg(
+                "mode",
+                ArgKind::Choice(&["reject", "pass"]),
+                ArgDefault::Text("reject"),
+            ),
+            arg(
+                "taper",
+                ArgKind::Float,
+                ArgDefault::Value(DEFAULT_FK_TAPER as f64),
+            ),
+        ],
+        description: "Reject (or pass) events by their apparent velocity (m/ns) with a velocity fan in the frequency-wavenumber (f-k) domain. The apparent velocity of an event is 2 / its slope in two-way time, so the limbs of a diffraction hyperbola have the velocity of the medium, and reflections of the air wave from objects beside the profile have ~0.3 m/ns. Events dipping in both directions are filtered. The edges of the fan are tapered by a fraction (taper) of their slope. Requires uniformly spaced traces, e.g. from equidistant_traces with no traces removed afterwards. Examples: fk_velocity_filter(0.25 0.35), fk_velocity_filter(min_velocity=0.1 max_velocity=inf mode=pass)",
+    },
+    StepSpec {
+        name: "fk_dip_filter",
+        args: &[
+            arg("min_dip", ArgKind::Float, ArgDefault::Required),
+            arg("max_dip", ArgKind::Float, ArgDefault::Value(90.)),
+            arg(
+                "mode",
+                ArgKind::Choice(&["reject", "pass"]),
+                ArgDefault::Text("reject"),
+            ),
+            arg(
+                "taper",
+                ArgKind::Float,
+                ArgDefault::Value(DEFAULT_FK_TAPER as f64),
+            ),
+        ],
+        description: "Reject (or pass) events by their dip (degrees from horizontal) in the medium, with a frequency-wavenumber (f-k) filter. The dips are converted to slopes in two-way time with the medium velocity. Events dipping in both directions are filtered. The edges of the dip range are tapered by a fraction (taper) of their slope. Requires uniformly spaced traces, e.g. from equidistant_traces with no traces removed afterwards. Examples: fk_dip_filter(40), fk_dip_filter(min_dip=0 max_dip=30 mode=pass)",
+    },
+    StepSpec {
+        name: "normalize_horizontal_magnitudes",
+        args: &[arg(
+            "skip_first",
+            ArgKind::SamplesOrFraction,
+            ArgDefault::Value(DEFAULT_NORMALIZE_HORIZONTAL_MAGNITUDES_CUTOFF as f64),
+        )],
+        description: "Normalize the magnitudes of the traces in the horizontal axis. This removes or reduces horizontal banding. The uppermost samples of the trace can be excluded, either by sample number (integer; e.g. 'normalize_horizontal_magnitudes(300)') or by a fraction of the trace (float; e.g. 'normalize_horizontal_magnitudes(0.3)'). Default: 0.3",
+    },
+    StepSpec {
+        name: "dewow",
+        args: &[
+            arg("window_ns", ArgKind::Float, ArgDefault::Auto),
+            arg(
+                "periods",
+                ArgKind::Float,
+                ArgDefault::Value(DEFAULT_DEWOW_PERIODS as f64),
+            ),
+            arg(
+                "statistic",
+                ArgKind::Choice(&["mean", "median"]),
+                ArgDefault::Text("mean"),
+            ),
+        ],
+        description: "Subtract a running mean or median that is centered on each sample of each trace. This removes the low-frequency 'wow' that the direct wave induces in the receiver. The window is given in ns, or by default as a number of periods of the antenna center frequency. The median is less affected by strong reflections. Examples: dewow, dewow(window_ns=20), dewow(periods=2 statistic=median)",
+    },
+    StepSpec {
+        name: "background_removal",
+        args: &[
+            arg(
+                "statistic",
+                ArgKind::Choice(&["mean", "median"]),
+                ArgDefault::Text("mean"),
+            ),
+            arg("window", ArgKind::Unsigned, ArgDefault::Value(0.)),
+            arg("min_sample", ArgKind::Unsigned, ArgDefault::Value(0.)),
+            arg("max_sample", ArgKind::Integer, ArgDefault::Value(-1.)),
+        ],
+        description: "Subtract the mean or median trace (the background) from every trace. This removes horizontal ringing and antenna coupling. The background is found from all traces (window=0), or from a sliding window of traces centered on each trace. The removal can be limited to a sample range, where a max_sample of -1 means the end. The median is less affected by strong reflectors. Must be run before correct_topography. Examples: background_removal, background_removal(median window=200 max_sample=300)",
+    },
+    StepSpec {
+        name: "auto_gain",
+        args: &[arg(
+            "n_bins",
+            ArgKind::Unsigned,
+            ArgDefault::Value(DEFAULT_AUTOGAIN_N_BINS as f64),
+        )],
+        description: "Automatically determine the best gain factor and apply it. The data are binned vertically and the mean absolute deviation of the values is used as a proxy for signal attenuation. The median attenuation in decibel volts is given to the gain filter. The amounts of bins can be given, e.g. 'auto_gain(100). Default: 100",
+    },
+    StepSpec {
+        name: "gain",
+        args: &[arg("factor", ArgKind::Float, ArgDefault::Required)],
+        description: "Multiply the magnitude as a function of depth. This is most often used to correct for signal attenuation with time/distance. Gain is applied as: '10 ^(gain * twt / 20)' (dB / ns) where gain is the given gain factor and twt is the two-way travel time of the signal. Examples: gain(0.002). No default value.",
+    },
+    StepSpec {
+        name: "sec_gain",
+        args: &[arg("attenuation", ArgKind::Float, ArgDefault::Required)],
+        description: "Spreading and exponential compensation (SEC) gain. The samples are multiplied by the two-way travel time (spreading) and by '10 ^(attenuation * v * twt / 20)', where the attenuation is in dB / m along the travel path and v is the medium velocity. Unlike auto_gain, the gain is physically motivated and the same for every profile. Examples: sec_gain(0.05). No default value.",
+    },
+    StepSpec {
+        name: "power_gain",
+        args: &[arg("exponent", ArgKind::Float, ArgDefault::Value(1.))],
+        description: "Multiply the samples by the two-way travel time (in ns) to the power of the exponent: 't ^exponent'. An exponent of 1 compensates spherical spreading, and larger exponents also compensate some attenuation. Examples: power_gain, power_gain(1.5). Default: 1",
+    },
+    StepSpec {
+        name: "spherical_divergence",
+        args: &[arg("exponent", ArgKind::Float, ArgDefault::Value(1.))],
+        description: "Compensate the geometric spreading of the signal by multiplying the samples by their depth (in m, corrected for the antenna separation) to the power of the exponent. An exponent of 1 is for spherical spreading from a point source and 0.5 for cylindrical spreading. Examples: spherical_divergence, spherical_divergence(0.5). Default: 1",
+    },
+    StepSpec {
+        name: "agc",
+        args: &[
+            arg("window_ns", ArgKind::Float, ArgDefault::Required),
+            arg(
+                "method",
+                ArgKind::Choice(&["rms", "mean_abs"]),
+                ArgDefault::Text("rms"),
+            ),
+            arg(
+                "save",
+                ArgKind::Choice(&["true", "false"]),
+                ArgDefault::Text("false"),
+            ),
+        ],
+        description: "Automatic gain control (AGC). Each sample is divided by the RMS or mean absolute amplitude in a window (in ns) centered on it. This evens out the amplitudes for interpretation, but the relative amplitudes are lost. With save=true, the gain is kept (also in the exported file) so that it can be undone with undo_agc. Must be run before correct_topography. Examples: agc(50), agc(window_ns=30 method=mean_abs save=true)",
+    },
+    StepSpec {
+        name: "undo_agc",
+        args: &[],
+        description: "Undo an AGC that was run with save=true, by dividing by its gain. It has to be run before correct_topography. The gain is discarded by steps that move traces or samples after the AGC, such as equidistant_traces or migrations (except subset).",
+    },
+    StepSpec {
+        name: "kirchhoff_migration2d",
+        args: &[],
+        description: "Migrate sample magnitudes in the horizontal and vertical distance dimension to correct hyperbolae in the data. The correction is needed because the GPR does not observe only what is directly below it, but rather in a cone that is determined by the dominant antenna frequency. Thus, without migration, each trace is the sum of a cone beneath it. Topographic Kirchhoff migration (in 2D) corrects for this in two dimensions.",
+    },
+    StepSpec {
+        name: "stolt_migration",
+        args: &[arg("velocity", ArgKind::Float, ArgDefault::Auto)],
+        description: "Migrate the data with Stolt (f-k) migration, which collapses diffraction hyperbolae much faster than kirchhoff_migration2d, but with a constant velocity (m/ns; default: the medium velocity) and a flat surface. The migrated data stay in two-way time. Requires uniformly spaced traces, e.g. from equidistant_traces, and must be run before correct_topography. Examples: stolt_migration, stolt_migration(0.17)",
+    },
+    StepSpec {
+        name: "gazdag_migration",
+        args: &[arg("layers", ArgKind::VelocityLayers, ArgDefault::Auto)],
+        description: "Migrate the data with Gazdag phase-shift migration, with a velocity that varies with depth. Each layer is given as its top depth (m) and velocity (m/ns), separated by a colon, and lasts until the next layer. The first layer has to start at the surface (0 m). Without layers, the velocity model (see \"--velocity-model\") or the medium velocity is used. The migrated data stay in two-way time. Requires uniformly spaced traces, e.g. from equidistant_traces, and must be run before correct_topography. Example for 15 m of firn over ice: gazdag_migration(0:0.2 15:0.168)",
+    },
+    StepSpec {
+        name: "abslog",
+        args: &[],
+        description: "Run a log10 operation on the absolute values (log10(abs(data))), converting it to a logarithmic scale. This is useful for visualisation. Before conversion, the data are added with the 1st percentile (absolute) value in the dataset to avoid log10(0) == inf.",
+    },
+    StepSpec {
+        name: "siglog",
+        args: &[arg(
+            "minval_log10",
+            ArgKind::Float,
+            ArgDefault::Value(DEFAULT_SIGLOG_MINVAL_LOG10 as f64),
+        )],
+        description: "Run a log10 operation on absolute values and then account for the sign. Values smaller than the set minimum magnitude are truncated to zero. E.g. with an exponent offset of 0: 1000 -> 3, -1000 -> -3, 0.001 -> 0. The argument specifies the exponent offset to apply to allow for values smaller than +-1 (e.g. 10e-1). Default: -1",
+    },
+    StepSpec {
+        name: "unphase",
+        args: &[],
+        description: "Combine the positive and negative phases of the signal into one positive magntiude. The assumption is made that the positive magnitude of the signal comes first, followed by an offset negative component. The distance between the positive and negative peaks are found, and then the negative part is shifted accordingly. See envelope for a magnitude that does not depend on the wavelet.",
+    },
+    StepSpec {
+        name: "envelope",
+        args: &[],
+        description: "Replace the data with their envelope (instantaneous amplitude), the magnitude of the analytic signal from a Hilbert transform. Unlike unphase, this makes no assumption on the shape of the wavelet, so it is well suited for a magnitude display (e.g. followed by abslog).",
+    },
+    StepSpec {
+        name: "instantaneous_phase",
+        args: &[],
+        description: "Replace the data with their instantaneous phase (radians, -π to π) from a Hilbert transform. The phase does not depend on the amplitude, so it shows the continuity of weak reflectors.",
+    },
+    StepSpec {
+        name: "instantaneous_frequency",
+        args: &[],
+        description: "Replace the data with their instantaneous frequency (MHz), the rate of change of the instantaneous phase. A drop in frequency with depth shows attenuation, but the frequency is noisy where the envelope is weak.",
+    },
+    StepSpec {
+        name: "correct_topography",
+        args: &[],
+        description: "Make a copy of the data and topographically correct it. In the output, the data will be called \"topo_data\". Note that the copying means any step run after this will not be reflected in \"topo_data\". This is thus recommended to run last.",
+    },
+    StepSpec {
+        name: "correct_antenna_separation",
+        args: &[],
+        description: "Correct for the separation between the antenna transmitter and receiver. The consequence is that depths are slightly over-exaggerated at low return-times before correction. This step averages samples so that each sample represents a consistent depth interval.",
+    },
+    StepSpec {
+        name: "pick_horizon",
+        args: &[
+            arg("horizon", ArgKind::Name, ArgDefault::Required),
+            arg("seeds", ArgKind::Seeds, ArgDefault::Required),
+            arg(
+                "phase",
+                ArgKind::Choice(&["max", "min", "zero_crossing"]),
+                ArgDefault::Text("max"),
+            ),
+            arg("window_ns", ArgKind::Float, ArgDefault::Auto),
+            arg(
+                "max_gap",
+                ArgKind::Unsigned,
+                ArgDefault::Value(DEFAULT_PICK_MAX_GAP as f64),
+            ),
+            arg(
+                "threshold",
+                ArgKind::Float,
+                ArgDefault::Value(DEFAULT_PICK_THRESHOLD as f64),
+            ),
+        ],
+        description: "Pick a named horizon (e.g. the bed or an internal layer) by tracking a reflection from seed picks, given as a trace index and return time (ns) separated by a colon. From each seed, the peak (max), trough (min) or zero crossing of the reflection is followed along the profile within a window (ns; default: one period of the antenna center frequency) centered on the previous pick. Traces where the phase is missing or weaker than the threshold (relative to the seed) are left as gaps, and the tracking stops after max_gap consecutive gaps. Picking again with the same name replaces the horizon. The picks are discarded if the number of traces or samples changes afterwards (except with subset and the zero corrections, which move them). Examples: pick_horizon(bed 120:250), pick_horizon(layer1 10:80 900:95 phase=zero_crossing window_ns=4)",
+    },
+    StepSpec {
+        name: "detect_bed",
+        args: &[
+            arg(
+                "min_depth",
+                ArgKind::Float,
+                ArgDefault::Value(DEFAULT_BED_MIN_DEPTH as f64),
+            ),
+            arg("max_jump_ns", ArgKind::Float, ArgDefault::Auto),
+            arg("prior", ArgKind::Path, ArgDefault::None),
+            arg(
+                "prior_tolerance",
+                ArgKind::Float,
+                ArgDefault::Value(DEFAULT_BED_PRIOR_TOLERANCE as f64),
+            ),
+        ],
+        description: "Detect the bed automatically as the strongest continuous reflector below a minimum depth (m), and save it as the horizon 'bed'. The reflector is followed in the energy of the trace envelopes, and may move at most max_jump_ns (default: one period of the antenna center frequency) between neighbouring traces. With a prior raster of the expected ice thickness (m), only depths within prior_tolerance (m) of it are searched. The ice thickness, bed elevation and a quality (0-1) of each pick are exported to the NetCDF output and the track file. Examples: detect_bed, detect_bed(min_depth=30 prior=thickness.tif prior_tolerance=100)",
+    },
+    StepSpec {
+        name: "fit_hyperbolae",
+        args: &[
+            arg("apexes", ArgKind::Seeds, ArgDefault::None),
+            arg("aperture_m", ArgKind::Float, ArgDefault::Auto),
+            arg(
+                "max_count",
+                ArgKind::Unsigned,
+                ArgDefault::Value(DEFAULT_HYPERBOLA_MAX_COUNT as f64),
+            ),
+        ],
+        description: "Estimate the velocity of the medium by fitting diffraction hyperbolae in the envelope of the data. Each apex is given as a trace index and return time (ns) separated by a colon, and its return time is moved to the strongest envelope nearby. Without apexes, up to max_count point-like diffractions are found automatically. The hyperbolae are fitted within aperture_m (m; default: the depth of the apex at the medium velocity) of the apex, using the trace distances and antenna separation, and the velocity and its uncertainty of each one are logged. The data are not changed. The diffractions can be written as a velocity model with --velocity-table. Examples: fit_hyperbolae, fit_hyperbolae(120:250 900:180 aperture_m=5)",
+    },
+];
+
+/// How many of the uppermost samples to exclude when normalizing horizontal magnitudes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SkipFirst {
+    /// A number of samples
+    Samples(isize),
+    /// A fraction (0-1) of the trace
+    Fraction(f32),
+}
+
+/// A processing step with all of its arguments
+///
+/// See [`STEPS`] for descriptions of each step.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    Subset {
+        min_trace: u32,
+        max_trace: Option<u32>,
+        min_sample: u32,
+        max_sample: Option<u32>,
+    },
+    RemoveTraces {
+        traces: Vec<usize>,
+    },
+    RemoveEmptyTraces {
+        strength: f32,
+    },
+    AverageTraces {
+        window: usize,
+    },
+    ZeroCorrMaxPeak,
+    ZeroCorr {
+        threshold_multiplier: f32,
+    },
+    Bandpass {
+        low_cutoff: f32,
+        high_cutoff: f32,
+        q: f32,
+        zero_phase: bool,
+    },
+    BandpassMhz {
+        low_cutoff: f32,
+        high_cutoff: f32,
+        q: f32,
+        zero_phase: bool,
+    },
+    FftBandpass {
+        low_cut: f32,
+        low_pass: f32,
+        high_pass: f32,
+        high_cut: f32,
+    },
+    Notch {
+        frequency: f32,
+        width: Option<f32>,
+        harmonics: usize,
+    },
+    EquidistantTraces {
+        step: Option<f32>,
+    },
+    FkVelocityFilter {
+        min_velocity: f32,
+        max_velocity: f32,
+        mode: FkMode,
+        taper: f32,
+    },
+    FkDipFilter {
+        min_dip: f32,
+        max_dip: f32,
+        mode: FkMode,
+        taper: f32,
+    },
+    NormalizeHorizontalMagnitudes {
+        skip_first: SkipFirst,
+    },
+    Dewow {
+        window_ns: Option<f32>,
+        periods: f32,
+        statistic: Statistic,
+    },
+    BackgroundRemoval {
+        statistic: Statistic,
+        window: usize,
+        min_sample: u32,
+        max_sample: Option<u32>,
+    },
+    AutoGain {
+        n_bins: usize,
+    },
+    Gain {
+        factor: f32,
+    },
+    SecGain {
+        attenuation: f32,
+    },
+    PowerGain {
+        exponent: f32,
+    },
+    SphericalDivergence {
+        exponent: f32,
+    },
+    Agc {
+        window_ns: f32,
+        method: AgcMethod,
+        save: bool,
+    },
+    UndoAgc,
+    KirchhoffMigration2d,
+    StoltMigration {
+        velocity: Option<f32>,
+    },
+    GazdagMigration {
+        layers: Vec<(f32, f32)>,
+    },
+    Abslog,
+    Siglog {
+        minval_log10: f32,
+    },
+    Unphase,
+    Envelope,
+    InstantaneousPhase,
+    InstantaneousFrequency,
+    CorrectTopography,
+    CorrectAntennaSeparation,
+    PickHorizon {
+        horizon: String,
+        seeds: Vec<(usize, f32)>,
+        phase: Phase,
+        window_ns: Option<f32>,
+        max_gap: usize,
+        threshold: f32,
+    },
+    DetectBed {
+        min_depth: f32,
+        max_jump_ns: Option<f32>,
+        prior: Option<PathBuf>,
+        prior_tolerance: f32,
+    },
+    FitHyperbolae {
+        apexes: Vec<(usize, f32)>,
+        aperture_m: Option<f32>,
+        max_count: usize,
+    },
+}
+
+impl Step {
+    /// The name of the step, as written in step lists
+    pub fn name(&self) -> &'static str {
+        match self {
+            Step::Subset { .. } => "subset",
+            Step::RemoveTraces { .. } => "remove_traces",
+            Step::RemoveEmptyTraces { .. } => "remove_empty_traces",
+            Step::AverageTraces { .. } => "average_traces",
+            Step::ZeroCorrMaxPeak => "zero_corr_max_peak",
+            Step::ZeroCorr { .. } => "zero_corr",
+            Step::Bandpass { .. } => "bandpass",
+            Step::BandpassMhz { .. } => "bandpass_mhz",
+            Step::FftBandpass { .. } => "fft_bandpass",
+            Step::Notch { .. } => "notch",
+            Step::EquidistantTraces { .. } => "equidistant_traces",
+            Step::FkVelocityFilter { .. } => "fk_velocity_filter",
+            Step::FkDipFilter { .. } => "fk_dip_filter",
+            Step::NormalizeHorizontalMagnitudes { .. } => "normalize_horizontal_magnitudes",
+            Step::Dewow { .. } => "dewow",
+            Step::BackgroundRemoval { .. } => "background_removal",
+            Step::AutoGain { .. } => "auto_gain",
+            Step::Gain { .. } => "gain",
+            Step::SecGain { .. } => "sec_gain",
+            Step::PowerGain { .. } => "power_gain",
+            Step::SphericalDivergence { .. } => "spherical_divergence",
+            Step::Agc { .. } => "agc",
+            Step::UndoAgc => "undo_agc",
+            Step::KirchhoffMigration2d => "kirchhoff_migration2d",
+            Step::StoltMigration { .. } => "stolt_migration",
+            Step::GazdagMigration { .. } => "gazdag_migration",
+            Step::Abslog => "abslog",
+            Step::Siglog { .. } => "siglog",
+            Step::Unphase => "unphase",
+            Step::Envelope => "envelope",
+            Step::InstantaneousPhase => "instantaneous_phase",
+            Step::InstantaneousFrequency => "instantaneous_frequency",
+            Step::CorrectTopography => "correct_topography",
+            Step::CorrectAntennaSeparation => "correct_antenna_separation",
+            Step::PickHorizon { .. } => "pick_horizon",
+            Step::DetectBed { .. } => "detect_bed",
+            Step::FitHyperbolae { .. } => "fit_hyperbolae",
+        }
+    }
+
+    /// The named arguments of the step, formatted as they would be written
+    fn arguments(&self) -> Vec<(&'static str, String)> {
+        let opt = |v: Option<u32>| v.map(|v| v as i64).unwrap_or(-1).to_string();
+        match self {
+            Step::Subset {
+                min_trace,
+                max_trace,
+                min_sample,
+                max_sample,
+            } => vec![
+                ("min_trace", min_trace.to_string()),
+                ("max_trace", opt(*max_trace)),
+                ("min_sample", min_sample.to_string()),
+                ("max_sample", opt(*max_sample)),
+            ],
+            Step::RemoveTraces { traces } => vec![("traces", format_trace_ranges(traces))],
+            Step::RemoveEmptyTraces { strength } => vec![("strength", strength.to_string())],
+            Step::AverageTraces { window } => vec![("window", window.to_string())],
+            Step::ZeroCorr {
+                threshold_multiplier,
+            } => vec![("threshold_multiplier", threshold_multiplier.to_string())],
+            Step::Bandpass {
+                low_cutoff,
+                high_cutoff,
+                q,
+                zero_phase,
+            }
+            | Step::BandpassMhz {
+                low_cutoff,
+                high_cutoff,
+                q,
+                zero_phase,
+            } => vec![
+                ("low_cutoff", low_cutoff.to_string()),
+                ("high_cutoff", high_cutoff.to_string()),
+                ("q", q.to_string()),
+                ("zero_phase", zero_phase.to_string()),
+            ],
+            Step::FftBandpass {
+                low_cut,
+                low_pass,
+                high_pass,
+                high_cut,
+            } => vec![
+                ("low_cut", low_cut.to_string()),
+                ("low_pass", low_pass.to_string()),
+                ("high_pass", high_pass.to_string()),
+                ("high_cut", high_cut.to_string()),
+            ],
+            Step::Notch {
+                frequency,
+                width,
+                harmonics,
+            } => {
+                let mut arguments = vec![("frequency", frequency.to_string())];
+                if let Some(w) = width {
+                    arguments.push(("width", w.to_string()));
+                }
+                arguments.push(("harmonics", harmonics.to_string()));
+                arguments
+            }
+            Step::FkVelocityFilter {
+                min_velocity,
+                max_velocity,
+                mode,
+                taper,
+            } => vec![
+                ("min_velocity", min_velocity.to_string()),
+                ("max_velocity", max_velocity.to_string()),
+                ("mode", mode.to_string()),
+                ("taper", taper.to_string()),
+            ],
+            Step::FkDipFilter {
+                min_dip,
+                max_dip,
+                mode,
+                taper,
+            } => vec![
+                ("min_dip", min_dip.to_string()),
+                ("max_dip", max_dip.to_string()),
+                ("mode", mode.to_string()),
+                ("taper", taper.to_string()),
+            ],
+            Step::EquidistantTraces { step } => match step {
+                Some(s) => vec![("step", s.to_string())],
+                None => vec![],
+            },
+            Step::NormalizeHorizontalMagnitudes { skip_first } => vec![(
+                "skip_first",
+                match skip_first {
+                    SkipFirst::Samples(n) => n.to_string(),
+                    // Make sure that a whole fraction is not read back as samples
+                    SkipFirst::Fraction(f) => format!("{f:?}"),
+                },
+            )],
+            Step::Dewow {
+                window_ns,
+                periods,
+                statistic,
+            } => {
+                let mut arguments = match window_ns {
+                    Some(w) => vec![("window_ns", w.to_string())],
+                    None => vec![],
+                };
+                arguments.push(("periods", periods.to_string()));
+                arguments.push(("statistic", statistic.to_string()));
+                arguments
+            }
+            Step::BackgroundRemoval {
+                statistic,
+                window,
+                min_sample,
+                max_sample,
+            } => vec![
+                ("statistic", statistic.to_string()),
+                ("window", window.to_string()),
+                ("min_sample", min_sample.to_string()),
+                ("max_sample", opt(*max_sample)),
+            ],
+            Step::AutoGain { n_bins } => vec![("n_bins", n_bins.to_string())],
+            Step::Gain { factor } => vec![("factor", factor.to_string())],
+            Step::SecGain { attenuation } => vec![("attenuation", attenuation.to_string())],
+            Step::PowerGain { exponent } | Step::SphericalDivergence { exponent } => {
+                vec![("exponent", exponent.to_string())]
+            }
+            Step::Agc {
+                window_ns,
+                method,
+                save,
+            } => vec![
+                ("window_ns", window_ns.to_string()),
+                ("method", method.to_string()),
+                ("save", save.to_string()),
+            ],
+            Step::Siglog { minval_log10 } => vec![("minval_log10", minval_log10.to_string())],
+            Step::StoltMigration { velocity } => match velocity {
+                Some(v) => vec![("velocity", v.to_string())],
+                None => Vec::new(),
+            },
+            Step::GazdagMigration { layers } => match layers.is_empty() {
+                true => Vec::new(),
+                false => vec![(
+                    "layers",
+                    layers
+                        .iter()
+                        .map(|(depth, velocity)| format!("{depth}:{velocity}"))
+                        .collect::<Vec<String>>()
+                        .join(" "),
+                )],
+            },
+            Step::PickHorizon {
+                horizon,
+                seeds,
+                phase,
+                window_ns,
+                max_gap,
+                threshold,
+            } => {
+                let mut arguments = vec![
+                    ("horizon", horizon.clone()),
+                    (
+                        "seeds",
+                        seeds
+                            .iter()
+                            .map(|(trace, time)| format!("{trace}:{time}"))
+                            .collect::<Vec<String>>()
+                            .join(" "),
+                    ),
+                    ("phase", phase.to_string()),
+                ];
+                if let Some(w) = window_ns {
+                    arguments.push(("window_ns", w.to_string()));
+                }
+                arguments.push(("max_gap", max_gap.to_string()));
+                arguments.push(("threshold", threshold.to_string()));
+                arguments
+            }
+            Step::DetectBed {
+                min_depth,
+                max_jump_ns,
+                prior,
+                prior_tolerance,
+            } => {
+                let mut arguments = vec![("min_depth", min_depth.to_string())];
+                if let Some(m) = max_jump_ns {
+                    arguments.push(("max_jump_ns", m.to_string()));
+                }
+                if let Some(p) = prior {
+                    arguments.push(("prior", quote_value(&p.display().to_string())));
+                }
+                arguments.push(("prior_tolerance", prior_tolerance.to_string()));
+                arguments
+            }
+            Step::FitHyperbolae {
+                apexes,
+                aperture_m,
+                max_count,
+            } => {
+                let mut arguments = Vec::new();
+                if !apexes.is_empty() {
+                    arguments.push((
+                        "apexes",
+                        apexes
+                            .iter()
+                            .map(|(trace, time)| format!("{trace}:{time}"))
+                            .collect::<Vec<String>>()
+                            .join(" "),
+                    ));
+                }
+                if let Some(a) = aperture_m {
+                    arguments.push(("aperture_m", a.to_string()));
+                }
+                arguments.push(("max_count", max_count.to_string()));
+                arguments
+            }
+            Step::ZeroCorrMaxPeak
+            | Step::UndoAgc
+            | Step::KirchhoffMigration2d
+            | Step::Abslog
+            | Step::Unphase
+            | Step::Envelope
+            | Step::InstantaneousPhase
+            | Step::InstantaneousFrequency
+            | Step::CorrectTopography
+            | Step::CorrectAntennaSeparation => vec![],
+        }
+    }
+
+    /// The steps of the default processing profile
+    pub fn default_profile() -> Vec<Step> {
+        vec![
+            Step::RemoveEmptyTraces {
+                strength: DEFAULT_EMPTY_TRACE_STRENGTH,
+            },
+            Step::ZeroCorrMaxPeak,
+            Step::EquidistantTraces { step: None },
+            Step::CorrectAntennaSeparation,
+            Step::NormalizeHorizontalMagnitudes {
+                skip_first: SkipFirst::Fraction(DEFAULT_NORMALIZE_HORIZONTAL_MAGNITUDES_CUTOFF),
+            },
+            Step::Dewow {
+                window_ns: None,
+                periods: DEFAULT_DEWOW_PERIODS,
+                statistic: Statistic::Mean,
+            },
+            Step::AutoGain {
+                n_bins: DEFAULT_AUTOGAIN_N_BINS,
+            },
+        ]
+    }
+}
+
+/// Write the step with named arguments, e.g. "average_traces(window=5)". The output can be parsed again.
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arguments = self.arguments();
+        if arguments.is_empty() {
+            return write!(f, "{}", self.name());
+        }
+        let arguments = arguments
+            .iter()
+            .map(|(name, value)| match name {
+                // Lists of trace ranges, layers, seeds and apexes are written positionally, as is
+                // the horizon name before the seeds
+                &"traces" | &"layers" | &"seeds" | &"apexes" | &"horizon" => value.clone(),
+                _ => format!("{name}={value}"),
+            })
+            .collect::<Vec<String>>();
+        write!(f, "{}({})", self.name(), arguments.join(" "))
+    }
+}
+
+/// Write trace indices with consecutive indices as ranges, e.g. "0-3 7"
+fn format_trace_ranges(traces: &[usize]) -> String {
+    let mut sorted = traces.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+
+    let mut ranges = Vec::<String>::new();
+    let mut i = 0;
+    while i < sorted.len() {
+        let start = sorted[i];
+        while i + 1 < sorted.len() && sorted[i + 1] == sorted[i] + 1 {
+            i += 1;
+        }
+        ranges.push(match sorted[i] == start {
+            true => start.to_string(),
+            false => format!("{start}-{}", sorted[i]),
+        });
+        i += 1;
+    }
+    ranges.join(" ")
+}
+
+/// An error from parsing a step, with the location of the problem in the input
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepParseError {
+    /// The step text that was parsed
+    pub input: String,
+    /// The byte range of the problem in the input
+    pub span: Range<usize>,
+    /// A description of the problem
+    pub message: String,
+}
+
+/// Show the message and underline the problem, e.g.
+/// ```text
+/// Unknown argument 'facter' for step 'gain'. Usage: gain(factor)
+///     gain(facter=0.002)
+///          ^^^^^^
+/// ```
+impl fmt::Display for StepParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let start = self.input[..self.span.start].chars().count();
+        let length = self.input[self.span.clone()].chars().count().max(1);
+        write!(
+            f,
+            "{}\n    {}\n    {}{}",
+            self.message,
+            self.input,
+            " ".repeat(start),
+            "^".repeat(length)
+        )
+    }
+}
+
+impl std::error::Error for StepParseError {}
+
+/// One argument token: an optional name and the value, with their locations in the input
+struct ArgToken<'a> {
+    name: Option<(&'a str, Range<usize>)>,
+    value: &'a str,
+    value_span: Range<usize>,
+    span: Range<usize>,
+}
+
+/// Find the first occurrence of a character that is not within a quoted value
+///
+/// As in [`tokenize`], a quote only starts a quoted value at the start of a token.
+pub fn find_unquoted(text: &str, target: char) -> Option<usize> {
+    let mut quote: Option<char> = None;
+    let mut previous: Option<char> = None;
+    for (i, c) in text.char_indices() {
+        let token_start =
+            previous.is_none_or(|p| p.is_whitespace() || p == ',' || p == '=' || p == '(');
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None if (c == '\'' || c == '"') && token_start => quote = Some(c),
+            None if c == target => return Some(i),
+            None => (),
+        }
+        previous = Some(c);
+    }
+    None
+}
+
+/// Quote a value if it would otherwise be split into several tokens, e.g. a path with spaces
+pub(crate) fn quote_value(value: &str) -> String {
+    if !value.is_empty()
+        && !value
+            .chars()
+            .any(|c| c.is_whitespace() || "=,()'\"".contains(c))
+    {
+        return value.to_string();
+    }
+    match value.contains('"') {
+        true => format!("'{value}'"),
+        false => format!("\"{value}\""),
+    }
+}
+
+/// Split the text within the parentheses into argument tokens
+///
+/// Tokens are separated by whitespace or commas. Named arguments are written as "name=value",
+/// optionally with whitespace around the "=". Values within single or double quotes may contain
+/// separators and parentheses, e.g. prior="my data/thickness.tif".
+fn tokenize(input: &str, range: Range<usize>) -> Result<Vec<ArgToken<'_>>, StepParseError> {
+    // The location of each word, and whether it was quoted
+    let mut words = Vec::<(Range<usize>, bool)>::new();
+    let mut start: Option<usize> = None;
+    let mut chars = input[range.clone()].char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let i = i + range.start;
+        if c.is_whitespace() || c == ',' || c == '=' {
+            if let Some(s) = start.take() {
+                words.push((s..i, false));
+            }
+            if c == '=' {
+                words.push((i..i + 1, false));
+            }
+        } else if (c == '\'' || c == '"') && start.is_none() {
+            let end = match input[i + 1..range.end].find(c) {
+                Some(end) => i + 1 + end,
+                None => {
+                    return Err(StepParseError {
+                        input: input.to_string(),
+                        span: i..range.end,
+                        message: "Missing closing quote".to_string(),
+                    })
+                }
+            };
+            words.push((i + 1..end, true));
+            // Skip to the closing quote, which has to be followed by a separator
+            while chars.next_if(|(j, _)| j + range.start < end).is_some() {}
+            chars.next();
+            if let Some((j, next)) = chars.peek() {
+                if !(next.is_whitespace() || *next == ',' || *next == '=') {
+                    let j = j + range.start;
+                    return Err(StepParseError {
+                        input: input.to_string(),
+                        span: j..j + next.len_utf8(),
+                        message: "Expected a separator after the closing quote".to_string(),
+                    });
+                }
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(s) = start {
+        words.push((s..range.end, false));
+    }
+
+    let mut tokens = Vec::<ArgToken>::new();
+    let mut i = 0;
+    while i < words.len() {
+        let is_equals = |j: usize| {
+            words
+                .get(j)
+                .is_some_and(|(w, quoted)| !quoted && &input[w.clone()] == "=")
+        };
+        let span = |j: usize| match words[j] {
+            (ref w, true) => w.start - 1..w.end + 1,
+            (ref w, false) => w.clone(),
+        };
+        if is_equals(i) {
+            return Err(StepParseError {
+                input: input.to_string(),
+                span: words[i].0.clone(),
+                message: "Expected an argument name before '='".to_string(),
+            });
+        }
+        let word = &words[i].0;
+        if is_equals(i + 1) {
+            match words.get(i + 2).filter(|_| !is_equals(i + 2)) {
+                Some((value, _)) => {
+                    tokens.push(ArgToken {
+                        name: Some((&input[word.clone()], word.clone())),
+                        value: &input[value.clone()],
+                        value_span: value.clone(),
+                        span: span(i).start..span(i + 2).end,
+                    });
+                    i += 3;
+                }
+                None => {
+                    return Err(StepParseError {
+                        input: input.to_string(),
+                        span: span(i).start..words[i + 1].0.end,
+                        message: format!("Expected a value after '{}='", &input[word.clone()]),
+                    })
+                }
+            }
+        } else {
+            tokens.push(ArgToken {
+                name: None,
+                value: &input[word.clone()],
+                value_span: word.clone(),
+                span: span(i),
+            });
+            i += 1;
+        }
+    }
+    Ok(tokens)
+}
+
+/// The arguments of a step, assigned to the arguments of its specification
+struct ParsedArgs<'a> {
+    input: &'a str,
+    spec: &'static StepSpec,
+    /// The argument index, value and location of the value of each given argument
+    values: Vec<(usize, &'a str, Range<usize>)>,
+    /// The number of arguments that were given without a name
+    n_positional: usize,
+    /// The location of the whole step
+    span: Range<usize>,
+}
+
+impl<'a> ParsedArgs<'a> {
+    fn error(&self, span: Range<usize>, message: String) -> StepParseError {
+        StepParseError {
+            input: self.input.to_string(),
+            span,
+            message,
+        }
+    }
+
+    /// Assign positional and named argument tokens to the arguments of the specification
+    fn new(
+        input: &'a str,
+        spec: &'static StepSpec,
+        tokens: Vec<ArgToken<'a>>,
+        span: Range<usize>,
+    ) -> Result<ParsedArgs<'a>, StepParseError> {
+        let mut parsed = ParsedArgs {
+            input,
+            spec,
+            values: Vec::new(),
+            n_positional: 0,
+            span,
+        };
+        let usage = format!("Usage: {}", spec.signature());
+
+        let mut seen_named = false;
+        let mut position = 0;
+        for token in tokens {
+            let index = match &token.name {
+                None => {
+                    if seen_named {
+                        return Err(parsed.error(
+                            token.span,
+                            "Positional arguments cannot follow named arguments".to_string(),
+                        ));
+                    }
+                    // A list argument takes the following positional values until one is not a
+                    // list item. A required list keeps its first value, and the last argument all
+                    // values, to report them if invalid.
+                    while spec.args.get(position).is_some_and(|arg| {
+                        arg.kind.is_list()
+                            && position + 1 < spec.args.len()
+                            && !arg.kind.is_list_item(token.value)
+                            && (arg.default != ArgDefault::Required
+                                || parsed.values.iter().any(|(i, _, _)| *i == position))
+                    }) {
+                        position += 1;
+                    }
+                    let index = position;
+                    if index >= spec.args.len() {
+                        return Err(parsed.error(
+                            token.span,
+                            format!(
+                                "Too many arguments for step '{}' (expected at most {}). {usage}",
+                                spec.name,
+                                spec.args.len()
+                            ),
+                        ));
+                    }
+                    if !spec.args[index].kind.is_list() {
+                        position += 1;
+                    }
+                    parsed.n_positional += 1;
+                    index
+                }
+                Some((name, name_span)) => {
+                    seen_named = true;
+                    let index = spec
+                        .args
+                        .iter()
+                        .position(|arg| arg.name == *name)
+                        .ok_or_else(|| {
+                            // The dewow window used to be given in samples
+                            let hint = match (spec.name, *name) {
+                                ("dewow", "window") => {
+                                    "The window is now given in ns with 'window_ns'. "
+                                }
+                                _ => "",
+                            };
+                            parsed.error(
+                                name_span.clone(),
+                                format!(
+                                    "Unknown argument '{name}' for step '{}'. {hint}{usage}",
+                                    spec.name
+                                ),
+                            )
+                        })?;
+                    if !spec.args[index].kind.is_list()
+                        && parsed.values.iter().any(|(i, _, _)| *i == index)
+                    {
+                        return Err(parsed.error(
+                            name_span.clone(),
+                            format!("Argument '{name}' was given more than once"),
+                        ));
+                    }
+                    index
+                }
+            };
+            parsed.values.push((index, token.value, token.value_span));
+        }
+
+        // Make sure that all required arguments were given
+        for (index, arg) in spec.args.iter().enumerate() {
+            if arg.default == ArgDefault::Required
+                && !parsed.values.iter().any(|(i, _, _)| *i == index)
+            {
+                return Err(parsed.error(
+                    parsed.span.clone(),
+                    format!(
+                        "Missing required argument '{}' for step '{}'. {usage}",
+                        arg.name, spec.name
+                    ),
+                ));
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    fn arg_index(&self, name: &str) -> usize {
+        self.spec
+            .args
+            .iter()
+            .position(|arg| arg.name == name)
+            .unwrap_or_else(|| panic!("No argument '{name}' in step '{}'", self.spec.name))
+    }
+
+    /// Get the given value of an argument, if any
+    fn given(&self, name: &str) -> Option<(&'a str, Range<usize>)> {
+        let index = self.arg_index(name);
+        self.values
+            .iter()
+            .find(|(i, _, _)| *i == index)
+            .map(|(_, value, span)| (*value, span.clone()))
+    }
+
+    /// Parse the value of an argument, or use its default value
+    ///
+    /// # Returns
+    /// The value, or None if it is determined automatically (not given, or given as "auto") or
+    /// not used (not given, or given as "none").
+    fn get<T: FromStr>(&self, name: &str) -> Result<Option<T>, StepParseError> {
+        let arg = &self.spec.args[self.arg_index(name)];
+        match self.given(name) {
+            Some(("auto", _)) if arg.default == ArgDefault::Auto => Ok(None),
+            Some(("none", _)) if arg.default == ArgDefault::None => Ok(None),
+            Some((value, span)) => match value.parse::<T>() {
+                Ok(v) => Ok(Some(v)),
+                Err(_) => Err(self.error(
+                    span,
+                    format!(
+                        "Expected {} for argument '{}', got '{}'",
+                        arg.kind.description(),
+                        arg.name,
+                        value
+                    ),
+                )),
+            },
+            None => match arg.default {
+                ArgDefault::Value(v) => Ok(v.to_string().parse::<T>().ok()),
+                ArgDefault::Text(t) => Ok(t.parse::<T>().ok()),
+                ArgDefault::Auto | ArgDefault::Required | ArgDefault::None => Ok(None),
+            },
+        }
+    }
+
+    /// Parse an argument that is always given or has a fixed default value
+    fn value<T: FromStr>(&self, name: &str) -> Result<T, StepParseError> {
+        Ok(self
+            .get::<T>(name)?
+            .unwrap_or_else(|| panic!("Argument '{name}' has no default value")))
+    }
+
+    /// Parse an index where -1 means the end of the data
+    fn end_index(&self, name: &str) -> Result<Option<u32>, StepParseError> {
+        match self.value::<i64>(name)? {
+            -1 => Ok(None),
+            v if v >= 0 && v <= u32::MAX as i64 => Ok(Some(v as u32)),
+            v => Err(self.error(
+                self.given(name).map(|g| g.1).unwrap_or(self.span.clone()),
+                format!("Expected a non-negative index or -1 (the end) for '{name}', got {v}"),
+            )),
+        }
+    }
+
+    /// Parse all trace indices and inclusive trace ranges (e.g. "5-10") of an argument
+    fn trace_ranges(&self, name: &str) -> Result<Vec<usize>, StepParseError> {
+        let index = self.arg_index(name);
+        let mut traces = Vec::<usize>::new();
+        for (_, value, span) in self.values.iter().filter(|(i, _, _)| *i == index) {
+            let range = match value.split_once('-') {
+                Some((start, end)) => start.parse::<usize>().ok().zip(end.parse::<usize>().ok()),
+                None => value.parse::<usize>().ok().map(|v| (v, v)),
+            };
+            match range {
+                Some((start, end)) if start <= end => traces.extend(start..=end),
+                _ => {
+                    return Err(self.error(
+                        span.clone(),
+                        format!(
+                            "Expected {} for argument '{name}', got '{value}'",
+                            ArgKind::TraceRanges.description()
+                        ),
+                    ))
+                }
+            }
+        }
+        traces.sort_unstable();
+        traces.dedup();
+        Ok(traces)
+    }
+
+    /// Parse all layers of an argument, given as their top depth and velocity (e.g. "15:0.168")
+    ///
+    /// # Returns
+    /// The top depth (m) and velocity (m/ns) of each layer, sorted by depth.
+    fn velocity_layers(&self, name: &str) -> Result<Vec<(f32, f32)>, StepParseError> {
+        let index = self.arg_index(name);
+        let mut layers = Vec::<(f32, f32)>::new();
+        for (_, value, span) in self.values.iter().filter(|(i, _, _)| *i == index) {
+            let layer = value.split_once(':').and_then(|(depth, velocity)| {
+                depth.parse::<f32>().ok().zip(velocity.parse::<f32>().ok())
+            });
+            match layer {
+                Some((depth, velocity)) if depth >= 0. && velocity > 0. => {
+                    layers.push((depth, velocity))
+                }
+                _ => {
+                    return Err(self.error(
+                        span.clone(),
+                        format!(
+                            "Expected {} for argument '{name}', got '{value}'",
+                            ArgKind::VelocityLayers.description()
+                        ),
+                    ))
+                }
+            }
+        }
+        layers.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(layers)
+    }
+
+    /// Parse all seeds of an argument, given as their trace index and return time (e.g. "120:250")
+    ///
+    /// # Returns
+    /// The trace index and return time (ns) of each seed, in the given order.
+    fn seeds(&self, name: &str) -> Result<Vec<(usize, f32)>, StepParseError> {
+        let index = self.arg_index(name);
+        let mut seeds = Vec::<(usize, f32)>::new();
+        for (_, value, span) in self.values.iter().filter(|(i, _, _)| *i == index) {
+            let seed = value.split_once(':').and_then(|(trace, time)| {
+                trace.parse::<usize>().ok().zip(time.parse::<f32>().ok())
+            });
+            match seed {
+                Some((trace, time)) if time >= 0. => seeds.push((trace, time)),
+                _ => {
+                    return Err(self.error(
+                        span.clone(),
+                        format!(
+                            "Expected {} for argument '{name}', got '{value}'",
+                            ArgKind::Seeds.description()
+                        ),
+                    ))
+                }
+            }
+        }
+        Ok(seeds)
+    }
+
+    /// Parse a name of letters, digits and underscores
+    fn name(&self, name: &str) -> Result<String, StepParseError> {
+        let value = self.value::<String>(name)?;
+        match value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            true => Ok(value),
+            false => Err(self.error(
+                self.given(name).map(|g| g.1).unwrap_or(self.span.clone()),
+                format!(
+                    "Expected {} for argument '{name}', got '{value}'",
+                    ArgKind::Name.description()
+                ),
+            )),
+        }
+    }
+
+    fn to_step(&self) -> Result<Step, StepParseError> {
+        Ok(match self.spec.name {
+            "subset" => {
+                if self.values.is_empty() {
+                    return Err(self.error(
+                        self.span.clone(),
+                        "Indices must be given when subsetting, e.g. subset(0 -1 0 500)"
+                            .to_string(),
+                    ));
+                }
+                Step::Subset {
+                    min_trace: self.value("min_trace")?,
+                    max_trace: self.end_index("max_trace")?,
+                    min_sample: self.value("min_sample")?,
+                    max_sample: self.end_index("max_sample")?,
+                }
+            }
+            "remove_traces" => Step::RemoveTraces {
+                traces: self.trace_ranges("traces")?,
+            },
+            "remove_empty_traces" => Step::RemoveEmptyTraces {
+                strength: self.value("strength")?,
+            },
+            "average_traces" => Step::AverageTraces {
+                window: self.value("window")?,
+            },
+            "zero_corr_max_peak" => Step::ZeroCorrMaxPeak,
+            "zero_corr" => Step::ZeroCorr {
+                threshold_multiplier: self.value("threshold_multiplier")?,
+            },
+            "bandpass" => Step::Bandpass {
+                low_cutoff: self.value("low_cutoff")?,
+                high_cutoff: self.value("high_cutoff")?,
+                q: self.value("q")?,
+                zero_phase: self.value("zero_phase")?,
+            },
+            "bandpass_mhz" => Step::BandpassMhz {
+                low_cutoff: self.value("low_cutoff")?,
+                high_cutoff: self.value("high_cutoff")?,
+                q: self.value("q")?,
+                zero_phase: self.value("zero_phase")?,
+            },
+            "fft_bandpass" => Step::FftBandpass {
+                low_cut: self.value("low_cut")?,
+                low_pass: self.value("low_pass")?,
+                high_pass: self.value("high_pass")?,
+                high_cut: self.value("high_cut")?,
+            },
+            "notch" => Step::Notch {
+                frequency: self.value("frequency")?,
+                width: self.get("width")?,
+                harmonics: self.value("harmonics")?,
+            },
+            "equidistant_traces" => Step::EquidistantTraces {
+                step: self.get("step")?,
+            },
+            "fk_velocity_filter" => Step::FkVelocityFilter {
+                min_velocity: self.value("min_velocity")?,
+                max_velocity: self.value("max_velocity")?,
+                mode: self.value("mode")?,
+                taper: self.value("taper")?,
+            },
+            "fk_dip_filter" => Step::FkDipFilter {
+                min_dip: self.value("min_dip")?,
+                max_dip: self.value("max_dip")?,
+                mode: self.value("mode")?,
+                taper: self.value("taper")?,
+            },
+            "normalize_horizontal_magnitudes" => {
+                // Integers are sample numbers and floats are fractions of the trace
+                let skip_first = match self.get::<isize>("skip_first") {
+                    Ok(Some(samples)) if self.given("skip_first").is_some() => {
+                        SkipFirst::Samples(samples)
+                    }
+                    _ => {
+                        let fraction: f32 = self.value("skip_first")?;
+                        if !(0. ..1.).contains(&fraction) {
+                            return Err(self.error(
+                                self.given("skip_first")
+                                    .map(|g| g.1)
+                                    .unwrap_or(self.span.clone()),
+                                format!(
+                                    "Invalid fraction: {fraction}. Must be between 0.0 and 1.0."
+                                ),
+                            ));
+                        }
+                        SkipFirst::Fraction(fraction)
+                    }
+                };
+                Step::NormalizeHorizontalMagnitudes { skip_first }
+            }
+            "dewow" => {
+                // "dewow(5)" used to be a window of 5 samples, so a window without a unit would
+                // silently give other results
+                if let Some((_, span)) = self.given("window_ns").filter(|_| self.n_positional > 0) {
+                    return Err(self.error(
+                        span,
+                        "The dewow window has to be given by name, since it used to be in samples. Give it in ns, e.g. dewow(window_ns=10)".to_string(),
+                    ));
+                }
+                Step::Dewow {
+                    window_ns: self.get("window_ns")?,
+                    periods: self.value("periods")?,
+                    statistic: self.value("statistic")?,
+                }
+            }
+            "background_removal" => Step::BackgroundRemoval {
+                statistic: self.value("statistic")?,
+                window: self.value("window")?,
+                min_sample: self.value("min_sample")?,
+                max_sample: self.end_index("max_sample")?,
+            },
+            "auto_gain" => Step::AutoGain {
+                n_bins: self.value("n_bins")?,
+            },
+            "gain" => Step::Gain {
+                factor: self.value("factor")?,
+            },
+            "sec_gain" => Step::SecGain {
+                attenuation: self.value("attenuation")?,
+            },
+            "power_gain" => Step::PowerGain {
+                exponent: self.value("exponent")?,
+            },
+            "spherical_divergence" => Step::SphericalDivergence {
+                exponent: self.value("exponent")?,
+            },
+            "agc" => Step::Agc {
+                window_ns: self.value("window_ns")?,
+                method: self.value("method")?,
+                save: self.value("save")?,
+            },
+            "undo_agc" => Step::UndoAgc,
+            "kirchhoff_migration2d" => Step::KirchhoffMigration2d,
+            "stolt_migration" => Step::StoltMigration {
+                velocity: self.get("velocity")?,
+            },
+            "gazdag_migration" => Step::GazdagMigration {
+                layers: self.velocity_layers("layers")?,
+            },
+            "pick_horizon" => Step::PickHorizon {
+                horizon: self.name("horizon")?,
+                seeds: self.seeds("seeds")?,
+                phase: self.value("phase")?,
+                window_ns: self.get("window_ns")?,
+                max_gap: self.value("max_gap")?,
+                threshold: self.value("threshold")?,
+            },
+            "detect_bed" => Step::DetectBed {
+                min_depth: self.value("min_depth")?,
+                max_jump_ns: self.get("max_jump_ns")?,
+                prior: self.get("prior")?,
+                prior_tolerance: self.value("prior_tolerance")?,
+            },
+            "fit_hyperbolae" => Step::FitHyperbolae {
+                apexes: self.seeds("apexes")?,
+                aperture_m: self.get("aperture_m")?,
+                max_count: self.value("max_count")?,
+            },
+            "abslog" => Step::Abslog,
+            "siglog" => Step::Siglog {
+                minval_log10: self.value("minval_log10")?,
+            },
+            "unphase" => Step::Unphase,
+            "envelope" => Step::Envelope,
+            "instantaneous_phase" => Step::InstantaneousPhase,
+            "instantaneous_frequency" => Step::InstantaneousFrequency,
+            "correct_topography" => Step::CorrectTopography,
+            "correct_antenna_separation" => Step::CorrectAntennaSeparation,
+            name => unreachable!("Step '{name}' is specified but not implemented"),
+        })
+    }
+}
+
+/// The number of single character edits needed to turn one string into another
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b_chars = b.chars().collect::<Vec<char>>();
+    let mut previous = (0..=b_chars.len()).collect::<Vec<usize>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b_chars.iter().enumerate() {
+            current.push(
+                (previous[j] + (ca != *cb) as usize)
+                    .min(previous[j + 1] + 1)
+                    .min(current[j] + 1),
+            );
+        }
+        previous = current;
+    }
+    previous[b_chars.len()]
+}
+
+impl FromStr for Step {
+    type Err = StepParseError;
+
+    /// Parse a step, e.g. "dewow", "dewow(window_ns=5)" or "average_traces(5)"
+    fn from_str(input: &str) -> Result<Step, StepParseError> {
+        let error = |span: Range<usize>, message: String| StepParseError {
+            input: input.to_string(),
+            span,
+            message,
+        };
+        let trimmed_start = input.len() - input.trim_start().len();
+        let trimmed_end = input.trim_end().len();
+
+        let open = input.find('(');
+        let name_end = open.unwrap_or(trimmed_end).max(trimmed_start);
+        let name = input[trimmed_start..name_end].trim_end();
+        let name_span = trimmed_start..trimmed_start + name.len();
+        if name.is_empty() {
+            return Err(error(name_span, "Missing step name".to_string()));
+        }
+
+        let spec = StepSpec::from_name(name).ok_or_else(|| {
+            let closest = STEPS
+                .iter()
+                .map(|spec| (edit_distance(name, spec.name), spec.name))
+                .min()
+                .filter(|(distance, _)| *distance <= 3);
+            error(
+                name_span.clone(),
+                match closest {
+                    Some((_, suggestion)) => {
+                        format!("Unknown step '{name}'. Did you mean '{suggestion}'?")
+                    }
+                    None => format!(
+                        "Unknown step '{name}'. See \"--show-all-steps\" for the available steps"
+                    ),
+                },
+            )
+        })?;
+
+        let tokens = match open {
+            None => Vec::new(),
+            Some(open) => {
+                let close = match find_unquoted(&input[open..], ')') {
+                    Some(i) => open + i,
+                    None => {
+                        return Err(error(
+                            open..trimmed_end,
+                            "Missing closing parenthesis".to_string(),
+                        ))
+                    }
+                };
+                let trailing = input[close + 1..trimmed_end].trim_start();
+                if !trailing.is_empty() {
+                    return Err(error(
+                        trimmed_end - trailing.len()..trimmed_end,
+                        "Unexpected text after the closing parenthesis".to_string(),
+                    ));
+                }
+                tokenize(input, open + 1..close)?
+            }
+        };
+
+        ParsedArgs::new(input, spec, tokens, trimmed_start..trimmed_end)?.to_step()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        SkipFirst, Step, StepSpec, DEFAULT_BED_MIN_DEPTH, DEFAULT_BED_PRIOR_TOLERANCE,
+        DEFAULT_HYPERBOLA_MAX_COUNT, DEFAULT_PICK_MAX_GAP, DEFAULT_PICK_THRESHOLD, STEPS,
+    };
+    use crate::filters::{AgcMethod, Statistic};
+    use crate::horizons::Phase;
+
+    #[test]
+    fn test_parse_step() {
+        // Positional and named arguments should give the same step
+        for text in [
+            "gain(0.002)",
+            "gain(factor=0.002)",
+            "gain( factor = 0.002 )",
+            "  gain(factor=0.002)  ",
+        ] {
+            assert_eq!(
+                text.parse::<Step>().unwrap(),
+                Step::Gain { factor: 0.002 },
+                "{text}"
+            );
+        }
+
+        // Steps whose names are prefixes of others should not be confused
+        assert_eq!("zero_corr_max_peak".parse(), Ok(Step::ZeroCorrMaxPeak));
+        assert_eq!(
+            "zero_corr".parse(),
+            Ok(Step::ZeroCorr {
+                threshold_multiplier: 1.
+            })
+        );
+        assert_eq!("auto_gain".parse(), Ok(Step::AutoGain { n_bins: 100 }));
+        assert_eq!(
+            "bandpass_mhz(50, 150)".parse(),
+            Ok(Step::BandpassMhz {
+                low_cutoff: 50.,
+                high_cutoff: 150.,
+                q: 0.707,
+                zero_phase: false
+            })
+        );
+
+        assert_eq!(
+            "subset(0 -1 0 500)".parse(),
+            Ok(Step::Subset {
+                min_trace: 0,
+                max_trace: None,
+                min_sample: 0,
+                max_sample: Some(500)
+            })
+        );
+        assert_eq!(
+            "subset(max_sample=500)".parse::<Step>(),
+            "subset(0 -1 0 500)".parse::<Step>()
+        );
+        assert_eq!(
+            "remove_traces(0 1, 5-7)".parse(),
+            Ok(Step::RemoveTraces {
+                traces: vec![0, 1, 5, 6, 7]
+            })
+        );
+        assert_eq!(
+            "normalize_horizontal_magnitudes(300)".parse(),
+            Ok(Step::NormalizeHorizontalMagnitudes {
+                skip_first: SkipFirst::Samples(300)
+            })
+        );
+        assert_eq!(
+            "normalize_horizontal_magnitudes".parse(),
+            Ok(Step::NormalizeHorizontalMagnitudes {
+                skip_first: SkipFirst::Fraction(0.3)
+            })
+        );
+        assert_eq!(
+            "equidistant_traces".parse(),
+            Ok(Step::EquidistantTraces { step: None })
+        );
+        assert_eq!(
+            "dewow(window_ns=20 statistic=median)".parse(),
+            Ok(Step::Dewow {
+                window_ns: Some(20.),
+                periods: 1.,
+                statistic: Statistic::Median
+            })
+        );
+        assert_eq!(
+            "agc(window_ns=30 save=true)".parse(),
+            Ok(Step::Agc {
+                window_ns: 30.,
+                method: AgcMethod::Rms,
+                save: true
+            })
+        );
+        assert_eq!(
+            "equidistant_traces(step=auto)".parse(),
+            Ok(Step::EquidistantTraces { step: None })
+        );
+        // Layers are sorted by depth
+        assert_eq!(
+            "gazdag_migration(15:0.168, 0:0.2)".parse(),
+            Ok(Step::GazdagMigration {
+                layers: vec![(0., 0.2), (15., 0.168)]
+            })
+        );
+        assert_eq!(
+            "gazdag_migration".parse(),
+            Ok(Step::GazdagMigration { layers: Vec::new() })
+        );
+        // The seeds take all positional values after the horizon name, in the given order
+        assert_eq!(
+            "pick_horizon(bed 500:250 20:240.5 phase=min)".parse(),
+            Ok(Step::PickHorizon {
+                horizon: "bed".to_string(),
+                seeds: vec![(500, 250.), (20, 240.5)],
+                phase: Phase::Min,
+                window_ns: None,
+                max_gap: DEFAULT_PICK_MAX_GAP,
+                threshold: DEFAULT_PICK_THRESHOLD,
+            })
+        );
+        assert_eq!(
+            "detect_bed(prior=thickness.tif)".parse(),
+            Ok(Step::DetectBed {
+                min_depth: DEFAULT_BED_MIN_DEPTH,
+                max_jump_ns: None,
+                prior: Some("thickness.tif".into()),
+                prior_tolerance: DEFAULT_BED_PRIOR_TOLERANCE,
+            })
+        );
+        assert_eq!(
+            "detect_bed(prior=none)".parse::<Step>().unwrap(),
+            "detect_bed".parse::<Step>().unwrap()
+        );
+        // Without apexes, the diffractions are found automatically
+        assert_eq!(
+            "fit_hyperbolae(max_count=5)".parse(),
+            Ok(Step::FitHyperbolae {
+                apexes: vec![],
+                aperture_m: None,
+                max_count: 5,
+            })
+        );
+        // A list ends at the first positional value that is not a list item
+        assert_eq!(
+            "fit_hyperbolae(120:250 900:180 5 3)".parse(),
+            Ok(Step::FitHyperbolae {
+                apexes: vec![(120, 250.), (900, 180.)],
+                aperture_m: Some(5.),
+                max_count: 3,
+            })
+        );
+        assert_eq!(
+            "fit_hyperbolae(5)".parse(),
+            Ok(Step::FitHyperbolae {
+                apexes: vec![],
+                aperture_m: Some(5.),
+                max_count: DEFAULT_HYPERBOLA_MAX_COUNT,
+            })
+        );
+        assert_eq!(
+            "pick_horizon(bed 120:250 min 4)".parse(),
+            Ok(Step::PickHorizon {
+                horizon: "bed".to_string(),
+                seeds: vec![(120, 250.)],
+                phase: Phase::Min,
+                window_ns: Some(4.),
+                max_gap: DEFAULT_PICK_MAX_GAP,
+                threshold: DEFAULT_PICK_THRESHOLD,
+            })
+        );
+        // Quoted values may contain separators, parentheses and '='
+        for text in [
+            "detect_bed(prior=\"my data/thickness (2023), v=2.tif\")",
+            "detect_bed(prior = 'my data/thickness (2023), v=2.tif')",
+            "detect_bed(10 auto \"my data/thickness (2023), v=2.tif\")",
+        ] {
+            assert_eq!(
+                text.parse::<Step>().map(|step| match step {
+                    Step::DetectBed { prior, .. } => prior,
+                    _ => None,
+                }),
+                Ok(Some("my data/thickness (2023), v=2.tif".into())),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_step_errors() {
+        let error = |text: &str| text.parse::<Step>().unwrap_err();
+
+        let e = error("gian(0.1)");
+        assert_eq!(e.span, 0..4);
+        assert!(e.message.contains("Did you mean 'gain'?"), "{}", e.message);
+
+        let e = error("gain(facter=0.1)");
+        assert_eq!(&e.input[e.span.clone()], "facter");
+        assert!(e.message.contains("Usage: gain(factor)"));
+
+        let e = error("gain(factor=abc)");
+        assert_eq!(&e.input[e.span.clone()], "abc");
+        assert!(e.message.contains("Expected a number"));
+
+        let e = error("gain");
+        assert!(e.message.contains("Missing required argument 'factor'"));
+
+        let e = error("average_traces(5 6)");
+        assert_eq!(&e.input[e.span.clone()], "6");
+        assert!(e.message.contains("Too many arguments"));
+
+        let e = error("average_traces(window=5 window=6)");
+        assert!(e.message.contains("more than once"));
+
+        let e = error("bandpass(q=0.5 0.1)");
+        assert_eq!(&e.input[e.span.clone()], "0.1");
+
+        let e = error("average_traces(5");
+        assert!(e.message.contains("Missing closing parenthesis"));
+
+        let e = error("average_traces(5) x");
+        assert_eq!(&e.input[e.span.clone()], "x");
+
+        let e = error("remove_traces(5-2)");
+        assert_eq!(&e.input[e.span.clone()], "5-2");
+
+        let e = error("gazdag_migration(0:0.2 15)");
+        assert_eq!(&e.input[e.span.clone()], "15");
+        assert!(e.message.contains("layer top depth"));
+
+        let e = error("gazdag_migration(0:-0.2)");
+        assert_eq!(&e.input[e.span.clone()], "0:-0.2");
+
+        let e = error("pick_horizon(bed)");
+        assert!(e.message.contains("Missing required argument 'seeds'"));
+
+        let e = error("pick_horizon(bed 120:250 0.5)");
+        assert_eq!(&e.input[e.span.clone()], "0.5");
+        assert!(e.message.contains("one of: max, min, zero_crossing"));
+
+        let e = error("pick_horizon(bed 0.5)");
+        assert_eq!(&e.input[e.span.clone()], "0.5");
+        assert!(e.message.contains("trace index and return time"));
+
+        let e = error("detect_bed(prior=\"thickness.tif)");
+        assert!(e.message.contains("Missing closing"), "{}", e.message);
+
+        let e = error("detect_bed(prior=\"a\"b)");
+        assert_eq!(&e.input[e.span.clone()], "b");
+
+        let e = error("pick_horizon(bed-rock 120:250)");
+        assert_eq!(&e.input[e.span.clone()], "bed-rock");
+
+        let e = error("pick_horizon(bed 120:250 phase=peak)");
+        assert!(e.message.contains("one of: max, min, zero_crossing"));
+
+        let e = error("normalize_horizontal_magnitudes(1.5)");
+        assert!(e.message.contains("Invalid fraction"));
+
+        let e = error("subset");
+        assert!(e.message.contains("Indices must be given"));
+
+        // The dewow window used to be in samples, so it has to be named to be in ns
+        let e = error("dewow(5)");
+        assert_eq!(&e.input[e.span.clone()], "5");
+        assert!(e.message.contains("window_ns="), "{}", e.message);
+        let e = error("dewow(window=5)");
+        assert_eq!(&e.input[e.span.clone()], "window");
+        assert!(
+            e.message.contains("in ns with 'window_ns'"),
+            "{}",
+            e.message
+        );
+
+        // The problem should be underlined in the message
+        assert_eq!(
+            error("gain(facter=0.1)").to_string().lines().last(),
+            Some("         ^^^^^^")
+        );
+    }
+
+    #[test]
+    fn test_step_roundtrip() {
+        let steps = [
+            "subset(10 300)",
+            "remove_traces(8 0-3 5)",
+            "zero_corr(0.9)",
+            "bandpass(0.2 0.8 1)",
+            "bandpass_mhz(50 150 zero_phase=true)",
+            "fft_bandpass(50 100 600 800)",
+            "notch(156.8 harmonics=2)",
+            "notch(50 width=5)",
+            "equidistant_traces(2.)",
+            "fk_velocity_filter(0.25 0.35)",
+            "fk_velocity_filter(0.1 inf mode=pass taper=0)",
+            "fk_dip_filter(40)",
+            "stolt_migration",
+            "stolt_migration(0.17)",
+            "gazdag_migration",
+            "gazdag_migration(0:0.2 15:0.168)",
+            "normalize_horizontal_magnitudes(1.0e-1)",
+            "normalize_horizontal_magnitudes(300)",
+            "siglog(-2)",
+            "background_removal(median 50 max_sample=300)",
+            "kirchhoff_migration2d",
+            "dewow(window_ns=12.5 statistic=median)",
+            "agc(50 mean_abs true)",
+            "sec_gain(0.05)",
+            "power_gain(1.5)",
+            "spherical_divergence(0.5)",
+            "pick_horizon(bed 120:250)",
+            "pick_horizon(layer_1 10:80.5 900:95 phase=zero_crossing window_ns=4 max_gap=3)",
+            "detect_bed",
+            "envelope",
+            "instantaneous_frequency",
+            "detect_bed(30 max_jump_ns=2.5 prior=data/thickness.tif prior_tolerance=100)",
+            "detect_bed(prior=\"my data/thickness (2023).tif\")",
+            "fit_hyperbolae",
+            "fit_hyperbolae(120:250 900:180.5 aperture_m=5 max_count=3)",
+        ];
+        for text in steps
+            .iter()
+            .map(|s| s.to_string())
+            .chain(Step::default_profile().iter().map(|s| s.to_string()))
+        {
+            let step = text.parse::<Step>().unwrap();
+            assert_eq!(step.to_string().parse::<Step>(), Ok(step.clone()), "{text}");
+        }
+        assert_eq!(
+            "remove_traces(8 0-3 5)"
+                .parse::<Step>()
+                .unwrap()
+                .to_string(),
+            "remove_traces(0-3 5 8)"
+        );
+        assert_eq!(
+            Step::AverageTraces { window: 5 }.to_string(),
+            "average_traces(window=5)".to_string()
+        );
+    }
+
+    #[test]
+    fn test_step_specs() {
+        for spec in STEPS {
+            assert_eq!(StepSpec::from_name(spec.name).unwrap().name, spec.name);
+
+            // Steps without required arguments should parse from their name alone
+            if let Ok(step) = spec.name.parse::<Step>() {
+                assert_eq!(step.name(), spec.name);
+            }
+        }
+        assert_eq!(
+            StepSpec::from_name("dewow").unwrap().signature(),
+            "dewow(window_ns=auto periods=1 statistic=mean)"
+        );
+        assert_eq!(
+            StepSpec::from_name("remove_traces").unwrap().signature(),
+            "remove_traces(traces...)"
+        );
+        assert_eq!(
+            StepSpec::from_name("detect_bed").unwrap().signature(),
+            "detect_bed(min_depth=10 max_jump_ns=auto prior=none prior_tolerance=50)"
+        );
+        assert_eq!(
+            StepSpec::from_name("fit_hyperbolae").unwrap().signature(),
+            "fit_hyperbolae(apexes... aperture_m=auto max_count=20)"
+        );
+    }
+}
@@ -5,13 +5,13 @@ use ndarray_stats::QuantileExt;
 use num::Float;
 use rayon::prelude::*;
 /// Miscellaneous functions that are used in other parts of the program
-use std::{
-    path::{Path, PathBuf},
-    str::FromStr,
-};
+use std::path::{Path, PathBuf};
 
 /// Parse a provided step list (or filepath to a step list)
 ///
+/// Steps in a list are separated by commas. Commas within parentheses separate the arguments of a
+/// step and are kept. In a file, each line is one step. Empty steps are skipped.
+///
 /// # Arguments
 /// - `steps`: An unformatted list of steps, or a filepath
 ///
@@ -19,14 +19,45 @@ use std::{
 /// Formatted steps either from the string itself or from the parsed file.
 pub fn parse_step_list(steps: &str) -> Result<Vec<String>, String> {
     let filepath = Path::new(steps);
-    if filepath.is_file() {
+    let steps = if filepath.is_file() {
         match crate::tools::read_text(&filepath.to_path_buf()) {
-            Ok(s) => Ok(s),
-            Err(e) => Err(format!("Tried to read step file but failed: {e:?}")),
+            Ok(s) => s,
+            Err(e) => return Err(format!("Tried to read step file but failed: {e:?}")),
         }
     } else {
-        Ok(steps.split(',').map(|s| s.trim().to_string()).collect())
-    }
+        let mut parts = Vec::<String>::new();
+        let mut depth = 0_i32;
+        let mut current = String::new();
+        let mut rest = steps;
+        while let Some(c) = rest.chars().next() {
+            rest = &rest[c.len_utf8()..];
+            // Quoted values in the arguments may contain commas and parentheses
+            let token_start = current
+                .chars()
+                .last()
+                .is_some_and(|p| p.is_whitespace() || ",=(".contains(p));
+            if depth > 0 && (c == '\'' || c == '"') && token_start {
+                let end = rest.find(c).map(|i| i + 1).unwrap_or(rest.len());
+                current.push(c);
+                current.push_str(&rest[..end]);
+                rest = &rest[end..];
+                continue;
+            }
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                ',' if depth <= 0 => {
+                    parts.push(std::mem::take(&mut current).trim().to_string());
+                    continue;
+                }
+                _ => (),
+            }
+            current.push(c);
+        }
+        parts.push(current.trim().to_string());
+        parts
+    };
+    Ok(steps.into_iter().filter(|s| !s.is_empty()).collect())
 }
 
 /// Read a text file and return all lines as a vec
@@ -178,62 +209,6 @@ pub fn seconds_to_rfc3339(seconds: f64) -> String {
         .to_rfc3339()
 }
 
-/// Parse the options (arguments) of a user-supplied step
-///
-/// # Arguments
-/// - `string`: The string to parse
-/// - `argument_index`: The expected index of the argument
-///
-/// # Examples
-/// ```
-/// assert_eq!(parse_option::<u32>("dewow(5)", 0), Ok(Some(5)));
-/// assert_eq!(parse_option::<f32>("some_fancy_step(1 2.0)", 1), Ok(Some(2.0)));
-/// assert_eq!(parse_option::<f32>("some_fancy_step", 1), Ok(None));
-///
-/// ```
-///
-/// # Returns
-/// - Ok(Some(x)) where x is the successfully parsed argument
-/// - Ok(None) if there is no argument in the string
-/// - Err(e) if the argument could not be parsed
-pub fn parse_option<T: FromStr>(string: &str, argument_index: usize) -> Result<Option<T>, String> {
-    match string.split_once('(') {
-        None => Ok(None),
-        Some((_, first_part)) => {
-            match first_part.split_once(')') {
-                Some((within_parentheses, _)) => {
-                    // Replace has to be run twice, as it may be an odd number of whitespaces:
-                    // "_-_-_" => "_-_" => "_"
-                    let removed_consecutive_whitespace =
-                        within_parentheses.replace("  ", " ").replace("  ", " ");
-
-                    let arguments = removed_consecutive_whitespace
-                        .split(' ')
-                        .collect::<Vec<&str>>();
-
-                    match arguments.get(argument_index) {
-                        Some(s) => match s.trim().parse::<T>() {
-                            Ok(v) => Ok(Some(v)),
-                            Err(_) => Err(format!(
-                                "Could not parse argument {} as value in string {}: {}",
-                                argument_index, string, s
-                            )),
-                        },
-                        None => Err(format!(
-                            "Argument {} out of bounds in string: {}",
-                            argument_index, string
-                        )),
-                    }
-                }
-                None => Err(format!(
-                    "String: {} has opening parenthesis but not closing",
-                    string
-                )),
-            }
-        }
-    }
-}
-
 pub enum Axis2D {
     Row,
     Col,
@@ -299,11 +274,17 @@ pub struct Resampler<F: Float> {
 }
 
 fn equally_spaced_from_sparse<F: Float>(sparse: &Array1<F>, resolution: F) -> Array1<F> {
-    Array1::<F>::range(
-        *sparse.min().unwrap(),
-        *sparse.max().unwrap() + resolution,
-        resolution,
-    )
+    let min = *sparse.min().unwrap();
+    let steps = (*sparse.max().unwrap() - min) / resolution;
+    // A last partial step gets its own value, like in a range up to max + resolution. Rounding
+    // errors just above a whole number of steps should however not add a value beyond the maximum.
+    let count = (steps - F::from(1e-6).unwrap())
+        .ceil()
+        .max(F::zero())
+        .to_usize()
+        .unwrap()
+        + 1;
+    Array1::from_shape_fn(count, |i| min + F::from(i).unwrap() * resolution)
 }
 
 impl<F: Float + std::fmt::Display + std::iter::Sum + Send + Sync + std::fmt::Debug> Resampler<F> {
@@ -541,36 +522,31 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_option() {
-        assert_eq!(super::parse_option::<u32>("dewow", 0), Ok(None));
-        assert_eq!(super::parse_option::<u32>("dewow(1)", 0), Ok(Some(1_u32)));
+    fn test_parse_step_list() {
         assert_eq!(
-            super::parse_option::<f32>("dewow(1 2.0)", 1),
-            Ok(Some(2_f32))
+            super::parse_step_list("dewow(5), bandpass(0.1, 0.9),, abslog"),
+            Ok(vec![
+                "dewow(5)".to_string(),
+                "bandpass(0.1, 0.9)".to_string(),
+                "abslog".to_string()
+            ])
         );
+        // Commas and parentheses within quotes do not separate steps
         assert_eq!(
-            super::parse_option::<i64>("dewow(1  -2)", 1),
-            Ok(Some(-2_i64))
+            super::parse_step_list("detect_bed(prior=\"a, b (1).tif\"), abslog"),
+            Ok(vec![
+                "detect_bed(prior=\"a, b (1).tif\")".to_string(),
+                "abslog".to_string()
+            ])
         );
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let step_path = temp_dir.path().join("steps.txt");
+        std::fs::write(&step_path, "dewow(5)\n\n  gain(factor=0.1)\n").unwrap();
         assert_eq!(
-            super::parse_option::<i64>("kirchoff_migration2d    (1    -2)    ", 1),
-            Ok(Some(-2_i64))
+            super::parse_step_list(step_path.to_str().unwrap()),
+            Ok(vec!["dewow(5)".to_string(), "gain(factor=0.1)".to_string()])
         );
-
-        assert!(super::parse_option::<f32>("dewow(", 0)
-            .unwrap_err()
-            .contains("opening parenthesis but not closing"));
-        assert!(super::parse_option::<f32>("dewow(1)", 1)
-            .unwrap_err()
-            .contains("Argument 1 out of bounds"));
-
-        assert!(super::parse_option::<f32>("dewow(1,1.0)", 1)
-            .unwrap_err()
-            .contains("Argument 1 out of bounds"));
-
-        assert!(super::parse_option::<f32>("dewow(1 1,1)", 1)
-            .unwrap_err()
-            .contains("Could not parse argument 1"));
     }
 
     #[test]
@@ -699,6 +675,22 @@ mod tests {
         assert_eq!(super::digitize(&values, &bins), expected);
     }
 
+    #[test]
+    fn test_equally_spaced_from_sparse() {
+        // 0.2 / 0.1 is slightly larger than 2, which should not give a value of 0.3
+        let values = super::equally_spaced_from_sparse(&Array1::from_vec(vec![0.2, 0.]), 0.1);
+        assert_eq!(values.len(), 3);
+        assert!((values[2] - 0.2_f64).abs() < 1e-9);
+
+        // The last partial step is kept, even if it is less than half the resolution
+        let values = super::equally_spaced_from_sparse(&Array1::from_vec(vec![0., 0.23]), 0.1);
+        assert_eq!(values.len(), 4);
+        assert!((values[3] - 0.3_f64).abs() < 1e-9);
+
+        let values = super::equally_spaced_from_sparse(&Array1::from_vec(vec![2_f32]), 0.5);
+        assert_eq!(values.to_vec(), vec![2.]);
+    }
+
     #[test]
     fn test_resample() {
         let x_values = Array1::from_vec(vec![0., 0.01, 0.5, 0.99, 1.99, 2.05, 2.05, 5.05]);
@@ -0,0 +1,442 @@
+/// Velocity models of the medium, for converting return times to depths
+///
+/// A model consists of layers with a constant velocity, each starting at a top depth. The last
+/// layer continues indefinitely. Models can be given as layers by depth or by two-way return time
+/// (TWT), or as a density profile of snow, firn and ice that is converted to velocities.
+///
+/// They are loaded from comma separated values (CSV) files with a header, for example:
+/// ```csv
+/// depth,velocity
+/// 0,0.21
+/// 15,0.168
+/// ```
+/// The first column is "depth" (m) or "twt" (ns), and the second is "velocity" (m/ns) or
+/// "density" (kg/m³ or g/cm³). Densities are converted with the Kovacs et al. (1995) relation
+/// ("density" or "density_kovacs") or with the Looyenga (1965) mixing model
+/// ("density_looyenga").
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// The speed of light in vacuum (m/ns)
+const SPEED_OF_LIGHT: f32 = 0.299_792_47;
+/// The density of pure ice (g/cm³)
+const ICE_DENSITY: f32 = 0.917;
+/// The relative permittivity of pure ice
+const ICE_PERMITTIVITY: f32 = 3.15;
+
+/// How to convert a density to a velocity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DensityModel {
+    /// The empirical relation of Kovacs et al. (1995): ε = (1 + 0.845ρ)²
+    Kovacs,
+    /// The mixing model of Looyenga (1965) for ice and air
+    Looyenga,
+}
+
+impl DensityModel {
+    /// Convert a density to a velocity
+    ///
+    /// # Arguments
+    /// - `density`: The density in g/cm³
+    ///
+    /// # Returns
+    /// The velocity (m/ns)
+    pub fn velocity(&self, density: f32) -> f32 {
+        let permittivity = match self {
+            DensityModel::Kovacs => (1. + 0.845 * density).powi(2),
+            DensityModel::Looyenga => {
+                (1. + density / ICE_DENSITY * (ICE_PERMITTIVITY.cbrt() - 1.)).powi(3)
+            }
+        };
+        SPEED_OF_LIGHT / permittivity.sqrt()
+    }
+}
+
+/// A layered velocity model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VelocityModel {
+    /// The top depth (m) and velocity (m/ns) of each layer, sorted by depth. The first layer
+    /// starts at 0 m.
+    layers: Vec<(f32, f32)>,
+}
+
+impl VelocityModel {
+    /// Create a model from layers by depth
+    ///
+    /// # Arguments
+    /// - `layers`: The top depth (m) and velocity (m/ns) of each layer
+    ///
+    /// # Errors
+    /// - There are no layers
+    /// - The first layer does not start at 0 m
+    /// - The depths are not increasing, or a velocity is not positive
+    pub fn from_depth_layers(layers: &[(f32, f32)]) -> Result<VelocityModel, String> {
+        validate_layers(layers, "depth", "m")?;
+        Ok(VelocityModel {
+            layers: layers.to_vec(),
+        })
+    }
+
+    /// Create a model from layers by two-way return time
+    ///
+    /// # Arguments
+    /// - `layers`: The top two-way return time (ns) and velocity (m/ns) of each layer
+    ///
+    /// # Errors
+    /// The same as for `from_depth_layers`
+    pub fn from_time_layers(layers: &[(f32, f32)]) -> Result<VelocityModel, String> {
+        validate_layers(layers, "return time", "ns")?;
+        let mut depth = 0_f32;
+        let mut depth_layers = Vec::<(f32, f32)>::with_capacity(layers.len());
+        for (i, (time, velocity)) in layers.iter().enumerate() {
+            if i > 0 {
+                let (previous_time, previous_velocity) = layers[i - 1];
+                depth += previous_velocity * (time - previous_time) / 2.;
+            }
+            depth_layers.push((depth, *velocity));
+        }
+        Ok(VelocityModel {
+            layers: depth_layers,
+        })
+    }
+
+    /// Create a model from a density profile
+    ///
+    /// Each density is assumed to be valid from its depth to the depth of the next density.
+    ///
+    /// # Arguments
+    /// - `points`: The depth (m) and density of each point. Densities above 10 are assumed to be
+    ///   in kg/m³, and the others in g/cm³.
+    /// - `model`: How to convert the densities to velocities
+    ///
+    /// # Errors
+    /// The same as for `from_depth_layers`, and if a density is not positive
+    pub fn from_density(
+        points: &[(f32, f32)],
+        model: DensityModel,
+    ) -> Result<VelocityModel, String> {
+        if let Some((depth, density)) = points.iter().find(|(_, density)| *density <= 0.) {
+            return Err(format!(
+                "The density at {depth} m needs to be positive, not {density}"
+            ));
+        }
+        let layers = points
+            .iter()
+            .map(|(depth, density)| {
+                let density = match *density > 10. {
+                    true => density / 1000.,
+                    false => *density,
+                };
+                (*depth, model.velocity(density))
+            })
+            .collect::<Vec<(f32, f32)>>();
+        Self::from_depth_layers(&layers)
+    }
+
+    /// Create a model from root-mean-square (RMS) velocities, e.g. of diffraction hyperbolae
+    ///
+    /// The layers are the interval velocities of [`dix_interval_velocities`].
+    ///
+    /// # Arguments
+    /// - `points`: The two-way return time (ns) and RMS velocity (m/ns) of each point
+    ///
+    /// # Errors
+    /// - There are no points, or a velocity is not positive
+    pub fn from_rms_velocities(points: &[(f32, f32)]) -> Result<VelocityModel, String> {
+        Self::from_time_layers(&dix_interval_velocities(points))
+    }
+
+    /// Load a model from a CSV file (see the module documentation for the format)
+    ///
+    /// # Errors
+    /// - The file cannot be read
+    /// - The file cannot be parsed or describes an invalid model
+    pub fn from_csv(filepath: &Path) -> Result<VelocityModel, String> {
+        let content = std::fs::read_to_string(filepath)
+            .map_err(|e| format!("Could not read velocity model {filepath:?}: {e}"))?;
+        Self::from_csv_str(&content)
+            .map_err(|e| format!("Error in velocity model {filepath:?}: {e}"))
+    }
+
+    /// Parse a model from CSV text (see the module documentation for the format)
+    ///
+    /// Empty lines and lines starting with "#" are skipped.
+    ///
+    /// # Errors
+    /// - The header is missing or has unknown columns
+    /// - A row does not have two numeric values
+    /// - The values describe an invalid model
+    pub fn from_csv_str(text: &str) -> Result<VelocityModel, String> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        let (_, header) = lines.next().ok_or("The velocity model is empty")?;
+        let columns = header
+            .split(',')
+            .map(|s| s.trim().to_lowercase())
+            .collect::<Vec<String>>();
+        if columns.len() != 2 {
+            return Err(format!(
+                "Expected two columns in the header, got {header:?}"
+            ));
+        }
+
+        let rows = lines
+            .map(|(line_n, line)| {
+                let values = line
+                    .split(',')
+                    .map(|s| s.trim().parse::<f32>())
+                    .collect::<Result<Vec<f32>, _>>()
+                    .map_err(|e| format!("Error parsing line {line_n} ({line:?}): {e}"))?;
+                match values[..] {
+                    [first, second] => Ok((first, second)),
+                    _ => Err(format!(
+                        "Expected two values on line {line_n}, got {line:?}"
+                    )),
+                }
+            })
+            .collect::<Result<Vec<(f32, f32)>, String>>()?;
+
+        match (columns[0].as_str(), columns[1].as_str()) {
+            ("depth", "velocity") => Self::from_depth_layers(&rows),
+            ("twt", "velocity") => Self::from_time_layers(&rows),
+            ("depth", "density" | "density_kovacs") => {
+                Self::from_density(&rows, DensityModel::Kovacs)
+            }
+            ("depth", "density_looyenga") => Self::from_density(&rows, DensityModel::Looyenga),
+            _ => Err(format!(
+                "Unknown columns {header:?}. The first column is \"depth\" or \"twt\", and the second is \"velocity\", \"density\", \"density_kovacs\" or \"density_looyenga\" (densities need depths)"
+            )),
+        }
+    }
+
+    /// Write the model as CSV text by depth, which can be read with `from_csv_str`
+    pub fn to_csv_string(&self) -> String {
+        let mut text = "depth,velocity\n".to_string();
+        for (depth, velocity) in &self.layers {
+            text += &format!("{depth},{velocity}\n");
+        }
+        text
+    }
+
+    /// The top depth (m) and velocity (m/ns) of each layer, sorted by depth
+    pub fn layers(&self) -> &[(f32, f32)] {
+        &self.layers
+    }
+
+    /// Convert a two-way return time to a (vertical) depth
+    ///
+    /// # Arguments
+    /// - `return_time`: The two-way return time (ns)
+    ///
+    /// # Returns
+    /// The depth (m)
+    pub fn time_to_depth(&self, return_time: f32) -> f32 {
+        let mut time = 0_f32;
+        for (i, (top, velocity)) in self.layers.iter().enumerate() {
+            let bottom_time = match self.layers.get(i + 1) {
+                Some((bottom, _)) => time + 2. * (bottom - top) / velocity,
+                None => f32::INFINITY,
+            };
+            if return_time <= bottom_time {
+                return top + (return_time - time).max(0.) * velocity / 2.;
+            }
+            time = bottom_time;
+        }
+        unreachable!("The last layer has no bottom")
+    }
+
+    /// The average velocity from the surface to the depth of a two-way return time
+    ///
+    /// # Arguments
+    /// - `return_time`: The two-way return time (ns)
+    ///
+    /// # Returns
+    /// The average velocity (m/ns). Within the first layer, it is the velocity of that layer.
+    pub fn average_velocity(&self, return_time: f32) -> f32 {
+        let first_bottom_time = match self.layers.get(1) {
+            Some((bottom, _)) => 2. * bottom / self.layers[0].1,
+            None => f32::INFINITY,
+        };
+        match return_time <= first_bottom_time {
+            true => self.layers[0].1,
+            false => 2. * self.time_to_depth(return_time) / return_time,
+        }
+    }
+}
+
+/// Convert root-mean-square (RMS) velocities to interval velocities
+///
+/// The velocity of each layer between two return times is derived with the Dix (1955) equation,
+/// and the first layer has the first RMS velocity. RMS velocities that would give an interval
+/// velocity above the speed of light (or an imaginary one) are skipped, since they are not
+/// consistent with the velocities above them.
+///
+/// # Arguments
+/// - `points`: The two-way return time (ns) and RMS velocity (m/ns) of each point, in any order
+///
+/// # Returns
+/// The top two-way return time (ns) and interval velocity (m/ns) of each layer
+pub fn dix_interval_velocities(points: &[(f32, f32)]) -> Vec<(f32, f32)> {
+    let mut sorted = points.to_vec();
+    sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut layers = Vec::<(f32, f32)>::new();
+    let mut previous: Option<(f32, f32)> = None;
+    for (time, velocity) in sorted {
+        match previous {
+            None => layers.push((0., velocity)),
+            Some((previous_time, previous_velocity)) => {
+                let squared = (velocity.powi(2) * time - previous_velocity.powi(2) * previous_time)
+                    / (time - previous_time);
+                if time <= previous_time || squared <= 0. || squared.sqrt() > SPEED_OF_LIGHT {
+                    continue;
+                }
+                layers.push((previous_time, squared.sqrt()));
+            }
+        }
+        previous = Some((time, velocity));
+    }
+    layers
+}
+
+impl std::fmt::Display for VelocityModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = self
+            .layers
+            .iter()
+            .map(|(top, velocity)| format!("{velocity} m/ns from {top} m"))
+            .collect::<Vec<String>>()
+            .join(", ");
+        write!(f, "{text}")
+    }
+}
+
+/// Check that the layers start at zero and are increasing, with positive velocities
+fn validate_layers(layers: &[(f32, f32)], quantity: &str, unit: &str) -> Result<(), String> {
+    let Some((first, _)) = layers.first() else {
+        return Err("The velocity model has no layers".to_string());
+    };
+    if *first != 0. {
+        return Err(format!(
+            "The first layer needs to start at a {quantity} of 0 {unit}, not {first} {unit}"
+        ));
+    }
+    for window in layers.windows(2) {
+        if window[1].0 <= window[0].0 {
+            return Err(format!(
+                "The layer {quantity}s need to be increasing, but {} {unit} follows {} {unit}",
+                window[1].0, window[0].0
+            ));
+        }
+    }
+    if let Some((top, velocity)) = layers
+        .iter()
+        .find(|(_, velocity)| !(velocity.is_finite() && *velocity > 0.))
+    {
+        return Err(format!(
+            "The velocity of the layer at {top} {unit} needs to be positive, not {velocity}"
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DensityModel, VelocityModel};
+
+    #[test]
+    fn test_conversions() {
+        let model = VelocityModel::from_depth_layers(&[(0., 0.2), (10., 0.1)]).unwrap();
+
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-5;
+        // The first layer takes 100 ns (two-way) to pass
+        assert!(close(model.time_to_depth(50.), 5.));
+        assert!(close(model.time_to_depth(100.), 10.));
+        assert!(close(model.time_to_depth(140.), 12.));
+        assert_eq!(model.time_to_depth(0.), 0.);
+
+        assert_eq!(model.average_velocity(0.), 0.2);
+        assert_eq!(model.average_velocity(50.), 0.2);
+        assert!(close(model.average_velocity(200.), 0.15));
+
+        // The same model by return time
+        let by_time = VelocityModel::from_time_layers(&[(0., 0.2), (100., 0.1)]).unwrap();
+        assert!(close(by_time.layers()[1].0, 10.));
+
+        assert_eq!(model.to_string(), "0.2 m/ns from 0 m, 0.1 m/ns from 10 m");
+
+        // 100 ns at 0.2 m/ns over 100 ns at 0.15 m/ns, where the point at 150 ns is inconsistent
+        let rms = ((0.2_f32.powi(2) * 100. + 0.15_f32.powi(2) * 100.) / 200.).sqrt();
+        let from_rms =
+            VelocityModel::from_rms_velocities(&[(200., rms), (100., 0.2), (150., 0.5)]).unwrap();
+        assert_eq!(from_rms.layers().len(), 2);
+        assert!(close(from_rms.layers()[1].0, 10.));
+        assert!(close(from_rms.layers()[1].1, 0.15));
+        let intervals = super::dix_interval_velocities(&[(200., rms), (100., 0.2)]);
+        assert_eq!(intervals[1].0, 100.);
+        assert!(close(intervals[1].1, 0.15));
+        assert!(VelocityModel::from_rms_velocities(&[]).is_err());
+    }
+
+    #[test]
+    fn test_density() {
+        // Pure ice should give the typical ice velocity with both models
+        let kovacs = DensityModel::Kovacs.velocity(0.917);
+        let looyenga = DensityModel::Looyenga.velocity(0.917);
+        assert!((kovacs - 0.169).abs() < 0.002, "{kovacs}");
+        assert!((looyenga - 0.169).abs() < 0.002, "{looyenga}");
+        // Air travels at the speed of light
+        assert!((DensityModel::Looyenga.velocity(0.) - 0.2998).abs() < 1e-4);
+
+        // kg/m³ and g/cm³ give the same model
+        let model = VelocityModel::from_density(&[(0., 400.), (20., 917.)], DensityModel::Kovacs);
+        assert_eq!(
+            model,
+            VelocityModel::from_density(&[(0., 0.4), (20., 0.917)], DensityModel::Kovacs)
+        );
+        let model = model.unwrap();
+        // Firn is faster than ice
+        assert!(model.layers()[0].1 > model.layers()[1].1);
+
+        assert!(VelocityModel::from_density(&[(0., 0.)], DensityModel::Kovacs).is_err());
+    }
+
+    #[test]
+    fn test_csv() {
+        let model =
+            VelocityModel::from_csv_str("# A comment\ndepth, velocity\n0,0.2\n\n10,0.1\n").unwrap();
+        assert_eq!(model.layers(), &[(0., 0.2), (10., 0.1)]);
+        assert_eq!(
+            VelocityModel::from_csv_str(&model.to_csv_string()).unwrap(),
+            model
+        );
+        let by_time = VelocityModel::from_csv_str("TWT,Velocity\n0,0.2\n100,0.1").unwrap();
+        assert!((by_time.layers()[1].0 - 10.).abs() < 1e-5);
+        assert!(VelocityModel::from_csv_str("depth,density_looyenga\n0,350\n10,600").is_ok());
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let filepath = tempdir.path().join("velocity.csv");
+        std::fs::write(&filepath, model.to_csv_string()).unwrap();
+        assert_eq!(VelocityModel::from_csv(&filepath).unwrap(), model);
+
+        for (text, expected_error) in [
+            ("", "empty"),
+            ("depth\n0", "two columns"),
+            ("height,velocity\n0,0.2", "Unknown columns"),
+            ("twt,density\n0,0.2", "Unknown columns"),
+            ("depth,velocity\n0,fast", "line 2"),
+            ("depth,velocity\n0,0.2,3", "two values on line 2"),
+            ("depth,velocity\n5,0.2", "0 m, not 5 m"),
+            ("depth,velocity\n0,0.2\n0,0.1", "increasing"),
+            ("depth,velocity\n0,-0.2", "positive"),
+            ("depth,velocity\n", "no layers"),
+        ] {
+            let error = VelocityModel::from_csv_str(text).unwrap_err();
+            assert!(error.contains(expected_error), "{text:?}: {error}");
+        }
+    }
+}